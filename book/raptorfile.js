@@ -26,7 +26,7 @@ export default function(hljs) {
             hljs.QUOTE_STRING_MODE,
             hljs.NUMBER_MODE,
             {
-                beginKeywords: 'RENDER WRITE MKDIR COPY INCLUDE RUN WORKDIR ENTRYPOINT CMD',
+                beginKeywords: 'RENDER WRITE MKDIR COPY INCLUDE RUN WORKDIR USER NETWORK ARG ENTRYPOINT CMD',
                 starts: {
                     end: /[^\\]$/,
                     subLanguage: 'bash'
@@ -5,6 +5,7 @@ use serde_json::Value;
 
 use crate::digest::Digest;
 use crate::error::{DResult, DockerError};
+use crate::platform::Platform;
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct DockerTagsList {
@@ -12,14 +13,14 @@ pub struct DockerTagsList {
     pub tags: BTreeSet<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(untagged)]
 pub enum Manifest {
     V1(V1Manifest),
     V2(V2Manifest),
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(tag = "mediaType")]
 pub enum V2Manifest {
     #[serde(rename = "application/vnd.oci.image.index.v1+json")]
@@ -82,6 +83,14 @@ pub enum MediaType {
     #[serde(alias = "application/vnd.docker.image.rootfs.diff.tar.gzip")]
     ImageLayer,
 
+    #[serde(rename = "application/vnd.oci.image.layer.v1.tar+zstd")]
+    #[serde(alias = "application/vnd.docker.image.rootfs.diff.tar.zstd")]
+    ImageLayerZstd,
+
+    #[serde(rename = "application/vnd.oci.image.layer.v1.tar")]
+    #[serde(alias = "application/vnd.docker.image.rootfs.diff.tar")]
+    ImageLayerTar,
+
     #[serde(rename = "application/vnd.oci.image.config.v1+json")]
     #[serde(alias = "application/vnd.docker.container.image.v1+json")]
     ImageConfig,
@@ -94,9 +103,9 @@ pub enum MediaType {
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct DockerLayer {
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub data: Option<String>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
     pub annotations: BTreeMap<String, String>,
     pub digest: Digest,
     pub media_type: MediaType,
@@ -130,12 +139,59 @@ pub struct DockerManifestPlatform {
     pub features: Vec<String>,
 }
 
+impl Manifest {
+    /// Digest of the image config blob (only v2 image manifests have one)
+    #[must_use]
+    pub const fn config(&self) -> Option<&Digest> {
+        match self {
+            Self::V2(V2Manifest::Manifest(layers)) => Some(&layers.config.digest),
+            Self::V1(_) | Self::V2(V2Manifest::Index { .. }) => None,
+        }
+    }
+}
+
+/// Image configuration, as described in
+/// <https://github.com/opencontainers/image-spec/blob/main/config.md>
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ImageConfiguration {
+    pub architecture: String,
+    pub os: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>,
+    #[serde(default)]
+    pub config: ImageRuntimeConfig,
+    pub rootfs: ImageRootFs,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct ImageRuntimeConfig {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub env: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entrypoint: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cmd: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub working_dir: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ImageRootFs {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub diff_ids: Vec<Digest>,
+}
+
 impl V2Manifest {
-    pub fn select(&self, os: &str, arch: &str) -> DResult<Digest> {
+    pub fn select(&self, platform: &Platform) -> DResult<Digest> {
         match self {
             Self::Index { manifests } => {
                 for manifest in manifests {
-                    if manifest.platform.os == os && manifest.platform.architecture == arch {
+                    let mp = &manifest.platform;
+                    if platform.matches(&mp.os, &mp.architecture, mp.variant.as_deref()) {
                         return Ok(manifest.digest.clone());
                     }
                 }
@@ -146,3 +202,50 @@ impl V2Manifest {
         Err(DockerError::ManifestNotFound)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::api::V2Manifest;
+    use crate::digest::Digest;
+    use crate::error::{DResult, DockerError};
+    use crate::platform::Platform;
+
+    fn index() -> V2Manifest {
+        let entry = |n: u8, arch: &str, variant: Option<&str>| {
+            serde_json::json!({
+                "digest": Digest::sha256(&[n]),
+                "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                "platform": { "os": "linux", "architecture": arch, "variant": variant },
+                "size": 0,
+            })
+        };
+
+        serde_json::from_value(serde_json::json!({
+            "mediaType": "application/vnd.oci.image.index.v1+json",
+            "manifests": [
+                entry(1, "amd64", None),
+                entry(2, "arm", Some("v6")),
+                entry(3, "arm", Some("v7")),
+                entry(4, "arm64", None),
+            ],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn select_platform() -> DResult<()> {
+        let index = index();
+        let select = |platform: &str| index.select(&platform.parse::<Platform>()?);
+
+        assert_eq!(select("linux/amd64")?, Digest::sha256(&[1]));
+        assert_eq!(select("linux/arm")?, Digest::sha256(&[2]));
+        assert_eq!(select("linux/arm/v7")?, Digest::sha256(&[3]));
+        assert_eq!(select("linux/arm64/v8")?, Digest::sha256(&[4]));
+        assert!(matches!(
+            select("linux/arm/v5"),
+            Err(DockerError::ManifestNotFound)
+        ));
+
+        Ok(())
+    }
+}
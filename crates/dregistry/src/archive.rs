@@ -0,0 +1,285 @@
+use std::fs::Metadata;
+use std::io::{self, BufRead, Write};
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use log::trace;
+use nix::sys::stat::{Mode, SFlag, mknod};
+use tar::{Archive, Builder, EntryType, Header, HeaderMode};
+
+use crate::error::{DResult, DockerError};
+use crate::extract::{LayerExtractor, WHITEOUT_OPAQUE, WHITEOUT_PREFIX, decompress};
+
+const OVERLAY_OPAQUE_XATTRS: &[&str] = &["trusted.overlay.opaque", "user.overlay.opaque"];
+
+/// Check if `md` describes an overlayfs whiteout (a 0/0 character device)
+#[must_use]
+pub fn is_whiteout(md: &Metadata) -> bool {
+    md.file_type().is_char_device() && md.rdev() == 0
+}
+
+/// Check if `path` is an overlayfs opaque directory
+#[must_use]
+pub fn is_opaque(path: &Utf8Path) -> bool {
+    OVERLAY_OPAQUE_XATTRS
+        .iter()
+        .any(|name| matches!(xattr::get(path, name), Ok(Some(value)) if value == b"y"))
+}
+
+/// Creates OCI image layers (uncompressed tar) from overlayfs upper
+/// directories, converting overlayfs whiteouts (0/0 character devices) and
+/// opaque directories to their OCI equivalents.
+///
+/// See <https://github.com/opencontainers/image-spec/blob/main/layer.md>
+pub struct LayerArchiver<'a> {
+    root: &'a Utf8Path,
+}
+
+impl<'a> LayerArchiver<'a> {
+    #[must_use]
+    pub const fn new(root: &'a Utf8Path) -> Self {
+        Self { root }
+    }
+
+    pub fn write<W: Write>(&self, writer: W) -> DResult<W> {
+        let mut builder = Builder::new(writer);
+        builder.follow_symlinks(false);
+        builder.mode(HeaderMode::Complete);
+
+        self.append_dir(&mut builder, Utf8Path::new(""))?;
+
+        Ok(builder.into_inner()?)
+    }
+
+    fn append_dir<W: Write>(&self, builder: &mut Builder<W>, dir: &Utf8Path) -> DResult<()> {
+        let full = self.root.join(dir);
+
+        if is_opaque(&full) {
+            trace!("Opaque directory {dir}");
+            Self::append_empty(builder, &dir.join(WHITEOUT_OPAQUE))?;
+        }
+
+        let mut names = full
+            .read_dir_utf8()?
+            .map(|dent| Ok(dent?.file_name().to_string()))
+            .collect::<DResult<Vec<_>>>()?;
+
+        /* sort entries, to make archives reproducible */
+        names.sort();
+
+        for name in names {
+            let path = dir.join(&name);
+            let full = self.root.join(&path);
+            let md = full.symlink_metadata()?;
+
+            if is_whiteout(&md) {
+                trace!("Whiteout {path}");
+                Self::append_empty(builder, &dir.join(format!("{WHITEOUT_PREFIX}{name}")))?;
+                continue;
+            }
+
+            trace!("Archiving {path}");
+            builder.append_path_with_name(&full, &path)?;
+
+            if md.is_dir() {
+                self.append_dir(builder, &path)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn append_empty<W: Write>(builder: &mut Builder<W>, path: &Utf8Path) -> DResult<()> {
+        let mut header = Header::new_gnu();
+        header.set_entry_type(EntryType::Regular);
+        header.set_mode(0o644);
+        header.set_size(0);
+        header.set_mtime(0);
+
+        builder.append_data(&mut header, path, io::empty())?;
+
+        Ok(())
+    }
+}
+
+/// Restores archives created by [`LayerArchiver`] as overlayfs upper
+/// directories, converting OCI whiteouts and opaque directories back to their
+/// overlayfs equivalents.
+///
+/// Unlike [`LayerExtractor`], the archive is not applied on top of anything,
+/// so the target directory is expected to be empty. Creating whiteouts
+/// (character devices) and trusted xattrs requires root.
+pub struct LayerUnarchiver<'a> {
+    root: &'a Utf8Path,
+}
+
+impl<'a> LayerUnarchiver<'a> {
+    #[must_use]
+    pub const fn new(root: &'a Utf8Path) -> Self {
+        Self { root }
+    }
+
+    pub fn apply(&self, reader: impl BufRead) -> DResult<()> {
+        let mut archive = Archive::new(decompress(reader)?);
+        archive.set_preserve_permissions(true);
+        archive.set_preserve_ownerships(true);
+        archive.set_preserve_mtime(true);
+
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let path = LayerExtractor::normalize(&entry.path()?)?;
+
+            let Some(name) = path.file_name() else {
+                continue;
+            };
+
+            let parent = path.parent().unwrap_or_else(|| Utf8Path::new(""));
+
+            if name == WHITEOUT_OPAQUE {
+                trace!("Opaque directory {parent}");
+                xattr::set(self.dir(parent)?, OVERLAY_OPAQUE_XATTRS[0], b"y")?;
+            } else if let Some(target) = name.strip_prefix(WHITEOUT_PREFIX) {
+                trace!("Whiteout {parent}/{target}");
+                let path = self.dir(parent)?.join(target);
+                mknod(path.as_std_path(), SFlag::S_IFCHR, Mode::empty(), 0)?;
+            } else {
+                trace!("Unpacking {path}");
+                entry.unpack_in(self.root)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Resolve an (already unpacked) directory, making sure that symlinks in
+    /// the archive cannot redirect us outside of the root directory.
+    fn dir(&self, dir: &Utf8Path) -> DResult<Utf8PathBuf> {
+        let root = self.root.canonicalize_utf8()?;
+        let full = self.root.join(dir).canonicalize_utf8()?;
+
+        if !full.starts_with(&root) {
+            return Err(DockerError::InvalidLayerPath(dir.to_string()));
+        }
+
+        Ok(full)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::{PermissionsExt, symlink};
+
+    use camino::Utf8Path;
+    use camino_tempfile::Utf8TempDir;
+    use tar::Archive;
+
+    use crate::archive::{LayerArchiver, LayerUnarchiver};
+    use crate::error::DResult;
+    use crate::extract::LayerExtractor;
+
+    fn entries(root: &Utf8Path) -> DResult<Vec<String>> {
+        let data = LayerArchiver::new(root).write(vec![])?;
+
+        Archive::new(&data[..])
+            .entries()?
+            .map(|entry| Ok(entry?.path()?.to_string_lossy().to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn archive_roundtrip() -> DResult<()> {
+        let src = Utf8TempDir::new()?;
+        fs::create_dir_all(src.path().join("etc/conf.d"))?;
+        fs::write(src.path().join("etc/conf.d/b"), "b")?;
+        fs::write(src.path().join("etc/a"), "a")?;
+        fs::set_permissions(src.path().join("etc/a"), fs::Permissions::from_mode(0o600))?;
+        symlink("a", src.path().join("etc/link"))?;
+
+        assert_eq!(
+            entries(src.path())?,
+            ["etc", "etc/a", "etc/conf.d", "etc/conf.d/b", "etc/link"]
+        );
+
+        let dst = Utf8TempDir::new()?;
+        let data = LayerArchiver::new(src.path()).write(vec![])?;
+        LayerExtractor::new(dst.path()).apply(&data[..])?;
+
+        let root = dst.path();
+        assert_eq!(fs::read_to_string(root.join("etc/conf.d/b"))?, "b");
+        assert_eq!(
+            root.join("etc/a").metadata()?.permissions().mode() & 0o7777,
+            0o600
+        );
+        assert_eq!(root.join("etc/link").read_link_utf8()?, "a");
+
+        Ok(())
+    }
+
+    #[test]
+    fn archive_opaque() -> DResult<()> {
+        let src = Utf8TempDir::new()?;
+        let dir = src.path().join("dir");
+        fs::create_dir(&dir)?;
+        fs::write(dir.join("file"), "")?;
+
+        if xattr::set(&dir, "user.overlay.opaque", b"y").is_err() {
+            /* user xattrs not supported on this filesystem */
+            return Ok(());
+        }
+
+        assert_eq!(
+            entries(src.path())?,
+            ["dir", "dir/.wh..wh..opq", "dir/file"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn unarchive_overlay() -> DResult<()> {
+        use std::os::unix::fs::{FileTypeExt, MetadataExt};
+
+        use nix::sys::stat::{Mode, SFlag, mknod};
+        use nix::unistd::Uid;
+
+        /* whiteouts and trusted xattrs can only be created by root */
+        if !Uid::effective().is_root() {
+            return Ok(());
+        }
+
+        let src = Utf8TempDir::new()?;
+        let dir = src.path().join("dir");
+        fs::create_dir(&dir)?;
+        fs::write(dir.join("file"), "data")?;
+        mknod(
+            src.path().join("gone").as_std_path(),
+            SFlag::S_IFCHR,
+            Mode::empty(),
+            0,
+        )?;
+
+        if xattr::set(&dir, "trusted.overlay.opaque", b"y").is_err() {
+            /* trusted xattrs not supported on this filesystem */
+            return Ok(());
+        }
+
+        let data = LayerArchiver::new(src.path()).write(vec![])?;
+
+        let dst = Utf8TempDir::new()?;
+        LayerUnarchiver::new(dst.path()).apply(&data[..])?;
+
+        let root = dst.path();
+        assert_eq!(fs::read_to_string(root.join("dir/file"))?, "data");
+        assert_eq!(
+            xattr::get(root.join("dir"), "trusted.overlay.opaque")?,
+            Some(b"y".to_vec())
+        );
+
+        let md = root.join("gone").symlink_metadata()?;
+        assert!(md.file_type().is_char_device());
+        assert_eq!(md.rdev(), 0);
+
+        Ok(())
+    }
+}
@@ -1,19 +1,31 @@
 use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap};
+use std::io::Read;
 
 use log::trace;
 use reqwest::blocking::{Client, RequestBuilder, Response};
-use reqwest::header::{ACCEPT, HeaderValue, WWW_AUTHENTICATE};
+use reqwest::header::{
+    ACCEPT, CONTENT_RANGE, CONTENT_TYPE, HeaderValue, LOCATION, WWW_AUTHENTICATE,
+};
 use reqwest::{IntoUrl, Method, StatusCode};
 use serde::Deserialize;
 use serde::de::DeserializeOwned;
 
 use crate::api::{DockerTagsList, Manifest, V2Manifest};
 use crate::authparse::parse_www_authenticate;
+use crate::credentials::Credentials;
 use crate::digest::Digest;
 use crate::error::{DResult, DockerError};
+use crate::platform::Platform;
+use crate::registry::Scheme;
 
 pub trait Reference {
     fn reference(&self) -> Cow<str>;
+
+    /// The expected digest of the referenced content, if it is known up front
+    fn digest(&self) -> Option<&Digest> {
+        None
+    }
 }
 
 impl Reference for &str {
@@ -32,23 +44,45 @@ impl Reference for Digest {
     fn reference(&self) -> Cow<str> {
         Cow::Owned(self.to_string())
     }
+
+    fn digest(&self) -> Option<&Digest> {
+        Some(self)
+    }
 }
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct DockerAuthResult {
-    token: String,
+    token: Option<String>,
+    access_token: Option<String>,
+}
+
+impl DockerAuthResult {
+    fn into_token(self) -> DResult<String> {
+        self.token
+            .or(self.access_token)
+            .ok_or(DockerError::UnsupportedAuthMethod)
+    }
+}
+
+enum Authorization {
+    Bearer(String),
+    Basic(String, String),
 }
 
 pub struct DockerClient {
     client: Client,
+    scheme: Scheme,
     domain: String,
-    token: Option<String>,
+    auth: Option<Authorization>,
+    credentials: Option<Credentials>,
+    scopes: BTreeSet<String>,
     image: String,
 }
 
 impl DockerClient {
-    const MIME_TYPE_MANIFEST: &str = "application/vnd.oci.image.manifest.v1+json";
+    pub const MIME_TYPE_MANIFEST: &str = "application/vnd.oci.image.manifest.v1+json";
     const MIME_TYPE_INDEX: &str = "application/vnd.oci.image.index.v1+json";
+    const DOCKER_CONTENT_DIGEST: &str = "Docker-Content-Digest";
 
     pub fn new(client: Client, domain: impl AsRef<str>, image: impl AsRef<str>) -> DResult<Self> {
         let image = image.as_ref().to_string();
@@ -56,70 +90,175 @@ impl DockerClient {
 
         Ok(Self {
             client,
+            scheme: Scheme::Https,
             domain,
-            token: None,
+            auth: None,
+            credentials: None,
+            scopes: BTreeSet::new(),
             image,
         })
     }
 
-    fn request(&self, method: Method, url: impl IntoUrl) -> RequestBuilder {
-        let mut res = self.client.request(method, url);
+    #[must_use]
+    pub const fn with_scheme(mut self, scheme: Scheme) -> Self {
+        self.scheme = scheme;
+        self
+    }
+
+    #[must_use]
+    pub fn with_credentials(mut self, credentials: Option<Credentials>) -> Self {
+        self.credentials = credentials;
+        self
+    }
+
+    /// Request push access to the repository, in addition to the scopes
+    /// requested by the registry
+    #[must_use]
+    pub fn with_push(mut self) -> Self {
+        let scope = format!("repository:{}:pull,push", self.image);
+        self.add_scope(scope);
+        self
+    }
 
-        if let Some(token) = &self.token {
-            res = res.header("Authorization", format!("Bearer {token}"));
+    /// Request an additional token scope (e.g. pull access to another
+    /// repository, for cross-repository mounts). If the scope is new, the
+    /// current token is dropped, so a new one is requested when needed.
+    fn add_scope(&mut self, scope: String) {
+        if self.scopes.insert(scope) && matches!(self.auth, Some(Authorization::Bearer(_))) {
+            self.auth = None;
         }
+    }
+
+    fn request(&self, method: Method, url: impl IntoUrl) -> RequestBuilder {
+        let res = self.client.request(method, url);
 
-        res
+        match &self.auth {
+            Some(Authorization::Bearer(token)) => res.bearer_auth(token),
+            Some(Authorization::Basic(username, password)) => {
+                res.basic_auth(username, Some(password))
+            }
+            None => res,
+        }
     }
 
     fn api_url(&self, url: impl AsRef<str>) -> String {
+        let scheme = &self.scheme;
         let domain = &self.domain;
         let image = &self.image;
         let url = url.as_ref();
 
-        format!("https://{domain}/v2/{image}/{url}")
+        format!("{scheme}://{domain}/v2/{image}/{url}")
     }
 
     fn get<T: DeserializeOwned>(&mut self, url: impl IntoUrl, accept: &str) -> DResult<T> {
-        let auth_header = &self.token.as_deref().map_or_else(String::new, |tok| {
-            format!(" -H 'Authorization: Bearer {tok}'")
-        });
+        Ok(serde_json::from_slice(&self.get_bytes(url, accept)?)?)
+    }
+
+    fn get_bytes(&mut self, url: impl IntoUrl, accept: &str) -> DResult<Vec<u8>> {
+        let auth_header = match &self.auth {
+            Some(Authorization::Bearer(tok)) => format!(" -H 'Authorization: Bearer {tok}'"),
+            Some(Authorization::Basic(user, _)) => format!(" -u '{user}:<password>'"),
+            None => String::new(),
+        };
 
         trace!("curl{auth_header} -H 'Accept: {accept}' {}", url.as_str());
 
         let url = url.into_url()?;
-        let resp = self
-            .request(Method::GET, url.clone())
-            .header(ACCEPT, accept)
-            .send()?;
+        let resp = self.send(|dc| dc.request(Method::GET, url.clone()).header(ACCEPT, accept))?;
+
+        Ok(resp.error_for_status()?.bytes()?.to_vec())
+    }
+
+    /// Send a request, authenticating if the registry asks for it, and we
+    /// are not authenticated yet (or our token was rejected, e.g. because it
+    /// expired during a long upload).
+    ///
+    /// The request is built by `req`, since it has to be rebuilt with the new
+    /// authorization after authenticating. Basic credentials are never sent
+    /// twice: if they are rejected, so is the request.
+    fn send(&mut self, req: impl Fn(&Self) -> RequestBuilder) -> DResult<Response> {
+        let resp = req(self).send()?;
 
         trace!("Response: {resp:?}");
 
         if let Some(header) = resp.headers().get(WWW_AUTHENTICATE)
             && resp.status() == StatusCode::UNAUTHORIZED
-            && self.token.is_none()
+            && !matches!(self.auth, Some(Authorization::Basic(..)))
         {
-            self.token = Some(self.get_docker_token(header)?.token);
-            return self.get(url, accept);
+            self.authenticate(header)?;
+            let resp = req(self).send()?;
+            trace!("Response: {resp:?}");
+            return Ok(resp);
         }
 
-        Ok(resp.error_for_status()?.json()?)
+        Ok(resp)
     }
 
-    fn get_docker_token(&self, header: &HeaderValue) -> DResult<DockerAuthResult> {
+    fn authenticate(&mut self, header: &HeaderValue) -> DResult<()> {
         let mut w = parse_www_authenticate(header.to_str()?)?;
 
-        let Some(mut settings) = w.remove("Bearer") else {
+        if let Some(settings) = w.remove("Bearer") {
+            let token = self.get_docker_token(settings)?.into_token()?;
+            self.auth = Some(Authorization::Bearer(token));
+        } else if w.contains_key("Basic") {
+            match &self.credentials {
+                Some(Credentials::Basic { username, password }) => {
+                    self.auth = Some(Authorization::Basic(username.clone(), password.clone()));
+                }
+                Some(Credentials::IdentityToken(_)) => {
+                    return Err(DockerError::IdentityTokenWithBasicAuth(self.domain.clone()));
+                }
+                None => return Err(DockerError::MissingCredentials(self.domain.clone())),
+            }
+        } else {
             return Err(DockerError::UnsupportedAuthMethod);
-        };
+        }
 
+        Ok(())
+    }
+
+    fn get_docker_token(&self, mut settings: HashMap<String, String>) -> DResult<DockerAuthResult> {
         let Some(realm) = settings.remove("realm") else {
             return Err(DockerError::UnsupportedAuthMethod);
         };
 
-        let auth_req = self.request(Method::GET, realm).query(&settings);
+        /* combine the scope asked for by the registry with our own */
+        let mut scopes = self.scopes.clone();
+        scopes.extend(settings.remove("scope"));
+
+        let mut query: Vec<(String, String)> = settings.into_iter().collect();
+
+        let auth_req = match &self.credentials {
+            None => {
+                query.extend(scopes.into_iter().map(|scope| ("scope".into(), scope)));
+                self.client.get(realm).query(&query)
+            }
+
+            Some(Credentials::Basic { username, password }) => {
+                query.extend(scopes.into_iter().map(|scope| ("scope".into(), scope)));
+                self.client
+                    .get(realm)
+                    .query(&query)
+                    .basic_auth(username, Some(password))
+            }
+
+            /* identity tokens are exchanged using the OAuth2 refresh_token
+             * flow, where multiple scopes are separated by spaces */
+            Some(Credentials::IdentityToken(token)) => {
+                query.push(("grant_type".into(), "refresh_token".into()));
+                query.push(("refresh_token".into(), token.clone()));
+                query.push(("client_id".into(), "raptor".into()));
+                if !scopes.is_empty() {
+                    query.push((
+                        "scope".into(),
+                        scopes.into_iter().collect::<Vec<_>>().join(" "),
+                    ));
+                }
+                self.client.post(realm).form(&query)
+            }
+        };
 
-        Ok(auth_req.send()?.json()?)
+        Ok(auth_req.send()?.error_for_status()?.json()?)
     }
 
     pub fn tags(&mut self) -> DResult<DockerTagsList> {
@@ -129,20 +268,215 @@ impl DockerClient {
     }
 
     pub fn manifest(&mut self, reference: &impl Reference) -> DResult<Manifest> {
+        Ok(self.manifest_digest(reference)?.0)
+    }
+
+    /// Fetch a manifest, along with its digest (e.g. to resolve a tag)
+    pub fn manifest_digest(&mut self, reference: &impl Reference) -> DResult<(Manifest, Digest)> {
         let url = self.api_url(format!("manifests/{}", reference.reference()));
 
         let mime_type = [Self::MIME_TYPE_INDEX, Self::MIME_TYPE_MANIFEST].join(",");
 
-        self.get(url, &mime_type)
+        let data = self.get_bytes(url, &mime_type)?;
+
+        if let Some(digest) = reference.digest() {
+            digest.verify(&data)?;
+        }
+
+        Ok((serde_json::from_slice(&data)?, Digest::sha256(&data)))
     }
 
     pub fn blob(&mut self, digest: &Digest) -> DResult<Response> {
         let url = self.api_url(format!("blobs/{digest}"));
 
-        Ok(self.request(Method::GET, url).send()?)
+        Ok(self.request(Method::GET, url).send()?.error_for_status()?)
+    }
+
+    /// Look up a manifest, returning `None` if the registry does not have it
+    pub fn find_manifest(&mut self, reference: &impl Reference) -> DResult<Option<Manifest>> {
+        let url = self.api_url(format!("manifests/{}", reference.reference()));
+
+        let mime_type = [Self::MIME_TYPE_INDEX, Self::MIME_TYPE_MANIFEST].join(",");
+
+        let resp = self.send(|dc| dc.request(Method::GET, &url).header(ACCEPT, &mime_type))?;
+
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let data = resp.error_for_status()?.bytes()?;
+
+        if let Some(digest) = reference.digest() {
+            digest.verify(&data)?;
+        }
+
+        Ok(Some(serde_json::from_slice(&data)?))
     }
 
-    pub fn digests(&mut self, manifest: &Manifest, os: &str, arch: &str) -> DResult<Vec<Digest>> {
+    /// Upload a manifest under `reference` (a tag or digest), returning the
+    /// digest of the uploaded manifest.
+    ///
+    /// The manifest is uploaded exactly as given, so its digest is preserved.
+    /// If the registry reports a different digest for it, this is an error.
+    pub fn put_manifest(
+        &mut self,
+        reference: &impl Reference,
+        media_type: &str,
+        data: &[u8],
+    ) -> DResult<Digest> {
+        let url = self.api_url(format!("manifests/{}", reference.reference()));
+
+        let resp = self
+            .send(|dc| {
+                dc.request(Method::PUT, &url)
+                    .header(CONTENT_TYPE, media_type)
+                    .body(data.to_vec())
+            })?
+            .error_for_status()?;
+
+        let digest = Digest::sha256(data);
+
+        if let Some(header) = resp.headers().get(Self::DOCKER_CONTENT_DIGEST) {
+            let actual = Digest::parse(header.to_str()?)?;
+            if actual != digest {
+                return Err(DockerError::DigestMismatch {
+                    expected: digest,
+                    actual,
+                });
+            }
+        }
+
+        Ok(digest)
+    }
+
+    pub fn blob_exists(&mut self, digest: &Digest) -> DResult<bool> {
+        let url = self.api_url(format!("blobs/{digest}"));
+
+        let resp = self.send(|dc| dc.request(Method::HEAD, &url))?;
+
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Ok(false);
+        }
+
+        resp.error_for_status()?;
+
+        Ok(true)
+    }
+
+    /// Resolve the upload location returned by the registry, which may be
+    /// relative to the registry
+    fn upload_location(&self, resp: &Response) -> DResult<String> {
+        let location = resp
+            .headers()
+            .get(LOCATION)
+            .ok_or(DockerError::MissingUploadLocation)?
+            .to_str()?;
+
+        if location.starts_with('/') {
+            Ok(format!("{}://{}{location}", self.scheme, self.domain))
+        } else {
+            Ok(location.to_string())
+        }
+    }
+
+    /// Start a blob upload session, returning its location
+    fn start_upload(&mut self) -> DResult<String> {
+        let url = self.api_url("blobs/uploads/");
+
+        let resp = self
+            .send(|dc| dc.request(Method::POST, &url))?
+            .error_for_status()?;
+
+        self.upload_location(&resp)
+    }
+
+    fn finish_upload(&mut self, location: &str, digest: &Digest, data: &[u8]) -> DResult<()> {
+        let query = [("digest", digest.to_string())];
+
+        self.send(|dc| {
+            dc.request(Method::PUT, location)
+                .query(&query)
+                .header(CONTENT_TYPE, "application/octet-stream")
+                .body(data.to_vec())
+        })?
+        .error_for_status()?;
+
+        Ok(())
+    }
+
+    /// Upload a (small) blob, using a monolithic upload (a single POST to
+    /// start the upload session, followed by a single PUT).
+    ///
+    /// The data is kept in memory, so it can be sent again if the registry
+    /// asks to authenticate. Use [`Self::push_blob_chunked`] for large blobs.
+    pub fn push_blob(&mut self, digest: &Digest, data: &[u8]) -> DResult<()> {
+        let location = self.start_upload()?;
+
+        self.finish_upload(&location, digest, data)
+    }
+
+    /// Upload a blob using a chunked upload, sending (at most) `chunk_size`
+    /// bytes per PATCH request. This keeps individual requests small, for
+    /// registries (or proxies) that limit the request size.
+    pub fn push_blob_chunked(
+        &mut self,
+        digest: &Digest,
+        mut reader: impl Read,
+        chunk_size: u64,
+    ) -> DResult<()> {
+        let mut location = self.start_upload()?;
+        let mut offset = 0;
+
+        loop {
+            let mut chunk = vec![];
+            (&mut reader).take(chunk_size).read_to_end(&mut chunk)?;
+
+            if chunk.is_empty() {
+                break;
+            }
+
+            let end = offset + chunk.len() as u64;
+
+            trace!("Uploading chunk {offset}-{end} of {digest}");
+
+            let range = format!("{offset}-{}", end - 1);
+            let resp = self
+                .send(|dc| {
+                    dc.request(Method::PATCH, &location)
+                        .header(CONTENT_TYPE, "application/octet-stream")
+                        .header(CONTENT_RANGE, &range)
+                        .body(chunk.clone())
+                })?
+                .error_for_status()?;
+
+            location = self.upload_location(&resp)?;
+            offset = end;
+        }
+
+        self.finish_upload(&location, digest, &[])
+    }
+
+    /// Try to mount a blob from another repository on the same registry,
+    /// avoiding an upload.
+    ///
+    /// Returns `false` if the registry did not mount the blob (e.g. because
+    /// it is not present in `from`, or not accessible).
+    pub fn mount_blob(&mut self, digest: &Digest, from: &str) -> DResult<bool> {
+        self.add_scope(format!("repository:{from}:pull"));
+
+        let url = self.api_url("blobs/uploads/");
+        let query = [("mount", digest.to_string()), ("from", from.to_string())];
+
+        let resp = self
+            .send(|dc| dc.request(Method::POST, &url).query(&query))?
+            .error_for_status()?;
+
+        /* on failure, the registry starts a regular upload session instead,
+         * which is simply left to expire */
+        Ok(resp.status() == StatusCode::CREATED)
+    }
+
+    pub fn digests(&mut self, manifest: &Manifest, platform: &Platform) -> DResult<Vec<Digest>> {
         let res = match manifest {
             Manifest::V1(manifest) => manifest
                 .fs_layers
@@ -151,10 +485,10 @@ impl DockerClient {
                 .collect(),
 
             Manifest::V2(v2 @ V2Manifest::Index { .. }) => {
-                let digest = v2.select(os, arch)?;
+                let digest = v2.select(platform)?;
 
                 let manifest = self.manifest(&digest)?;
-                self.digests(&manifest, os, arch)?
+                self.digests(&manifest, platform)?
             }
 
             Manifest::V2(V2Manifest::Manifest(docker_layers)) => docker_layers
@@ -167,3 +501,233 @@ impl DockerClient {
         Ok(res)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use reqwest::blocking::Client;
+    use reqwest::header::HeaderValue;
+
+    use crate::api::{DockerLayer, DockerLayers, Manifest, MediaType, V2Manifest};
+    use crate::client::{Authorization, DockerClient};
+    use crate::credentials::Credentials;
+    use crate::digest::Digest;
+    use crate::error::{DResult, DockerError};
+    use crate::registry::Scheme;
+    use crate::testutil::{
+        TestRequest, respond, respond_json, serve, serve_registry, serve_registry_expiring,
+    };
+
+    fn client(credentials: Option<Credentials>) -> DResult<DockerClient> {
+        Ok(
+            DockerClient::new(Client::new(), "registry.test", "library/test")?
+                .with_credentials(credentials),
+        )
+    }
+
+    fn bearer_challenge(addr: &str) -> HeaderValue {
+        HeaderValue::from_str(&format!(
+            r#"Bearer realm="http://{addr}/token",service="registry.test",scope="repository:library/test:pull""#
+        ))
+        .unwrap()
+    }
+
+    fn token_server() -> String {
+        serve(|req: &TestRequest| {
+            let body = String::from_utf8_lossy(&req.body);
+
+            match req.header("Authorization").as_deref() {
+                None if req.method() == "GET" => respond_json(r#"{"token": "anonymous"}"#),
+
+                /* base64("user:secret") */
+                Some("Basic dXNlcjpzZWNyZXQ=") if req.url().contains("scope=repository") => {
+                    respond_json(r#"{"token": "basic"}"#)
+                }
+
+                None if req.method() == "POST"
+                    && body.contains("grant_type=refresh_token")
+                    && body.contains("refresh_token=refresh") =>
+                {
+                    respond_json(r#"{"access_token": "identity"}"#)
+                }
+
+                _ => respond(401, ""),
+            }
+        })
+    }
+
+    fn bearer_token(client: &DockerClient) -> Option<&str> {
+        match &client.auth {
+            Some(Authorization::Bearer(token)) => Some(token),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn token_anonymous() -> DResult<()> {
+        let addr = token_server();
+
+        let mut dc = client(None)?;
+        dc.authenticate(&bearer_challenge(&addr))?;
+
+        assert_eq!(bearer_token(&dc), Some("anonymous"));
+        Ok(())
+    }
+
+    #[test]
+    fn token_basic_credentials() -> DResult<()> {
+        let addr = token_server();
+
+        let mut dc = client(Some(Credentials::basic("user", "secret")))?;
+        dc.authenticate(&bearer_challenge(&addr))?;
+        assert_eq!(bearer_token(&dc), Some("basic"));
+
+        let mut dc = client(Some(Credentials::basic("user", "wrong")))?;
+        assert!(matches!(
+            dc.authenticate(&bearer_challenge(&addr)),
+            Err(DockerError::ReqwestError(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn token_identity_token() -> DResult<()> {
+        let addr = token_server();
+
+        let mut dc = client(Some(Credentials::IdentityToken("refresh".into())))?;
+        dc.authenticate(&bearer_challenge(&addr))?;
+
+        assert_eq!(bearer_token(&dc), Some("identity"));
+        Ok(())
+    }
+
+    #[test]
+    fn basic_auth() -> DResult<()> {
+        let challenge = HeaderValue::from_static(r#"Basic realm="registry""#);
+
+        let mut dc = client(Some(Credentials::basic("user", "secret")))?;
+        dc.authenticate(&challenge)?;
+        assert!(matches!(
+            &dc.auth,
+            Some(Authorization::Basic(user, pass)) if user == "user" && pass == "secret"
+        ));
+
+        let mut dc = client(None)?;
+        assert!(matches!(
+            dc.authenticate(&challenge),
+            Err(DockerError::MissingCredentials(_))
+        ));
+
+        let mut dc = client(Some(Credentials::IdentityToken("refresh".into())))?;
+        assert!(matches!(
+            dc.authenticate(&challenge),
+            Err(DockerError::IdentityTokenWithBasicAuth(_))
+        ));
+
+        Ok(())
+    }
+
+    fn registry_client(addr: &str, image: &str) -> DResult<DockerClient> {
+        Ok(DockerClient::new(Client::new(), addr, image)?
+            .with_scheme(Scheme::Http)
+            .with_push())
+    }
+
+    #[test]
+    fn push_and_find() -> DResult<()> {
+        let (addr, _) = serve_registry();
+        let mut dc = registry_client(&addr, "library/test")?;
+
+        let data = b"layer data";
+        let digest = Digest::sha256(data);
+
+        assert!(!dc.blob_exists(&digest)?);
+        dc.push_blob(&digest, data)?;
+        assert!(dc.blob_exists(&digest)?);
+
+        let descriptor = |media_type| DockerLayer {
+            data: None,
+            annotations: BTreeMap::new(),
+            digest: digest.clone(),
+            media_type,
+            size: data.len() as u64,
+        };
+
+        let manifest = V2Manifest::Manifest(DockerLayers {
+            config: descriptor(MediaType::ImageConfig),
+            layers: vec![descriptor(MediaType::ImageLayerZstd)],
+            schema_version: 2,
+        });
+
+        let manifest = serde_json::to_vec(&manifest)?;
+
+        assert!(dc.find_manifest(&"tag")?.is_none());
+        let pushed = dc.put_manifest(&"tag", DockerClient::MIME_TYPE_MANIFEST, &manifest)?;
+        assert_eq!(pushed, Digest::sha256(&manifest));
+
+        let Some(Manifest::V2(V2Manifest::Manifest(found))) = dc.find_manifest(&"tag")? else {
+            panic!("manifest not found");
+        };
+        assert_eq!(found.layers[0].digest, digest);
+
+        Ok(())
+    }
+
+    #[test]
+    fn push_chunked() -> DResult<()> {
+        let (addr, store) = serve_registry();
+        let mut dc = registry_client(&addr, "library/test")?;
+
+        let data: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let digest = Digest::sha256(&data);
+
+        dc.push_blob_chunked(&digest, &data[..], 300)?;
+
+        let key = format!("library/test/blobs/{digest}");
+        assert_eq!(store.lock().unwrap()[&key], data);
+
+        Ok(())
+    }
+
+    #[test]
+    fn push_chunked_expired_token() -> DResult<()> {
+        /* tokens expire in the middle of the upload */
+        let (addr, store) = serve_registry_expiring(2);
+        let mut dc = registry_client(&addr, "library/test")?;
+
+        let data: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let digest = Digest::sha256(&data);
+
+        dc.push_blob_chunked(&digest, &data[..], 300)?;
+
+        let key = format!("library/test/blobs/{digest}");
+        assert_eq!(store.lock().unwrap()[&key], data);
+
+        Ok(())
+    }
+
+    #[test]
+    fn push_mount() -> DResult<()> {
+        let (addr, store) = serve_registry();
+
+        let data = b"shared layer";
+        let digest = Digest::sha256(data);
+
+        let mut dc = registry_client(&addr, "library/base")?;
+        assert!(!dc.mount_blob(&digest, "library/other")?);
+        dc.push_blob(&digest, data)?;
+
+        /* mounting requires pull access to the source repository, which is
+         * requested on demand */
+        let mut dc = registry_client(&addr, "library/app")?;
+        assert!(dc.mount_blob(&digest, "library/base")?);
+        assert!(dc.blob_exists(&digest)?);
+
+        let key = format!("library/app/blobs/{digest}");
+        assert_eq!(store.lock().unwrap()[&key], data);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,263 @@
+use std::collections::HashMap;
+use std::fmt::{self, Debug};
+
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::Deserialize;
+
+use crate::error::{DResult, DockerError};
+
+#[derive(Clone, PartialEq, Eq)]
+pub enum Credentials {
+    /// Username and password (or access token used as password)
+    Basic { username: String, password: String },
+
+    /// Identity (refresh) token, as stored by `docker login` for some registries
+    IdentityToken(String),
+}
+
+impl Credentials {
+    #[must_use]
+    pub fn basic(username: impl AsRef<str>, password: impl AsRef<str>) -> Self {
+        Self::Basic {
+            username: username.as_ref().to_string(),
+            password: password.as_ref().to_string(),
+        }
+    }
+
+    /// Parse credentials in `username:password` form
+    pub fn parse(value: &str) -> DResult<Self> {
+        let (username, password) = value
+            .split_once(':')
+            .ok_or(DockerError::InvalidCredentials)?;
+
+        Ok(Self::basic(username, password))
+    }
+
+    /// Parse credentials in base64-encoded `username:password` form (the
+    /// `auth` field in docker's `config.json`)
+    pub fn parse_base64(value: &str) -> DResult<Self> {
+        let data = BASE64_STANDARD.decode(value.trim())?;
+
+        Self::parse(&String::from_utf8_lossy(&data))
+    }
+}
+
+impl Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Basic { username, .. } => f
+                .debug_struct("Basic")
+                .field("username", username)
+                .finish_non_exhaustive(),
+            Self::IdentityToken(_) => f.debug_tuple("IdentityToken").finish_non_exhaustive(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct DockerConfig {
+    #[serde(default)]
+    auths: HashMap<String, DockerConfigAuth>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct DockerConfigAuth {
+    auth: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+    identitytoken: Option<String>,
+}
+
+/// Registry credentials, indexed by registry domain.
+///
+/// Credentials can be loaded from docker's `config.json`, and added
+/// explicitly. In addition, environment variables of the form
+/// `RAPTOR_AUTH_<DOMAIN>` (e.g. `RAPTOR_AUTH_GHCR_IO=user:token`) are
+/// consulted on lookup, and take precedence over everything else.
+#[derive(Debug, Default, Clone)]
+pub struct CredentialStore {
+    creds: HashMap<String, Credentials>,
+}
+
+impl CredentialStore {
+    pub(crate) const ENV_PREFIX: &str = "RAPTOR_AUTH_";
+
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load credentials from the standard docker locations: the json contents
+    /// of `$DOCKER_AUTH_CONFIG`, or `$DOCKER_CONFIG/config.json`, falling back
+    /// to `~/.docker/config.json`.
+    pub fn from_env() -> DResult<Self> {
+        let mut res = Self::new();
+
+        if let Ok(json) = std::env::var("DOCKER_AUTH_CONFIG") {
+            res.load_docker_config(&json)?;
+        } else if let Some(path) = Self::docker_config_path()
+            && path.exists()
+        {
+            res.load_docker_config(&std::fs::read_to_string(path)?)?;
+        }
+
+        Ok(res)
+    }
+
+    fn docker_config_path() -> Option<Utf8PathBuf> {
+        if let Ok(dir) = std::env::var("DOCKER_CONFIG") {
+            return Some(Utf8Path::new(&dir).join("config.json"));
+        }
+
+        let home = std::env::var("HOME").ok()?;
+        Some(Utf8Path::new(&home).join(".docker/config.json"))
+    }
+
+    pub fn load_docker_config(&mut self, json: &str) -> DResult<()> {
+        let config: DockerConfig = serde_json::from_str(json)?;
+
+        for (registry, auth) in config.auths {
+            let creds = if let Some(token) = auth.identitytoken {
+                Credentials::IdentityToken(token)
+            } else if let Some(auth) = auth.auth {
+                Credentials::parse_base64(&auth)?
+            } else if let (Some(username), Some(password)) = (auth.username, auth.password) {
+                Credentials::Basic { username, password }
+            } else {
+                /* entries managed by credential helpers have no inline credentials */
+                continue;
+            };
+
+            self.insert(&registry, creds);
+        }
+
+        Ok(())
+    }
+
+    pub fn insert(&mut self, registry: &str, creds: Credentials) {
+        self.creds.insert(Self::normalize(registry), creds);
+    }
+
+    pub fn get(&self, registry: &str) -> DResult<Option<Credentials>> {
+        let registry = Self::normalize(registry);
+
+        if let Ok(value) = std::env::var(Self::env_name(&registry)) {
+            return Ok(Some(Credentials::parse(&value)?));
+        }
+
+        Ok(self.creds.get(&registry).cloned())
+    }
+
+    /// Name of environment variable holding credentials for `registry`
+    #[must_use]
+    pub fn env_name(registry: &str) -> String {
+        let name: String = Self::normalize(registry)
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() {
+                    c.to_ascii_uppercase()
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+
+        format!("{}{name}", Self::ENV_PREFIX)
+    }
+
+    /// Reduce registry names to bare domains, so that all of
+    /// `https://index.docker.io/v1/`, `docker.io` and `index.docker.io`
+    /// refer to the same registry.
+    #[must_use]
+    pub fn normalize(registry: &str) -> String {
+        let registry = registry
+            .strip_prefix("https://")
+            .or_else(|| registry.strip_prefix("http://"))
+            .unwrap_or(registry);
+
+        let domain = registry.split('/').next().unwrap_or_default();
+
+        match domain {
+            "docker.io" | "registry-1.docker.io" => "index.docker.io",
+            domain => domain,
+        }
+        .to_ascii_lowercase()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::credentials::{CredentialStore, Credentials};
+    use crate::error::{DResult, DockerError};
+
+    #[test]
+    fn parse_credentials() -> DResult<()> {
+        assert_eq!(
+            Credentials::parse("user:pass:word")?,
+            Credentials::basic("user", "pass:word")
+        );
+        assert_eq!(
+            Credentials::parse_base64("dXNlcjpzZWNyZXQ=")?,
+            Credentials::basic("user", "secret")
+        );
+        assert!(matches!(
+            Credentials::parse("token"),
+            Err(DockerError::InvalidCredentials)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn load_docker_config() -> DResult<()> {
+        let mut store = CredentialStore::new();
+
+        store.load_docker_config(
+            r#"{
+                "auths": {
+                    "https://index.docker.io/v1/": { "auth": "dXNlcjpzZWNyZXQ=" },
+                    "ghcr.io": { "username": "ghuser", "password": "ghtoken" },
+                    "registry.example.org:5000": { "identitytoken": "refresh" },
+                    "helper.example.org": {}
+                },
+                "credsStore": "desktop"
+            }"#,
+        )?;
+
+        assert_eq!(
+            store.get("index.docker.io")?,
+            Some(Credentials::basic("user", "secret"))
+        );
+        assert_eq!(
+            store.get("docker.io")?,
+            Some(Credentials::basic("user", "secret"))
+        );
+        assert_eq!(
+            store.get("GHCR.io")?,
+            Some(Credentials::basic("ghuser", "ghtoken"))
+        );
+        assert_eq!(
+            store.get("registry.example.org:5000")?,
+            Some(Credentials::IdentityToken("refresh".into()))
+        );
+        assert_eq!(store.get("helper.example.org")?, None);
+        assert_eq!(store.get("registry.example.org")?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn env_name() {
+        assert_eq!(CredentialStore::env_name("ghcr.io"), "RAPTOR_AUTH_GHCR_IO");
+        assert_eq!(
+            CredentialStore::env_name("https://localhost:5000/v2/"),
+            "RAPTOR_AUTH_LOCALHOST_5000"
+        );
+        assert_eq!(
+            CredentialStore::env_name("docker.io"),
+            "RAPTOR_AUTH_INDEX_DOCKER_IO"
+        );
+    }
+}
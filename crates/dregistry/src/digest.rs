@@ -1,6 +1,8 @@
 use std::fmt::{Debug, Display};
+use std::io::{self, Write};
 
 use serde::{Deserialize, Serialize};
+use sha2::{Digest as _, Sha256};
 
 use crate::error::{DResult, DockerError};
 
@@ -27,6 +29,103 @@ impl Digest {
             _ => Err(DockerError::DigestError),
         }
     }
+
+    #[must_use]
+    pub fn sha256(data: &[u8]) -> Self {
+        Self::Sha256(Sha256::digest(data).into())
+    }
+
+    pub fn verify(&self, data: &[u8]) -> DResult<()> {
+        let mut verifier = DigestVerifier::new(self);
+        verifier.update(data);
+        verifier.finish()
+    }
+}
+
+/// Incrementally computes the digest of streamed data, and compares it
+/// against the expected value when finished.
+pub struct DigestVerifier {
+    expected: Digest,
+    hasher: Sha256,
+}
+
+impl DigestVerifier {
+    #[must_use]
+    pub fn new(expected: &Digest) -> Self {
+        Self {
+            expected: expected.clone(),
+            hasher: Sha256::new(),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.hasher.update(data);
+    }
+
+    pub fn finish(self) -> DResult<()> {
+        let actual = match self.expected {
+            Digest::Sha256(_) => Digest::Sha256(self.hasher.finalize().into()),
+        };
+
+        if actual == self.expected {
+            Ok(())
+        } else {
+            Err(DockerError::DigestMismatch {
+                expected: self.expected,
+                actual,
+            })
+        }
+    }
+}
+
+impl Write for DigestVerifier {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Passes written data through to an inner writer, while computing the
+/// digest (and size) of everything written.
+pub struct DigestWriter<W> {
+    inner: W,
+    hasher: Sha256,
+    size: u64,
+}
+
+impl<W: Write> DigestWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+            size: 0,
+        }
+    }
+
+    pub fn finish(self) -> (W, Digest, u64) {
+        (
+            self.inner,
+            Digest::Sha256(self.hasher.finalize().into()),
+            self.size,
+        )
+    }
+}
+
+impl<W: Write> Write for DigestWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.inner.write(buf)?;
+        self.hasher.update(&buf[..len]);
+        self.size += len as u64;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
 }
 
 impl Display for Digest {
@@ -75,7 +174,10 @@ impl<'de> Deserialize<'de> for Digest {
 
 #[cfg(test)]
 mod tests {
-    use crate::digest::Digest;
+    use std::io::Write;
+
+    use crate::digest::{Digest, DigestVerifier, DigestWriter};
+    use crate::error::DockerError;
 
     // simple pseudo-random generator, used to provide non-pathological test cases
     #[allow(clippy::needless_range_loop)]
@@ -109,4 +211,53 @@ mod tests {
 
         assert_eq!(src, dst);
     }
+
+    #[test]
+    fn sha256_verify() {
+        let digest = Digest::parse(
+            "sha256:b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9",
+        )
+        .unwrap();
+
+        assert_eq!(Digest::sha256(b"hello world"), digest);
+        assert!(digest.verify(b"hello world").is_ok());
+        assert!(matches!(
+            digest.verify(b"hello world\n"),
+            Err(DockerError::DigestMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn sha256_verify_streaming() {
+        let mut data = [0; 4096];
+        simple_rand(&mut data);
+
+        let digest = Digest::sha256(&data);
+
+        let mut verifier = DigestVerifier::new(&digest);
+        for chunk in data.chunks(100) {
+            verifier.update(chunk);
+        }
+        assert!(verifier.finish().is_ok());
+
+        let mut verifier = DigestVerifier::new(&digest);
+        verifier.update(&data[..4095]);
+        assert!(verifier.finish().is_err());
+    }
+
+    #[test]
+    fn sha256_writer() {
+        let mut data = [0; 4096];
+        simple_rand(&mut data);
+
+        let mut writer = DigestWriter::new(vec![]);
+        for chunk in data.chunks(100) {
+            writer.write_all(chunk).unwrap();
+        }
+
+        let (inner, digest, size) = writer.finish();
+        assert_eq!(inner, data);
+        assert_eq!(digest, Digest::sha256(&data));
+        assert_eq!(size, 4096);
+    }
 }
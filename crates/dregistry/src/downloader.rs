@@ -1,22 +1,28 @@
 use std::fmt;
 use std::fs::{self, File};
-use std::io::{Read, Write};
+use std::io::{self, Read, Write};
 
 use camino::{Utf8Path, Utf8PathBuf};
 use indicatif::{ProgressBar, ProgressState, ProgressStyle};
-use log::info;
-use reqwest::blocking::{Client, ClientBuilder};
+use log::{info, warn};
+use reqwest::blocking::Client;
 use serde::Serialize;
 use serde::de::DeserializeOwned;
 
+use crate::api::{Manifest, V2Manifest};
 use crate::client::DockerClient;
-use crate::digest::Digest;
+use crate::credentials::CredentialStore;
+use crate::digest::{Digest, DigestVerifier};
 use crate::error::DResult;
+use crate::platform::Platform;
+use crate::registry::{RegistryConfig, RegistryStore};
 use crate::source::DockerSource;
 
 pub struct DockerDownloader {
     root: Utf8PathBuf,
     client: Client,
+    credentials: CredentialStore,
+    registries: RegistryStore,
 }
 
 impl DockerDownloader {
@@ -27,15 +33,29 @@ impl DockerDownloader {
         fs::create_dir_all(download_dir.join(Self::LAYER_PATH))?;
         fs::create_dir_all(download_dir.join(Self::MANIFEST_PATH))?;
 
-        let builder = ClientBuilder::new();
+        let builder = RegistryConfig::default().client_builder()?;
         let client = builder.build()?;
 
         Ok(Self {
             root: download_dir,
             client,
+            credentials: CredentialStore::new(),
+            registries: RegistryStore::new(),
         })
     }
 
+    #[must_use]
+    pub fn with_registries(mut self, registries: RegistryStore) -> Self {
+        self.registries = registries;
+        self
+    }
+
+    #[must_use]
+    pub fn with_credentials(mut self, credentials: CredentialStore) -> Self {
+        self.credentials = credentials;
+        self
+    }
+
     #[must_use]
     pub fn progress_bar_style() -> ProgressStyle {
         ProgressStyle::with_template("{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {bytes}/{total_bytes} ({eta})")
@@ -59,23 +79,61 @@ impl DockerDownloader {
             .with_extension("json")
     }
 
-    fn download_single_layer(&self, dc: &mut DockerClient, layer: &Digest) -> DResult<()> {
-        let dst_file = self.layer_file_name(layer);
+    /// Cache file for the manifest of `source`. Sources pinned to a digest
+    /// share the cache of manifests referenced by digest.
+    fn source_manifest_file_name(&self, source: &DockerSource) -> Utf8PathBuf {
+        source.digest.as_ref().map_or_else(
+            || self.manifest_file_name(source),
+            |digest| self.manifest_digest_file_name(digest),
+        )
+    }
+
+    /// Cache file for manifests referenced by digest (e.g. the per-platform
+    /// manifests of an image index). These are immutable, so they can be
+    /// shared between all sources.
+    fn manifest_digest_file_name(&self, digest: &Digest) -> Utf8PathBuf {
+        self.root
+            .join(Self::MANIFEST_PATH)
+            .join(digest.to_string())
+            .with_extension("json")
+    }
+
+    pub fn download_blob(&self, dc: &mut DockerClient, digest: &Digest) -> DResult<()> {
+        let dst_file = self.layer_file_name(digest);
         let tmp_file = dst_file.with_extension("tmp");
 
         if fs::exists(&dst_file)? {
-            /* eprintln!("Already downloaded!"); */
-            return Ok(());
+            /* cached blobs are unpacked as-is, so never trust a corrupt one */
+            match Self::verify_blob(&dst_file, digest) {
+                Ok(()) => return Ok(()),
+                Err(err) => {
+                    warn!("Downloading again, invalid cache file {dst_file:?}: {err}");
+                    fs::remove_file(&dst_file)?;
+                }
+            }
         }
 
-        let mut fd = File::create(&tmp_file)?;
+        if let Err(err) = Self::fetch_blob(dc, digest, &tmp_file) {
+            /* never leave partial or corrupt downloads behind */
+            let _ = fs::remove_file(&tmp_file);
+            return Err(err);
+        }
 
-        let mut res = dc.blob(layer)?;
+        fs::rename(&tmp_file, &dst_file)?;
+
+        Ok(())
+    }
+
+    fn fetch_blob(dc: &mut DockerClient, digest: &Digest, path: &Utf8Path) -> DResult<()> {
+        let mut fd = File::create(path)?;
+
+        let mut res = dc.blob(digest)?;
 
         let total_size = res.content_length().unwrap_or_default();
         let pb = ProgressBar::new(total_size);
         pb.set_style(Self::progress_bar_style());
 
+        let mut verifier = DigestVerifier::new(digest);
         let mut buf = vec![0u8; 1024 * 1024];
 
         loop {
@@ -84,12 +142,59 @@ impl DockerDownloader {
                 break;
             }
             pb.inc(n as u64);
+            verifier.update(&buf[..n]);
             fd.write_all(&buf[..n])?;
         }
 
-        fs::rename(&tmp_file, &dst_file)?;
+        verifier.finish()
+    }
 
-        Ok(())
+    /// Re-verify the digest of every blob in the download cache.
+    ///
+    /// Returns the list of invalid files (corrupt blobs, unparseable names and
+    /// leftover temporary files). If `remove` is set, these are also deleted.
+    pub fn verify_cache(&self, remove: bool) -> DResult<Vec<Utf8PathBuf>> {
+        let mut invalid = vec![];
+
+        for path in self.blobs()? {
+            let res = Digest::parse(path.file_name().unwrap_or_default()).and_then(|digest| {
+                info!("Verifying {digest}");
+                Self::verify_blob(&path, &digest)
+            });
+
+            if let Err(err) = res {
+                warn!("Invalid cache file {path:?}: {err}");
+
+                if remove {
+                    fs::remove_file(&path)?;
+                }
+
+                invalid.push(path);
+            }
+        }
+
+        Ok(invalid)
+    }
+
+    fn verify_blob(path: &Utf8Path, digest: &Digest) -> DResult<()> {
+        let mut verifier = DigestVerifier::new(digest);
+        io::copy(&mut File::open(path)?, &mut verifier)?;
+        verifier.finish()
+    }
+
+    /// List all files in the blob cache (sorted), including any leftover
+    /// temporary files.
+    pub fn blobs(&self) -> DResult<Vec<Utf8PathBuf>> {
+        let mut files = self
+            .root
+            .join(Self::LAYER_PATH)
+            .read_dir_utf8()?
+            .map(|dent| Ok(dent?.into_path()))
+            .collect::<DResult<Vec<_>>>()?;
+
+        files.sort();
+
+        Ok(files)
     }
 
     fn read_json<T: DeserializeOwned>(path: &Utf8Path) -> DResult<T> {
@@ -110,31 +215,317 @@ impl DockerDownloader {
         Ok(())
     }
 
-    pub fn pull(&self, source: &DockerSource, os: &str, arch: &str) -> DResult<Vec<Digest>> {
+    /// Create a registry client for the repository of `source`, using the
+    /// configured registry settings and credentials
+    pub fn client(&self, source: &DockerSource) -> DResult<DockerClient> {
+        let domain = source.domain();
+        let config = self.registries.get(&domain)?;
+
+        let client = if config.is_default() {
+            self.client.clone()
+        } else {
+            config.client()?
+        };
+
+        Ok(DockerClient::new(client, &domain, source.image_ref())?
+            .with_scheme(config.scheme)
+            .with_credentials(self.credentials.get(&domain)?))
+    }
+
+    /// Resolve the tag of `source` to the digest of its manifest, always
+    /// asking the registry (ignoring the manifest cache).
+    pub fn resolve(&self, source: &DockerSource) -> DResult<Digest> {
+        let mut dc = self.client(source)?;
+
+        let (manifest, digest) = dc.manifest_digest(&source.image_tag())?;
+
+        Self::write_json(&self.manifest_digest_file_name(&digest), &manifest)?;
+
+        Ok(digest)
+    }
+
+    pub fn pull(&self, source: &DockerSource, platform: &Platform) -> DResult<Vec<Digest>> {
         info!("Logging in to registry..");
-        let mut dc = DockerClient::new(self.client.clone(), source.domain(), source.image_ref())?;
+        let mut dc = self.client(source)?;
 
         info!("Loading manifests..");
-        let manifest_file = self.manifest_file_name(source);
+        let manifest_file = self.source_manifest_file_name(source);
 
         let manifest = if manifest_file.exists() {
             Self::read_json(&manifest_file)?
         } else {
             fs::create_dir_all(manifest_file.parent().unwrap())?;
 
-            dc.manifest(&source.image_tag())?
+            match &source.digest {
+                Some(digest) => dc.manifest(digest)?,
+                None => dc.manifest(&source.image_tag())?,
+            }
         };
 
-        let layers = dc.digests(&manifest, os, arch)?;
+        let image = self.image_manifest(&mut dc, &manifest, platform)?;
+        let layers = dc.digests(&image, platform)?;
+
+        /* the config is not needed for building, but downloading it verifies
+         * that it matches the digest in the manifest */
+        if let Some(config) = image.config() {
+            info!("Downloading image config {config}");
+            self.download_blob(&mut dc, config)?;
+        }
 
         info!("Downloading layers..");
         for layer in &layers {
             info!("Downloading layer {layer}");
-            self.download_single_layer(&mut dc, layer)?;
+            self.download_blob(&mut dc, layer)?;
         }
 
         Self::write_json(&manifest_file, &manifest)?;
 
         Ok(layers)
     }
+
+    /// Resolve an image index to the image manifest for the given platform,
+    /// caching the selected manifest.
+    fn image_manifest(
+        &self,
+        dc: &mut DockerClient,
+        manifest: &Manifest,
+        platform: &Platform,
+    ) -> DResult<Manifest> {
+        let Manifest::V2(index @ V2Manifest::Index { .. }) = manifest else {
+            return Ok(manifest.clone());
+        };
+
+        let digest = index.select(platform)?;
+        let manifest_file = self.manifest_digest_file_name(&digest);
+
+        let manifest = if manifest_file.exists() {
+            Self::read_json(&manifest_file)?
+        } else {
+            let manifest = dc.manifest(&digest)?;
+            Self::write_json(&manifest_file, &manifest)?;
+            manifest
+        };
+
+        self.image_manifest(dc, &manifest, platform)
+    }
+
+    /// Resolve the blob digests (layers and image config) of a previously
+    /// pulled source, using only the manifest cache.
+    ///
+    /// Returns `None` if the needed manifests are not cached.
+    pub fn cached_blobs(
+        &self,
+        source: &DockerSource,
+        platform: &Platform,
+    ) -> DResult<Option<Vec<Digest>>> {
+        let mut manifest_file = self.source_manifest_file_name(source);
+
+        loop {
+            if !manifest_file.exists() {
+                return Ok(None);
+            }
+
+            let manifest: Manifest = Self::read_json(&manifest_file)?;
+
+            let res = match manifest {
+                Manifest::V1(manifest) => manifest
+                    .fs_layers
+                    .into_iter()
+                    .map(|layer| layer.blob_sum)
+                    .collect(),
+
+                Manifest::V2(index @ V2Manifest::Index { .. }) => {
+                    manifest_file = self.manifest_digest_file_name(&index.select(platform)?);
+                    continue;
+                }
+
+                Manifest::V2(V2Manifest::Manifest(docker_layers)) => docker_layers
+                    .layers
+                    .into_iter()
+                    .map(|layer| layer.digest)
+                    .chain([docker_layers.config.digest])
+                    .collect(),
+            };
+
+            return Ok(Some(res));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use camino_tempfile::Utf8TempDir;
+
+    use crate::credentials::{CredentialStore, Credentials};
+    use crate::digest::Digest;
+    use crate::downloader::DockerDownloader;
+    use crate::error::{DResult, DockerError};
+    use crate::platform::Platform;
+    use crate::registry::RegistryStore;
+    use crate::source::DockerSource;
+    use crate::testutil::{TestRequest, respond, respond_json, respond_unauthorized, serve};
+
+    const LAYER: &[u8] = b"layer data";
+
+    /// Stand-in registry with a single image (library/test:latest), requiring
+    /// basic authentication. The blob served is `blob`.
+    fn registry(blob: &'static [u8]) -> DockerSource {
+        let digest = Digest::sha256(LAYER);
+        let config = Digest::sha256(b"{}");
+        let manifest = format!(
+            r#"{{
+                "schemaVersion": 2,
+                "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                "config": {{
+                    "mediaType": "application/vnd.oci.image.config.v1+json",
+                    "digest": "{config}",
+                    "size": 2
+                }},
+                "layers": [{{
+                    "mediaType": "application/vnd.oci.image.layer.v1.tar+gzip",
+                    "digest": "{digest}",
+                    "size": {size}
+                }}]
+            }}"#,
+            size = LAYER.len(),
+        );
+
+        let addr = serve(move |req: &TestRequest| {
+            /* base64("user:secret") */
+            if req.header("Authorization").as_deref() != Some("Basic dXNlcjpzZWNyZXQ=") {
+                return respond_unauthorized(r#"Basic realm="test""#);
+            }
+
+            match req.url() {
+                "/v2/library/test/manifests/latest" => respond_json(&manifest),
+                url if url == format!("/v2/library/test/blobs/{digest}") => respond(200, blob),
+                url if url == format!("/v2/library/test/blobs/{config}") => respond(200, "{}"),
+                _ => respond(404, ""),
+            }
+        });
+
+        let (host, port) = addr.split_once(':').unwrap();
+
+        DockerSource {
+            host: Some(host.into()),
+            port: Some(port.parse().unwrap()),
+            namespace: Some("library".into()),
+            repository: "test".into(),
+            tag: None,
+            digest: None,
+        }
+    }
+
+    fn downloader(
+        tmp: &Utf8TempDir,
+        source: &DockerSource,
+        auth: bool,
+    ) -> DResult<DockerDownloader> {
+        let mut registries = RegistryStore::new();
+        registries.insert(&source.domain(), "http".parse()?);
+
+        let mut credentials = CredentialStore::new();
+        if auth {
+            credentials.insert(&source.domain(), Credentials::basic("user", "secret"));
+        }
+
+        Ok(DockerDownloader::new(tmp.path().to_path_buf())?
+            .with_registries(registries)
+            .with_credentials(credentials))
+    }
+
+    #[test]
+    fn pull_http() -> DResult<()> {
+        let tmp = Utf8TempDir::new()?;
+        let source = registry(LAYER);
+        let dc = downloader(&tmp, &source, true)?;
+
+        let layers = dc.pull(&source, &Platform::default())?;
+
+        assert_eq!(layers, [Digest::sha256(LAYER)]);
+        assert_eq!(std::fs::read(dc.layer_file_name(&layers[0]))?, LAYER);
+        assert_eq!(
+            dc.cached_blobs(&source, &Platform::default())?,
+            Some(vec![Digest::sha256(LAYER), Digest::sha256(b"{}")])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn pull_missing_credentials() -> DResult<()> {
+        let tmp = Utf8TempDir::new()?;
+        let source = registry(LAYER);
+        let dc = downloader(&tmp, &source, false)?;
+
+        assert!(matches!(
+            dc.pull(&source, &Platform::default()),
+            Err(DockerError::MissingCredentials(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn pull_corrupt_blob() -> DResult<()> {
+        let tmp = Utf8TempDir::new()?;
+        let source = registry(b"corrupt data");
+        let dc = downloader(&tmp, &source, true)?;
+
+        assert!(matches!(
+            dc.pull(&source, &Platform::default()),
+            Err(DockerError::DigestMismatch { .. })
+        ));
+
+        let layer = dc.layer_file_name(&Digest::sha256(LAYER));
+        assert!(!layer.exists());
+        assert!(!layer.with_extension("tmp").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn pull_replaces_corrupt_cache() -> DResult<()> {
+        let tmp = Utf8TempDir::new()?;
+        let source = registry(LAYER);
+        let dc = downloader(&tmp, &source, true)?;
+
+        let layer = dc.layer_file_name(&Digest::sha256(LAYER));
+        std::fs::write(&layer, b"corrupt data")?;
+
+        dc.pull(&source, &Platform::default())?;
+
+        assert_eq!(std::fs::read(&layer)?, LAYER);
+
+        Ok(())
+    }
+
+    #[test]
+    fn verify_cache() -> DResult<()> {
+        let tmp = Utf8TempDir::new()?;
+        let dc = DockerDownloader::new(tmp.path().to_path_buf())?;
+
+        let good = dc.layer_file_name(&Digest::sha256(b"good"));
+        let bad = dc.layer_file_name(&Digest::sha256(b"bad"));
+        let partial = good.with_extension("tmp");
+
+        std::fs::write(&good, b"good")?;
+        std::fs::write(&bad, b"corrupt")?;
+        std::fs::write(&partial, b"go")?;
+
+        let mut expected = vec![bad.clone(), partial.clone()];
+        expected.sort();
+
+        assert_eq!(dc.verify_cache(false)?, expected);
+        assert!(bad.exists());
+
+        assert_eq!(dc.verify_cache(true)?, expected);
+        assert!(good.exists());
+        assert!(!bad.exists());
+        assert!(!partial.exists());
+
+        assert!(dc.verify_cache(true)?.is_empty());
+
+        Ok(())
+    }
 }
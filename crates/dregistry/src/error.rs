@@ -1,3 +1,4 @@
+use crate::digest::Digest;
 use crate::reference::Rule;
 
 #[derive(thiserror::Error, Debug)]
@@ -26,14 +27,48 @@ pub enum DockerError {
     #[error(transparent)]
     ParseError(#[from] crate::authparse::ParseError),
 
-    #[error("Registry uses unsupported authentication method (only \"Bearer\" is supported)")]
+    #[error(transparent)]
+    Base64Error(#[from] base64::DecodeError),
+
+    #[error(transparent)]
+    Errno(#[from] nix::Error),
+
+    #[error(
+        "Registry uses unsupported authentication method (only \"Bearer\" and \"Basic\" are supported)"
+    )]
     UnsupportedAuthMethod,
 
+    #[error("Registry {0} requires authentication, but no credentials are configured")]
+    MissingCredentials(String),
+
+    #[error("Registry {0} uses \"Basic\" authentication, which does not support identity tokens")]
+    IdentityTokenWithBasicAuth(String),
+
+    #[error("Invalid credentials (expected \"username:password\")")]
+    InvalidCredentials,
+
+    #[error(
+        "Invalid registry configuration {0:?} (expected https, http, https+insecure or https+ca=<path>)"
+    )]
+    InvalidRegistryConfig(String),
+
+    #[error("Invalid platform {0:?} (expected os/architecture[/variant])")]
+    InvalidPlatform(String),
+
     #[error("Could not parse digest")]
     DigestError,
 
-    #[error("Manifest not found for selected os/architecture")]
+    #[error("Digest mismatch: expected {expected}, but got {actual}")]
+    DigestMismatch { expected: Digest, actual: Digest },
+
+    #[error("Manifest not found for selected platform")]
     ManifestNotFound,
+
+    #[error("Registry did not return a location for the blob upload")]
+    MissingUploadLocation,
+
+    #[error("Invalid path in layer archive: {0:?}")]
+    InvalidLayerPath(String),
 }
 
 pub type DResult<T> = Result<T, DockerError>;
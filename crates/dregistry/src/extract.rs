@@ -0,0 +1,427 @@
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, ErrorKind, Read};
+use std::path::Path;
+
+use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
+use flate2::read::GzDecoder;
+use log::{debug, trace};
+use tar::{Archive, EntryType};
+
+use crate::error::{DResult, DockerError};
+
+pub(crate) const WHITEOUT_PREFIX: &str = ".wh.";
+pub(crate) const WHITEOUT_OPAQUE: &str = ".wh..wh..opq";
+
+const GZIP_MAGIC: &[u8] = &[0x1F, 0x8B];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xB5, 0x2F, 0xFD];
+
+/// Wrap a layer stream in the matching decompressor (gzip or zstd), based on
+/// its magic bytes. Uncompressed streams are passed through.
+pub(crate) fn decompress<'a>(mut reader: impl BufRead + 'a) -> DResult<Box<dyn Read + 'a>> {
+    let magic = reader.fill_buf()?;
+
+    let stream: Box<dyn Read> = if magic.starts_with(GZIP_MAGIC) {
+        Box::new(GzDecoder::new(reader))
+    } else if magic.starts_with(ZSTD_MAGIC) {
+        Box::new(zstd::Decoder::with_buffer(reader)?)
+    } else {
+        Box::new(reader)
+    };
+
+    Ok(stream)
+}
+
+/// Applies OCI image layers (tar, tar+gzip or tar+zstd) on top of a root
+/// directory, honoring whiteout files and opaque directories.
+///
+/// See <https://github.com/opencontainers/image-spec/blob/main/layer.md>
+pub struct LayerExtractor<'a> {
+    root: &'a Utf8Path,
+    written: HashSet<Utf8PathBuf>,
+}
+
+impl<'a> LayerExtractor<'a> {
+    #[must_use]
+    pub fn new(root: &'a Utf8Path) -> Self {
+        Self {
+            root,
+            written: HashSet::new(),
+        }
+    }
+
+    pub fn apply_file(self, path: &Utf8Path) -> DResult<()> {
+        self.apply(BufReader::new(File::open(path)?))
+    }
+
+    pub fn apply(mut self, reader: impl BufRead) -> DResult<()> {
+        let mut archive = Archive::new(decompress(reader)?);
+        archive.set_preserve_permissions(true);
+        archive.set_preserve_ownerships(true);
+        archive.set_unpack_xattrs(true);
+        archive.set_overwrite(true);
+
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let path = Self::normalize(&entry.path()?)?;
+
+            let Some(name) = path.file_name() else {
+                continue;
+            };
+
+            let parent = path.parent().unwrap_or_else(|| Utf8Path::new(""));
+
+            if name == WHITEOUT_OPAQUE {
+                self.opaque(parent)?;
+            } else if let Some(target) = name.strip_prefix(WHITEOUT_PREFIX) {
+                self.whiteout(&parent.join(target))?;
+            } else {
+                trace!("Extracting {path}");
+                self.prepare(&path, entry.header().entry_type())?;
+                entry.unpack_in(self.root)?;
+                self.written.insert(path);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Convert an archive path to a relative path, rejecting any path that
+    /// could point outside the root directory.
+    pub(crate) fn normalize(path: &Path) -> DResult<Utf8PathBuf> {
+        let lossy = || DockerError::InvalidLayerPath(path.to_string_lossy().to_string());
+
+        let path = Utf8Path::from_path(path).ok_or_else(lossy)?;
+
+        let mut res = Utf8PathBuf::new();
+        for comp in path.components() {
+            match comp {
+                Utf8Component::Normal(name) => res.push(name),
+                Utf8Component::CurDir | Utf8Component::RootDir => {}
+                Utf8Component::ParentDir | Utf8Component::Prefix(_) => return Err(lossy()),
+            }
+        }
+
+        Ok(res)
+    }
+
+    /// Resolve a relative path inside the root directory, making sure that
+    /// symlinks in the (already extracted) parent directories cannot redirect
+    /// us outside of it. Returns `None` if the parent does not exist.
+    fn resolve(&self, path: &Utf8Path) -> DResult<Option<Utf8PathBuf>> {
+        let full = self.root.join(path);
+
+        let Some(parent) = full.parent() else {
+            return Ok(None);
+        };
+
+        let parent = match parent.canonicalize_utf8() {
+            Ok(parent) => parent,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        if !parent.starts_with(self.root.canonicalize_utf8()?) {
+            return Err(DockerError::InvalidLayerPath(path.to_string()));
+        }
+
+        Ok(Some(parent.join(full.file_name().unwrap_or_default())))
+    }
+
+    fn remove(path: &Utf8Path) -> DResult<()> {
+        let res = match path.symlink_metadata() {
+            Ok(md) if md.is_dir() => fs::remove_dir_all(path),
+            Ok(_) => fs::remove_file(path),
+            Err(err) => Err(err),
+        };
+
+        match res {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Remove whatever is in the way of a new entry, unless both are
+    /// directories (in which case the contents are merged).
+    fn prepare(&self, path: &Utf8Path, kind: EntryType) -> DResult<()> {
+        let Some(full) = self.resolve(path)? else {
+            return Ok(());
+        };
+
+        match full.symlink_metadata() {
+            Ok(md) if md.is_dir() && kind == EntryType::Directory => Ok(()),
+            Ok(_) => Self::remove(&full),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Handle `.wh.<name>`: delete `<name>` from the lower layers
+    fn whiteout(&self, path: &Utf8Path) -> DResult<()> {
+        if self.written.contains(path) {
+            return Ok(());
+        }
+
+        debug!("Whiteout {path}");
+
+        if let Some(full) = self.resolve(path)? {
+            Self::remove(&full)?;
+        }
+
+        Ok(())
+    }
+
+    /// Handle `.wh..wh..opq`: delete all lower-layer contents of the directory
+    fn opaque(&self, dir: &Utf8Path) -> DResult<()> {
+        debug!("Opaque directory {dir}");
+
+        let full = if dir.as_str().is_empty() {
+            self.root.canonicalize_utf8()?
+        } else if let Some(full) = self.resolve(dir)? {
+            full
+        } else {
+            return Ok(());
+        };
+
+        if !full.symlink_metadata().is_ok_and(|md| md.is_dir()) {
+            return Ok(());
+        }
+
+        for dent in full.read_dir_utf8()? {
+            let dent = dent?;
+            if !self.written.contains(&dir.join(dent.file_name())) {
+                Self::remove(dent.path())?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    use camino::Utf8Path;
+    use camino_tempfile::Utf8TempDir;
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use tar::{EntryType, Header};
+
+    use crate::error::{DResult, DockerError};
+    use crate::extract::LayerExtractor;
+
+    struct Layer(tar::Builder<Vec<u8>>, u64, u64);
+
+    impl Layer {
+        fn new(root: &Utf8Path) -> Self {
+            let md = root.metadata().unwrap();
+            Self(tar::Builder::new(vec![]), md.uid().into(), md.gid().into())
+        }
+
+        fn header(&self, kind: EntryType, mode: u32) -> Header {
+            let mut header = Header::new_gnu();
+            header.set_entry_type(kind);
+            header.set_mode(mode);
+            header.set_uid(self.1);
+            header.set_gid(self.2);
+            header.set_size(0);
+            header
+        }
+
+        fn file(mut self, path: &str, data: &str) -> Self {
+            let mut header = self.header(EntryType::Regular, 0o640);
+            header.set_size(data.len() as u64);
+            self.0
+                .append_data(&mut header, path, data.as_bytes())
+                .unwrap();
+            self
+        }
+
+        fn dir(mut self, path: &str) -> Self {
+            let mut header = self.header(EntryType::Directory, 0o755);
+            self.0.append_data(&mut header, path, &[][..]).unwrap();
+            self
+        }
+
+        fn symlink(mut self, path: &str, target: &str) -> Self {
+            let mut header = self.header(EntryType::Symlink, 0o777);
+            self.0.append_link(&mut header, path, target).unwrap();
+            self
+        }
+
+        fn raw(mut self, path: &str) -> Self {
+            let mut header = self.header(EntryType::Regular, 0o644);
+            header.as_old_mut().name[..path.len()].copy_from_slice(path.as_bytes());
+            header.set_cksum();
+            self.0.append(&header, &[][..]).unwrap();
+            self
+        }
+
+        fn tar(self) -> Vec<u8> {
+            self.0.into_inner().unwrap()
+        }
+
+        fn gzip(self) -> Vec<u8> {
+            let mut enc = GzEncoder::new(vec![], Compression::fast());
+            enc.write_all(&self.tar()).unwrap();
+            enc.finish().unwrap()
+        }
+
+        fn zstd(self) -> Vec<u8> {
+            zstd::encode_all(&self.tar()[..], 1).unwrap()
+        }
+    }
+
+    fn apply(root: &Utf8Path, data: &[u8]) -> DResult<()> {
+        LayerExtractor::new(root).apply(data)
+    }
+
+    fn read(root: &Utf8Path, path: &str) -> String {
+        std::fs::read_to_string(root.join(path)).unwrap()
+    }
+
+    #[test]
+    fn extract_formats() -> DResult<()> {
+        let tmp = Utf8TempDir::new()?;
+        let root = tmp.path();
+
+        apply(root, &Layer::new(root).file("plain", "1").tar())?;
+        apply(root, &Layer::new(root).file("gzip", "2").gzip())?;
+        apply(root, &Layer::new(root).file("zstd", "3").zstd())?;
+
+        assert_eq!(read(root, "plain"), "1");
+        assert_eq!(read(root, "gzip"), "2");
+        assert_eq!(read(root, "zstd"), "3");
+
+        Ok(())
+    }
+
+    #[test]
+    fn extract_metadata() -> DResult<()> {
+        let tmp = Utf8TempDir::new()?;
+        let root = tmp.path();
+
+        let layer = Layer::new(root)
+            .dir("etc")
+            .file("etc/secret", "data")
+            .symlink("etc/link", "secret");
+        apply(root, &layer.gzip())?;
+
+        let md = root.join("etc/secret").metadata()?;
+        assert_eq!(md.permissions().mode() & 0o7777, 0o640);
+        assert_eq!(md.uid(), root.metadata()?.uid());
+
+        let link = root.join("etc/link").read_link_utf8()?;
+        assert_eq!(link, "secret");
+
+        Ok(())
+    }
+
+    #[test]
+    fn whiteout_file() -> DResult<()> {
+        let tmp = Utf8TempDir::new()?;
+        let root = tmp.path();
+
+        apply(
+            root,
+            &Layer::new(root)
+                .dir("a")
+                .file("a/x", "x")
+                .file("a/y", "y")
+                .gzip(),
+        )?;
+        apply(root, &Layer::new(root).dir("a").file("a/.wh.x", "").gzip())?;
+
+        assert!(!root.join("a/x").exists());
+        assert!(!root.join("a/.wh.x").exists());
+        assert_eq!(read(root, "a/y"), "y");
+
+        Ok(())
+    }
+
+    #[test]
+    fn whiteout_dir() -> DResult<()> {
+        let tmp = Utf8TempDir::new()?;
+        let root = tmp.path();
+
+        apply(
+            root,
+            &Layer::new(root)
+                .dir("a")
+                .dir("a/b")
+                .file("a/b/c", "c")
+                .gzip(),
+        )?;
+        apply(root, &Layer::new(root).dir("a").file("a/.wh.b", "").gzip())?;
+
+        assert!(!root.join("a/b").exists());
+        assert!(root.join("a").is_dir());
+
+        Ok(())
+    }
+
+    #[test]
+    fn whiteout_opaque() -> DResult<()> {
+        let tmp = Utf8TempDir::new()?;
+        let root = tmp.path();
+
+        let lower = Layer::new(root)
+            .dir("a")
+            .file("a/old1", "")
+            .dir("a/old2")
+            .file("keep", "");
+        apply(root, &lower.gzip())?;
+
+        // entries from the same layer must survive, regardless of ordering
+        let upper = Layer::new(root)
+            .dir("a")
+            .file("a/new", "new")
+            .file("a/.wh..wh..opq", "");
+        apply(root, &upper.gzip())?;
+
+        assert!(!root.join("a/old1").exists());
+        assert!(!root.join("a/old2").exists());
+        assert!(!root.join("a/.wh..wh..opq").exists());
+        assert_eq!(read(root, "a/new"), "new");
+        assert!(root.join("keep").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn replace_kind() -> DResult<()> {
+        let tmp = Utf8TempDir::new()?;
+        let root = tmp.path();
+
+        apply(
+            root,
+            &Layer::new(root)
+                .dir("a")
+                .file("a/x", "")
+                .file("b", "")
+                .gzip(),
+        )?;
+        apply(root, &Layer::new(root).file("a", "file").dir("b").gzip())?;
+
+        assert_eq!(read(root, "a"), "file");
+        assert!(root.join("b").is_dir());
+
+        Ok(())
+    }
+
+    #[test]
+    fn reject_parent_path() -> DResult<()> {
+        let tmp = Utf8TempDir::new()?;
+        let root = tmp.path().join("root");
+        std::fs::create_dir(&root)?;
+
+        let res = apply(&root, &Layer::new(&root).raw("../escape").tar());
+
+        assert!(matches!(res, Err(DockerError::InvalidLayerPath(_))));
+        assert!(!tmp.path().join("escape").exists());
+
+        Ok(())
+    }
+}
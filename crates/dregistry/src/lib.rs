@@ -1,8 +1,18 @@
 pub mod api;
+pub mod archive;
 pub mod authparse;
 pub mod client;
+pub mod credentials;
 pub mod digest;
 pub mod downloader;
 pub mod error;
+pub mod extract;
+pub mod oci;
+pub mod platform;
+pub mod push;
 pub mod reference;
+pub mod registry;
 pub mod source;
+
+#[cfg(any(test, feature = "testutil"))]
+pub mod testutil;
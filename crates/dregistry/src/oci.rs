@@ -0,0 +1,290 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, IntoInnerError, Write};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use log::debug;
+use serde_json::json;
+use tar::{Builder, HeaderMode};
+
+use crate::api::{
+    DockerLayer, DockerLayers, ImageConfiguration, ImageRootFs, ImageRuntimeConfig, MediaType,
+    V2Manifest,
+};
+use crate::archive::LayerArchiver;
+use crate::digest::{Digest, DigestWriter};
+use crate::error::DResult;
+use crate::platform::Platform;
+
+const OCI_INDEX_MEDIA_TYPE: &str = "application/vnd.oci.image.index.v1+json";
+const OCI_REF_NAME: &str = "org.opencontainers.image.ref.name";
+
+struct OciImage {
+    manifest: DockerLayer,
+    config: Digest,
+    layers: Vec<Digest>,
+    name: Option<String>,
+}
+
+/// Writer for OCI image layouts, as described in
+/// <https://github.com/opencontainers/image-spec/blob/main/image-layout.md>
+///
+/// The finished layout can be kept as a directory, or packed as a tarball
+/// that can be loaded with `docker load`.
+pub struct OciLayout {
+    root: Utf8PathBuf,
+    images: Vec<OciImage>,
+}
+
+impl OciLayout {
+    pub fn create(root: impl Into<Utf8PathBuf>) -> DResult<Self> {
+        let root = root.into();
+
+        fs::create_dir_all(root.join("blobs/sha256"))?;
+        fs::write(
+            root.join("oci-layout"),
+            json!({ "imageLayoutVersion": "1.0.0" }).to_string(),
+        )?;
+
+        Ok(Self {
+            root,
+            images: vec![],
+        })
+    }
+
+    #[must_use]
+    pub fn root(&self) -> &Utf8Path {
+        &self.root
+    }
+
+    fn blob_name(digest: &Digest) -> String {
+        match digest {
+            Digest::Sha256(hash) => format!("blobs/sha256/{}", hex::encode(hash)),
+        }
+    }
+
+    /// Path of a blob in the layout
+    #[must_use]
+    pub fn blob_path(&self, digest: &Digest) -> Utf8PathBuf {
+        self.root.join(Self::blob_name(digest))
+    }
+
+    const fn descriptor(media_type: MediaType, digest: Digest, size: u64) -> DockerLayer {
+        DockerLayer {
+            data: None,
+            annotations: BTreeMap::new(),
+            digest,
+            media_type,
+            size,
+        }
+    }
+
+    pub fn add_blob(&self, media_type: MediaType, data: &[u8]) -> DResult<DockerLayer> {
+        let digest = Digest::sha256(data);
+
+        fs::write(self.root.join(Self::blob_name(&digest)), data)?;
+
+        Ok(Self::descriptor(media_type, digest, data.len() as u64))
+    }
+
+    /// Add the contents of a directory (an overlayfs upper directory, or a
+    /// complete root filesystem) as an uncompressed layer
+    pub fn add_layer(&self, dir: &Utf8Path) -> DResult<DockerLayer> {
+        debug!("Archiving layer {dir}");
+
+        let tmp_file = self.root.join("blobs/layer.tmp");
+
+        let writer = DigestWriter::new(BufWriter::new(File::create(&tmp_file)?));
+        let (writer, digest, size) = LayerArchiver::new(dir).write(writer)?.finish();
+        writer.into_inner().map_err(IntoInnerError::into_error)?;
+
+        fs::rename(&tmp_file, self.root.join(Self::blob_name(&digest)))?;
+
+        Ok(Self::descriptor(MediaType::ImageLayerTar, digest, size))
+    }
+
+    /// Add an image, consisting of previously added layers (bottom first)
+    pub fn add_image(
+        &mut self,
+        platform: &Platform,
+        config: ImageRuntimeConfig,
+        layers: Vec<DockerLayer>,
+        name: Option<&str>,
+    ) -> DResult<Digest> {
+        let config = ImageConfiguration {
+            architecture: platform.architecture.clone(),
+            os: platform.os.clone(),
+            variant: platform.variant.clone(),
+            config,
+            rootfs: ImageRootFs {
+                kind: "layers".into(),
+                /* layers are uncompressed, so the diff ids equal the digests */
+                diff_ids: layers.iter().map(|layer| layer.digest.clone()).collect(),
+            },
+        };
+
+        let config = self.add_blob(MediaType::ImageConfig, &serde_json::to_vec(&config)?)?;
+
+        let config_digest = config.digest.clone();
+        let layer_digests = layers.iter().map(|layer| layer.digest.clone()).collect();
+
+        let manifest = V2Manifest::Manifest(DockerLayers {
+            config,
+            layers,
+            schema_version: 2,
+        });
+
+        let mut manifest =
+            self.add_blob(MediaType::ImageManifest, &serde_json::to_vec(&manifest)?)?;
+
+        if let Some(name) = name {
+            manifest
+                .annotations
+                .insert(OCI_REF_NAME.to_string(), name.to_string());
+        }
+
+        let digest = manifest.digest.clone();
+
+        self.images.push(OciImage {
+            manifest,
+            config: config_digest,
+            layers: layer_digests,
+            name: name.map(ToString::to_string),
+        });
+
+        Ok(digest)
+    }
+
+    fn write_index(&self) -> DResult<()> {
+        let manifests: Vec<_> = self.images.iter().map(|image| &image.manifest).collect();
+
+        let index = json!({
+            "schemaVersion": 2,
+            "mediaType": OCI_INDEX_MEDIA_TYPE,
+            "manifests": manifests,
+        });
+
+        fs::write(
+            self.root.join("index.json"),
+            serde_json::to_string_pretty(&index)?,
+        )?;
+
+        Ok(())
+    }
+
+    /// Finish the image layout directory
+    pub fn finish(self) -> DResult<()> {
+        self.write_index()
+    }
+
+    /// Finish the image layout, and pack it as a tarball compatible with
+    /// `docker load` (an OCI layout, with an additional `manifest.json`)
+    pub fn finish_archive<W: Write>(self, writer: W) -> DResult<W> {
+        self.write_index()?;
+
+        let manifest: Vec<_> = self
+            .images
+            .iter()
+            .map(|image| {
+                json!({
+                    "Config": Self::blob_name(&image.config),
+                    "RepoTags": image.name.iter().collect::<Vec<_>>(),
+                    "Layers": image.layers.iter().map(Self::blob_name).collect::<Vec<_>>(),
+                })
+            })
+            .collect();
+
+        fs::write(
+            self.root.join("manifest.json"),
+            serde_json::to_string_pretty(&manifest)?,
+        )?;
+
+        let mut builder = Builder::new(writer);
+        builder.mode(HeaderMode::Deterministic);
+
+        for name in ["oci-layout", "index.json", "manifest.json"] {
+            builder.append_path_with_name(self.root.join(name), name)?;
+        }
+        builder.append_dir_all("blobs", self.root.join("blobs"))?;
+
+        Ok(builder.into_inner()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use camino::Utf8Path;
+    use camino_tempfile::Utf8TempDir;
+    use serde_json::Value;
+    use tar::Archive;
+
+    use crate::api::{ImageConfiguration, ImageRuntimeConfig, Manifest, V2Manifest};
+    use crate::digest::Digest;
+    use crate::error::DResult;
+    use crate::oci::OciLayout;
+    use crate::platform::Platform;
+
+    fn read_json<T: serde::de::DeserializeOwned>(root: &Utf8Path, name: &str) -> T {
+        serde_json::from_str(&fs::read_to_string(root.join(name)).unwrap()).unwrap()
+    }
+
+    fn blob_name(digest: &Digest) -> String {
+        OciLayout::blob_name(digest)
+    }
+
+    #[test]
+    fn write_layout() -> DResult<()> {
+        let src = Utf8TempDir::new()?;
+        fs::write(src.path().join("hello"), "world")?;
+
+        let out = Utf8TempDir::new()?;
+        let mut layout = OciLayout::create(out.path())?;
+
+        let layer = layout.add_layer(src.path())?;
+        let config = ImageRuntimeConfig {
+            env: vec!["A=1".into()],
+            cmd: Some(vec!["/bin/sh".into()]),
+            ..ImageRuntimeConfig::default()
+        };
+
+        let platform = "linux/arm64".parse::<Platform>()?;
+        let digest = layout.add_image(&platform, config, vec![layer.clone()], Some("app"))?;
+
+        let data = layout.finish_archive(vec![])?;
+        let root = out.path();
+
+        let index: Value = read_json(root, "index.json");
+        assert_eq!(index["manifests"][0]["digest"], digest.to_string());
+        assert_eq!(
+            index["manifests"][0]["annotations"]["org.opencontainers.image.ref.name"],
+            "app"
+        );
+
+        let Manifest::V2(V2Manifest::Manifest(manifest)) = read_json(root, &blob_name(&digest))
+        else {
+            panic!("Expected image manifest");
+        };
+        assert_eq!(manifest.layers[0].digest, layer.digest);
+
+        let config: ImageConfiguration = read_json(root, &blob_name(&manifest.config.digest));
+        assert_eq!(config.architecture, "arm64");
+        assert_eq!(config.config.env, ["A=1"]);
+        assert_eq!(config.rootfs.diff_ids, std::slice::from_ref(&layer.digest));
+
+        layer
+            .digest
+            .verify(&fs::read(root.join(blob_name(&layer.digest)))?)?;
+
+        let names = Archive::new(&data[..])
+            .entries()?
+            .map(|entry| Ok(entry?.path()?.to_string_lossy().to_string()))
+            .collect::<DResult<Vec<_>>>()?;
+
+        assert!(names.contains(&"manifest.json".to_string()));
+        assert!(names.contains(&blob_name(&layer.digest)));
+
+        Ok(())
+    }
+}
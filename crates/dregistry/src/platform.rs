@@ -0,0 +1,142 @@
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use crate::error::DockerError;
+
+/// Image platform, in `os/architecture[/variant]` form (e.g. `linux/arm64` or
+/// `linux/arm/v7`)
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize)]
+pub struct Platform {
+    pub os: String,
+    pub architecture: String,
+    pub variant: Option<String>,
+}
+
+impl Platform {
+    #[must_use]
+    pub fn new(os: &str, architecture: &str, variant: Option<&str>) -> Self {
+        Self {
+            os: os.to_string(),
+            architecture: architecture.to_string(),
+            variant: variant.map(ToString::to_string),
+        }
+    }
+
+    /// Check if a platform from an image index satisfies this (requested)
+    /// platform. If no variant is requested, any variant is accepted.
+    #[must_use]
+    pub fn matches(&self, os: &str, architecture: &str, variant: Option<&str>) -> bool {
+        if self.os != os || self.architecture != architecture {
+            return false;
+        }
+
+        let Some(wanted) = self.variant.as_deref() else {
+            return true;
+        };
+
+        /* arm64 images are commonly published without the (implied) v8 variant */
+        let variant = match (architecture, variant) {
+            ("arm64", None) => Some("v8"),
+            (_, variant) => variant,
+        };
+
+        variant == Some(wanted)
+    }
+}
+
+impl Default for Platform {
+    fn default() -> Self {
+        Self::new("linux", "amd64", None)
+    }
+}
+
+impl Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.os, self.architecture)?;
+
+        if let Some(variant) = &self.variant {
+            write!(f, "/{variant}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for Platform {
+    type Err = DockerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || DockerError::InvalidPlatform(s.to_string());
+
+        let mut parts = s.split('/');
+
+        let os = parts
+            .next()
+            .filter(|os| !os.is_empty())
+            .ok_or_else(invalid)?;
+        let arch = parts.next().filter(|a| !a.is_empty()).ok_or_else(invalid)?;
+        let variant = parts.next();
+
+        if parts.next().is_some() || variant.is_some_and(str::is_empty) {
+            return Err(invalid());
+        }
+
+        Ok(Self::new(os, arch, variant))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::{DResult, DockerError};
+    use crate::platform::Platform;
+
+    #[test]
+    fn parse_platform() -> DResult<()> {
+        assert_eq!("linux/amd64".parse::<Platform>()?, Platform::default());
+        assert_eq!(
+            "linux/arm/v7".parse::<Platform>()?,
+            Platform::new("linux", "arm", Some("v7"))
+        );
+        assert_eq!(
+            Platform::new("linux", "arm", Some("v7")).to_string(),
+            "linux/arm/v7"
+        );
+
+        for invalid in [
+            "",
+            "linux",
+            "linux/",
+            "/amd64",
+            "linux/arm/",
+            "linux/arm/v7/x",
+        ] {
+            assert!(matches!(
+                invalid.parse::<Platform>(),
+                Err(DockerError::InvalidPlatform(_))
+            ));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn match_platform() -> DResult<()> {
+        let arm: Platform = "linux/arm".parse()?;
+        assert!(arm.matches("linux", "arm", Some("v6")));
+        assert!(arm.matches("linux", "arm", None));
+        assert!(!arm.matches("linux", "arm64", None));
+
+        let armv7: Platform = "linux/arm/v7".parse()?;
+        assert!(armv7.matches("linux", "arm", Some("v7")));
+        assert!(!armv7.matches("linux", "arm", Some("v6")));
+        assert!(!armv7.matches("linux", "arm", None));
+
+        let arm64v8: Platform = "linux/arm64/v8".parse()?;
+        assert!(arm64v8.matches("linux", "arm64", None));
+        assert!(arm64v8.matches("linux", "arm64", Some("v8")));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,195 @@
+use std::fs::{self, File};
+
+use camino::Utf8Path;
+use log::{debug, info};
+use serde::Deserialize;
+
+use crate::api::{DockerLayer, V2Manifest};
+use crate::client::{DockerClient, Reference};
+use crate::digest::Digest;
+use crate::error::{DResult, DockerError};
+use crate::oci::OciLayout;
+
+/// The media type of a manifest, as stated in the manifest itself
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ManifestHeader {
+    media_type: String,
+}
+
+/// Uploads images from an [`OciLayout`] to a registry.
+///
+/// Blobs already present in the target repository are skipped. Missing blobs
+/// are mounted from other repositories on the same registry when possible,
+/// and uploaded otherwise (in chunks, for large blobs).
+pub struct ImagePusher<'a> {
+    client: &'a mut DockerClient,
+    mount_from: Vec<String>,
+    chunk_size: u64,
+}
+
+impl<'a> ImagePusher<'a> {
+    /// Blobs larger than this are uploaded in chunks of this size
+    pub const DEFAULT_CHUNK_SIZE: u64 = 64 * 1024 * 1024;
+
+    /// Create a pusher, using `client` (which should have been created
+    /// [with push access](DockerClient::with_push))
+    pub const fn new(client: &'a mut DockerClient) -> Self {
+        Self {
+            client,
+            mount_from: vec![],
+            chunk_size: Self::DEFAULT_CHUNK_SIZE,
+        }
+    }
+
+    /// Try to mount missing blobs from these repositories, before uploading
+    #[must_use]
+    pub fn with_mount_from(mut self, repos: impl IntoIterator<Item = String>) -> Self {
+        self.mount_from.extend(repos);
+        self
+    }
+
+    #[must_use]
+    pub const fn with_chunk_size(mut self, chunk_size: u64) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    fn push_blob(&mut self, path: &Utf8Path, blob: &DockerLayer) -> DResult<()> {
+        let digest = &blob.digest;
+
+        if self.client.blob_exists(digest)? {
+            debug!("Blob {digest} already exists");
+            return Ok(());
+        }
+
+        for from in &self.mount_from {
+            if self.client.mount_blob(digest, from)? {
+                info!("Mounted blob {digest} from {from}");
+                return Ok(());
+            }
+        }
+
+        info!("Uploading blob {digest}");
+
+        if blob.size > self.chunk_size {
+            let file = File::open(path)?;
+            self.client.push_blob_chunked(digest, file, self.chunk_size)
+        } else {
+            self.client.push_blob(digest, &fs::read(path)?)
+        }
+    }
+
+    /// Push the image with manifest `digest` from `layout`, and tag it as
+    /// `reference`. Returns the digest of the uploaded manifest.
+    ///
+    /// The manifest is uploaded byte-for-byte, so the pushed image has the
+    /// same digest as the one in the layout.
+    pub fn push(
+        &mut self,
+        layout: &OciLayout,
+        digest: &Digest,
+        reference: &impl Reference,
+    ) -> DResult<Digest> {
+        let data = fs::read(layout.blob_path(digest))?;
+        digest.verify(&data)?;
+
+        let header: ManifestHeader = serde_json::from_slice(&data)?;
+        let manifest: V2Manifest = serde_json::from_slice(&data)?;
+        let V2Manifest::Manifest(image) = &manifest else {
+            return Err(DockerError::ManifestNotFound);
+        };
+
+        for blob in std::iter::once(&image.config).chain(&image.layers) {
+            self.push_blob(&layout.blob_path(&blob.digest), blob)?;
+        }
+
+        info!("Uploading manifest {}", reference.reference());
+
+        let pushed = self
+            .client
+            .put_manifest(reference, &header.media_type, &data)?;
+
+        if pushed != *digest {
+            return Err(DockerError::DigestMismatch {
+                expected: digest.clone(),
+                actual: pushed,
+            });
+        }
+
+        Ok(pushed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use camino_tempfile::Utf8TempDir;
+    use reqwest::blocking::Client;
+
+    use crate::api::{ImageRuntimeConfig, Manifest, V2Manifest};
+    use crate::client::DockerClient;
+    use crate::error::DResult;
+    use crate::oci::OciLayout;
+    use crate::platform::Platform;
+    use crate::push::ImagePusher;
+    use crate::registry::Scheme;
+    use crate::testutil::serve_registry;
+
+    #[test]
+    fn push_image() -> DResult<()> {
+        let (addr, store) = serve_registry();
+
+        let src = Utf8TempDir::new()?;
+        fs::write(src.path().join("hello"), "world".repeat(100))?;
+
+        let out = Utf8TempDir::new()?;
+        let mut layout = OciLayout::create(out.path())?;
+        let layer = layout.add_layer(src.path())?;
+        let platform = Platform::default();
+        let digest = layout.add_image(
+            &platform,
+            ImageRuntimeConfig::default(),
+            vec![layer.clone()],
+            None,
+        )?;
+
+        let client = || -> DResult<DockerClient> {
+            Ok(DockerClient::new(Client::new(), &addr, "library/app")?
+                .with_scheme(Scheme::Http)
+                .with_push())
+        };
+
+        /* small chunks, to exercise chunked uploads */
+        let mut dc = client()?;
+        let pushed = ImagePusher::new(&mut dc)
+            .with_chunk_size(1000)
+            .push(&layout, &digest, &"v1")?;
+        assert_eq!(pushed, digest);
+
+        let key = format!("library/app/blobs/{}", layer.digest);
+        assert_eq!(
+            store.lock().unwrap()[&key],
+            fs::read(layout.blob_path(&layer.digest))?
+        );
+
+        /* the manifest is uploaded unchanged */
+        assert_eq!(
+            store.lock().unwrap()["library/app/manifests/v1"],
+            fs::read(layout.blob_path(&digest))?
+        );
+
+        /* pushing again only uploads the manifest */
+        let mut dc = client()?;
+        ImagePusher::new(&mut dc).push(&layout, &digest, &"v2")?;
+
+        let mut dc = client()?;
+        let Some(Manifest::V2(V2Manifest::Manifest(manifest))) = dc.find_manifest(&"v2")? else {
+            panic!("manifest not found");
+        };
+        assert_eq!(manifest.layers[0].digest, layer.digest);
+
+        Ok(())
+    }
+}
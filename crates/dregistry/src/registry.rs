@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use camino::Utf8PathBuf;
+use reqwest::Certificate;
+use reqwest::blocking::{Client, ClientBuilder};
+use serde::Deserialize;
+
+use crate::credentials::CredentialStore;
+use crate::error::{DResult, DockerError};
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Scheme {
+    #[default]
+    Https,
+    Http,
+}
+
+impl Display for Scheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Https => write!(f, "https"),
+            Self::Http => write!(f, "http"),
+        }
+    }
+}
+
+/// Connection settings for a single registry
+#[derive(Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct RegistryConfig {
+    #[serde(default)]
+    pub scheme: Scheme,
+
+    /// Additional CA bundle (pem) to trust for this registry
+    #[serde(default)]
+    pub ca_file: Option<Utf8PathBuf>,
+
+    /// Disable tls certificate verification
+    #[serde(default)]
+    pub skip_verify: bool,
+}
+
+impl RegistryConfig {
+    #[must_use]
+    pub fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+
+    pub fn client_builder(&self) -> DResult<ClientBuilder> {
+        let mut builder = ClientBuilder::new();
+
+        if let Some(ca_file) = &self.ca_file {
+            for cert in Certificate::from_pem_bundle(&std::fs::read(ca_file)?)? {
+                builder = builder.add_root_certificate(cert);
+            }
+        }
+
+        if self.skip_verify {
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+
+        Ok(builder)
+    }
+
+    pub fn client(&self) -> DResult<Client> {
+        Ok(self.client_builder()?.build()?)
+    }
+}
+
+/// Parse registry settings in short form:
+///
+///  - `https` (default)
+///  - `http`
+///  - `https+insecure` (skip certificate verification)
+///  - `https+ca=<path>` (trust additional CA bundle)
+impl FromStr for RegistryConfig {
+    type Err = DockerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let res = match s {
+            "https" => Self::default(),
+            "http" => Self {
+                scheme: Scheme::Http,
+                ..Self::default()
+            },
+            "https+insecure" => Self {
+                skip_verify: true,
+                ..Self::default()
+            },
+            _ => {
+                let ca_file = s
+                    .strip_prefix("https+ca=")
+                    .ok_or_else(|| DockerError::InvalidRegistryConfig(s.to_string()))?;
+
+                Self {
+                    ca_file: Some(ca_file.into()),
+                    ..Self::default()
+                }
+            }
+        };
+
+        Ok(res)
+    }
+}
+
+/// Registry connection settings, indexed by registry domain.
+///
+/// Environment variables of the form `RAPTOR_REGISTRY_<DOMAIN>` (e.g.
+/// `RAPTOR_REGISTRY_LOCALHOST_5000=http`) are consulted on lookup, and take
+/// precedence over explicitly configured settings.
+#[derive(Debug, Default, Clone)]
+pub struct RegistryStore {
+    configs: HashMap<String, RegistryConfig>,
+}
+
+impl RegistryStore {
+    const ENV_PREFIX: &str = "RAPTOR_REGISTRY_";
+
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, registry: &str, config: RegistryConfig) {
+        self.configs
+            .insert(CredentialStore::normalize(registry), config);
+    }
+
+    pub fn get(&self, registry: &str) -> DResult<RegistryConfig> {
+        let registry = CredentialStore::normalize(registry);
+
+        if let Ok(value) = std::env::var(Self::env_name(&registry)) {
+            return value.parse();
+        }
+
+        Ok(self.configs.get(&registry).cloned().unwrap_or_default())
+    }
+
+    /// Name of environment variable holding settings for `registry` (named
+    /// like the credential variables, e.g. `RAPTOR_REGISTRY_GHCR_IO`)
+    #[must_use]
+    pub fn env_name(registry: &str) -> String {
+        let name = CredentialStore::env_name(registry);
+        let name = name
+            .strip_prefix(CredentialStore::ENV_PREFIX)
+            .unwrap_or(&name);
+
+        format!("{}{name}", Self::ENV_PREFIX)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::{DResult, DockerError};
+    use crate::registry::{RegistryConfig, RegistryStore, Scheme};
+
+    #[test]
+    fn parse_short_form() -> DResult<()> {
+        assert_eq!(
+            "https".parse::<RegistryConfig>()?,
+            RegistryConfig::default()
+        );
+        assert_eq!("http".parse::<RegistryConfig>()?.scheme, Scheme::Http);
+        assert!("https+insecure".parse::<RegistryConfig>()?.skip_verify);
+        assert_eq!(
+            "https+ca=/etc/ca.pem".parse::<RegistryConfig>()?.ca_file,
+            Some("/etc/ca.pem".into())
+        );
+        assert!(matches!(
+            "ftp".parse::<RegistryConfig>(),
+            Err(DockerError::InvalidRegistryConfig(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn store_lookup() -> DResult<()> {
+        let mut store = RegistryStore::new();
+        store.insert("http://Mirror.example.org:5000/", "http".parse()?);
+
+        assert_eq!(store.get("mirror.example.org:5000")?.scheme, Scheme::Http);
+        assert!(store.get("mirror.example.org")?.is_default());
+
+        Ok(())
+    }
+
+    #[test]
+    fn env_name() {
+        assert_eq!(
+            RegistryStore::env_name("https://localhost:5000/v2/"),
+            "RAPTOR_REGISTRY_LOCALHOST_5000"
+        );
+        assert_eq!(
+            RegistryStore::env_name("docker.io"),
+            "RAPTOR_REGISTRY_INDEX_DOCKER_IO"
+        );
+    }
+}
@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use tiny_http::{Header, Request, Response, Server};
+
+use crate::digest::Digest;
+
+pub type TestResponse = Response<Cursor<Vec<u8>>>;
+
+/// Start a stand-in http server on a random local port, answering every
+/// request using `handler`. Returns the address of the server.
+pub fn serve(handler: impl Fn(&TestRequest) -> TestResponse + Send + 'static) -> String {
+    let server = Server::http("127.0.0.1:0").unwrap();
+    let addr = server.server_addr().to_string();
+
+    thread::spawn(move || {
+        for mut req in server.incoming_requests() {
+            let mut body = vec![];
+            req.as_reader().read_to_end(&mut body).unwrap();
+
+            let resp = handler(&TestRequest { req: &req, body });
+            req.respond(resp).unwrap();
+        }
+    });
+
+    addr
+}
+
+pub struct TestRequest<'a> {
+    req: &'a Request,
+    pub body: Vec<u8>,
+}
+
+impl TestRequest<'_> {
+    #[must_use]
+    pub fn method(&self) -> String {
+        self.req.method().to_string()
+    }
+
+    #[must_use]
+    pub fn url(&self) -> &str {
+        self.req.url()
+    }
+
+    #[must_use]
+    pub fn header(&self, name: &str) -> Option<String> {
+        self.req
+            .headers()
+            .iter()
+            .find(|hdr| hdr.field.as_str().as_str().eq_ignore_ascii_case(name))
+            .map(|hdr| hdr.value.to_string())
+    }
+}
+
+pub fn respond(status: u16, body: impl Into<Vec<u8>>) -> TestResponse {
+    Response::from_data(body.into()).with_status_code(status)
+}
+
+#[must_use]
+pub fn respond_json(body: &str) -> TestResponse {
+    respond(200, body).with_header(Header::from_bytes("Content-Type", "application/json").unwrap())
+}
+
+#[must_use]
+pub fn respond_unauthorized(www_authenticate: &str) -> TestResponse {
+    respond(401, "").with_header(Header::from_bytes("WWW-Authenticate", www_authenticate).unwrap())
+}
+
+/// Decode the query string of a url (enough for the values used in tests)
+#[must_use]
+pub fn query(url: &str) -> HashMap<String, Vec<String>> {
+    let mut res: HashMap<String, Vec<String>> = HashMap::new();
+
+    let Some((_, query)) = url.split_once('?') else {
+        return res;
+    };
+
+    for pair in query.split('&') {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let value = value
+            .replace('+', " ")
+            .replace("%3A", ":")
+            .replace("%2C", ",")
+            .replace("%2F", "/");
+        res.entry(key.to_string()).or_default().push(value);
+    }
+
+    res
+}
+
+/// Contents of a [`serve_registry`] stand-in registry, keyed by
+/// `<repo>/blobs/<digest>` and `<repo>/manifests/<reference>`
+pub type RegistryStore = Arc<Mutex<HashMap<String, Vec<u8>>>>;
+
+/// Start a minimal stand-in registry, supporting pulls, (monolithic and
+/// chunked) blob uploads, cross-repository mounts and manifest uploads.
+///
+/// The registry requires bearer tokens (issued by `/token` to anyone), which
+/// must carry the scope needed for each request: `pull` for reads, and
+/// `pull,push` for writes.
+#[must_use]
+pub fn serve_registry() -> (String, RegistryStore) {
+    serve_registry_expiring(usize::MAX)
+}
+
+/// Like [`serve_registry`], but each token is only accepted for `uses`
+/// requests, after which it is rejected as if it expired.
+#[must_use]
+pub fn serve_registry_expiring(uses: usize) -> (String, RegistryStore) {
+    let store = RegistryStore::default();
+    let uploads: Arc<Mutex<HashMap<String, Vec<u8>>>> = Arc::default();
+    let tokens: Mutex<HashMap<String, usize>> = Mutex::default();
+    let serial = AtomicUsize::new(0);
+    let addr: Arc<Mutex<String>> = Arc::default();
+
+    let handler = {
+        let store = store.clone();
+        let addr = addr.clone();
+        move |req: &TestRequest| {
+            let addr = addr.lock().unwrap().clone();
+
+            /* count the uses of each token, so it can expire */
+            if let Some(token) = req
+                .header("Authorization")
+                .filter(|auth| auth.starts_with("Bearer "))
+            {
+                let used = *tokens
+                    .lock()
+                    .unwrap()
+                    .entry(token)
+                    .and_modify(|used| *used += 1)
+                    .or_insert(1);
+
+                if used > uses {
+                    return respond_unauthorized(&format!(
+                        r#"Bearer realm="http://{addr}/token",service="registry.test""#
+                    ));
+                }
+            }
+
+            let serial = serial.fetch_add(1, Ordering::Relaxed);
+            registry(&addr, &store, &uploads, serial, req)
+        }
+    };
+
+    let res = serve(handler);
+    addr.lock().unwrap().clone_from(&res);
+
+    (res, store)
+}
+
+fn has_scope(req: &TestRequest, repo: &str, action: &str) -> bool {
+    let Some(token) = req.header("Authorization") else {
+        return false;
+    };
+
+    let token = token.strip_prefix("Bearer ").unwrap_or_default();
+
+    token.split(' ').any(|scope| {
+        scope == format!("repository:{repo}:{action}")
+            || scope == format!("repository:{repo}:pull,push")
+    })
+}
+
+fn registry(
+    addr: &str,
+    store: &RegistryStore,
+    uploads: &Mutex<HashMap<String, Vec<u8>>>,
+    serial: usize,
+    req: &TestRequest,
+) -> TestResponse {
+    let method = req.method();
+    let url = req.url();
+    let path = url.split_once('?').map_or(url, |(path, _)| path);
+    let query = query(url);
+
+    if path == "/token" {
+        /* tokens are unique, so each new one can be used again */
+        let token = query.get("scope").cloned().unwrap_or_default().join(" ");
+        return respond_json(&format!(r#"{{"token": "{serial} {token}"}}"#));
+    }
+
+    let path = path.strip_prefix("/v2/").unwrap();
+    let (repo, kind, rest) = ["/blobs/", "/manifests/"]
+        .iter()
+        .find_map(|kind| {
+            let (repo, rest) = path.split_once(kind)?;
+            Some((repo, kind.trim_matches('/'), rest))
+        })
+        .unwrap();
+
+    let action = if matches!(method.as_str(), "GET" | "HEAD") {
+        "pull"
+    } else {
+        "pull,push"
+    };
+
+    if !has_scope(req, repo, action) {
+        return respond_unauthorized(&format!(
+            r#"Bearer realm="http://{addr}/token",service="registry.test",scope="repository:{repo}:{action}""#
+        ));
+    }
+
+    let location = |loc: String| Header::from_bytes("Location", loc).unwrap();
+
+    let mut store = store.lock().unwrap();
+
+    match (method.as_str(), kind, rest) {
+        ("POST", "blobs", "uploads/") => {
+            if let (Some(digest), Some(from)) = (query.get("mount"), query.get("from")) {
+                let blob = store
+                    .get(&format!("{}/blobs/{}", from[0], digest[0]))
+                    .cloned();
+                if let Some(blob) = blob
+                    && has_scope(req, &from[0], "pull")
+                {
+                    store.insert(format!("{repo}/blobs/{}", digest[0]), blob);
+                    return respond(201, "")
+                        .with_header(location(format!("/v2/{repo}/blobs/{}", digest[0])));
+                }
+            }
+
+            let id = {
+                let mut uploads = uploads.lock().unwrap();
+                let id = uploads.len().to_string();
+                uploads.insert(id.clone(), vec![]);
+                id
+            };
+            respond(202, "").with_header(location(format!("/v2/{repo}/blobs/uploads/{id}")))
+        }
+
+        ("PATCH", "blobs", upload) => {
+            let id = upload.strip_prefix("uploads/").unwrap();
+            uploads
+                .lock()
+                .unwrap()
+                .get_mut(id)
+                .unwrap()
+                .extend(&req.body);
+            respond(202, "").with_header(location(format!("/v2/{repo}/blobs/uploads/{id}")))
+        }
+
+        ("PUT", "blobs", upload) => {
+            let id = upload.strip_prefix("uploads/").unwrap();
+            let mut data = uploads.lock().unwrap().remove(id).unwrap();
+            data.extend(&req.body);
+
+            let digest = &query["digest"][0];
+            if Digest::parse(digest).ok() != Some(Digest::sha256(&data)) {
+                return respond(400, "");
+            }
+
+            store.insert(format!("{repo}/blobs/{digest}"), data);
+            respond(201, "")
+        }
+
+        ("PUT", "manifests", reference) => {
+            store.insert(format!("{repo}/manifests/{reference}"), req.body.clone());
+            let digest = Digest::sha256(&req.body).to_string();
+            respond(201, "")
+                .with_header(Header::from_bytes("Docker-Content-Digest", digest).unwrap())
+        }
+
+        ("GET" | "HEAD", kind, reference) => store
+            .get(&format!("{repo}/{kind}/{reference}"))
+            .map_or_else(|| respond(404, ""), |data| respond(200, data.clone())),
+
+        _ => respond(405, ""),
+    }
+}
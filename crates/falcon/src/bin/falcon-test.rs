@@ -17,6 +17,7 @@ fn main() -> FalconResult<()> {
             .into_iter()
             .map(ToOwned::to_owned)
             .collect(),
+        secrets: vec![],
     });
 
     info!("writing frame: {req:?}");
@@ -1,34 +1,255 @@
 use std::collections::HashMap;
-use std::fs::{File, OpenOptions};
+use std::ffi::CString;
+use std::fs::{File, OpenOptions, Permissions};
 use std::io::{Error, ErrorKind, Write};
 use std::os::fd::{AsFd, AsRawFd};
-use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt, PermissionsExt, lchown, symlink};
 use std::os::unix::net::UnixStream;
 use std::os::unix::process::{CommandExt, ExitStatusExt};
 use std::process::Command;
 use std::str::FromStr;
 
+use camino::{Utf8Path, Utf8PathBuf};
 use nix::errno::Errno;
+use nix::mount::{MntFlags, MsFlags, mount, umount2};
+use nix::sched::{CloneFlags, unshare};
 use nix::sys::stat::{Mode, umask};
-use nix::unistd::{Gid, Group, Uid, User, chown, fchown};
+use nix::unistd::{Gid, Group, Uid, User, chown, fchown, getgrouplist, setgid, setgroups, setuid};
 
 use log::{LevelFilter, debug, error, trace};
 
 use falcon::client::{
     Account, FramedRead, FramedWrite, Request, RequestChangeDir, RequestCloseFd, RequestCreateDir,
-    RequestCreateFile, RequestRun, RequestSetEnv, RequestWriteFd, Response,
+    RequestCreateFile, RequestCreateSymlink, RequestRun, RequestSetEnv, RequestSetUser,
+    RequestStat, RequestWriteFd, Response, SecretMount,
 };
 use falcon::error::{FalconError, FalconResult};
 use falcon::umask_proc::Umask;
 
-fn request_run(req: &RequestRun) -> FalconResult<i32> {
+/// Credentials for running commands, as set by [`Request::SetUser`]
+struct RunAs {
+    uid: Uid,
+    gid: Gid,
+    groups: Vec<Gid>,
+    user: Option<User>,
+}
+
+/// Mount a tmpfs-backed overlay on `dir`, so mount points can be created in
+/// it without changing the build root.
+///
+/// Existing entries of `dir` are bind-mounted back on the overlay, so changes
+/// to them are kept. New entries in `dir` itself only exist in the private
+/// mount namespace, and are discarded with it.
+fn overlay_dir(dir: &Utf8Path) -> Result<(), Error> {
+    const NONE: Option<&str> = None;
+
+    /* mounts on / are not visible to path lookups, which start below them */
+    if dir.parent().is_none() {
+        error!("Cannot create secret mount points in /");
+        return Err(Errno::EINVAL.into());
+    }
+
+    let lower = File::open(dir)?;
+    let lower_path = format!("/proc/self/fd/{}", lower.as_raw_fd());
+    let meta = lower.metadata()?;
+
+    let mut entries = vec![];
+    for dent in dir.read_dir_utf8()? {
+        let dent = dent?;
+        let file_type = dent.file_type()?;
+        if file_type.is_dir() || file_type.is_file() {
+            entries.push(dent.file_name().to_string());
+        }
+    }
+
+    mount(
+        Some("tmpfs"),
+        dir.as_str(),
+        Some("tmpfs"),
+        MsFlags::MS_NOSUID | MsFlags::MS_NODEV,
+        Some("mode=0700"),
+    )?;
+
+    let upper = dir.join("upper");
+    let work = dir.join("work");
+    std::fs::create_dir(&upper)?;
+    std::fs::create_dir(&work)?;
+
+    /* the overlay takes its attributes from the upper directory */
+    std::fs::set_permissions(&upper, meta.permissions())?;
+    chown(
+        upper.as_std_path(),
+        Some(Uid::from_raw(meta.uid())),
+        Some(Gid::from_raw(meta.gid())),
+    )?;
+
+    let options = format!("lowerdir={lower_path},upperdir={upper},workdir={work}");
+    mount(
+        Some("overlay"),
+        dir.as_str(),
+        Some("overlay"),
+        MsFlags::empty(),
+        Some(options.as_str()),
+    )?;
+
+    for name in entries {
+        mount(
+            Some(format!("{lower_path}/{name}").as_str()),
+            dir.join(name).as_str(),
+            NONE,
+            MsFlags::MS_BIND | MsFlags::MS_REC,
+            NONE,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Create an empty file to mount a secret on (and any missing parent
+/// directories), in the private mount namespace.
+///
+/// The nearest existing parent directory is overlaid (see [`overlay_dir`]),
+/// unless it is in `writable`: directories created or overlaid for earlier
+/// secrets, which already only exist in the namespace.
+fn create_mount_point(target: &Utf8Path, writable: &mut Vec<Utf8PathBuf>) -> Result<(), Error> {
+    let missing = target
+        .ancestors()
+        .skip(1)
+        .take_while(|dir| !dir.as_str().is_empty() && !dir.exists())
+        .collect::<Vec<_>>();
+
+    let parent = missing.last().copied().unwrap_or(target);
+    if let Some(existing) = parent.parent()
+        && !writable.iter().any(|dir| dir == existing)
+    {
+        overlay_dir(existing)?;
+        writable.push(existing.to_path_buf());
+    }
+
+    for dir in missing.into_iter().rev() {
+        std::fs::create_dir(dir)?;
+        writable.push(dir.to_path_buf());
+    }
+
+    File::create(target)?;
+
+    Ok(())
+}
+
+/// Mount secrets (read-only) in a private mount namespace. This runs in the
+/// child process, so the mounts disappear when the command exits.
+///
+/// Each secret is written to a tmpfs, temporarily mounted on the parent
+/// directory of the target, and bind-mounted on the target, after which the
+/// tmpfs is detached again. This way, the command only has access to the
+/// secrets it requested, and nothing is written to the build root.
+fn mount_secrets(secrets: &[SecretMount]) -> Result<(), Error> {
+    const NONE: Option<&str> = None;
+
+    unshare(CloneFlags::CLONE_NEWNS)?;
+    mount(NONE, "/", NONE, MsFlags::MS_REC | MsFlags::MS_PRIVATE, NONE)?;
+
+    let mut writable = vec![];
+
+    for secret in secrets {
+        let target = secret.target.as_str();
+
+        if secret.target.symlink_metadata().is_err() {
+            create_mount_point(&secret.target, &mut writable)?;
+        }
+
+        let Some(staging) = secret.target.parent().filter(|dir| dir.parent().is_some()) else {
+            error!("Cannot mount secrets directly in /");
+            return Err(Errno::EINVAL.into());
+        };
+
+        /* the target is hidden by the tmpfs, so refer to it by fd */
+        let dest = OpenOptions::new()
+            .read(true)
+            .custom_flags(nix::libc::O_PATH)
+            .open(target)?;
+        let dest_path = format!("/proc/self/fd/{}", dest.as_raw_fd());
+        let source = staging.join(".raptor-secret");
+
+        mount(
+            Some("tmpfs"),
+            staging.as_str(),
+            Some("tmpfs"),
+            MsFlags::MS_NOSUID | MsFlags::MS_NODEV | MsFlags::MS_NOEXEC,
+            Some("mode=0700"),
+        )?;
+
+        /* readable by all users, so commands run after USER can use it */
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o444)
+            .open(&source)?
+            .write_all(&secret.data)?;
+
+        mount(
+            Some(source.as_str()),
+            dest_path.as_str(),
+            NONE,
+            MsFlags::MS_BIND,
+            NONE,
+        )?;
+
+        umount2(staging.as_str(), MntFlags::MNT_DETACH)?;
+
+        mount(
+            NONE,
+            target,
+            NONE,
+            MsFlags::MS_BIND | MsFlags::MS_REMOUNT | MsFlags::MS_RDONLY,
+            NONE,
+        )?;
+    }
+
+    Ok(())
+}
+
+fn request_run(req: &RequestRun, run_as: Option<&RunAs>) -> FalconResult<i32> {
     debug!("Exec {} {:?}", req.arg0, &req.argv);
-    Ok(Command::new(&req.argv[0])
-        .arg0(&req.argv[0])
+    let mut cmd = Command::new(&req.argv[0]);
+
+    cmd.arg0(&req.argv[0])
         .args(&req.argv[1..])
-        .umask(Mode::S_IWGRP | Mode::S_IWOTH)
-        .status()
-        .map(ExitStatusExt::into_raw)?)
+        .umask(Mode::S_IWGRP | Mode::S_IWOTH);
+
+    /* mount secrets before dropping privileges (below) */
+    if !req.secrets.is_empty() {
+        let secrets = req.secrets.clone();
+        unsafe {
+            cmd.pre_exec(move || mount_secrets(&secrets));
+        }
+    }
+
+    if let Some(run_as) = run_as {
+        if let Some(user) = &run_as.user {
+            cmd.env("HOME", &user.dir)
+                .env("USER", &user.name)
+                .env("LOGNAME", &user.name);
+        }
+
+        let (uid, gid, groups) = (run_as.uid, run_as.gid, run_as.groups.clone());
+
+        /* drop privileges in the child, after fork(). Supplementary groups
+         * can only be changed by root. */
+        let root = Uid::effective().is_root();
+        unsafe {
+            cmd.pre_exec(move || {
+                if root {
+                    setgroups(&groups)?;
+                }
+                setgid(gid)?;
+                setuid(uid)?;
+                Ok(())
+            });
+        }
+    }
+
+    Ok(cmd.status()?.into_raw())
 }
 
 fn request_changedir(req: &RequestChangeDir) -> FalconResult<i32> {
@@ -47,6 +268,45 @@ fn request_setenv(req: &RequestSetEnv) -> FalconResult<i32> {
     Ok(0)
 }
 
+fn request_setuser(req: &RequestSetUser) -> FalconResult<Option<RunAs>> {
+    let Some(acct) = &req.user else {
+        debug!("Setuser (root)");
+        return Ok(None);
+    };
+
+    let uid = uid_from_account(acct)?;
+    let user = User::from_uid(uid)?;
+
+    /* default to the primary group of the user, if known, and otherwise to
+     * the root group (like docker) */
+    let gid = match (&req.group, &user) {
+        (Some(group), _) => gid_from_account(group)?,
+        (None, Some(user)) => user.gid,
+        (None, None) => Gid::from_raw(0),
+    };
+
+    let groups = if let Some(user) = &user {
+        let name = CString::new(user.name.as_str()).map_err(|_| Errno::EINVAL)?;
+        getgrouplist(&name, gid)?
+    } else {
+        vec![gid]
+    };
+
+    debug!("Setuser {uid}:{gid} (groups {groups:?})");
+
+    Ok(Some(RunAs {
+        uid,
+        gid,
+        groups,
+        user,
+    }))
+}
+
+fn request_stat(req: &RequestStat) -> FalconResult<i32> {
+    trace!("Stat {:?}", req.path);
+    Ok(std::fs::metadata(&req.path)?.mode().cast_signed())
+}
+
 fn uid_from_account(acct: &Account) -> FalconResult<Uid> {
     match acct {
         Account::Id(uid) => Ok(Uid::from_raw(*uid)),
@@ -124,11 +384,20 @@ impl FileMap {
         let path = &req.path;
 
         if req.parents {
+            /* existing directories are left as they are, so mode and owner
+             * are only applied to a directory created here */
+            if path.is_dir() {
+                return Ok(0);
+            }
             std::fs::create_dir_all(path)?;
         } else {
             std::fs::create_dir(path)?;
         }
 
+        if let Some(mode) = req.mode {
+            std::fs::set_permissions(path, Permissions::from_mode(mode))?;
+        }
+
         let uid = req.user.as_ref().map(uid_from_account).transpose()?;
         let gid = req.group.as_ref().map(gid_from_account).transpose()?;
         if uid.is_some() | gid.is_some() {
@@ -138,6 +407,26 @@ impl FileMap {
         Ok(0)
     }
 
+    #[allow(clippy::unused_self)]
+    pub fn create_symlink(&self, req: &RequestCreateSymlink) -> FalconResult<i32> {
+        let path = &req.path;
+
+        /* replace existing files, like create_file() does */
+        if path.symlink_metadata().is_ok_and(|md| !md.is_dir()) {
+            std::fs::remove_file(path)?;
+        }
+
+        symlink(&req.target, path)?;
+
+        let uid = req.user.as_ref().map(uid_from_account).transpose()?;
+        let gid = req.group.as_ref().map(gid_from_account).transpose()?;
+        if uid.is_some() | gid.is_some() {
+            lchown(path, uid.map(Uid::as_raw), gid.map(Gid::as_raw))?;
+        }
+
+        Ok(0)
+    }
+
     fn write_fd(&mut self, req: &RequestWriteFd) -> FalconResult<i32> {
         self.get(req.fd)?.write_all(&req.data)?;
         Ok(0)
@@ -171,6 +460,7 @@ fn main() -> FalconResult<()> {
     let mut stream = UnixStream::connect(socket_name)?;
 
     let mut files = FileMap::new();
+    let mut run_as = None;
 
     umask(Mode::empty());
 
@@ -187,13 +477,19 @@ fn main() -> FalconResult<()> {
         trace!("read request: {req:?}");
 
         let res = match req {
-            Request::Run(req) => request_run(&req),
+            Request::Run(req) => request_run(&req, run_as.as_ref()),
             Request::CreateFile(req) => files.create_file(&req),
             Request::CreateDir(req) => files.create_dir(&req),
+            Request::CreateSymlink(req) => files.create_symlink(&req),
+            Request::Stat(req) => request_stat(&req),
             Request::WriteFd(req) => files.write_fd(&req),
             Request::CloseFd(req) => files.close_fd(&req),
             Request::ChangeDir(req) => request_changedir(&req),
             Request::SetEnv(req) => request_setenv(&req),
+            Request::SetUser(req) => request_setuser(&req).map(|user| {
+                run_as = user;
+                0
+            }),
             Request::Shutdown => {
                 break;
             }
@@ -1,3 +1,5 @@
+use std::fmt::{self, Debug};
+
 use camino::Utf8PathBuf;
 use serde::{Deserialize, Serialize};
 
@@ -7,10 +9,28 @@ pub enum Account {
     Name(String),
 }
 
+/// File with the contents `data`, mounted (read-only) at `target` only while
+/// running a single command. The contents are never written to the build root.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SecretMount {
+    pub data: Vec<u8>,
+    pub target: Utf8PathBuf,
+}
+
+impl Debug for SecretMount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SecretMount")
+            .field("data", &format_args!("<{} bytes>", self.data.len()))
+            .field("target", &self.target)
+            .finish()
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RequestRun {
     pub arg0: String,
     pub argv: Vec<String>,
+    pub secrets: Vec<SecretMount>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -30,6 +50,19 @@ pub struct RequestCreateDir {
     pub parents: bool,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RequestCreateSymlink {
+    pub path: Utf8PathBuf,
+    pub target: Utf8PathBuf,
+    pub user: Option<Account>,
+    pub group: Option<Account>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RequestStat {
+    pub path: Utf8PathBuf,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RequestWriteFd {
     pub fd: i32,
@@ -52,13 +85,24 @@ pub struct RequestSetEnv {
     pub value: String,
 }
 
+/// Set the user (and optionally group) that subsequent [`Request::Run`]
+/// commands are executed as. If `user` is `None`, commands run as root again.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RequestSetUser {
+    pub user: Option<Account>,
+    pub group: Option<Account>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum Request {
     Run(RequestRun),
     ChangeDir(RequestChangeDir),
     SetEnv(RequestSetEnv),
+    SetUser(RequestSetUser),
     CreateFile(RequestCreateFile),
     CreateDir(RequestCreateDir),
+    CreateSymlink(RequestCreateSymlink),
+    Stat(RequestStat),
     WriteFd(RequestWriteFd),
     CloseFd(RequestCloseFd),
     Shutdown,
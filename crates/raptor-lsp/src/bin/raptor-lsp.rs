@@ -0,0 +1,36 @@
+use std::env;
+
+use log::{LevelFilter, error, info};
+use lsp_server::Connection;
+
+use raptor_lsp::LspResult;
+use raptor_lsp::server::Server;
+
+fn run() -> LspResult<()> {
+    let (connection, io_threads) = Connection::stdio();
+
+    Server::new(connection).run()?;
+    io_threads.join()?;
+
+    Ok(())
+}
+
+fn main() {
+    /* stdout carries the protocol; log messages go to stderr */
+    let mut builder = colog::default_builder();
+
+    builder.filter(None, LevelFilter::Info);
+
+    if let Ok(rust_log) = env::var("RUST_LOG") {
+        builder.parse_filters(&rust_log);
+    }
+    builder.init();
+
+    match run() {
+        Ok(()) => info!("Language server exited"),
+        Err(err) => {
+            error!("Language server failed: {err}");
+            std::process::exit(1);
+        }
+    }
+}
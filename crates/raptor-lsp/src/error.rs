@@ -0,0 +1,22 @@
+#[derive(thiserror::Error, Debug)]
+pub enum LspError {
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+
+    #[error(transparent)]
+    ProtocolError(#[from] lsp_server::ProtocolError),
+
+    #[error(transparent)]
+    SerdeJsonError(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    RaptorError(#[from] raptor::RaptorError),
+
+    #[error("Connection closed")]
+    SendError,
+
+    #[error("Unsupported document uri: {0}")]
+    InvalidUri(String),
+}
+
+pub type LspResult<T> = Result<T, LspError>;
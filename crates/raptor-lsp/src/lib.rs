@@ -0,0 +1,10 @@
+#[macro_use]
+extern crate log;
+
+mod error;
+pub mod linemap;
+pub mod server;
+pub mod uri;
+pub mod workspace;
+
+pub use error::{LspError, LspResult};
@@ -0,0 +1,168 @@
+use lsp_types::Position;
+
+/// Limit for the alignment table (source lines * rendered lines). Larger
+/// files fall back to mapping lines one-to-one.
+const MAX_CELLS: usize = 4_000_000;
+
+/// Mapping from lines of rendered template output to lines of the template
+/// source.
+///
+/// Origins of parsed statements refer to the rendered output, which differs
+/// from the source when line statements (`$ for ...`) or expressions are used.
+/// Lines that are unchanged by rendering are matched up directly (using the
+/// longest common subsequence). The remaining rendered lines are attributed
+/// to the unmatched source lines between them, in order (repeating for
+/// loops).
+pub struct LineMap {
+    map: Vec<usize>,
+}
+
+fn is_content(line: &str) -> bool {
+    let line = line.trim();
+    !(line.is_empty() || line == "$" || line.starts_with("$ "))
+}
+
+impl LineMap {
+    #[must_use]
+    pub fn new(source: &str, rendered: &str) -> Self {
+        let src: Vec<&str> = source.lines().map(str::trim_end).collect();
+        let out: Vec<&str> = rendered.lines().map(str::trim_end).collect();
+        let (n, m) = (src.len(), out.len());
+
+        if n == 0 || (n + 1) * (m + 1) > MAX_CELLS {
+            let last = n.saturating_sub(1);
+            return Self {
+                map: (0..m).map(|line| line.min(last)).collect(),
+            };
+        }
+
+        /* lcs[i][j] is the length of the common subsequence of src[i..] and out[j..] */
+        let width = m + 1;
+        let mut lcs = vec![0u32; (n + 1) * width];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lcs[i * width + j] = if src[i] == out[j] {
+                    lcs[(i + 1) * width + j + 1] + 1
+                } else {
+                    lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+                };
+            }
+        }
+
+        let mut anchors = vec![];
+        let (mut i, mut j) = (0, 0);
+        while i < n && j < m {
+            if src[i] == out[j] {
+                anchors.push((i, j));
+                i += 1;
+                j += 1;
+            } else if lcs[(i + 1) * width + j] >= lcs[i * width + j + 1] {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+        anchors.push((n, m));
+
+        let mut map = vec![0; m];
+        let (mut si, mut sj) = (0, 0);
+
+        for (ai, aj) in anchors {
+            let candidates: Vec<usize> = (si..ai).filter(|&k| is_content(src[k])).collect();
+
+            for (index, line) in (sj..aj).enumerate() {
+                map[line] = if candidates.is_empty() {
+                    si.min(n - 1)
+                } else {
+                    candidates[index % candidates.len()]
+                };
+            }
+
+            if aj < m {
+                map[aj] = ai;
+            }
+
+            (si, sj) = (ai + 1, aj + 1);
+        }
+
+        Self { map }
+    }
+
+    /// Source line for a line of rendered output
+    #[must_use]
+    pub fn source_line(&self, line: usize) -> usize {
+        self.map
+            .get(line)
+            .or_else(|| self.map.last())
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+/// Position (line, and character in utf-16 code units) of byte `offset` in `text`
+#[must_use]
+pub fn position(text: &str, offset: usize) -> Position {
+    let offset = offset.min(text.len());
+    let start = text[..offset].rfind('\n').map_or(0, |nl| nl + 1);
+    let line = text[..start].matches('\n').count();
+    let character = text[start..offset].encode_utf16().count();
+
+    Position::new(
+        u32::try_from(line).unwrap_or(u32::MAX),
+        u32::try_from(character).unwrap_or(u32::MAX),
+    )
+}
+
+/// Byte offset in `line` of `character` (in utf-16 code units)
+#[must_use]
+pub fn line_offset(line: &str, character: u32) -> usize {
+    let mut units = 0;
+    for (index, ch) in line.char_indices() {
+        if units >= character as usize {
+            return index;
+        }
+        units += ch.len_utf16();
+    }
+    line.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::Position;
+
+    use crate::linemap::{LineMap, line_offset, position};
+
+    #[test]
+    fn linemap_identity() {
+        let text = "FROM base\n\nRUN id\n";
+        let map = LineMap::new(text, text);
+
+        assert_eq!(
+            (0..3).map(|n| map.source_line(n)).collect::<Vec<_>>(),
+            [0, 1, 2]
+        );
+    }
+
+    #[test]
+    fn linemap_template() {
+        let source =
+            "FROM base\n$ for x in [1, 2]\nRUN a {{ x }}\nRUN b {{ x }}\n$ endfor\nRUN id\n";
+        let rendered = "FROM base\nRUN a 1\nRUN b 1\nRUN a 2\nRUN b 2\nRUN id\n";
+        let map = LineMap::new(source, rendered);
+
+        assert_eq!(
+            (0..6).map(|n| map.source_line(n)).collect::<Vec<_>>(),
+            [0, 2, 3, 2, 3, 5]
+        );
+    }
+
+    #[test]
+    fn positions() {
+        let text = "FROM base\nRUN \u{1F600} id\n";
+
+        assert_eq!(position(text, 0), Position::new(0, 0));
+        assert_eq!(position(text, 14), Position::new(1, 4));
+        assert_eq!(position(text, 18), Position::new(1, 6));
+        assert_eq!(line_offset("RUN \u{1F600} id", 6), 8);
+    }
+}
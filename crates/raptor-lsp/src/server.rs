@@ -0,0 +1,238 @@
+use camino::Utf8PathBuf;
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, RequestId, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, DidSaveTextDocument,
+    Notification as _, PublishDiagnostics,
+};
+use lsp_types::request::{Completion, GotoDefinition, HoverRequest, Request as _};
+use lsp_types::{
+    CompletionOptions, CompletionParams, CompletionResponse, DidChangeTextDocumentParams,
+    DidCloseTextDocumentParams, DidOpenTextDocumentParams, DidSaveTextDocumentParams,
+    GotoDefinitionParams, GotoDefinitionResponse, HoverParams, HoverProviderCapability,
+    InitializeParams, OneOf, PublishDiagnosticsParams, ServerCapabilities,
+    TextDocumentSyncCapability, TextDocumentSyncKind,
+};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::workspace::Workspace;
+use crate::{LspError, LspResult, uri};
+
+pub struct Server {
+    connection: Connection,
+}
+
+fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        definition_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec!["-".into()]),
+            ..CompletionOptions::default()
+        }),
+        ..ServerCapabilities::default()
+    }
+}
+
+/// Project root from the initialize request, falling back to the current directory
+fn project_root(params: &InitializeParams) -> Utf8PathBuf {
+    #[allow(deprecated)]
+    let root = params
+        .workspace_folders
+        .as_ref()
+        .and_then(|folders| folders.first())
+        .map(|folder| &folder.uri)
+        .or(params.root_uri.as_ref())
+        .and_then(|root| uri::to_path(root).ok());
+
+    root.or_else(|| {
+        std::env::current_dir()
+            .ok()
+            .and_then(|dir| Utf8PathBuf::from_path_buf(dir).ok())
+    })
+    .unwrap_or_default()
+}
+
+impl Server {
+    #[must_use]
+    pub const fn new(connection: Connection) -> Self {
+        Self { connection }
+    }
+
+    fn send(&self, msg: impl Into<Message>) -> LspResult<()> {
+        self.connection
+            .sender
+            .send(msg.into())
+            .map_err(|_| LspError::SendError)
+    }
+
+    fn notify(&self, method: &str, params: impl Serialize) -> LspResult<()> {
+        self.send(Notification::new(method.to_string(), params))
+    }
+
+    fn respond(&self, id: RequestId, result: impl Serialize) -> LspResult<()> {
+        self.send(Response::new_ok(id, result))
+    }
+
+    /// Run the server until the client shuts it down
+    pub fn run(self) -> LspResult<()> {
+        let caps = serde_json::to_value(capabilities())?;
+        let params: InitializeParams = serde_json::from_value(self.connection.initialize(caps)?)?;
+
+        let root = project_root(&params);
+        info!("Starting language server for {root}");
+
+        let mut workspace = Workspace::new(root);
+
+        for msg in &self.connection.receiver.clone() {
+            match msg {
+                Message::Request(req) => {
+                    if self.connection.handle_shutdown(&req)? {
+                        break;
+                    }
+                    self.handle_request(&workspace, req)?;
+                }
+                Message::Notification(not) => {
+                    let method = not.method.clone();
+
+                    /* notifications cannot be answered, so just skip invalid
+                     * ones (e.g. for documents that are not local files) */
+                    match self.handle_notification(&mut workspace, not) {
+                        Err(LspError::SendError) => return Err(LspError::SendError),
+                        Err(err) => warn!("Ignoring invalid {method} notification: {err}"),
+                        Ok(()) => {}
+                    }
+                }
+                Message::Response(_) => {}
+            }
+        }
+
+        info!("Language server stopped");
+
+        Ok(())
+    }
+
+    /// Answer a request. Invalid requests (e.g. for documents that are not
+    /// local files) are answered with an error, and do not stop the server.
+    fn handle_request(&self, workspace: &Workspace, req: Request) -> LspResult<()> {
+        let id = req.id.clone();
+        let method = req.method.clone();
+
+        match Self::request(workspace, req) {
+            Ok(Some(result)) => self.respond(id, result),
+
+            Ok(None) => {
+                debug!("Unsupported request: {method}");
+                self.send(Response::new_err(
+                    id,
+                    ErrorCode::MethodNotFound as i32,
+                    format!("Unsupported request: {method}"),
+                ))
+            }
+
+            Err(err) => {
+                warn!("Invalid {method} request: {err}");
+                self.send(Response::new_err(
+                    id,
+                    ErrorCode::InvalidParams as i32,
+                    err.to_string(),
+                ))
+            }
+        }
+    }
+
+    /// Result of a request, or `None` if the request is not supported
+    fn request(workspace: &Workspace, req: Request) -> LspResult<Option<serde_json::Value>> {
+        fn params<P: DeserializeOwned>(req: Request) -> LspResult<P> {
+            Ok(serde_json::from_value(req.params)?)
+        }
+
+        let res = match req.method.as_str() {
+            GotoDefinition::METHOD => {
+                let params: GotoDefinitionParams = params(req)?;
+                let doc = params.text_document_position_params;
+                let path = uri::to_path(&doc.text_document.uri)?;
+                let res = workspace
+                    .definition(&path, doc.position)
+                    .map(GotoDefinitionResponse::Scalar);
+                serde_json::to_value(res)?
+            }
+
+            Completion::METHOD => {
+                let params: CompletionParams = params(req)?;
+                let doc = params.text_document_position;
+                let path = uri::to_path(&doc.text_document.uri)?;
+                let res = CompletionResponse::Array(workspace.completion(&path, doc.position));
+                serde_json::to_value(res)?
+            }
+
+            HoverRequest::METHOD => {
+                let params: HoverParams = params(req)?;
+                let doc = params.text_document_position_params;
+                let path = uri::to_path(&doc.text_document.uri)?;
+                serde_json::to_value(workspace.hover(&path, doc.position))?
+            }
+
+            _ => return Ok(None),
+        };
+
+        Ok(Some(res))
+    }
+
+    fn handle_notification(&self, workspace: &mut Workspace, not: Notification) -> LspResult<()> {
+        match not.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params: DidOpenTextDocumentParams = serde_json::from_value(not.params)?;
+                let doc = params.text_document;
+                workspace.open(uri::to_path(&doc.uri)?, doc.text);
+            }
+
+            DidChangeTextDocument::METHOD => {
+                let params: DidChangeTextDocumentParams = serde_json::from_value(not.params)?;
+                let path = uri::to_path(&params.text_document.uri)?;
+                if let Some(change) = params.content_changes.into_iter().next_back() {
+                    workspace.open(path, change.text);
+                }
+            }
+
+            DidSaveTextDocument::METHOD => {
+                let params: DidSaveTextDocumentParams = serde_json::from_value(not.params)?;
+                if let Some(text) = params.text {
+                    workspace.open(uri::to_path(&params.text_document.uri)?, text);
+                }
+            }
+
+            DidCloseTextDocument::METHOD => {
+                let params: DidCloseTextDocumentParams = serde_json::from_value(not.params)?;
+                let path = uri::to_path(&params.text_document.uri)?;
+                workspace.close(&path);
+
+                /* clear diagnostics for the closed document */
+                self.notify(
+                    PublishDiagnostics::METHOD,
+                    PublishDiagnosticsParams::new(params.text_document.uri, vec![], None),
+                )?;
+            }
+
+            method => {
+                debug!("Ignoring notification: {method}");
+                return Ok(());
+            }
+        }
+
+        self.publish_diagnostics(workspace)
+    }
+
+    /// Publish diagnostics for all open documents, since a change in one file
+    /// can affect any file that includes it
+    fn publish_diagnostics(&self, workspace: &Workspace) -> LspResult<()> {
+        for path in workspace.paths() {
+            let diagnostics = workspace.diagnostics(path);
+            let params = PublishDiagnosticsParams::new(uri::from_path(path)?, diagnostics, None);
+            self.notify(PublishDiagnostics::METHOD, params)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,80 @@
+use std::str::FromStr;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use lsp_types::Uri;
+
+use crate::{LspError, LspResult};
+
+const fn unreserved(byte: u8) -> bool {
+    matches!(byte, b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/')
+}
+
+/// Convert a `file://` uri to a local path
+pub fn to_path(uri: &Uri) -> LspResult<Utf8PathBuf> {
+    let invalid = || LspError::InvalidUri(uri.as_str().to_string());
+
+    if !uri
+        .scheme()
+        .is_some_and(|scheme| scheme.as_str().eq_ignore_ascii_case("file"))
+    {
+        return Err(invalid());
+    }
+
+    let path = uri.path().as_str().as_bytes();
+    let mut res = Vec::with_capacity(path.len());
+    let mut index = 0;
+
+    while index < path.len() {
+        if path[index] == b'%' {
+            let hex = path.get(index + 1..index + 3).ok_or_else(invalid)?;
+            let hex = std::str::from_utf8(hex).map_err(|_| invalid())?;
+            res.push(u8::from_str_radix(hex, 16).map_err(|_| invalid())?);
+            index += 3;
+        } else {
+            res.push(path[index]);
+            index += 1;
+        }
+    }
+
+    String::from_utf8(res)
+        .map(Utf8PathBuf::from)
+        .map_err(|_| invalid())
+}
+
+/// Convert an absolute local path to a `file://` uri
+pub fn from_path(path: &Utf8Path) -> LspResult<Uri> {
+    let encoded: String = path
+        .as_str()
+        .bytes()
+        .map(|byte| {
+            if unreserved(byte) {
+                char::from(byte).to_string()
+            } else {
+                format!("%{byte:02X}")
+            }
+        })
+        .collect();
+
+    let res = format!("file://{encoded}");
+
+    Uri::from_str(&res).map_err(|_| LspError::InvalidUri(res))
+}
+
+#[cfg(test)]
+mod tests {
+    use camino::Utf8Path;
+
+    use crate::LspResult;
+    use crate::uri::{from_path, to_path};
+
+    #[test]
+    fn uri_roundtrip() -> LspResult<()> {
+        let path = Utf8Path::new("/srv/my project/base@.rapt");
+        let uri = from_path(path)?;
+
+        assert_eq!(uri.as_str(), "file:///srv/my%20project/base%40.rapt");
+        assert_eq!(to_path(&uri)?, path);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,439 @@
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range as Span;
+use std::sync::Arc;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionTextEdit, Diagnostic, DiagnosticSeverity, Hover,
+    HoverContents, Location, MarkupContent, MarkupKind, Position, Range, TextEdit,
+};
+use minijinja::{ErrorKind, Value, context};
+
+use raptor::dsl::{Item, Program};
+use raptor::make::parser::Make;
+use raptor::program::Loader;
+use raptor::{RaptorError, RaptorResult};
+use raptor_parser::ast::{FromSource, InstFrom, Instruction, MountType, Origin};
+use raptor_parser::parser;
+
+use crate::linemap::{LineMap, line_offset, position};
+use crate::uri;
+
+/// Location of an error, in one of the forms reported by the loader
+enum ErrorSpan {
+    /// Byte range in the rendered template output (origins of statements)
+    Rendered(Span<usize>),
+
+    /// Byte range in the template source (template errors)
+    Source(Span<usize>),
+
+    /// Line in the template source (0-based)
+    Line(usize),
+}
+
+/// Result of loading a program, with the include stack on error
+type Loaded = Result<Arc<Program>, (RaptorError, Vec<Origin>)>;
+
+/// Open documents, and the project they belong to
+pub struct Workspace {
+    root: Utf8PathBuf,
+    links: Vec<(String, Utf8PathBuf)>,
+    documents: HashMap<Utf8PathBuf, String>,
+}
+
+fn utf16_len(text: &str) -> u32 {
+    u32::try_from(text.encode_utf16().count()).unwrap_or(u32::MAX)
+}
+
+/// Range covering the contents of `line` (without indentation)
+fn line_range(text: &str, line: usize) -> Range {
+    let content = text.lines().nth(line).unwrap_or_default().trim_end();
+    let indent = content.len() - content.trim_start().len();
+    let line = u32::try_from(line).unwrap_or(u32::MAX);
+
+    Range::new(
+        Position::new(line, utf16_len(&content[..indent])),
+        Position::new(line, utf16_len(content)),
+    )
+}
+
+/// Template context for a file. Instanced files (`name@.rapt`) get a
+/// placeholder instance, since the real one is only known when used.
+fn file_context(path: &Utf8Path) -> Value {
+    if path.file_stem().is_some_and(|stem| stem.ends_with('@')) {
+        context! { instance => "instance" }
+    } else {
+        context! {}
+    }
+}
+
+fn describe(err: &RaptorError) -> (String, Option<(String, ErrorSpan)>) {
+    match err {
+        RaptorError::ParseError(loc) => (
+            loc.inner.to_string(),
+            Some((
+                loc.origin.path.to_string(),
+                ErrorSpan::Rendered(loc.origin.span.clone()),
+            )),
+        ),
+
+        RaptorError::ScriptError(_, origin)
+        | RaptorError::UndefinedVarError(_, origin)
+        | RaptorError::PackageNotFound(_, origin)
+        | RaptorError::GlobNoMatch(_, origin)
+        | RaptorError::GlobPatternError(_, origin)
+        | RaptorError::SecretMissing(_, origin) => (
+            err.to_string(),
+            Some((
+                origin.path.to_string(),
+                ErrorSpan::Rendered(origin.span.clone()),
+            )),
+        ),
+
+        RaptorError::MinijinjaError(err) => {
+            let message = err.detail().map_or_else(
+                || err.kind().to_string(),
+                |detail| format!("{}: {detail}", err.kind()),
+            );
+
+            let span = err.range().map(ErrorSpan::Source).or_else(|| {
+                err.line()
+                    .map(|line| ErrorSpan::Line(line.saturating_sub(1)))
+            });
+
+            let location = match (err.kind(), err.name(), span) {
+                (ErrorKind::BadInclude, _, _) | (_, None, _) | (_, _, None) => None,
+                (_, Some(name), Some(span)) => Some((name.to_string(), span)),
+            };
+
+            (message, location)
+        }
+
+        err => (err.to_string(), None),
+    }
+}
+
+impl Workspace {
+    #[must_use]
+    pub fn new(root: impl AsRef<Utf8Path>) -> Self {
+        let root = root.as_ref().to_path_buf();
+        let mut links = vec![];
+
+        let makefile = root.join("Raptor.toml");
+        if let Ok(text) = std::fs::read_to_string(&makefile) {
+            match toml::from_str::<Make>(&text) {
+                Ok(make) => {
+                    for (name, link) in make.raptor.link {
+                        links.push((name, Utf8PathBuf::from(link.source)));
+                    }
+                }
+                Err(err) => warn!("Failed to parse {makefile}: {err}"),
+            }
+        }
+
+        Self {
+            root,
+            links,
+            documents: HashMap::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn root(&self) -> &Utf8Path {
+        &self.root
+    }
+
+    pub fn open(&mut self, path: Utf8PathBuf, text: String) {
+        self.documents.insert(path, text);
+    }
+
+    pub fn close(&mut self, path: &Utf8Path) {
+        self.documents.remove(path);
+    }
+
+    #[must_use]
+    pub fn document(&self, path: &Utf8Path) -> Option<&str> {
+        self.documents.get(path).map(String::as_str)
+    }
+
+    /// Paths of all open documents, in sorted order
+    #[must_use]
+    pub fn paths(&self) -> Vec<&Utf8Path> {
+        let mut paths: Vec<&Utf8Path> = self.documents.keys().map(Utf8PathBuf::as_path).collect();
+        paths.sort();
+        paths
+    }
+
+    /// Create a loader for the project, reading open documents from memory
+    /// instead of from disk
+    fn loader(&self) -> RaptorResult<Loader<'static>> {
+        let mut loader = Loader::new()?;
+        loader.resolver_mut().set_base(&self.root);
+
+        for (name, path) in &self.links {
+            loader.resolver().add_package(name.clone(), path.clone());
+        }
+
+        for (path, text) in &self.documents {
+            loader.add_source(path, text.clone())?;
+        }
+
+        Ok(loader)
+    }
+
+    fn load(&self, path: &Utf8Path) -> RaptorResult<(Loader<'static>, Loaded)> {
+        let loader = self.loader()?;
+        let mut origins = vec![Origin::make(path, 0..0)];
+
+        /* build arguments without a default are only known when building,
+         * so check the file with the argument name as a placeholder value */
+        let placeholders: BTreeMap<String, String> = loader
+            .declared_args(path)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|arg| arg.default.is_none())
+            .map(|arg| (arg.name.clone(), arg.name))
+            .collect();
+
+        let ctx = context! {
+            ..file_context(path),
+            ..Value::from(placeholders),
+        };
+
+        let res = loader
+            .load_template(path, ctx, &mut origins)
+            .map_err(|err| (err, origins));
+
+        Ok((loader, res))
+    }
+
+    /// Convert an error location in `path` to a range in its source
+    fn range(loader: &Loader, path: &Utf8Path, text: &str, span: ErrorSpan) -> Range {
+        match span {
+            ErrorSpan::Source(span) => {
+                Range::new(position(text, span.start), position(text, span.end))
+            }
+
+            ErrorSpan::Line(line) => line_range(text, line),
+
+            ErrorSpan::Rendered(span) => {
+                let Some(rendered) = loader.source(path.as_str()) else {
+                    return Range::default();
+                };
+
+                let start = position(&rendered, span.start);
+                let end = position(&rendered, span.end);
+                let line = LineMap::new(text, &rendered).source_line(start.line as usize);
+
+                let rendered_line = rendered.lines().nth(start.line as usize);
+                if start.line == end.line && rendered_line == text.lines().nth(line) {
+                    let line = u32::try_from(line).unwrap_or(u32::MAX);
+                    Range::new(
+                        Position::new(line, start.character),
+                        Position::new(line, end.character),
+                    )
+                } else {
+                    line_range(text, line)
+                }
+            }
+        }
+    }
+
+    /// Check a document by loading it like `raptor check` would, and report
+    /// parse and template errors
+    #[must_use]
+    pub fn diagnostics(&self, path: &Utf8Path) -> Vec<Diagnostic> {
+        let Some(text) = self.document(path) else {
+            return vec![];
+        };
+
+        let (loader, (err, origins)) = match self.load(path) {
+            Ok((_, Ok(_))) => return vec![],
+            Ok((loader, Err(err))) => (loader, err),
+            Err(err) => {
+                return vec![Diagnostic {
+                    severity: Some(DiagnosticSeverity::ERROR),
+                    source: Some("raptor".into()),
+                    message: err.to_string(),
+                    ..Diagnostic::default()
+                }];
+            }
+        };
+
+        /* include files get their variables from the including file, so
+         * undefined variables are expected when checking them on their own */
+        let undefined = match &err {
+            RaptorError::UndefinedVarError(..) => true,
+            RaptorError::MinijinjaError(err) => err.kind() == ErrorKind::UndefinedError,
+            _ => false,
+        };
+        if undefined && path.extension() == Some("rinc") {
+            return vec![];
+        }
+
+        let (mut message, location) = describe(&err);
+
+        let range = match location {
+            Some((file, span)) if file == path.as_str() => Self::range(&loader, path, text, span),
+            location => {
+                /* errors in included files are reported on the INCLUDE */
+                if let Some((file, _)) = location {
+                    message = format!("{file}: {message}");
+                }
+
+                origins
+                    .get(1)
+                    .filter(|origin| origin.path.as_str() == path.as_str())
+                    .map(|origin| {
+                        let span = ErrorSpan::Rendered(origin.span.clone());
+                        Self::range(&loader, path, text, span)
+                    })
+                    .unwrap_or_default()
+            }
+        };
+
+        vec![Diagnostic {
+            range,
+            severity: Some(DiagnosticSeverity::ERROR),
+            source: Some("raptor".into()),
+            message,
+            ..Diagnostic::default()
+        }]
+    }
+
+    /// Find the file referenced by a `FROM` or `INCLUDE` instruction
+    #[must_use]
+    pub fn definition(&self, path: &Utf8Path, pos: Position) -> Option<Location> {
+        let text = self.document(path)?;
+
+        /* join continuation lines, to parse the complete instruction */
+        let mut line = String::new();
+        for next in text.lines().skip(pos.line as usize) {
+            line.push_str(next);
+            line.push('\n');
+            if !next.ends_with('\\') {
+                break;
+            }
+        }
+
+        let stmts = parser::parse(path.as_str(), &line).ok()?;
+        let origin = Origin::make(path, 0..0);
+        let loader = self.loader().ok()?;
+        let resolver = loader.resolver();
+
+        let target = match &stmts.first()?.inst {
+            Instruction::From(InstFrom {
+                from: FromSource::Raptor(name),
+                ..
+            }) => resolver.to_program_path(name, &origin).ok()?,
+            Instruction::Include(include) => {
+                resolver.to_include_path(&include.src, &origin).ok()?
+            }
+            _ => return None,
+        };
+
+        let target = resolver.path(target);
+        if !target.exists() && !self.documents.contains_key(&target) {
+            return None;
+        }
+
+        Some(Location::new(
+            uri::from_path(&target).ok()?,
+            Range::default(),
+        ))
+    }
+
+    /// Complete instruction keywords at the start of a line, and mount
+    /// types for `MOUNT`
+    #[must_use]
+    pub fn completion(&self, path: &Utf8Path, pos: Position) -> Vec<CompletionItem> {
+        let Some(line) = self
+            .document(path)
+            .and_then(|text| text.lines().nth(pos.line as usize))
+        else {
+            return vec![];
+        };
+
+        let prefix = &line[..line_offset(line, pos.character)];
+        let trimmed = prefix.trim_start();
+
+        if trimmed.starts_with(['$', '#']) {
+            return vec![];
+        }
+
+        let word = trimmed
+            .rsplit(char::is_whitespace)
+            .next()
+            .unwrap_or_default();
+
+        let edit = |label: &str| {
+            let start = Position::new(pos.line, pos.character - utf16_len(word));
+            Some(CompletionTextEdit::Edit(TextEdit::new(
+                Range::new(start, pos),
+                label.to_string(),
+            )))
+        };
+
+        if !trimmed.contains(char::is_whitespace) {
+            Instruction::NAMES
+                .iter()
+                .map(|name| CompletionItem {
+                    label: (*name).to_string(),
+                    kind: Some(CompletionItemKind::KEYWORD),
+                    text_edit: edit(name),
+                    ..CompletionItem::default()
+                })
+                .collect()
+        } else if trimmed.starts_with("MOUNT") && word.starts_with('-') {
+            MountType::ALL
+                .iter()
+                .map(|mtype| CompletionItem {
+                    label: mtype.option().to_string(),
+                    kind: Some(CompletionItemKind::ENUM_MEMBER),
+                    detail: Some("mount type".into()),
+                    text_edit: edit(mtype.option()),
+                    ..CompletionItem::default()
+                })
+                .collect()
+        } else {
+            vec![]
+        }
+    }
+
+    /// Show the rendered statement(s) produced by a line
+    #[must_use]
+    pub fn hover(&self, path: &Utf8Path, pos: Position) -> Option<Hover> {
+        let text = self.document(path)?;
+        let (loader, program) = self.load(path).ok()?;
+        let program = program.ok()?;
+        let rendered = loader.source(path.as_str())?;
+        let map = LineMap::new(text, &rendered);
+
+        let stmts: Vec<&str> = program
+            .code
+            .iter()
+            .filter_map(|item| match item {
+                Item::Statement(stmt) => Some(stmt),
+                Item::Program(_) => None,
+            })
+            .filter(|stmt| {
+                let start = position(&rendered, stmt.origin.span.start).line;
+                let end = position(&rendered, stmt.origin.span.end).line;
+                (start..=end).any(|line| map.source_line(line as usize) == pos.line as usize)
+            })
+            .map(|stmt| rendered[stmt.origin.span.clone()].trim_end())
+            .collect();
+
+        if stmts.is_empty() {
+            return None;
+        }
+
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: format!("```raptor\n{}\n```", stmts.join("\n")),
+            }),
+            range: None,
+        })
+    }
+}
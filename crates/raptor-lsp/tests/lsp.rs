@@ -0,0 +1,320 @@
+use std::fs;
+use std::thread::JoinHandle;
+
+use camino_tempfile::Utf8TempDir;
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, RequestId, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidOpenTextDocument, Exit, Initialized, Notification as _,
+    PublishDiagnostics,
+};
+use lsp_types::request::{
+    Completion, GotoDefinition, HoverRequest, Initialize, Request as _, Shutdown,
+};
+use lsp_types::{
+    CompletionItem, CompletionResponse, DidChangeTextDocumentParams, DidOpenTextDocumentParams,
+    GotoDefinitionResponse, Hover, HoverContents, InitializeParams, Position,
+    PublishDiagnosticsParams, Range, TextDocumentContentChangeEvent, TextDocumentIdentifier,
+    TextDocumentItem, TextDocumentPositionParams, Uri, VersionedTextDocumentIdentifier,
+    WorkspaceFolder,
+};
+use pretty_assertions::assert_eq;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use raptor_lsp::server::Server;
+use raptor_lsp::{LspResult, uri};
+
+struct Client {
+    conn: Connection,
+    server: Option<JoinHandle<LspResult<()>>>,
+    next_id: i32,
+    root: Utf8TempDir,
+}
+
+impl Client {
+    fn start(files: &[(&str, &str)]) -> LspResult<Self> {
+        let root = Utf8TempDir::new()?;
+        for (name, text) in files {
+            let path = root.path().join(name);
+            fs::create_dir_all(path.parent().unwrap())?;
+            fs::write(path, text)?;
+        }
+
+        let (server, conn) = Connection::memory();
+        let server = std::thread::spawn(move || Server::new(server).run());
+
+        let mut client = Self {
+            conn,
+            server: Some(server),
+            next_id: 0,
+            root,
+        };
+
+        let params = InitializeParams {
+            workspace_folders: Some(vec![WorkspaceFolder {
+                uri: uri::from_path(client.root.path())?,
+                name: "test".into(),
+            }]),
+            ..InitializeParams::default()
+        };
+        client.request::<serde_json::Value>(Initialize::METHOD, params)?;
+        client.notify(Initialized::METHOD, serde_json::json!({}));
+
+        Ok(client)
+    }
+
+    fn uri(&self, name: &str) -> Uri {
+        uri::from_path(&self.root.path().join(name)).unwrap()
+    }
+
+    fn notify(&self, method: &str, params: impl Serialize) {
+        let not = Notification::new(method.to_string(), params);
+        self.conn.sender.send(not.into()).unwrap();
+    }
+
+    fn response(&mut self, method: &str, params: impl Serialize) -> Response {
+        self.next_id += 1;
+        let id = RequestId::from(self.next_id);
+        let req = Request::new(id.clone(), method.to_string(), params);
+        self.conn.sender.send(req.into()).unwrap();
+
+        loop {
+            match self.conn.receiver.recv().unwrap() {
+                Message::Response(resp) if resp.id == id => return resp,
+                _ => {}
+            }
+        }
+    }
+
+    fn request<R: DeserializeOwned>(
+        &mut self,
+        method: &str,
+        params: impl Serialize,
+    ) -> LspResult<R> {
+        let resp = self.response(method, params);
+        assert!(resp.error.is_none(), "{:?}", resp.error);
+        Ok(serde_json::from_value(resp.result.unwrap())?)
+    }
+
+    /// Wait for the diagnostics published for `name`
+    fn diagnostics(&self, name: &str) -> LspResult<PublishDiagnosticsParams> {
+        let uri = self.uri(name);
+
+        loop {
+            if let Message::Notification(not) = self.conn.receiver.recv().unwrap()
+                && not.method == PublishDiagnostics::METHOD
+            {
+                let params: PublishDiagnosticsParams = serde_json::from_value(not.params)?;
+                if params.uri == uri {
+                    return Ok(params);
+                }
+            }
+        }
+    }
+
+    fn open(&self, name: &str, text: &str) {
+        let params = DidOpenTextDocumentParams {
+            text_document: TextDocumentItem::new(self.uri(name), "raptor".into(), 1, text.into()),
+        };
+        self.notify(DidOpenTextDocument::METHOD, params);
+    }
+
+    fn change(&self, name: &str, text: &str) {
+        let params = DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier::new(self.uri(name), 2),
+            content_changes: vec![TextDocumentContentChangeEvent {
+                range: None,
+                range_length: None,
+                text: text.into(),
+            }],
+        };
+        self.notify(DidChangeTextDocument::METHOD, params);
+    }
+
+    fn position(&self, name: &str, line: u32, character: u32) -> TextDocumentPositionParams {
+        TextDocumentPositionParams::new(
+            TextDocumentIdentifier::new(self.uri(name)),
+            Position::new(line, character),
+        )
+    }
+
+    fn completion(&mut self, name: &str, line: u32, character: u32) -> LspResult<Vec<String>> {
+        let params = serde_json::json!(self.position(name, line, character));
+        let res: Option<CompletionResponse> = self.request(Completion::METHOD, params)?;
+
+        let items: Vec<CompletionItem> = match res {
+            Some(CompletionResponse::Array(items)) => items,
+            Some(CompletionResponse::List(list)) => list.items,
+            None => vec![],
+        };
+
+        Ok(items.into_iter().map(|item| item.label).collect())
+    }
+
+    fn definition(&mut self, name: &str, line: u32) -> LspResult<Option<Uri>> {
+        let params = self.position(name, line, 2);
+        let res: Option<GotoDefinitionResponse> = self.request(GotoDefinition::METHOD, params)?;
+
+        Ok(match res {
+            Some(GotoDefinitionResponse::Scalar(loc)) => Some(loc.uri),
+            _ => None,
+        })
+    }
+
+    fn shutdown(mut self) -> LspResult<()> {
+        self.request::<()>(Shutdown::METHOD, ())?;
+        self.notify(Exit::METHOD, ());
+        self.server.take().unwrap().join().unwrap()
+    }
+}
+
+fn range(line: u32, start: u32, end: u32) -> Range {
+    Range::new(Position::new(line, start), Position::new(line, end))
+}
+
+#[test]
+fn lsp_diagnostics() -> LspResult<()> {
+    let client = Client::start(&[])?;
+
+    client.open(
+        "test.rapt",
+        "FROM docker://debian\n$ set x = 1\n\nRUN id\nFOO bar\n",
+    );
+    let diags = client.diagnostics("test.rapt")?.diagnostics;
+    assert_eq!(diags.len(), 1);
+    assert_eq!(diags[0].range, range(4, 0, 3));
+
+    client.change("test.rapt", "FROM docker://debian\nRUN id\n");
+    let diags = client.diagnostics("test.rapt")?.diagnostics;
+    assert_eq!(diags, vec![]);
+
+    client.change("test.rapt", "FROM docker://debian\nRUN {{ x.y.z }}\n");
+    let diags = client.diagnostics("test.rapt")?.diagnostics;
+    assert_eq!(diags.len(), 1);
+    assert_eq!(diags[0].range.start.line, 1);
+
+    client.shutdown()
+}
+
+#[test]
+fn lsp_include_diagnostics() -> LspResult<()> {
+    let client = Client::start(&[("lib/broken.rinc", "RUN id\nFOO bar\n")])?;
+
+    /* include files are checked without their arguments */
+    client.open("lib/vars.rinc", "RUN echo {{ name }}\n");
+    let diags = client.diagnostics("lib/vars.rinc")?.diagnostics;
+    assert_eq!(diags, vec![]);
+
+    /* errors in included files are reported on the INCLUDE */
+    client.open("test.rapt", "FROM docker://debian\nINCLUDE lib.broken\n");
+    let diags = client.diagnostics("test.rapt")?.diagnostics;
+    assert_eq!(diags.len(), 1);
+    assert_eq!(diags[0].range.start.line, 1);
+    assert!(diags[0].message.contains("broken.rinc"));
+
+    client.shutdown()
+}
+
+#[test]
+fn lsp_definition() -> LspResult<()> {
+    let mut client = Client::start(&[
+        ("Raptor.toml", "[raptor.link]\npkg = \"vendor/pkg\"\n"),
+        ("base.rapt", "FROM docker://debian\n"),
+        ("vendor/pkg/setup.rinc", "RUN id\n"),
+        ("service@.rapt", "FROM base\n"),
+    ])?;
+
+    let text =
+        "FROM base\nINCLUDE $pkg.setup\nINCLUDE \\\n  $pkg.setup\nFROM service@web\nRUN id\n";
+    client.open("test.rapt", text);
+
+    assert_eq!(
+        client.definition("test.rapt", 0)?,
+        Some(client.uri("base.rapt"))
+    );
+    assert_eq!(
+        client.definition("test.rapt", 1)?,
+        Some(client.uri("vendor/pkg/setup.rinc"))
+    );
+    assert_eq!(
+        client.definition("test.rapt", 2)?,
+        Some(client.uri("vendor/pkg/setup.rinc"))
+    );
+    assert_eq!(
+        client.definition("test.rapt", 4)?,
+        Some(client.uri("service@.rapt"))
+    );
+    assert_eq!(client.definition("test.rapt", 5)?, None);
+
+    client.shutdown()
+}
+
+#[test]
+fn lsp_completion() -> LspResult<()> {
+    let mut client = Client::start(&[])?;
+    client.open("test.rapt", "FROM docker://debian\nMO\nMOUNT --\nRUN --\n");
+
+    let keywords = client.completion("test.rapt", 1, 2)?;
+    assert!(keywords.contains(&"MOUNT".to_string()));
+    assert!(keywords.contains(&"NETWORK".to_string()));
+
+    assert_eq!(
+        client.completion("test.rapt", 2, 8)?,
+        ["--simple", "--file", "--layers", "--overlay", "--cache"]
+    );
+    assert_eq!(client.completion("test.rapt", 3, 6)?, Vec::<String>::new());
+
+    client.shutdown()
+}
+
+#[test]
+fn lsp_hover() -> LspResult<()> {
+    let mut client = Client::start(&[])?;
+    let text = "FROM docker://debian\n$ for x in [1, 2]\nRUN echo {{ x }}\n$ endfor\n";
+    client.open("test.rapt", text);
+
+    let params = client.position("test.rapt", 2, 4);
+    let hover: Option<Hover> = client.request(HoverRequest::METHOD, params)?;
+    let Some(HoverContents::Markup(content)) = hover.map(|hover| hover.contents) else {
+        panic!("expected markup hover");
+    };
+    assert_eq!(content.value, "```raptor\nRUN echo 1\nRUN echo 2\n```");
+
+    let params = client.position("test.rapt", 1, 2);
+    let hover: Option<Hover> = client.request(HoverRequest::METHOD, params)?;
+    assert_eq!(hover, None);
+
+    client.shutdown()
+}
+
+#[test]
+fn lsp_untitled() -> LspResult<()> {
+    let mut client = Client::start(&[])?;
+
+    /* documents that are not local files are not supported, but must not
+     * stop the server */
+    let untitled: Uri = "untitled:Untitled-1".parse().unwrap();
+    let params = DidOpenTextDocumentParams {
+        text_document: TextDocumentItem::new(
+            untitled.clone(),
+            "raptor".into(),
+            1,
+            "RUN id\n".into(),
+        ),
+    };
+    client.notify(DidOpenTextDocument::METHOD, params);
+
+    let params =
+        TextDocumentPositionParams::new(TextDocumentIdentifier::new(untitled), Position::new(0, 1));
+    let resp = client.response(HoverRequest::METHOD, params);
+    assert_eq!(
+        resp.error.map(|err| err.code),
+        Some(ErrorCode::InvalidParams as i32)
+    );
+
+    client.open("test.rapt", "FROM docker://debian\nMO\n");
+    let keywords = client.completion("test.rapt", 1, 2)?;
+    assert!(keywords.contains(&"MOUNT".to_string()));
+
+    client.shutdown()
+}
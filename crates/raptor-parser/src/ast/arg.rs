@@ -0,0 +1,24 @@
+use std::fmt::{Debug, Display};
+
+use colored::Colorize;
+use serde::Serialize;
+
+use crate::print::Theme;
+
+/// Declaration of a build argument, with an optional default value
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize)]
+pub struct InstArg {
+    pub name: String,
+    pub default: Option<String>,
+}
+
+impl Display for InstArg {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.keyword("ARG")?;
+        f.name(&self.name)?;
+        if let Some(default) = &self.default {
+            write!(f, "{}{}", "=".dimmed(), format!("{default:?}").red())?;
+        }
+        Ok(())
+    }
+}
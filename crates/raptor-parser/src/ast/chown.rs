@@ -1,6 +1,8 @@
 use std::fmt::Display;
 
-#[derive(Clone, Debug, Hash, Default, PartialEq, Eq)]
+use serde::Serialize;
+
+#[derive(Clone, Debug, Hash, Default, PartialEq, Eq, Serialize)]
 pub struct Chown {
     pub user: Option<String>,
     pub group: Option<String>,
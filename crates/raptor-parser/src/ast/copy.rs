@@ -1,11 +1,12 @@
 use std::fmt::Display;
 
 use camino::Utf8PathBuf;
+use serde::Serialize;
 
 use crate::ast::Chown;
 use crate::print::Theme;
 
-#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize)]
 pub struct InstCopy {
     pub srcs: Vec<Utf8PathBuf>,
     pub dest: Utf8PathBuf,
@@ -1,10 +1,11 @@
 use std::fmt::{Debug, Display};
 
 use camino::Utf8Path;
+use serde::Serialize;
 
 use crate::print::Theme;
 
-#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize)]
 pub struct InstEntrypoint {
     pub entrypoint: Vec<String>,
 }
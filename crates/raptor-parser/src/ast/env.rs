@@ -1,14 +1,16 @@
 use std::fmt::Display;
 
+use serde::Serialize;
+
 use crate::print::Theme;
 
-#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize)]
 pub struct InstEnvAssign {
     pub key: String,
     pub value: String,
 }
 
-#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize)]
 pub struct InstEnv {
     pub env: Vec<InstEnvAssign>,
 }
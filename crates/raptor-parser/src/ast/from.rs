@@ -1,23 +1,36 @@
 use std::fmt::{self, Debug, Display};
 
+use serde::Serialize;
+
+use crate::ast::IncludeArg;
 use crate::print::Theme;
 use crate::util::module_name::ModuleName;
 
-#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum FromSource {
     Raptor(ModuleName),
     Docker(String),
 }
 
-#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize)]
 pub struct InstFrom {
     pub from: FromSource,
+    pub platform: Option<String>,
+
+    /// Parameters for the template context of a raptor source
+    pub args: Vec<IncludeArg>,
 }
 
 impl Display for InstFrom {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.keyword("FROM")?;
-        f.from(&self.from)
+        f.platform(&self.platform)?;
+        f.from(&self.from)?;
+        for arg in &self.args {
+            f.include_arg(arg)?;
+        }
+        Ok(())
     }
 }
 
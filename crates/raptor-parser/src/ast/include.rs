@@ -8,7 +8,7 @@ use crate::ast::Origin;
 use crate::print::Theme;
 use crate::util::module_name::ModuleName;
 
-#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize)]
 pub struct Lookup {
     pub path: ModuleName,
     pub origin: Origin,
@@ -27,13 +27,14 @@ impl Display for Lookup {
     }
 }
 
-#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Expression {
     Lookup(Lookup),
     Value(Value),
 }
 
-#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize)]
 pub struct IncludeArg {
     pub name: String,
     pub value: Expression,
@@ -65,7 +66,7 @@ impl IncludeArg {
     }
 }
 
-#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize)]
 pub struct InstInclude {
     pub src: ModuleName,
     pub args: Vec<IncludeArg>,
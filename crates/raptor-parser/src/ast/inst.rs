@@ -1,14 +1,17 @@
 use std::fmt::{Debug, Display};
 
 use camino::Utf8Path;
+use serde::Serialize;
 
 use crate::ast::{
-    Chown, IncludeArg, InstCmd, InstCopy, InstEntrypoint, InstEnv, InstEnvAssign, InstFrom,
-    InstInclude, InstMkdir, InstMount, InstRender, InstRun, InstWorkdir, InstWrite,
+    Chown, IncludeArg, InstArg, InstCmd, InstCopy, InstEntrypoint, InstEnv, InstEnvAssign,
+    InstFrom, InstInclude, InstMkdir, InstMount, InstNetwork, InstRender, InstRun, InstUser,
+    InstWorkdir, InstWrite, NetworkMode,
 };
 use crate::util::module_name::ModuleName;
 
-#[derive(Clone, Hash, PartialEq, Eq)]
+#[derive(Clone, Hash, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "UPPERCASE")]
 pub enum Instruction {
     From(InstFrom),
     Mount(InstMount),
@@ -20,11 +23,33 @@ pub enum Instruction {
     Run(InstRun),
     Env(InstEnv),
     Workdir(InstWorkdir),
+    User(InstUser),
+    Network(InstNetwork),
+    Arg(InstArg),
     Entrypoint(InstEntrypoint),
     Cmd(InstCmd),
 }
 
 impl Instruction {
+    /// Keywords of all instructions
+    pub const NAMES: [&str; 15] = [
+        "FROM",
+        "MOUNT",
+        "COPY",
+        "RENDER",
+        "WRITE",
+        "MKDIR",
+        "INCLUDE",
+        "RUN",
+        "ENV",
+        "WORKDIR",
+        "USER",
+        "NETWORK",
+        "ARG",
+        "ENTRYPOINT",
+        "CMD",
+    ];
+
     #[must_use]
     pub const fn name(&self) -> &'static str {
         match self {
@@ -38,6 +63,9 @@ impl Instruction {
             Self::Run(_) => "RUN",
             Self::Env(_) => "ENV",
             Self::Workdir(_) => "WORKDIR",
+            Self::User(_) => "USER",
+            Self::Network(_) => "NETWORK",
+            Self::Arg(_) => "ARG",
             Self::Entrypoint(_) => "ENTRYPOINT",
             Self::Cmd(_) => "CMD",
         }
@@ -56,6 +84,25 @@ impl Instruction {
         })
     }
 
+    pub fn user(user: impl AsRef<str>, group: Option<&str>) -> Self {
+        Self::User(InstUser {
+            user: user.as_ref().to_string(),
+            group: group.map(ToString::to_string),
+        })
+    }
+
+    #[must_use]
+    pub const fn network(mode: NetworkMode) -> Self {
+        Self::Network(InstNetwork { mode })
+    }
+
+    pub fn arg(name: impl AsRef<str>, default: Option<&str>) -> Self {
+        Self::Arg(InstArg {
+            name: name.as_ref().to_string(),
+            default: default.map(ToString::to_string),
+        })
+    }
+
     #[must_use]
     pub fn env(env: impl IntoIterator<Item = InstEnvAssign>) -> Self {
         Self::Env(InstEnv {
@@ -66,6 +113,7 @@ impl Instruction {
     pub fn run(run: &[impl AsRef<str>]) -> Self {
         Self::Run(InstRun {
             run: run.iter().map(|s| s.as_ref().to_string()).collect(),
+            secrets: vec![],
         })
     }
 
@@ -156,6 +204,9 @@ impl Display for Instruction {
             Self::Run(inst) => Display::fmt(inst, f),
             Self::Env(inst) => Display::fmt(inst, f),
             Self::Workdir(inst) => Display::fmt(inst, f),
+            Self::User(inst) => Display::fmt(inst, f),
+            Self::Network(inst) => Display::fmt(inst, f),
+            Self::Arg(inst) => Display::fmt(inst, f),
             Self::Entrypoint(inst) => Display::fmt(inst, f),
             Self::Cmd(inst) => Display::fmt(inst, f),
         }
@@ -175,6 +226,9 @@ impl Debug for Instruction {
             Self::Run(inst) => Debug::fmt(inst, f),
             Self::Env(inst) => Debug::fmt(inst, f),
             Self::Workdir(inst) => Debug::fmt(inst, f),
+            Self::User(inst) => Debug::fmt(inst, f),
+            Self::Network(inst) => Debug::fmt(inst, f),
+            Self::Arg(inst) => Debug::fmt(inst, f),
             Self::Entrypoint(inst) => Debug::fmt(inst, f),
             Self::Cmd(inst) => Debug::fmt(inst, f),
         }
@@ -2,11 +2,12 @@ use std::fmt::{Debug, Display};
 
 use camino::Utf8PathBuf;
 use colored::Colorize;
+use serde::Serialize;
 
 use crate::ast::Chown;
 use crate::print::Theme;
 
-#[derive(Clone, Hash, Debug, PartialEq, Eq)]
+#[derive(Clone, Hash, Debug, PartialEq, Eq, Serialize)]
 pub struct InstMkdir {
     pub dest: Utf8PathBuf,
     pub chmod: Option<u32>,
@@ -1,3 +1,4 @@
+mod arg;
 mod chown;
 mod cmd;
 mod copy;
@@ -8,12 +9,15 @@ mod include;
 mod inst;
 mod mkdir;
 mod mount;
+mod network;
 mod origin;
 mod render;
 mod run;
+mod user;
 mod workdir;
 mod write;
 
+pub use arg::*;
 pub use chown::*;
 pub use cmd::*;
 pub use copy::*;
@@ -24,13 +28,17 @@ pub use include::*;
 pub use inst::*;
 pub use mkdir::*;
 pub use mount::*;
+pub use network::*;
 pub use origin::*;
 pub use render::*;
 pub use run::*;
+pub use user::*;
 pub use workdir::*;
 pub use write::*;
 
-#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+use serde::Serialize;
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize)]
 pub struct Statement {
     pub inst: Instruction,
     pub origin: Origin,
@@ -2,25 +2,28 @@ use std::fmt::Display;
 
 use camino::Utf8PathBuf;
 use colored::Colorize;
+use serde::Serialize;
 
 use crate::print::Theme;
 
-#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum MountType {
     File,
     Simple,
     Layers,
     Overlay,
+    Cache,
 }
 
-#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize)]
 pub struct MountOptions {
     pub mtype: MountType,
     pub readonly: bool,
     pub optional: bool,
 }
 
-#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize)]
 pub struct InstMount {
     pub opts: MountOptions,
     pub name: String,
@@ -42,15 +45,30 @@ impl Display for MountOptions {
     }
 }
 
-impl Display for MountType {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let name = match self {
+impl MountType {
+    pub const ALL: [Self; 5] = [
+        Self::Simple,
+        Self::File,
+        Self::Layers,
+        Self::Overlay,
+        Self::Cache,
+    ];
+
+    /// Option selecting this mount type (e.g. `--simple`)
+    #[must_use]
+    pub const fn option(self) -> &'static str {
+        match self {
             Self::File => "--file",
             Self::Simple => "--simple",
             Self::Layers => "--layers",
             Self::Overlay => "--overlay",
-        };
+            Self::Cache => "--cache",
+        }
+    }
+}
 
-        write!(f, "{}", name.bright_white())
+impl Display for MountType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.option().bright_white())
     }
 }
@@ -0,0 +1,86 @@
+use std::fmt::{Debug, Display};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::ParseError;
+use crate::print::Theme;
+
+/// Network access for a build or run sandbox
+#[derive(Clone, Debug, Default, Hash, PartialEq, Eq)]
+pub enum NetworkMode {
+    /// Share the network of the host
+    #[default]
+    Host,
+
+    /// No network access (only a private loopback device)
+    None,
+
+    /// Private network, connected to the host with a virtual ethernet link
+    Veth,
+
+    /// Private network, connected to a bridge shared with other containers
+    /// in the same zone
+    Zone(String),
+}
+
+impl NetworkMode {
+    pub(crate) const EXPECTED: &str = "network mode (host, none, veth or zone:<name>)";
+}
+
+impl Display for NetworkMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Host => write!(f, "host"),
+            Self::None => write!(f, "none"),
+            Self::Veth => write!(f, "veth"),
+            Self::Zone(name) => write!(f, "zone:{name}"),
+        }
+    }
+}
+
+impl FromStr for NetworkMode {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "host" => Ok(Self::Host),
+            None if s == "none" => Ok(Self::None),
+            None if s == "veth" => Ok(Self::Veth),
+            Some(("zone", name)) if !name.is_empty() => Ok(Self::Zone(name.to_string())),
+            _ => Err(ParseError::Expected(Self::EXPECTED)),
+        }
+    }
+}
+
+impl Serialize for NetworkMode {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for NetworkMode {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let val = String::deserialize(deserializer)?;
+
+        val.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize)]
+pub struct InstNetwork {
+    pub mode: NetworkMode,
+}
+
+impl Display for InstNetwork {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.keyword("NETWORK")?;
+        f.name(&self.mode.to_string())
+    }
+}
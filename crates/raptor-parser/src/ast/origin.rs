@@ -3,10 +3,11 @@ use std::ops::Range;
 use std::sync::Arc;
 
 use camino::{Utf8Path, Utf8PathBuf};
+use serde::Serialize;
 
 use crate::util::{SafeParent, SafeParentError};
 
-#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize)]
 pub struct Origin {
     pub path: Arc<Utf8PathBuf>,
     pub span: Range<usize>,
@@ -1,11 +1,12 @@
 use std::fmt::{Debug, Display};
 
 use camino::Utf8PathBuf;
+use serde::Serialize;
 
 use crate::ast::{Chown, IncludeArg};
 use crate::print::Theme;
 
-#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize)]
 pub struct InstRender {
     pub src: Utf8PathBuf,
     pub dest: Utf8PathBuf,
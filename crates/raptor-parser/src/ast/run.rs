@@ -1,17 +1,64 @@
 use std::fmt::{Debug, Display};
 
-use camino::Utf8Path;
+use camino::{Utf8Path, Utf8PathBuf};
+use colored::Colorize;
+use serde::Serialize;
 
 use crate::print::Theme;
 
-#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize)]
 pub struct InstRun {
     pub run: Vec<String>,
+    pub secrets: Vec<RunSecret>,
+}
+
+/// Secret made available to a single `RUN` instruction (`--secret`)
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize)]
+pub struct RunSecret {
+    pub id: String,
+    pub target: Option<Utf8PathBuf>,
+}
+
+impl RunSecret {
+    pub const DEFAULT_DIR: &str = "/run/secrets";
+
+    /// Secret ids are used as file names, so they must be a plain file name
+    #[must_use]
+    pub fn valid_id(id: &str) -> bool {
+        !id.is_empty() && id != "." && id != ".." && !id.contains('/')
+    }
+
+    /// Path of the secret inside the build namespace (by default,
+    /// `/run/secrets/<id>`)
+    #[must_use]
+    pub fn target(&self) -> Utf8PathBuf {
+        self.target
+            .clone()
+            .unwrap_or_else(|| Utf8Path::new(Self::DEFAULT_DIR).join(&self.id))
+    }
+}
+
+impl Display for RunSecret {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "id={}", self.id)?;
+        if let Some(target) = &self.target {
+            write!(f, ",target={target}")?;
+        }
+        Ok(())
+    }
 }
 
 impl Display for InstRun {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         f.keyword("RUN")?;
+        for secret in &self.secrets {
+            write!(
+                f,
+                " {} {}",
+                "--secret".bright_white(),
+                secret.to_string().cyan()
+            )?;
+        }
         f.dest(Utf8Path::new(&self.run[0]))?;
         for arg in &self.run[1..] {
             f.src(Utf8Path::new(arg.as_str()))?;
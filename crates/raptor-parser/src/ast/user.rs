@@ -0,0 +1,23 @@
+use std::fmt::{Debug, Display};
+
+use colored::Colorize;
+use serde::Serialize;
+
+use crate::print::Theme;
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize)]
+pub struct InstUser {
+    pub user: String,
+    pub group: Option<String>,
+}
+
+impl Display for InstUser {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.keyword("USER")?;
+        f.name(&self.user)?;
+        if let Some(group) = &self.group {
+            write!(f, "{}{}", ":".dimmed(), group.yellow())?;
+        }
+        Ok(())
+    }
+}
@@ -1,11 +1,12 @@
 use std::fmt::{Debug, Display};
 
 use camino::Utf8PathBuf;
+use serde::Serialize;
 
 use crate::ast::Chown;
 use crate::print::Theme;
 
-#[derive(Clone, Hash, Debug, PartialEq, Eq)]
+#[derive(Clone, Hash, Debug, PartialEq, Eq, Serialize)]
 pub struct InstWrite {
     pub dest: Utf8PathBuf,
     pub body: String,
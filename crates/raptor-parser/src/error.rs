@@ -13,6 +13,9 @@ pub enum ParseError {
     )]
     InvalidPermissionMask,
 
+    #[error("Invalid secret id {0:?} (must be a plain file name)")]
+    InvalidSecretId(String),
+
     #[error("Expected {0}")]
     Expected(&'static str),
 
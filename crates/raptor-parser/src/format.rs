@@ -0,0 +1,631 @@
+use std::borrow::Cow;
+use std::fmt::{self, Display};
+use std::sync::Arc;
+
+use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
+use logos::Logos;
+use minijinja::Value;
+use minijinja::value::ValueKind;
+
+use crate::ParseError;
+use crate::ast::{Chown, Expression, FromSource, IncludeArg, Instruction};
+use crate::lexer::Token;
+use crate::parser::{parse, parse_line};
+use crate::util::Location;
+
+/// Indentation of continuation lines
+const INDENT: &str = "    ";
+
+/// Template delimiters, that mark a line as unparsable before rendering
+const TEMPLATE_OPEN: &[&str] = &["{{", "{%", "{#"];
+const TEMPLATE_CLOSE: &[&str] = &["}}", "%}", "#}"];
+
+/// Glob pattern characters, that are not quoted in paths
+const GLOB: [char; 4] = ['*', '?', '[', ']'];
+
+/// A (logical) line of a source file.
+///
+/// Unlike the list of [`crate::ast::Statement`] produced by the parser, this
+/// keeps all the trivia needed to write the file back out: comments, blank
+/// lines, line continuations and template syntax.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Line {
+    /// Empty line (consecutive empty lines are collapsed into one)
+    Blank,
+
+    /// Comment on a line of its own
+    Comment(String),
+
+    /// Minijinja line statement (`$ for ...`), kept as-is
+    Template(String),
+
+    /// Line(s) containing template expressions (`{{ .. }}`), that cannot be
+    /// parsed before rendering, so they are kept as-is
+    Verbatim(String),
+
+    /// Instruction, in canonical form
+    Inst(InstLine),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InstLine {
+    pub inst: Instruction,
+
+    /// Line continuations, as the number of words following each break
+    pub breaks: Vec<usize>,
+
+    /// Trailing comment
+    pub comment: Option<String>,
+}
+
+/// Source file, as a list of [`Line`]s. The [`Display`] implementation
+/// produces the canonical formatting of the file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Document {
+    pub lines: Vec<Line>,
+}
+
+const fn blacklisted(ch: char) -> bool {
+    !matches!(ch, 'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '=' | '/' | ',' | '.' | '+')
+}
+
+fn escape(value: &str) -> String {
+    let mut res = String::with_capacity(value.len() + 2);
+
+    res.push('"');
+
+    for c in value.chars() {
+        match c {
+            '\"' => res.push_str("\\\""),
+            '\t' => res.push_str("\\t"),
+            '\n' => res.push_str("\\n"),
+            '\\' => res.push_str("\\\\"),
+            c => res.push(c),
+        }
+    }
+
+    res.push('"');
+
+    res
+}
+
+/// Quote a word, using the same rules as the `sh` template filter: words
+/// consisting only of safe characters are left bare.
+fn quote(value: &str) -> Cow<'_, str> {
+    if value.is_empty()
+        || value.contains(blacklisted)
+        || Token::lexer(value).any(|token| token.is_err())
+    {
+        Cow::Owned(escape(value))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+/// Quote a value where the grammar only accepts a single bareword or string
+fn quote_value(value: &str) -> Cow<'_, str> {
+    let mut lexer = Token::lexer(value);
+
+    if !value.contains(blacklisted)
+        && lexer.next() == Some(Ok(Token::Bareword))
+        && lexer.next().is_none()
+    {
+        Cow::Borrowed(value)
+    } else {
+        Cow::Owned(escape(value))
+    }
+}
+
+/// Format a value as a raptor expression, as it would be written in a file
+#[must_use]
+pub fn expression(value: &Value) -> String {
+    match value.kind() {
+        ValueKind::String => escape(value.as_str().unwrap_or_default()),
+        ValueKind::Seq => {
+            let items = value
+                .try_iter()
+                .into_iter()
+                .flatten()
+                .map(|item| expression(&item))
+                .collect::<Vec<_>>();
+
+            format!("[{}]", items.join(", "))
+        }
+        ValueKind::Map => {
+            let items = value
+                .try_iter()
+                .into_iter()
+                .flatten()
+                .map(|key| {
+                    let item = value.get_item(&key).unwrap_or_default();
+                    format!("{}: {}", expression(&key), expression(&item))
+                })
+                .collect::<Vec<_>>();
+
+            format!("{{{}}}", items.join(", "))
+        }
+        _ => value.to_string(),
+    }
+}
+
+/// Normalize a path by removing empty and `.` components, keeping any
+/// trailing slash (which is significant for destinations)
+fn normalize(path: &Utf8Path) -> Utf8PathBuf {
+    let mut res: Utf8PathBuf = path
+        .components()
+        .filter(|comp| comp != &Utf8Component::CurDir)
+        .collect();
+
+    if res.as_str().is_empty() {
+        res = Utf8PathBuf::from(".");
+    }
+
+    if path.as_str().ends_with('/') && !res.as_str().ends_with('/') {
+        res.push("");
+    }
+
+    res
+}
+
+fn canonical(mut inst: Instruction) -> Instruction {
+    match &mut inst {
+        Instruction::Copy(copy) => {
+            copy.srcs.iter_mut().for_each(|src| *src = normalize(src));
+            copy.dest = normalize(&copy.dest);
+        }
+        Instruction::Render(render) => {
+            render.src = normalize(&render.src);
+            render.dest = normalize(&render.dest);
+        }
+        Instruction::Write(write) => write.dest = normalize(&write.dest),
+        Instruction::Mkdir(mkdir) => mkdir.dest = normalize(&mkdir.dest),
+        Instruction::Workdir(workdir) => workdir.dir = normalize(&workdir.dir),
+        Instruction::Mount(mount) => mount.dest = normalize(&mount.dest),
+        Instruction::Run(run) => {
+            for target in run.secrets.iter_mut().filter_map(|sec| sec.target.as_mut()) {
+                *target = normalize(target);
+            }
+        }
+        Instruction::From(_)
+        | Instruction::Include(_)
+        | Instruction::Env(_)
+        | Instruction::User(_)
+        | Instruction::Network(_)
+        | Instruction::Arg(_)
+        | Instruction::Entrypoint(_)
+        | Instruction::Cmd(_) => {}
+    }
+
+    inst
+}
+
+/// Replace origins of lookups, to compare instructions parsed from
+/// different sources
+fn without_origins(mut inst: Instruction) -> Instruction {
+    let args = match &mut inst {
+        Instruction::From(from) => &mut from.args,
+        Instruction::Include(include) => &mut include.args,
+        Instruction::Render(render) => &mut render.args,
+        _ => return inst,
+    };
+
+    for arg in args {
+        if let Expression::Lookup(lookup) = &mut arg.value {
+            lookup.origin = crate::ast::Origin::inline();
+        }
+    }
+
+    inst
+}
+
+fn chown(chown: &Chown) -> String {
+    let mut res = String::from("--chown ");
+
+    if let Some(user) = &chown.user {
+        res.push_str(&quote_value(user));
+    }
+
+    if let Some(group) = &chown.group {
+        res.push(':');
+        res.push_str(group);
+    }
+
+    res
+}
+
+fn fileopts(words: &mut Vec<String>, chmod: Option<u32>, chown: Option<&Chown>) {
+    if let Some(chmod) = chmod {
+        words.push(format!("--chmod {chmod:04o}"));
+    }
+
+    if let Some(owner) = chown {
+        words.push(self::chown(owner));
+    }
+}
+
+fn include_arg(arg: &IncludeArg) -> String {
+    match &arg.value {
+        Expression::Lookup(lookup) if lookup.path.to_string() == arg.name => arg.name.clone(),
+        Expression::Lookup(lookup) => format!("{}={}", arg.name, lookup.path),
+        Expression::Value(value) => format!("{}={}", arg.name, expression(value)),
+    }
+}
+
+/// Quote a path like any other word, except glob patterns are left bare
+fn path(path: &Utf8Path) -> String {
+    let path = path.as_str();
+
+    if path.contains(GLOB) {
+        let unglobbed = path.replace(GLOB, "x");
+        if quote(&unglobbed) == unglobbed.as_str() {
+            return path.to_string();
+        }
+    }
+
+    quote(path).into_owned()
+}
+
+fn args(words: &mut Vec<String>, args: &[String]) {
+    words.extend(args.iter().map(|arg| quote(arg).into_owned()));
+}
+
+/// Canonical form of an instruction, as a list of words (that are never
+/// split across lines). The first word is the keyword.
+fn words(inst: &Instruction) -> Vec<String> {
+    let mut words = vec![inst.name().to_string()];
+
+    match inst {
+        Instruction::From(from) => {
+            if let Some(platform) = &from.platform {
+                words.push(format!("--platform {}", quote(platform)));
+            }
+            words.push(match &from.from {
+                FromSource::Raptor(name) => name.to_string(),
+                FromSource::Docker(src) => format!("docker://{src}"),
+            });
+            words.extend(from.args.iter().map(include_arg));
+        }
+
+        Instruction::Mount(mount) => {
+            words.push(mount.opts.mtype.option().into());
+            if mount.opts.readonly {
+                words.push("--readonly".into());
+            }
+            if mount.opts.optional {
+                words.push("--optional".into());
+            }
+            words.push(mount.name.clone());
+            words.push(path(&mount.dest));
+        }
+
+        Instruction::Copy(copy) => {
+            fileopts(&mut words, copy.chmod, copy.chown.as_ref());
+            words.extend(copy.srcs.iter().map(|src| path(src)));
+            words.push(path(&copy.dest));
+        }
+
+        Instruction::Render(render) => {
+            fileopts(&mut words, render.chmod, render.chown.as_ref());
+            words.push(path(&render.src));
+            words.push(path(&render.dest));
+            words.extend(render.args.iter().map(include_arg));
+        }
+
+        Instruction::Write(write) => {
+            fileopts(&mut words, write.chmod, write.chown.as_ref());
+            words.push(quote_value(&write.body).into_owned());
+            words.push(path(&write.dest));
+        }
+
+        Instruction::Mkdir(mkdir) => {
+            if mkdir.parents {
+                words.push("-p".into());
+            }
+            fileopts(&mut words, mkdir.chmod, mkdir.chown.as_ref());
+            words.push(path(&mkdir.dest));
+        }
+
+        Instruction::Include(include) => {
+            words.push(include.src.to_string());
+            words.extend(include.args.iter().map(include_arg));
+        }
+
+        Instruction::Run(run) => {
+            for secret in &run.secrets {
+                match &secret.target {
+                    Some(target) => words.push(format!(
+                        "--secret id={},target={}",
+                        quote(&secret.id),
+                        path(target)
+                    )),
+                    None => words.push(format!("--secret id={}", quote(&secret.id))),
+                }
+            }
+            args(&mut words, &run.run);
+        }
+
+        Instruction::Env(env) => {
+            for assign in &env.env {
+                if assign.key == assign.value {
+                    words.push(assign.key.clone());
+                } else {
+                    words.push(format!("{}={}", assign.key, quote_value(&assign.value)));
+                }
+            }
+        }
+
+        Instruction::Workdir(workdir) => words.push(path(&workdir.dir)),
+
+        Instruction::User(user) => match &user.group {
+            Some(group) => words.push(format!("{}:{group}", user.user)),
+            None => words.push(user.user.clone()),
+        },
+
+        Instruction::Network(network) => words.push(network.mode.to_string()),
+
+        Instruction::Arg(arg) => match &arg.default {
+            Some(default) => words.push(format!("{}={}", arg.name, quote(default))),
+            None => words.push(arg.name.clone()),
+        },
+
+        Instruction::Entrypoint(entrypoint) => args(&mut words, &entrypoint.entrypoint),
+
+        Instruction::Cmd(cmd) => args(&mut words, &cmd.cmd),
+    }
+
+    words
+}
+
+/// Find line continuations in `text` (the source of a single instruction),
+/// as the number of words following each break
+fn breaks(text: &str) -> Vec<usize> {
+    let mut lexer = Token::lexer(text);
+    let mut before = vec![];
+    let mut words = 0;
+    let mut in_word = false;
+    let mut depth = 0usize;
+
+    while let Some(Ok(token)) = lexer.next() {
+        match token {
+            Token::Comment | Token::Newline => break,
+            Token::Whitespace if depth == 0 => {
+                if lexer.slice().contains('\n') {
+                    before.push(words);
+                }
+                in_word = false;
+            }
+            Token::Whitespace => {}
+            token => {
+                if !in_word {
+                    words += 1;
+                    in_word = true;
+                }
+                match token {
+                    Token::LBracket | Token::LBrace => depth += 1,
+                    Token::RBracket | Token::RBrace => depth = depth.saturating_sub(1),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    let mut res: Vec<usize> = before.into_iter().map(|n| words - n).collect();
+    res.dedup();
+    res
+}
+
+/// Number of unclosed template delimiters after `line`, starting from `open`
+fn template_depth(open: usize, line: &str) -> usize {
+    let count = |delims: &[&str]| -> usize { delims.iter().map(|d| line.matches(d).count()).sum() };
+
+    (open + count(TEMPLATE_OPEN)).saturating_sub(count(TEMPLATE_CLOSE))
+}
+
+fn trim_lines(text: &str) -> String {
+    text.lines()
+        .map(str::trim_end)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+impl InstLine {
+    /// Build a line from a parsed instruction, in canonical form. Returns
+    /// `None` if the canonical form does not parse back into the same
+    /// instruction, in which case the original source should be kept.
+    #[must_use]
+    pub fn new(inst: Instruction, breaks: Vec<usize>, comment: Option<String>) -> Option<Self> {
+        let inst = canonical(inst);
+        let line = Self {
+            inst,
+            breaks,
+            comment: None,
+        };
+
+        let reparsed = parse("<format>", &format!("{line}\n")).ok()?;
+        match reparsed.as_slice() {
+            [stmt] if without_origins(stmt.inst.clone()) == without_origins(line.inst.clone()) => {
+                Some(Self { comment, ..line })
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Display for InstLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let words = words(&self.inst);
+
+        write!(f, "{}", words[0])?;
+        for (index, word) in words.iter().enumerate().skip(1) {
+            if self.breaks.contains(&(words.len() - index)) {
+                write!(f, " \\\n{INDENT}{word}")?;
+            } else {
+                write!(f, " {word}")?;
+            }
+        }
+
+        if let Some(comment) = &self.comment {
+            write!(f, " {comment}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Document {
+    pub fn parse(name: &str, buf: &str) -> Result<Self, Location<ParseError>> {
+        let path = Arc::new(Utf8PathBuf::from(name));
+        let mut lines = vec![];
+        let mut open = 0;
+        let mut pos = 0;
+
+        while pos < buf.len() {
+            let start = pos;
+            let mut end = buf[pos..].find('\n').map_or(buf.len(), |nl| pos + nl + 1);
+            let text = buf[start..end].trim();
+
+            if open > 0 {
+                open = template_depth(open, text);
+                lines.push(Line::Verbatim(buf[start..end].trim_end().to_string()));
+            } else if text.is_empty() {
+                if !matches!(lines.last(), None | Some(Line::Blank)) {
+                    lines.push(Line::Blank);
+                }
+            } else if text.starts_with('#') {
+                lines.push(Line::Comment(text.to_string()));
+            } else if text == "$" || text.starts_with("$ ") {
+                lines.push(Line::Template(buf[start..end].trim_end().to_string()));
+            } else {
+                /* join continuation lines */
+                while buf[start..end].ends_with("\\\n") && end < buf.len() {
+                    end = buf[end..].find('\n').map_or(buf.len(), |nl| end + nl + 1);
+                }
+
+                let source = &buf[start..end];
+
+                if TEMPLATE_OPEN.iter().any(|delim| source.contains(delim)) {
+                    open = template_depth(0, source);
+                    lines.push(Line::Verbatim(trim_lines(source)));
+                } else {
+                    let line = parse_line(path.clone(), buf, start..end)?
+                        .and_then(|(stmt, comment)| {
+                            InstLine::new(stmt.inst, breaks(source), comment.map(String::from))
+                        })
+                        .map_or_else(|| Line::Verbatim(trim_lines(source)), Line::Inst);
+
+                    lines.push(line);
+                }
+            }
+
+            pos = end;
+        }
+
+        if lines.last() == Some(&Line::Blank) {
+            lines.pop();
+        }
+
+        Ok(Self { lines })
+    }
+}
+
+impl Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Blank => Ok(()),
+            Self::Comment(text) | Self::Template(text) | Self::Verbatim(text) => {
+                write!(f, "{text}")
+            }
+            Self::Inst(inst) => write!(f, "{inst}"),
+        }
+    }
+}
+
+impl Display for Document {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for line in &self.lines {
+            writeln!(f, "{line}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Format the source of a Raptor file (`.rapt` or `.rinc`) in canonical form
+pub fn format(name: &str, buf: &str) -> Result<String, Location<ParseError>> {
+    Ok(Document::parse(name, buf)?.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::format::format;
+
+    fn check(input: &str, expected: &str) {
+        let output = format("test.rapt", input).unwrap();
+        assert_eq!(output, expected);
+
+        /* formatting is idempotent */
+        assert_eq!(format("test.rapt", &output).unwrap(), output);
+    }
+
+    #[test]
+    fn format_trivia() {
+        check(
+            "\n\n# comment  \nFROM   base\n\n\n\nRUN  id   # trailing\n\n",
+            "# comment\nFROM base\n\nRUN id # trailing\n",
+        );
+    }
+
+    #[test]
+    fn format_quoting() {
+        check(
+            "RUN \"ls\" \"-l\" \"a b\"\nWRITE bar /foo\nENV A=\"x\" B=\"/usr/bin\"\n",
+            "RUN ls -l \"a b\"\nWRITE bar /foo\nENV A=x B=\"/usr/bin\"\n",
+        );
+    }
+
+    #[test]
+    fn format_options() {
+        check(
+            "COPY --chown=user:group --chmod=644 ./a//b conf/*.conf [ab].txt ./c/\nMKDIR --chmod 755 -p /x/./y\n",
+            "COPY --chmod 0644 --chown user:group a/b conf/*.conf [ab].txt c/\nMKDIR -p --chmod 0755 /x/y\n",
+        );
+    }
+
+    #[test]
+    fn format_continuation() {
+        check(
+            "RUN apt-get install \\\n  foo \\\n        bar\n",
+            "RUN apt-get install \\\n    foo \\\n    bar\n",
+        );
+    }
+
+    #[test]
+    fn format_template() {
+        check(
+            "$ for x in [1, 2]\nRUN  echo {{ x }}\n$ endfor\nRENDER foo bar a=[1,2,3] b={\"k\": true}\n",
+            "$ for x in [1, 2]\nRUN  echo {{ x }}\n$ endfor\nRENDER foo bar a=[1, 2, 3] b={\"k\": true}\n",
+        );
+    }
+
+    #[test]
+    fn format_template_multiline() {
+        check(
+            "{#\n  not   parsed\n#}\nINCLUDE  lib  data\n",
+            "{#\n  not   parsed\n#}\nINCLUDE lib data\n",
+        );
+    }
+
+    #[test]
+    fn format_mount() {
+        check(
+            "MOUNT  input /input/.\nMOUNT --readonly --file output /output\n",
+            "MOUNT --simple input /input\nMOUNT --file --readonly output /output\n",
+        );
+    }
+
+    #[test]
+    fn format_error() {
+        assert!(format("test.rapt", "FROB foo\n").is_err());
+    }
+}
@@ -52,6 +52,12 @@ pub enum Token {
     #[token("@")]
     At,
 
+    #[token("*")]
+    Star,
+
+    #[token("?")]
+    Question,
+
     #[token("true")]
     True,
 
@@ -123,6 +129,8 @@ impl Token {
             Self::Minus => "-",
             Self::Dollar => "$",
             Self::At => "@",
+            Self::Star => "*",
+            Self::Question => "?",
             Self::True => "true",
             Self::False => "false",
             Self::Bareword => "<bareword>",
@@ -150,6 +158,8 @@ impl Token {
             Self::Minus => "'-' (minus)",
             Self::Dollar => "'$' (dollar)",
             Self::At => "'@' (at)",
+            Self::Star => "'*' (star)",
+            Self::Question => "'?' (question mark)",
             Self::True => "true (keyword)",
             Self::False => "false (keyword)",
             Self::Bareword => "<bareword>",
@@ -222,6 +232,16 @@ mod tests {
         next_ok!(lexer, Token::String(s) if s == "foo\\bar");
     }
 
+    #[test]
+    fn glob_path() {
+        let mut lexer = Token::lexer("conf/*.conf?");
+        next_ok!(lexer, Token::Bareword);
+        next_ok!(lexer, Token::Slash);
+        next_ok!(lexer, Token::Star);
+        next_ok!(lexer, Token::Dot);
+        next_ok!(lexer, Token::Bareword);
+    }
+
     #[test]
     fn string_escape_err() {
         let mut lexer = Token::lexer(r#""foo\xbar"#);
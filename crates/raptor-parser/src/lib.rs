@@ -1,5 +1,6 @@
 pub mod ast;
 pub mod error;
+pub mod format;
 pub mod lexer;
 pub mod parser;
 pub mod print;
@@ -1,4 +1,5 @@
 use std::collections::BTreeMap;
+use std::ops::Range;
 use std::sync::Arc;
 
 use camino::Utf8PathBuf;
@@ -6,9 +7,10 @@ use logos::{Lexer, Logos};
 use minijinja::Value;
 
 use crate::ast::{
-    Chown, Expression, FromSource, IncludeArg, InstCmd, InstCopy, InstEntrypoint, InstEnv,
-    InstEnvAssign, InstFrom, InstInclude, InstMkdir, InstMount, InstRender, InstRun, InstWorkdir,
-    InstWrite, Instruction, Lookup, MountOptions, MountType, Origin, Statement,
+    Chown, Expression, FromSource, IncludeArg, InstArg, InstCmd, InstCopy, InstEntrypoint, InstEnv,
+    InstEnvAssign, InstFrom, InstInclude, InstMkdir, InstMount, InstNetwork, InstRender, InstRun,
+    InstUser, InstWorkdir, InstWrite, Instruction, Lookup, MountOptions, MountType, NetworkMode,
+    Origin, RunSecret, Statement,
 };
 use crate::lexer::{LexerError, Token};
 use crate::util::Location;
@@ -259,10 +261,89 @@ impl<'src> Parser<'src> {
         Ok(args)
     }
 
+    /// Accept the option `--<name>`, or leave the input untouched
+    fn accept_option(&mut self, name: &str) -> ParseResult<bool> {
+        let state = self.lexer.clone();
+
+        if self.accept(&Token::Minus)?
+            && self.accept(&Token::Minus)?
+            && self.accept(&Token::Bareword)?
+            && self.token() == name
+        {
+            return Ok(true);
+        }
+
+        self.lexer = state;
+        Ok(false)
+    }
+
+    /// Value in a comma-separated option list (`key=value,key=value`)
+    fn option_value(&mut self) -> ParseResult<String> {
+        let mut res = String::new();
+
+        loop {
+            match self.peek()? {
+                Token::Comma | Token::Whitespace | Token::Newline | Token::Comment | Token::Eof => {
+                    break;
+                }
+                _ => match self.next()? {
+                    Token::String(string) => res.push_str(&string),
+                    _ => res.push_str(self.token()),
+                },
+            }
+        }
+
+        if res.is_empty() {
+            return Err(ParseError::Expected("value"));
+        }
+
+        Ok(res)
+    }
+
+    pub fn parse_run_secret(&mut self) -> ParseResult<RunSecret> {
+        if !self.accept(&Token::Equals)? {
+            self.expect(&Token::Whitespace)?;
+        }
+
+        let mut id = None;
+        let mut target = None;
+
+        loop {
+            let key = self.bareword()?;
+            self.expect(&Token::Equals)?;
+            let value = self.option_value()?;
+
+            match key {
+                "id" => id = Some(value),
+                "target" => target = Some(value.into()),
+                _ => return Err(ParseError::Expected("secret option (id or target)")),
+            }
+
+            if !self.accept(&Token::Comma)? {
+                break;
+            }
+        }
+
+        let id = id.ok_or(ParseError::Expected("secret id"))?;
+        if !RunSecret::valid_id(&id) {
+            return Err(ParseError::InvalidSecretId(id));
+        }
+
+        Ok(RunSecret { id, target })
+    }
+
     pub fn parse_run(&mut self) -> ParseResult<InstRun> {
+        self.trim()?;
+
+        let mut secrets = vec![];
+        while self.accept_option("secret")? {
+            secrets.push(self.parse_run_secret()?);
+            self.trim()?;
+        }
+
         let run = self.consume_line()?;
 
-        Ok(InstRun { run })
+        Ok(InstRun { run, secrets })
     }
 
     pub fn parse_entrypoint(&mut self) -> ParseResult<InstEntrypoint> {
@@ -287,6 +368,72 @@ impl<'src> Parser<'src> {
         Ok(InstWorkdir { dir })
     }
 
+    fn account_name(&mut self, expected: &'static str) -> ParseResult<String> {
+        let mut res = String::new();
+
+        while let Token::Bareword | Token::Number | Token::Minus | Token::Dot = self.peek()? {
+            self.next()?;
+            res.push_str(self.token());
+        }
+
+        if res.is_empty() {
+            return Err(ParseError::Expected(expected));
+        }
+
+        Ok(res)
+    }
+
+    pub fn parse_user(&mut self) -> ParseResult<InstUser> {
+        self.trim()?;
+
+        let user = self.account_name("user or group name")?;
+
+        let group = if self.accept(&Token::Colon)? {
+            Some(self.account_name("user or group name")?)
+        } else {
+            None
+        };
+
+        self.end_of_line()?;
+
+        Ok(InstUser { user, group })
+    }
+
+    pub fn parse_network(&mut self) -> ParseResult<InstNetwork> {
+        self.trim()?;
+
+        let mode = match self.bareword()? {
+            "host" => NetworkMode::Host,
+            "none" => NetworkMode::None,
+            "veth" => NetworkMode::Veth,
+            "zone" => {
+                self.expect(&Token::Colon)?;
+                NetworkMode::Zone(self.account_name("zone name")?)
+            }
+            _ => return Err(ParseError::Expected(NetworkMode::EXPECTED)),
+        };
+
+        self.end_of_line()?;
+
+        Ok(InstNetwork { mode })
+    }
+
+    pub fn parse_arg(&mut self) -> ParseResult<InstArg> {
+        self.trim()?;
+
+        let name = self.bareword()?.to_string();
+
+        let default = if self.accept(&Token::Equals)? {
+            Some(self.parse_word()?)
+        } else {
+            None
+        };
+
+        self.end_of_line()?;
+
+        Ok(InstArg { name, default })
+    }
+
     pub fn parse_env_assign(&mut self) -> ParseResult<Option<InstEnvAssign>> {
         if self.peek()? == Token::Newline {
             return Ok(None);
@@ -362,22 +509,63 @@ impl<'src> Parser<'src> {
         Ok(self.parse_path()?.to_string())
     }
 
+    pub fn parse_from_options(&mut self) -> ParseResult<Option<String>> {
+        let mut platform = None;
+
+        while self.accept(&Token::Minus)? {
+            self.expect(&Token::Minus)?;
+
+            match self.bareword()? {
+                "platform" => {
+                    if !self.accept(&Token::Equals)? {
+                        self.expect(&Token::Whitespace)?;
+                    }
+
+                    platform = Some(self.parse_word()?);
+                }
+
+                _ => return Err(ParseError::Expected("from option")),
+            }
+
+            self.trim()?;
+        }
+
+        Ok(platform)
+    }
+
     pub fn parse_from(&mut self) -> ParseResult<InstFrom> {
         self.trim()?;
 
+        let platform = self.parse_from_options()?;
+
         let state = self.lexer.clone();
         let next = self.bareword();
         self.lexer = state;
 
         let from = if matches!(next, Ok("docker")) {
             FromSource::Docker(self.parse_docker_from()?)
-        } else {
+        } else if platform.is_none() {
             FromSource::Raptor(self.module_name()?)
+        } else {
+            return Err(ParseError::Expected(
+                "docker source (--platform requires docker://)",
+            ));
+        };
+
+        self.trim()?;
+
+        let args = match from {
+            FromSource::Raptor(_) => self.fill(Self::parse_include_arg)?,
+            FromSource::Docker(_) => vec![],
         };
 
         self.end_of_line()?;
 
-        Ok(InstFrom { from })
+        Ok(InstFrom {
+            from,
+            platform,
+            args,
+        })
     }
 
     pub fn parse_mount_options(&mut self) -> ParseResult<MountOptions> {
@@ -396,6 +584,7 @@ impl<'src> Parser<'src> {
                 "simple" => opts.mtype = MountType::Simple,
                 "layers" => opts.mtype = MountType::Layers,
                 "overlay" => opts.mtype = MountType::Overlay,
+                "cache" => opts.mtype = MountType::Cache,
                 "optional" => opts.optional = true,
                 "required" => opts.optional = false,
                 "readonly" => opts.readonly = true,
@@ -537,6 +726,7 @@ impl<'src> Parser<'src> {
             let origin = Origin::new(self.filename.clone(), self.lexer.span());
             let path = ModuleName::new(vec![name.clone()]);
             let value = Expression::Lookup(Lookup { path, origin });
+            self.trim()?;
 
             return Ok(Some(IncludeArg { name, value }));
         }
@@ -698,6 +888,9 @@ impl<'src> Parser<'src> {
             "RUN" => Instruction::Run(self.parse_run()?),
             "ENV" => Instruction::Env(self.parse_env()?),
             "WORKDIR" => Instruction::Workdir(self.parse_workdir()?),
+            "USER" => Instruction::User(self.parse_user()?),
+            "NETWORK" => Instruction::Network(self.parse_network()?),
+            "ARG" => Instruction::Arg(self.parse_arg()?),
             "ENTRYPOINT" => Instruction::Entrypoint(self.parse_entrypoint()?),
             "CMD" => Instruction::Cmd(self.parse_cmd()?),
             _ => return Err(ParseError::Expected("statement")),
@@ -715,33 +908,70 @@ impl<'src> Parser<'src> {
     }
 }
 
+fn locate(parser: &Parser, buf: &str, err: ParseError) -> Location<ParseError> {
+    let mut origin = Origin::new(parser.filename.clone(), parser.lexer.span());
+
+    /* Error spans that include a newline at the end, are presented quite
+     * awkwardly in the terminal, so trim the final newline */
+    if parser.lexer.slice().ends_with('\n') {
+        origin.span.end -= 1;
+    }
+
+    /* Generic lexer errors are reported *before* the token that fails, so
+     * attempt to synthesize a useful error span, by pointing at the
+     * remainder of the line */
+    if matches!(err, ParseError::LexerError(LexerError::LexerError)) {
+        let remainder = &buf[origin.span.start..];
+        if let Some(nl) = remainder.find('\n') {
+            origin.span.start += 1;
+            origin.span.end += nl - 1;
+        }
+    }
+
+    Location::make(origin, err)
+}
+
 pub fn parse(name: &str, buf: &str) -> Result<Vec<Statement>, Location<ParseError>> {
     let lexer = Token::lexer(buf);
     let path = Arc::new(Utf8PathBuf::from(name));
-    let mut parser = Parser::new(lexer, path.clone());
+    let mut parser = Parser::new(lexer, path);
 
-    parser.file().map_err(|err| {
-        let mut origin = Origin::new(path.clone(), parser.lexer.span());
+    parser.file().map_err(|err| locate(&parser, buf, err))
+}
 
-        /* Error spans that include a newline at the end, are presented quite
-         * awkwardly in the terminal, so trim the final newline */
-        if parser.lexer.slice().ends_with('\n') {
-            origin.span.end -= 1;
-        }
+/// Parse a single statement from `buf[span]`, along with its trailing
+/// comment (if any).
+///
+/// Returns `None` if anything but whitespace follows the statement.
+pub(crate) fn parse_line(
+    path: Arc<Utf8PathBuf>,
+    buf: &str,
+    span: Range<usize>,
+) -> Result<Option<(Statement, Option<&str>)>, Location<ParseError>> {
+    let mut lexer = Token::lexer(&buf[..span.end]);
+    lexer.bump(span.start);
+
+    let mut parser = Parser::new(lexer, path);
+
+    let res = (|| {
+        let Some(stmt) = parser.statement()? else {
+            return Ok(None);
+        };
+
+        let comment = Some(parser.token())
+            .filter(|token| token.starts_with('#'))
+            .map(str::trim_end);
 
-        /* Generic lexer errors are reported *before* the token that fails, so
-         * attempt to synthesize a useful error span, by pointing at the
-         * remainder of the line */
-        if matches!(err, ParseError::LexerError(LexerError::LexerError)) {
-            let remainder = &buf[origin.span.start..];
-            if let Some(nl) = remainder.find('\n') {
-                origin.span.start += 1;
-                origin.span.end += nl - 1;
+        loop {
+            match parser.next()? {
+                Token::Whitespace | Token::Newline => {}
+                Token::Eof => return Ok(Some((stmt, comment))),
+                _ => return Ok(None),
             }
         }
+    })();
 
-        Location::make(origin, err)
-    })
+    res.map_err(|err| locate(&parser, buf, err))
 }
 
 #[cfg(test)]
@@ -754,7 +984,7 @@ mod tests {
     use serde_json::json;
 
     use crate::ParseResult;
-    use crate::ast::{Chown, MountOptions, MountType};
+    use crate::ast::{Chown, FromSource, MountOptions, MountType};
     use crate::lexer::Token;
     use crate::parser::Parser;
     use crate::util::module_name::ModuleRoot;
@@ -998,6 +1228,7 @@ mod tests {
         mount_opts_test!("--simple", MountType::Simple, false, false);
         mount_opts_test!("--layers", MountType::Layers, false, false);
         mount_opts_test!("--overlay", MountType::Overlay, false, false);
+        mount_opts_test!("--cache", MountType::Cache, false, false);
 
         mount_opts_test!("--optional --file", MountType::File, false, true);
         mount_opts_test!("--optional --simple", MountType::Simple, false, true);
@@ -1008,6 +1239,7 @@ mod tests {
         mount_opts_test!("--readonly --simple", MountType::Simple, true, false);
         mount_opts_test!("--readonly --layers", MountType::Layers, true, false);
         mount_opts_test!("--readonly --overlay", MountType::Overlay, true, false);
+        mount_opts_test!("--readonly --cache", MountType::Cache, true, false);
 
         mount_opts_test!("--optional --file", MountType::File, false, true);
         mount_opts_test!("--optional --simple", MountType::Simple, false, true);
@@ -1064,4 +1296,35 @@ mod tests {
         test_err!("$foo");
         test_err!("$foo");
     }
+
+    #[test]
+    fn parse_from_platform() -> ParseResult<()> {
+        let mut parser = make_parser("--platform linux/arm/v7 docker://debian\n");
+        let inst = parser.parse_from()?;
+        assert_eq!(inst.from, FromSource::Docker("debian".into()));
+        assert_eq!(inst.platform.as_deref(), Some("linux/arm/v7"));
+
+        let mut parser = make_parser("--platform=linux/arm64 base\n");
+        parser.parse_from().unwrap_err();
+
+        let mut parser = make_parser("--arch=arm64 docker://debian\n");
+        parser.parse_from().unwrap_err();
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_from_params() -> ParseResult<()> {
+        let mut parser = make_parser("base@prod region replicas=3 env\n");
+        let inst = parser.parse_from()?;
+        assert_eq!(inst.from, FromSource::Raptor("base@prod".into()));
+
+        let names: Vec<_> = inst.args.iter().map(|arg| arg.name.as_str()).collect();
+        assert_eq!(names, ["region", "replicas", "env"]);
+
+        let mut parser = make_parser("docker://debian region=1\n");
+        parser.parse_from().unwrap_err();
+
+        Ok(())
+    }
 }
@@ -9,6 +9,7 @@ pub trait Theme {
     fn keyword(&mut self, name: &str) -> Result;
     fn chmod(&mut self, chmod: &Option<u32>) -> Result;
     fn chown(&mut self, chown: &Option<Chown>) -> Result;
+    fn platform(&mut self, platform: &Option<String>) -> Result;
     fn from(&mut self, src: &FromSource) -> Result;
     fn src(&mut self, src: &Utf8Path) -> Result;
     fn dest(&mut self, dest: &Utf8Path) -> Result;
@@ -47,6 +48,13 @@ impl Theme for Formatter<'_> {
         Ok(())
     }
 
+    fn platform(&mut self, platform: &Option<String>) -> Result {
+        if let Some(platform) = platform {
+            write!(self, " {} {}", "--platform".bright_white(), platform.cyan())?;
+        }
+        Ok(())
+    }
+
     fn from(&mut self, src: &FromSource) -> Result {
         write!(self, " {}", format!("{src}").green())
     }
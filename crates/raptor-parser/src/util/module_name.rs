@@ -1,6 +1,6 @@
 use std::fmt::Display;
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash, Ord, PartialOrd)]
 pub enum ModuleRoot {
@@ -16,6 +16,15 @@ pub struct ModuleName {
     instance: Option<String>,
 }
 
+impl Serialize for ModuleName {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
 impl<'de> Deserialize<'de> for ModuleName {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -115,8 +124,9 @@ impl Display for ModuleName {
 
         write!(f, "{}", self.names.join("."))?;
 
+        /* the last name keeps the trailing '@' of instanced modules */
         if let Some(instance) = &self.instance {
-            write!(f, "@{instance}")?;
+            write!(f, "{instance}")?;
         }
         Ok(())
     }
@@ -203,6 +213,8 @@ mod tests {
             "$.a.b.c", //
             "$a.b",    // package paths
             "$a.b.c",  //
+            "a@b",     // instances
+            "a.b@c",   //
         ];
 
         for test in TESTS {
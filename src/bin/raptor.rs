@@ -1,24 +1,33 @@
 use std::collections::HashMap;
 use std::env;
+use std::fs;
 use std::io::stdout;
 
-use camino::Utf8PathBuf;
+use bytesize::ByteSize;
+use camino::{Utf8Path, Utf8PathBuf};
 use clap::{ArgAction, CommandFactory, Parser as _};
 use clap_complete::Shell;
 use colored::Colorize;
+use dregistry::platform::Platform;
 use log::{LevelFilter, debug, error, info};
 use nix::unistd::Uid;
 use raptor::batch::ParallelRunner;
 use raptor::tui::TerminalParallelRunner;
 
-use raptor::build::{BuildTargetStats, Presenter, RaptorBuilder};
+use raptor::build::{
+    BuildTargetStats, ExportFormat, Exporter, GarbageCollector, GcPolicy, LayerDiff,
+    LayerInventory, LockFile, Presenter, RaptorBuilder, RemoteCache, RemoteCacheUrl, Reproducible,
+    SecretArg,
+};
 use raptor::make::maker::Maker;
 use raptor::make::parser::MakeTarget;
-use raptor::make::planner::Planner;
-use raptor::program::Loader;
+use raptor::make::planner::{Job, Planner};
+use raptor::program::{Dump, DumpFormat, Loader, show_parse_error_context};
 use raptor::runner::Runner;
 use raptor::sandbox::Sandbox;
 use raptor::{RaptorError, RaptorResult};
+use raptor_parser::ast::NetworkMode;
+use raptor_parser::format;
 use raptor_parser::util::SafeParent;
 use raptor_parser::util::module_name::ModuleName;
 
@@ -37,6 +46,24 @@ struct Cli {
     #[arg(short = 'q', long, action = ArgAction::Count, global = true, help_heading="Verbosity")]
     quiet: u8,
 
+    /// Platform for docker images (e.g. linux/arm64 or linux/arm/v7)
+    #[arg(
+        long,
+        global = true,
+        value_name = "os/arch[/variant]",
+        default_value = "linux/amd64"
+    )]
+    platform: Platform,
+
+    /// Network mode for builds (of targets without a NETWORK instruction) and runs
+    #[arg(
+        long,
+        global = true,
+        value_name = "host|none|veth|zone:<name>",
+        default_value = "host"
+    )]
+    network: NetworkMode,
+
     #[command(subcommand)]
     mode: Mode,
 
@@ -51,9 +78,72 @@ struct Cli {
         help_heading="Link packages",
     )]
     link: Vec<String>,
+
+    /// Provide a secret for RUN --secret, from a file or environment variable
+    #[arg(
+        long,
+        value_name = "id=<id>[,src=<path>|,env=<var>]",
+        action = ArgAction::Append,
+        global = true,
+        help_heading="Build secrets",
+    )]
+    secret: Vec<SecretArg>,
+
+    /// Set a build argument, for ARG instructions
+    #[arg(
+        long,
+        value_name = "name=value",
+        value_parser = parse_build_arg,
+        action = ArgAction::Append,
+        global = true,
+        help_heading = "Build arguments",
+    )]
+    arg: Vec<(String, String)>,
+
+    /// Shared layer cache (a directory, or docker://<registry>/<repository>)
+    #[arg(long, value_name = "url", global = true, help_heading = "Remote cache")]
+    remote_cache: Option<RemoteCacheUrl>,
+
+    /// Only fetch layers from the remote cache, never upload
+    #[arg(
+        long,
+        global = true,
+        requires = "remote_cache",
+        help_heading = "Remote cache"
+    )]
+    remote_cache_readonly: bool,
+
+    /// Build reproducible layers (set `SOURCE_DATE_EPOCH`, and clamp timestamps to it)
+    #[arg(long, global = true, help_heading = "Reproducible builds")]
+    reproducible: bool,
+
+    /// Timestamp for reproducible builds, in seconds since 1970-01-01
+    /// (default is `$SOURCE_DATE_EPOCH`, or 0 if unset)
+    #[arg(
+        long,
+        value_name = "secs",
+        global = true,
+        requires = "reproducible",
+        help_heading = "Reproducible builds"
+    )]
+    source_date_epoch: Option<i64>,
 }
 
 impl Cli {
+    fn reproducible(&self) -> RaptorResult<Option<Reproducible>> {
+        if !self.reproducible {
+            return Ok(None);
+        }
+
+        let epoch = match (self.source_date_epoch, env::var(Reproducible::ENV)) {
+            (Some(epoch), _) => epoch,
+            (None, Ok(value)) => value.parse()?,
+            (None, Err(_)) => 0,
+        };
+
+        Ok(Some(Reproducible::new(epoch)))
+    }
+
     #[must_use]
     const fn log_level(&self) -> LevelFilter {
         let verbosity = self.verbose as i32 - self.quiet as i32;
@@ -83,6 +173,10 @@ enum Mode {
     /// Dump mode: show output from templating pass
     #[command(alias = "d")]
     Dump {
+        /// Output format
+        #[arg(long, value_enum, default_value_t)]
+        format: DumpFormat,
+
         /// Targets to dump <target1 target2 ...>
         #[arg(value_name = "targets")]
         targets: Vec<ModuleName>,
@@ -100,6 +194,12 @@ enum Mode {
     #[command(alias = "r")]
     Run(RunCmd),
 
+    /// Export mode: build a target, and write it as an OCI image
+    Export(ExportCmd),
+
+    /// Push mode: build a target, and push it to a registry
+    Push(PushCmd),
+
     /// Show mode: print list of build targets
     #[command(alias = "s")]
     Show { dirs: Vec<ModuleName> },
@@ -120,6 +220,28 @@ enum Mode {
         batch: bool,
     },
 
+    /// Cache mode: maintain the download cache
+    Cache {
+        #[command(subcommand)]
+        action: CacheCmd,
+    },
+
+    /// Layers mode: list layers in the layer cache
+    #[command(alias = "l")]
+    Layers(LayersCmd),
+
+    /// Gc mode: remove unused layers and downloads
+    Gc(GcCmd),
+
+    /// Diff mode: compare two layers, or two builds of a target
+    Diff(DiffCmd),
+
+    /// Update mode: resolve docker images again, and update the lock file
+    Update(UpdateCmd),
+
+    /// Fmt mode: rewrite raptor files in canonical form
+    Fmt(FmtCmd),
+
     /// Completions mode: generate shell completion scripts
     Completion {
         #[arg(value_name = "shell")]
@@ -127,6 +249,12 @@ enum Mode {
     },
 }
 
+#[derive(clap::Subcommand, Clone, Debug)]
+enum CacheCmd {
+    /// Verify digests of downloaded blobs, removing any invalid files
+    Verify,
+}
+
 #[derive(clap::Args, Clone, Debug)]
 struct RunCmd {
     /// Target to run
@@ -192,14 +320,147 @@ struct RunCmd {
     args: Vec<String>,
 }
 
+#[derive(clap::Args, Clone, Debug)]
+struct LayersCmd {
+    #[arg(
+        short = 'f',
+        long,
+        help = "File",
+        default_value_t = Utf8PathBuf::from("Raptor.toml")
+    )]
+    file: Utf8PathBuf,
+
+    /// Output as json
+    #[arg(long)]
+    json: bool,
+
+    /// Targets to check layers against <target1 target2 ...> (defaults to all targets in makefile)
+    #[arg(value_name = "targets")]
+    targets: Vec<ModuleName>,
+}
+
+#[derive(clap::Args, Clone, Debug)]
+struct GcCmd {
+    #[arg(
+        short = 'f',
+        long,
+        help = "File",
+        default_value_t = Utf8PathBuf::from("Raptor.toml")
+    )]
+    file: Utf8PathBuf,
+
+    /// Keep entries modified more recently than this (e.g. "30m", "7d")
+    #[arg(long, value_name = "age", default_value = "1h")]
+    min_age: humantime::Duration,
+
+    /// Keep the most recently used unreachable entries, up to this size (e.g. "20GiB")
+    #[arg(long, value_name = "size")]
+    max_size: Option<ByteSize>,
+
+    /// Targets to keep <target1 target2 ...> (defaults to all targets in makefile)
+    #[arg(value_name = "targets")]
+    targets: Vec<ModuleName>,
+}
+
+#[derive(clap::Args, Clone, Debug)]
+struct DiffCmd {
+    /// Compare the complete file trees of targets (including the layers they
+    /// are built from), instead of only their own layers
+    #[arg(short = 'm', long)]
+    merged: bool,
+
+    /// Output as json
+    #[arg(long)]
+    json: bool,
+
+    /// Old layer (target name, layer id, or path to layer)
+    #[arg(value_name = "old")]
+    old: String,
+
+    /// New layer (target name, layer id, or path to layer)
+    #[arg(value_name = "new")]
+    new: String,
+}
+
+#[derive(clap::Args, Clone, Debug)]
+struct UpdateCmd {
+    #[arg(
+        short = 'f',
+        long,
+        help = "File",
+        default_value_t = Utf8PathBuf::from("Raptor.toml")
+    )]
+    file: Utf8PathBuf,
+
+    /// Targets to update images for <target1 target2 ...> (defaults to all
+    /// targets in makefile, and all images in the lock file)
+    #[arg(value_name = "targets")]
+    targets: Vec<ModuleName>,
+}
+
+#[derive(clap::Args, Clone, Debug)]
+struct FmtCmd {
+    /// Only check formatting, and fail if any files are not formatted
+    #[arg(long)]
+    check: bool,
+
+    /// Files or directories to format (defaults to current directory)
+    #[arg(value_name = "paths")]
+    paths: Vec<Utf8PathBuf>,
+}
+
+#[derive(clap::Args, Clone, Debug)]
+struct ExportCmd {
+    /// Output format
+    #[arg(long, value_enum, default_value_t)]
+    format: ExportFormat,
+
+    /// Image name (e.g. "app:latest")
+    #[arg(short = 't', long, value_name = "name")]
+    tag: Option<String>,
+
+    /// Target to export
+    #[arg(value_name = "target")]
+    target: ModuleName,
+
+    /// Output directory (oci) or file (docker-archive)
+    #[arg(value_name = "output")]
+    output: Utf8PathBuf,
+}
+
+#[derive(clap::Args, Clone, Debug)]
+struct PushCmd {
+    /// Mount blobs from this repository (on the same registry) when possible,
+    /// instead of uploading them (can be repeated)
+    #[arg(long, value_name = "repository", action = ArgAction::Append)]
+    mount_from: Vec<String>,
+
+    /// Target to push
+    #[arg(value_name = "target")]
+    target: ModuleName,
+
+    /// Destination image (e.g. `docker://registry.example.org/app:latest`)
+    #[arg(value_name = "image")]
+    image: String,
+}
+
 #[allow(dead_code)]
 impl Mode {
     const fn dump(&self) -> bool {
-        matches!(self, Self::Dump { .. })
+        matches!(
+            self,
+            Self::Dump {
+                format: DumpFormat::Text,
+                ..
+            }
+        )
     }
 
     const fn build(&self) -> bool {
-        matches!(self, Self::Build { .. } | Self::Run { .. })
+        matches!(
+            self,
+            Self::Build { .. } | Self::Run { .. } | Self::Export { .. } | Self::Push { .. }
+        )
     }
 
     const fn run(&self) -> bool {
@@ -275,11 +536,341 @@ fn check_for_falcon_binary() -> RaptorResult<Utf8PathBuf> {
     falcon_path
 }
 
+fn run_target(builder: &RaptorBuilder, run: &RunCmd) -> RaptorResult<()> {
+    check_for_root()?;
+
+    let program = builder.load(&run.target)?;
+
+    let layers = builder.build_program(program.clone(), builder.platform())?;
+
+    let mut runner = Runner::new()?;
+
+    runner
+        .with_args(&run.args)
+        .with_env(&run.env)
+        .with_mounts(run.mounts());
+
+    if let Some(state_dir) = &run.state {
+        runner.with_state_dir(state_dir.clone());
+    }
+
+    let res = runner.spawn(&program, builder, &layers)?;
+
+    if !res.success() {
+        error!("Run failed with status {}", res.code().unwrap_or_default());
+    }
+
+    Ok(())
+}
+
+fn list_layers(builder: &mut RaptorBuilder, cmd: &LayersCmd) -> RaptorResult<()> {
+    let makefile = cmd.targets.is_empty() && cmd.file.exists();
+
+    if makefile {
+        builder
+            .loader_mut()
+            .resolver_mut()
+            .set_base(cmd.file.try_parent()?);
+        builder.set_lock_file(cmd.file.with_file_name(LockFile::FILE));
+    }
+
+    let builder = &*builder;
+    let mut inventory = LayerInventory::new(builder);
+
+    if makefile {
+        let maker = Maker::load(builder, &cmd.file)?;
+
+        maker.add_links(builder.loader());
+
+        maker.add_registries();
+        maker.add_credentials()?;
+
+        for (name, job) in &maker.rules().run {
+            let platform = maker.platform(job)?;
+            let inputs = job
+                .input
+                .iter()
+                .map(|input| ModuleName::from(input.as_str()));
+            for target in std::iter::once(job.target.clone()).chain(inputs) {
+                let program = builder.load(&target)?;
+                inventory.add_program(name, program, &platform)?;
+            }
+        }
+
+        for (name, group) in &maker.rules().group {
+            for target in &group.build {
+                let program = builder.load(target)?;
+                inventory.add_program(&format!("%{name}"), program, builder.platform())?;
+            }
+        }
+    } else {
+        for target in &cmd.targets {
+            let program = builder.load(target)?;
+            inventory.add_program(&target.to_string(), program, builder.platform())?;
+        }
+    }
+
+    let entries = inventory.list()?;
+
+    if cmd.json {
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+    } else {
+        LayerInventory::present(&entries);
+    }
+
+    Ok(())
+}
+
+fn collect_garbage(builder: &mut RaptorBuilder, gc: &GcCmd) -> RaptorResult<()> {
+    if gc.targets.is_empty() {
+        builder
+            .loader_mut()
+            .resolver_mut()
+            .set_base(gc.file.try_parent()?);
+        builder.set_lock_file(gc.file.with_file_name(LockFile::FILE));
+    }
+
+    let builder = &*builder;
+    let mut collector = GarbageCollector::new(builder);
+
+    if gc.targets.is_empty() {
+        let maker = Maker::load(builder, &gc.file)?;
+
+        maker.add_links(builder.loader());
+
+        maker.add_registries();
+        maker.add_credentials()?;
+
+        let mut planner = Planner::new(&maker, builder);
+        for target in maker.rules().run.keys() {
+            planner.add_named_run_job(target)?;
+        }
+        for group in maker.rules().group.keys() {
+            planner.add(&MakeTarget::Group(group.clone()))?;
+        }
+
+        for job in planner.nodes().values() {
+            if let Job::Build(layer) = job {
+                collector.add_target(&layer.target)?;
+            }
+        }
+    } else {
+        for target in &gc.targets {
+            let program = builder.load(target)?;
+            collector.add_program(program, builder.platform())?;
+        }
+    }
+
+    let policy = GcPolicy {
+        min_age: *gc.min_age,
+        max_size: gc.max_size.map(|size| size.as_u64()),
+    };
+
+    let items = collector.run(&policy)?;
+    let size = ByteSize(items.iter().map(|item| item.size).sum());
+
+    if builder.dry_run() {
+        info!("Would free {size} in {} entries", items.len());
+    } else {
+        info!("Freed {size} in {} entries", items.len());
+    }
+
+    Ok(())
+}
+
+fn diff_layers(builder: &RaptorBuilder, cmd: &DiffCmd) -> RaptorResult<()> {
+    let changes = LayerDiff::new(builder)
+        .with_merged(cmd.merged)
+        .diff(&cmd.old, &cmd.new)?;
+
+    if cmd.json {
+        println!("{}", serde_json::to_string_pretty(&changes)?);
+    } else {
+        LayerDiff::present(&changes);
+    }
+
+    Ok(())
+}
+
+fn update_lock(builder: &mut RaptorBuilder, cmd: &UpdateCmd) -> RaptorResult<()> {
+    let makefile = cmd.targets.is_empty() && cmd.file.exists();
+
+    if makefile {
+        builder
+            .loader_mut()
+            .resolver_mut()
+            .set_base(cmd.file.try_parent()?);
+        builder.set_lock_file(cmd.file.with_file_name(LockFile::FILE));
+    }
+
+    let builder = &*builder;
+    let mut sources = vec![];
+
+    if cmd.targets.is_empty() {
+        if makefile {
+            let maker = Maker::load(builder, &cmd.file)?;
+
+            maker.add_links(builder.loader());
+
+            maker.add_registries();
+            maker.add_credentials()?;
+
+            for job in maker.rules().run.values() {
+                let platform = maker.platform(job)?;
+                let inputs = job
+                    .input
+                    .iter()
+                    .map(|input| ModuleName::from(input.as_str()));
+                for target in std::iter::once(job.target.clone()).chain(inputs) {
+                    let program = builder.load(&target)?;
+                    sources.extend(builder.docker_sources(program, &platform)?);
+                }
+            }
+
+            for group in maker.rules().group.values() {
+                for target in &group.build {
+                    let program = builder.load(target)?;
+                    sources.extend(builder.docker_sources(program, builder.platform())?);
+                }
+            }
+        }
+
+        /* images that are locked, but no longer used by any known target,
+         * are kept up to date as well */
+        for name in builder.locked_images()? {
+            sources.push(dregistry::reference::parse(&name)?);
+        }
+    } else {
+        for target in &cmd.targets {
+            let program = builder.load(target)?;
+            sources.extend(builder.docker_sources(program, builder.platform())?);
+        }
+    }
+
+    builder.lock_docker_sources(sources, true)
+}
+
+/// Collect raptor files (`.rapt` and `.rinc`) in `path`, skipping hidden
+/// files and directories
+fn source_files(path: &Utf8Path, files: &mut Vec<Utf8PathBuf>) -> RaptorResult<()> {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+
+    let mut entries = path
+        .read_dir_utf8()?
+        .map(|dent| Ok(dent?.into_path()))
+        .collect::<RaptorResult<Vec<_>>>()?;
+    entries.sort();
+
+    for entry in entries {
+        if entry.file_name().is_some_and(|name| name.starts_with('.')) {
+            continue;
+        }
+
+        if entry.is_dir() {
+            source_files(&entry, files)?;
+        } else if matches!(entry.extension(), Some("rapt" | "rinc")) {
+            files.push(entry);
+        }
+    }
+
+    Ok(())
+}
+
+fn dump_json(builder: &RaptorBuilder, targets: &[ModuleName]) -> RaptorResult<()> {
+    let mut dump = Dump::new();
+
+    for target in targets {
+        let program = builder.load(target)?;
+        dump.add_target(builder.loader(), target.clone(), program);
+    }
+
+    serde_json::to_writer_pretty(stdout().lock(), &dump)?;
+    println!();
+
+    Ok(())
+}
+
+fn format_files(cmd: &FmtCmd, no_act: bool) -> RaptorResult<()> {
+    let mut files = vec![];
+
+    if cmd.paths.is_empty() {
+        source_files(Utf8Path::new("."), &mut files)?;
+    } else {
+        for path in &cmd.paths {
+            source_files(path, &mut files)?;
+        }
+    }
+
+    let mut unformatted = 0;
+    let mut invalid = 0;
+
+    for file in &files {
+        let source = fs::read_to_string(file)?;
+
+        /* report parse errors, but keep formatting the remaining files */
+        let output = match format::format(file.as_str(), &source) {
+            Ok(output) => output,
+            Err(err) => {
+                show_parse_error_context(&source, &err)?;
+                invalid += 1;
+                continue;
+            }
+        };
+
+        if output == source {
+            debug!("{file} is formatted");
+            continue;
+        }
+
+        unformatted += 1;
+
+        if cmd.check || no_act {
+            info!("Would format {file}");
+        } else {
+            info!("Formatting {file}");
+            fs::write(file, output)?;
+        }
+    }
+
+    /* the parse errors have been reported above already */
+    if invalid > 0 {
+        return Err(RaptorError::FormatParseError(invalid));
+    }
+
+    if cmd.check && unformatted > 0 {
+        return Err(RaptorError::FormatCheckError(unformatted));
+    }
+
+    Ok(())
+}
+
+fn parse_build_arg(arg: &str) -> Result<(String, String), &'static str> {
+    let (name, value) = arg
+        .split_once('=')
+        .ok_or("build arguments must be specified as name=value")?;
+
+    Ok((name.to_string(), value.to_string()))
+}
+
+#[allow(clippy::too_many_lines)]
 fn raptor() -> RaptorResult<()> {
     let args = Cli::parse();
 
     log::set_max_level(args.log_level());
 
+    /* these modes do not build anything, so they work without falcon */
+    match &args.mode {
+        Mode::Fmt(cmd) => return format_files(cmd, args.no_act),
+        Mode::Completion { shell } => {
+            clap_complete::generate(*shell, &mut Cli::command(), "raptor", &mut stdout());
+            return Ok(());
+        }
+        _ => {}
+    }
+
     let falcon_path = check_for_falcon_binary()?;
 
     let loader = Loader::new()?.with_dump(args.mode.dump());
@@ -288,10 +879,33 @@ fn raptor() -> RaptorResult<()> {
         loader.resolver().add_package(name.into(), path.into());
     }
 
-    let mut builder = RaptorBuilder::new(loader, falcon_path, args.no_act);
+    let mut builder =
+        RaptorBuilder::new(loader, falcon_path, args.no_act).with_platform(args.platform.clone());
+
+    builder.set_network(args.network.clone());
+    builder.set_reproducible(args.reproducible()?);
+
+    for secret in &args.secret {
+        builder.add_secret(&secret.id, secret.source.clone());
+    }
+
+    for (name, value) in &args.arg {
+        builder.set_arg(name, value);
+    }
+
+    if let Some(url) = &args.remote_cache {
+        builder.set_remote_cache(
+            RemoteCache::new(url.clone()).with_readonly(args.remote_cache_readonly),
+        );
+    }
 
     match &args.mode {
-        Mode::Dump { targets } | Mode::Check { targets } | Mode::Build { targets } => {
+        Mode::Dump {
+            targets,
+            format: DumpFormat::Json,
+        } => dump_json(&builder, targets)?,
+
+        Mode::Dump { targets, .. } | Mode::Check { targets } | Mode::Build { targets } => {
             for file in targets {
                 let program = builder.load(file)?;
 
@@ -299,7 +913,7 @@ fn raptor() -> RaptorResult<()> {
                     if !args.no_act {
                         check_for_root()?;
                     }
-                    builder.build_program(program)?;
+                    builder.build_program(program, builder.platform())?;
                 }
             }
 
@@ -308,42 +922,40 @@ fn raptor() -> RaptorResult<()> {
             }
         }
 
-        Mode::Run(run) => {
-            check_for_root()?;
-
-            let program = builder.load(&run.target)?;
+        Mode::Run(run) => run_target(&builder, run)?,
 
-            builder.build_program(program.clone())?;
-
-            let mut layers = vec![];
-
-            for target in builder.stack(program.clone())? {
-                layers.push(builder.layer_info(&target)?.done_path());
+        Mode::Export(export) => {
+            if !args.no_act {
+                check_for_root()?;
             }
 
-            let mut runner = Runner::new()?;
-
-            runner
-                .with_args(&run.args)
-                .with_env(&run.env)
-                .with_mounts(run.mounts());
+            let program = builder.load(&export.target)?;
+            Exporter::new(&builder).export(
+                program,
+                builder.platform(),
+                export.format,
+                &export.output,
+                export.tag.as_deref(),
+            )?;
+        }
 
-            if let Some(state_dir) = &run.state {
-                runner.with_state_dir(state_dir.clone());
+        Mode::Push(push) => {
+            if !args.no_act {
+                check_for_root()?;
             }
 
-            let res = runner.spawn(&program, &builder, &layers)?;
+            let image = push.image.strip_prefix("docker://").unwrap_or(&push.image);
+            let dest = RaptorBuilder::parse_docker_source(image)?;
 
-            if !res.success() {
-                error!("Run failed with status {}", res.code().unwrap_or_default());
-            }
+            let program = builder.load(&push.target)?;
+            Exporter::new(&builder).push(program, builder.platform(), &dest, &push.mount_from)?;
         }
 
         Mode::Show { dirs } => {
             let mut stats = BuildTargetStats::new();
             for target in dirs {
                 let program = builder.load(target)?;
-                let stack = builder.stack(program)?;
+                let stack = builder.stack(program, builder.platform())?;
 
                 stats.merge(stack)?;
             }
@@ -360,11 +972,17 @@ fn raptor() -> RaptorResult<()> {
                 .loader_mut()
                 .resolver_mut()
                 .set_base(file.try_parent()?);
+            builder.set_lock_file(file.with_file_name(LockFile::FILE));
             let maker = Maker::load(&builder, file)?;
 
             maker.add_links(builder.loader());
 
-            let mut planner = Planner::new(&maker, &builder);
+            maker.add_registries();
+            maker.add_credentials()?;
+            maker.add_secrets()?;
+            maker.add_remote_cache()?;
+
+            let mut planner = Planner::new(&maker, &builder).with_lock(true);
 
             if targets.is_empty() {
                 for target in maker.rules().run.keys() {
@@ -388,9 +1006,34 @@ fn raptor() -> RaptorResult<()> {
             }
         }
 
-        Mode::Completion { shell } => {
-            clap_complete::generate(*shell, &mut Cli::command(), "raptor", &mut stdout());
+        Mode::Cache {
+            action: CacheCmd::Verify,
+        } => {
+            let invalid = builder.downloader()?.verify_cache(!args.no_act)?;
+
+            if !invalid.is_empty() {
+                return Err(RaptorError::CacheVerifyError(invalid.len()));
+            }
+
+            info!("Download cache verified successfully.");
         }
+
+        Mode::Layers(cmd) => list_layers(&mut builder, cmd)?,
+
+        Mode::Update(cmd) => update_lock(&mut builder, cmd)?,
+
+        Mode::Gc(gc) => {
+            if !args.no_act {
+                check_for_root()?;
+            }
+
+            collect_garbage(&mut builder, gc)?;
+        }
+
+        Mode::Diff(cmd) => diff_layers(&builder, cmd)?,
+
+        /* handled above */
+        Mode::Fmt(_) | Mode::Completion { .. } => {}
     }
 
     Ok(())
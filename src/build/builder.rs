@@ -1,35 +1,58 @@
+use std::cmp::Reverse;
+use std::collections::HashSet;
 use std::fs::{self, File};
-use std::hash::{Hash, Hasher};
-use std::process::Command;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, OnceLock};
 use std::time::SystemTime;
 
-use camino::{Utf8Path, Utf8PathBuf};
+use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
 use colored::Colorize;
-use dashmap::DashSet;
+use dashmap::{DashMap, DashSet};
+use dregistry::credentials::{CredentialStore, Credentials};
+use dregistry::digest::Digest;
 use dregistry::downloader::DockerDownloader;
+use dregistry::extract::LayerExtractor;
+use dregistry::platform::Platform;
+use dregistry::registry::{RegistryConfig, RegistryStore};
 use dregistry::source::DockerSource;
-use siphasher::sip::SipHasher13;
+use itertools::Itertools;
 
-use crate::RaptorResult;
-use crate::build::{Cacher, LayerInfo};
-use crate::dsl::Program;
+use crate::build::{
+    Cacher, KeyHasher, LayerIndex, LayerInfo, LockFile, RemoteCache, Reproducible, SecretSource,
+    SecretStore,
+};
+use crate::dsl::{BuildArgs, FromParams, Program};
 use crate::program::{Executor, Loader, PrintExecutor};
-use crate::sandbox::Sandbox;
-use raptor_parser::ast::{FromSource, Origin};
+use crate::sandbox::{BindMount, Sandbox};
+use crate::{RaptorError, RaptorResult};
+use raptor_parser::ast::{FromSource, Instruction, NetworkMode, Origin};
+use raptor_parser::format;
+use raptor_parser::util::SafeParent;
 use raptor_parser::util::module_name::ModuleName;
 
+/// Root directory for persistent build cache mounts (`MOUNT --cache`)
+const CACHE_MOUNT_ROOT: &str = "cache/mounts";
+
 pub struct RaptorBuilder<'a> {
     loader: Loader<'a>,
     done: DashSet<u64>,
+    credentials: DashMap<String, Credentials>,
+    registries: DashMap<String, RegistryConfig>,
+    secrets: DashMap<String, SecretSource>,
+    remote_cache: OnceLock<RemoteCache>,
+    lock_path: Utf8PathBuf,
+    lock: Mutex<Option<LockFile>>,
     falcon_path: Utf8PathBuf,
     dry_run: bool,
+    platform: Platform,
+    network: NetworkMode,
+    args: BuildArgs,
+    reproducible: Option<Reproducible>,
 }
 
 #[derive(Debug, Clone)]
 pub enum BuildTarget {
-    Program(Arc<Program>),
-    DockerSource(DockerSource),
+    Program(Arc<Program>, Platform),
+    DockerSource(DockerSource, Platform),
 }
 
 trait DockerSourceExt {
@@ -61,14 +84,86 @@ impl<'a> RaptorBuilder<'a> {
         Self {
             loader,
             done: DashSet::new(),
+            credentials: DashMap::new(),
+            registries: DashMap::new(),
+            secrets: DashMap::new(),
+            remote_cache: OnceLock::new(),
+            lock_path: Utf8PathBuf::from(LockFile::FILE),
+            lock: Mutex::new(None),
             falcon_path,
             dry_run,
+            platform: Platform::default(),
+            network: NetworkMode::default(),
+            args: BuildArgs::new(),
+            reproducible: None,
+        }
+    }
+
+    #[must_use]
+    pub fn with_platform(mut self, platform: Platform) -> Self {
+        self.platform = platform;
+        self
+    }
+
+    /// Default network mode, for builds of targets without a `NETWORK`
+    /// instruction, and for runs
+    pub fn set_network(&mut self, network: NetworkMode) {
+        self.network = network;
+    }
+
+    #[must_use]
+    pub const fn network(&self) -> &NetworkMode {
+        &self.network
+    }
+
+    /// Set a build argument, for targets that declare it with `ARG`
+    pub fn set_arg(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        self.args.insert(name.into(), value.into());
+    }
+
+    /// Build arguments given to the builder (e.g. on the command line)
+    #[must_use]
+    pub const fn args(&self) -> &BuildArgs {
+        &self.args
+    }
+
+    /// Build reproducible layers (see [`Reproducible`])
+    pub const fn set_reproducible(&mut self, reproducible: Option<Reproducible>) {
+        self.reproducible = reproducible;
+    }
+
+    #[must_use]
+    pub const fn reproducible(&self) -> Option<&Reproducible> {
+        self.reproducible.as_ref()
+    }
+
+    /// Default platform, used for builds that do not specify one
+    #[must_use]
+    pub const fn platform(&self) -> &Platform {
+        &self.platform
+    }
+
+    /// Resolve the platform for a run target or `FROM` instruction, falling
+    /// back to `default` when not specified
+    pub fn resolve_platform(platform: Option<&str>, default: &Platform) -> RaptorResult<Platform> {
+        match platform {
+            Some(platform) => Ok(platform.parse()?),
+            None => Ok(default.clone()),
         }
     }
 
     pub fn load(&self, name: &ModuleName) -> RaptorResult<Arc<Program>> {
+        self.load_with_args(name, &self.args)
+    }
+
+    pub fn load_with_args(
+        &self,
+        name: &ModuleName,
+        args: &BuildArgs,
+    ) -> RaptorResult<Arc<Program>> {
         let origin = Origin::inline();
-        self.loader.load_program(name, origin)
+        self.loader
+            .load_program(name, &FromParams::new(), origin, args)
     }
 
     pub const fn loader<'b>(&'b self) -> &'b Loader<'a> {
@@ -84,33 +179,218 @@ impl<'a> RaptorBuilder<'a> {
         self.dry_run
     }
 
-    pub fn layer_info(&self, target: &BuildTarget) -> RaptorResult<LayerInfo> {
-        let name;
-        let hash;
+    pub fn add_credentials(&self, registry: &str, credentials: Credentials) {
+        self.credentials.insert(registry.to_string(), credentials);
+    }
+
+    pub fn add_registry(&self, registry: &str, config: RegistryConfig) {
+        self.registries.insert(registry.to_string(), config);
+    }
+
+    pub fn add_secret(&self, id: &str, source: SecretSource) {
+        self.secrets.insert(id.to_string(), source);
+    }
+
+    #[must_use]
+    pub fn has_secret(&self, id: &str) -> bool {
+        self.secrets.contains_key(id)
+    }
+
+    /// Use a shared layer cache. Only the first remote cache set is used, so
+    /// a remote cache given on the command line takes precedence.
+    pub fn set_remote_cache(&self, cache: RemoteCache) {
+        let _ = self.remote_cache.set(cache);
+    }
+
+    #[must_use]
+    pub fn remote_cache(&self) -> Option<&RemoteCache> {
+        self.remote_cache.get()
+    }
+
+    /// Use the lock file at `path` (instead of `Raptor.lock` in the current
+    /// directory)
+    pub fn set_lock_file(&mut self, path: impl Into<Utf8PathBuf>) {
+        self.lock_path = path.into();
+        *self.lock.get_mut().unwrap() = None;
+    }
+
+    /// Access the lock file, loading it on first use
+    fn with_lock<T>(&self, fun: impl FnOnce(&mut LockFile) -> T) -> RaptorResult<T> {
+        let mut lock = self.lock.lock().unwrap();
+
+        if lock.is_none() {
+            *lock = Some(LockFile::load(&self.lock_path)?);
+        }
+
+        Ok(fun(lock.as_mut().unwrap()))
+    }
+
+    /// Digest pinned for a docker source in the lock file, if any
+    pub fn locked_digest(&self, source: &DockerSource) -> RaptorResult<Option<Digest>> {
+        self.with_lock(|lock| lock.get(source).cloned())
+    }
+
+    /// Names of all images in the lock file
+    pub fn locked_images(&self) -> RaptorResult<Vec<String>> {
+        self.with_lock(|lock| lock.docker.keys().cloned().collect())
+    }
+
+    /// Pin a docker source to the digest recorded in the lock file, if any
+    pub fn pin_docker_source(&self, source: DockerSource) -> RaptorResult<DockerSource> {
+        self.with_lock(|lock| lock.pin(source))
+    }
+
+    /// Resolve the digests of docker sources, and record them in the lock
+    /// file. Unless `update` is set, sources that are already pinned are
+    /// left alone.
+    pub fn lock_docker_sources(
+        &self,
+        sources: impl IntoIterator<Item = DockerSource>,
+        update: bool,
+    ) -> RaptorResult<()> {
+        let mut changed = false;
+        let mut seen = HashSet::new();
 
+        for source in sources {
+            if source.digest.is_some() || !seen.insert(LockFile::key(&source)) {
+                continue;
+            }
+
+            let locked = self.locked_digest(&source)?;
+            if locked.is_some() && !update {
+                continue;
+            }
+
+            let digest = self.downloader()?.resolve(&source)?;
+
+            if locked.as_ref() == Some(&digest) {
+                debug!("Image {source} is up to date [{digest}]");
+                continue;
+            }
+
+            match &locked {
+                Some(old) => info!("Updating image {source}: {old} -> {digest}"),
+                None => info!("Locking image {source} to {digest}"),
+            }
+
+            self.with_lock(|lock| lock.insert(&source, digest))?;
+            changed = true;
+        }
+
+        if changed && !self.dry_run {
+            self.with_lock(|lock| lock.save(&self.lock_path))??;
+        }
+
+        Ok(())
+    }
+
+    /// Docker sources used by a program, and the programs it is built from
+    /// (as written, i.e. not pinned by the lock file)
+    pub fn docker_sources(
+        &self,
+        program: Arc<Program>,
+        platform: &Platform,
+    ) -> RaptorResult<Vec<DockerSource>> {
+        Ok(self
+            .unpinned_stack(program, platform)?
+            .into_iter()
+            .filter_map(|target| match target {
+                BuildTarget::DockerSource(source, _) => Some(source),
+                BuildTarget::Program(..) => None,
+            })
+            .collect())
+    }
+
+    /// Resolve (and record in the lock file) the docker sources used by a
+    /// program, that are not pinned yet
+    pub fn lock_program(&self, program: Arc<Program>, platform: &Platform) -> RaptorResult<()> {
+        if self.dry_run {
+            return Ok(());
+        }
+
+        self.lock_docker_sources(self.docker_sources(program, platform)?, false)
+    }
+
+    /// Read the secrets requested by `RUN --secret` instructions in `prog`.
+    ///
+    /// Secrets that were not supplied are skipped here, and reported by the
+    /// executor when the instruction that needs them is reached.
+    fn stage_secrets(&self, prog: &Program) -> RaptorResult<SecretStore> {
+        let mut store = SecretStore::new();
+
+        prog.traverse(&mut |stmt| {
+            if let Instruction::Run(inst) = &stmt.inst {
+                for secret in &inst.secrets {
+                    if let Some(source) = self.secrets.get(&secret.id) {
+                        store.add(&secret.id, &source)?;
+                    }
+                }
+            }
+            Ok(())
+        })?;
+
+        Ok(store)
+    }
+
+    pub fn downloader(&self) -> RaptorResult<DockerDownloader> {
+        let mut credentials = CredentialStore::from_env()?;
+        for item in &self.credentials {
+            credentials.insert(item.key(), item.value().clone());
+        }
+
+        let mut registries = RegistryStore::new();
+        for item in &self.registries {
+            registries.insert(item.key(), item.value().clone());
+        }
+
+        Ok(DockerDownloader::new(Utf8PathBuf::from("cache"))?
+            .with_credentials(credentials)
+            .with_registries(registries))
+    }
+
+    pub fn layer_info(&self, target: &BuildTarget) -> RaptorResult<LayerInfo> {
         match target {
-            BuildTarget::Program(prog) => {
+            BuildTarget::Program(prog, platform) => {
                 debug!(
                     "Calculating hash for layer {}",
                     &self.loader.resolver().path(&prog.path)
                 );
 
-                name = prog.path.file_stem().unwrap().into();
-                hash = Cacher::cache_key(prog, self)?;
+                /* the full program path, so equally named programs in
+                 * different directories are kept apart in the index */
+                let mut name = prog.path.with_extension("").into_string();
+                let key = Cacher::layer_key(prog, self, platform).or_else(|err| {
+                    self.loader.explain_error(&err, &[])?;
+                    Err(err)
+                })?;
+
+                /* each combination of FROM parameters is a separate layer */
+                if !prog.params.is_empty() {
+                    let params = prog
+                        .params
+                        .iter()
+                        .map(|(name, value)| format!("{name}={}", format::expression(value)))
+                        .join(",");
+                    name = format!("{name}({params})");
+                }
+
+                Ok(LayerInfo::new(name, key.digest)
+                    .with_legacy(prog.path.file_stem().unwrap(), key.legacy))
             }
 
-            BuildTarget::DockerSource(image) => {
-                debug!("Calculating hash for image {image}");
+            BuildTarget::DockerSource(image, platform) => {
+                debug!("Calculating hash for image {image} ({platform})");
+
+                let name = image.safe_file_name()?;
 
-                name = image.safe_file_name()?;
+                let mut state = KeyHasher::new();
+                state.value(image)?;
+                state.value(platform)?;
 
-                let mut state = SipHasher13::new();
-                image.hash(&mut state);
-                hash = state.finish();
+                Ok(LayerInfo::new(name.to_string(), state.finish())
+                    .with_legacy(name.as_str(), Cacher::legacy_image_key(image, platform)))
             }
         }
-
-        Ok(LayerInfo::new(name.to_string(), hash))
     }
 
     pub fn clear_cache(&mut self) {
@@ -129,26 +409,55 @@ impl<'a> RaptorBuilder<'a> {
         Ok(source)
     }
 
-    pub fn stack(&self, program: Arc<Program>) -> RaptorResult<Vec<BuildTarget>> {
+    /// Build targets for a program (the program, and everything it is built
+    /// from), bottom first. Docker sources are pinned by the lock file.
+    pub fn stack(
+        &self,
+        program: Arc<Program>,
+        platform: &Platform,
+    ) -> RaptorResult<Vec<BuildTarget>> {
+        self.unpinned_stack(program, platform)?
+            .into_iter()
+            .map(|target| match target {
+                BuildTarget::DockerSource(source, platform) => Ok(BuildTarget::DockerSource(
+                    self.pin_docker_source(source)?,
+                    platform,
+                )),
+                target @ BuildTarget::Program(..) => Ok(target),
+            })
+            .collect()
+    }
+
+    fn unpinned_stack(
+        &self,
+        program: Arc<Program>,
+        platform: &Platform,
+    ) -> RaptorResult<Vec<BuildTarget>> {
         let mut data: Vec<BuildTarget> = vec![];
 
         let mut next = Some(program);
 
         while let Some(prog) = next.take() {
-            data.push(BuildTarget::Program(prog.clone()));
+            data.push(BuildTarget::Program(prog.clone(), platform.clone()));
 
-            let Some((source, origin)) = prog.from() else {
+            let Some((inst, origin)) = prog.from_inst() else {
                 continue;
             };
 
-            match source {
+            match &inst.from {
                 FromSource::Docker(src) => {
                     let source = Self::parse_docker_source(src)?;
-                    data.push(BuildTarget::DockerSource(source));
+                    let platform = Self::resolve_platform(inst.platform.as_deref(), platform)?;
+                    data.push(BuildTarget::DockerSource(source, platform));
                 }
 
                 FromSource::Raptor(from) => {
-                    let fromprog = self.loader.load_program(from, origin.clone())?;
+                    let fromprog = self.loader.load_program(
+                        from,
+                        &prog.from_params()?,
+                        origin.clone(),
+                        &prog.args,
+                    )?;
 
                     next = Some(fromprog);
                 }
@@ -162,49 +471,138 @@ impl<'a> RaptorBuilder<'a> {
 
     fn simulate(target: &BuildTarget) -> RaptorResult<()> {
         match target {
-            BuildTarget::Program(prog) => PrintExecutor::new().run(prog)?,
-            BuildTarget::DockerSource(image) => info!("Would download docker image [{image}]"),
+            BuildTarget::Program(prog, _) => PrintExecutor::new().run(prog)?,
+            BuildTarget::DockerSource(image, platform) => {
+                info!("Would download docker image [{image}] ({platform})");
+            }
         }
 
         Ok(())
     }
 
-    fn build(
+    /// Host directory for the cache mount `name`, created if missing
+    pub fn cache_mount_dir(name: &str) -> RaptorResult<Utf8PathBuf> {
+        /* the name must not escape the cache mount root */
+        let mut components = Utf8Path::new(name).components();
+        let (Some(Utf8Component::Normal(_)), None) = (components.next(), components.next()) else {
+            return Err(RaptorError::InvalidCacheMountName(name.to_string()));
+        };
+
+        let dir = Utf8Path::new(CACHE_MOUNT_ROOT).join(name);
+        fs::create_dir_all(&dir)?;
+
+        /* systemd-nspawn requires absolute paths for bind mounts */
+        Ok(dir.canonicalize_utf8()?)
+    }
+
+    /// Find the components of the mount destination `dest`, that do not
+    /// exist in any layer yet (deepest first). These will be created by
+    /// systemd-nspawn, as mount points.
+    fn missing_mount_points(
+        layers: &[Utf8PathBuf],
+        rootdir: &Utf8Path,
+        dest: &Utf8Path,
+    ) -> Vec<Utf8PathBuf> {
+        let dest = dest.strip_prefix("/").unwrap_or(dest);
+
+        dest.ancestors()
+            .filter(|path| !path.as_str().is_empty())
+            .take_while(|path| {
+                !layers
+                    .iter()
+                    .map(Utf8PathBuf::as_path)
+                    .chain([rootdir])
+                    .any(|layer| layer.join(path).symlink_metadata().is_ok())
+            })
+            .map(Utf8Path::to_path_buf)
+            .collect()
+    }
+
+    /// Run a program in a build sandbox, with its cache mounts and secrets
+    /// attached
+    fn build_program_layer(
         &self,
-        target: &BuildTarget,
+        prog: &Program,
         layers: &[Utf8PathBuf],
         rootdir: &Utf8Path,
     ) -> RaptorResult<()> {
-        match target {
-            BuildTarget::Program(prog) => {
-                let sandbox = Sandbox::new(layers, rootdir, &self.falcon_path)?;
+        let mounts = prog.cache_mounts();
+
+        let mut spawn = Sandbox::builder();
+        let mut mount_points = vec![];
+
+        for mount in &mounts {
+            let src = Self::cache_mount_dir(&mount.name)?;
+            debug!("Using cache mount {src} at {}", mount.dest);
+
+            mount_points.extend(Self::missing_mount_points(layers, rootdir, &mount.dest));
+
+            let bind = BindMount::new(src, &mount.dest);
+            spawn = if mount.opts.readonly {
+                spawn.bind_ro(bind)
+            } else {
+                spawn.bind(bind)
+            };
+        }
+
+        let secrets = self.stage_secrets(prog)?;
+
+        let network = prog.network()?.unwrap_or_else(|| self.network.clone());
+        debug!("Using network mode {network}");
+        spawn = spawn.network(network);
+
+        if let Some(repro) = &self.reproducible {
+            spawn = spawn.setenv(Reproducible::ENV, &repro.epoch().to_string());
+        }
+
+        let sandbox = Sandbox::custom(spawn, layers, rootdir, &self.falcon_path)?;
+
+        let mut exec = Executor::new(sandbox).with_secrets(secrets);
+
+        exec.run(&self.loader, prog)?;
 
-                let mut exec = Executor::new(sandbox);
+        exec.finish()?;
 
-                exec.run(&self.loader, prog)?;
+        /* keep the (empty) mount points out of the layer, unless something
+         * else was placed in them */
+        mount_points.sort_by_key(|path| Reverse(path.components().count()));
 
-                exec.finish()?;
+        for path in mount_points {
+            let path = rootdir.join(path);
+            if path.is_dir() && path.read_dir_utf8()?.next().is_none() {
+                fs::remove_dir(path)?;
+            }
+        }
+
+        if let Some(repro) = &self.reproducible {
+            repro.normalize(rootdir)?;
+        }
+
+        Ok(())
+    }
+
+    fn build(
+        &self,
+        target: &BuildTarget,
+        layers: &[Utf8PathBuf],
+        rootdir: &Utf8Path,
+    ) -> RaptorResult<()> {
+        match target {
+            BuildTarget::Program(prog, _) => {
+                self.build_program_layer(prog, layers, rootdir)?;
             }
 
-            BuildTarget::DockerSource(image) => {
+            BuildTarget::DockerSource(image, platform) => {
                 fs::create_dir_all(rootdir)?;
 
-                let dc = DockerDownloader::new(Utf8PathBuf::from("cache"))?;
+                let dc = self.downloader()?;
 
-                let layers = dc.pull(image, "linux", "amd64")?;
+                let layers = dc.pull(image, platform)?;
 
                 for layer in layers {
                     info!("Extracting layer [{layer}]");
 
-                    let filename = dc.layer_file_name(&layer);
-
-                    Command::new("tar")
-                        .arg("-x")
-                        .arg("-C")
-                        .arg(rootdir)
-                        .arg("-f")
-                        .arg(filename)
-                        .status()?;
+                    LayerExtractor::new(rootdir).apply_file(&dc.layer_file_name(&layer))?;
                 }
             }
         }
@@ -212,6 +610,97 @@ impl<'a> RaptorBuilder<'a> {
         Ok(())
     }
 
+    /// Move a layer from the legacy layer cache into the store, if present
+    fn migrate_layer(legacy_path: &Utf8Path, done_path: &Utf8Path) -> RaptorResult<bool> {
+        if !fs::exists(legacy_path)? {
+            return Ok(false);
+        }
+
+        debug!("Migrating legacy layer {legacy_path} -> {done_path}");
+
+        fs::create_dir_all(done_path.try_parent()?)?;
+        fs::rename(legacy_path, done_path)?;
+
+        Ok(true)
+    }
+
+    /// Atomically move a finished layer from its work directory into the
+    /// store. If the same layer was published concurrently, the work
+    /// directory is discarded instead.
+    fn publish_layer(work_path: &Utf8Path, done_path: &Utf8Path) -> RaptorResult<()> {
+        debug!("Layer finished. Moving {work_path} -> {done_path}");
+
+        File::open(work_path)?.set_modified(SystemTime::now())?;
+        fs::create_dir_all(done_path.try_parent()?)?;
+
+        if let Err(err) = fs::rename(work_path, done_path) {
+            if !fs::exists(done_path)? {
+                return Err(err.into());
+            }
+
+            debug!("Layer {done_path} already published, discarding {work_path}");
+            fs::remove_dir_all(work_path)?;
+        }
+
+        Ok(())
+    }
+
+    /// Try to fetch a missing layer from the remote cache (if any) into the
+    /// store. Failing to fetch is not fatal, since the layer can still be
+    /// built locally.
+    fn fetch_remote(&self, layer: &LayerInfo) -> RaptorResult<bool> {
+        let Some(remote) = self.remote_cache() else {
+            return Ok(false);
+        };
+
+        let work_path = layer.work_path();
+        if fs::exists(&work_path)? {
+            fs::remove_dir_all(&work_path)?;
+        }
+
+        match remote.fetch(self, layer, &work_path) {
+            Ok(true) => {}
+            Ok(false) => return Ok(false),
+            Err(err) => {
+                warn!(
+                    "Could not fetch layer {} from {}: {err}",
+                    layer.hash(),
+                    remote.url()
+                );
+                if fs::exists(&work_path)? {
+                    fs::remove_dir_all(&work_path)?;
+                }
+                return Ok(false);
+            }
+        }
+
+        Self::publish_layer(&work_path, &layer.done_path())?;
+
+        Ok(true)
+    }
+
+    /// Upload a freshly built layer to the remote cache (if any, and not
+    /// read-only). Failing to upload is not fatal.
+    fn store_remote(&self, layer: &LayerInfo) {
+        let Some(remote) = self.remote_cache() else {
+            return;
+        };
+
+        if remote.readonly() {
+            return;
+        }
+
+        debug!("Uploading layer {} to {}", layer.hash(), remote.url());
+
+        if let Err(err) = remote.store(self, layer, &layer.done_path()) {
+            warn!(
+                "Could not upload layer {} to {}: {err}",
+                layer.hash(),
+                remote.url()
+            );
+        }
+    }
+
     pub fn build_layer(
         &self,
         layers: &[Utf8PathBuf],
@@ -225,15 +714,32 @@ impl<'a> RaptorBuilder<'a> {
         }
 
         let layer_name = layer.name().to_string();
-        let work_path = layer.work_path();
 
-        if fs::exists(layer.done_path())? {
+        if fs::exists(&done_path)? {
             info!(
                 "{} [{}] {}",
                 "Completed".bright_white(),
                 layer.hash().dimmed(),
                 layer_name.yellow()
             );
+        } else if !self.dry_run
+            && layer.legacy_path().map_or(Ok(false), |legacy_path| {
+                Self::migrate_layer(&legacy_path, &done_path)
+            })?
+        {
+            info!(
+                "{} [{}] {}",
+                "Migrated".bright_white(),
+                layer.hash().dimmed(),
+                layer_name.yellow()
+            );
+        } else if !self.dry_run && self.fetch_remote(layer)? {
+            info!(
+                "{} [{}] {}",
+                "Fetched".bright_white(),
+                layer.hash().dimmed(),
+                layer_name.yellow()
+            );
         } else {
             info!(
                 "{} {}: {}",
@@ -246,20 +752,28 @@ impl<'a> RaptorBuilder<'a> {
                 Self::simulate(prog)?;
             } else {
                 self.build(prog, layers, &layer.work_path())?;
-
-                debug!("Layer {layer_name} finished. Moving {work_path} -> {done_path}");
-                File::open(&work_path)?.set_modified(SystemTime::now())?;
-                fs::rename(&work_path, &done_path)?;
+                Self::publish_layer(&layer.work_path(), &done_path)?;
+                self.store_remote(layer);
             }
         }
 
+        if !self.dry_run {
+            LayerIndex::update(Utf8Path::new(LayerInfo::ROOT), |index| {
+                index.insert(layer);
+            })?;
+        }
+
         self.done.insert(layer.hash_value());
 
         Ok(done_path)
     }
 
-    pub fn build_program(&self, program: Arc<Program>) -> RaptorResult<Vec<Utf8PathBuf>> {
-        let programs = self.stack(program)?;
+    pub fn build_program(
+        &self,
+        program: Arc<Program>,
+        platform: &Platform,
+    ) -> RaptorResult<Vec<Utf8PathBuf>> {
+        let programs = self.stack(program, platform)?;
 
         let mut layers: Vec<Utf8PathBuf> = vec![];
 
@@ -272,3 +786,98 @@ impl<'a> RaptorBuilder<'a> {
         Ok(layers)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use camino::Utf8Path;
+    use camino_tempfile::Utf8TempDir;
+
+    use crate::build::RaptorBuilder;
+    use crate::{RaptorError, RaptorResult};
+
+    #[test]
+    fn cache_mount_points() -> RaptorResult<()> {
+        let tempdir = Utf8TempDir::new()?;
+        let lower = tempdir.path().join("lower");
+        let rootdir = tempdir.path().join("work");
+
+        fs::create_dir_all(lower.join("var/cache"))?;
+        fs::create_dir_all(rootdir.join("opt"))?;
+
+        let layers = [lower];
+        let missing = |dest: &str| {
+            RaptorBuilder::missing_mount_points(&layers, &rootdir, Utf8Path::new(dest))
+        };
+
+        assert_eq!(
+            missing("/var/cache/apt/archives"),
+            ["var/cache/apt/archives", "var/cache/apt"]
+        );
+        assert_eq!(missing("/opt/cache"), ["opt/cache"]);
+        assert_eq!(missing("/srv/cache"), ["srv/cache", "srv"]);
+        assert!(missing("/var/cache").is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn cache_mount_name() {
+        for name in ["", ".", "..", "../x", "a/b", "/abs", "a/.."] {
+            assert!(
+                matches!(
+                    RaptorBuilder::cache_mount_dir(name),
+                    Err(RaptorError::InvalidCacheMountName(_))
+                ),
+                "{name:?} should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn migrate_layer() -> RaptorResult<()> {
+        let tempdir = Utf8TempDir::new()?;
+        let legacy = tempdir.path().join("app-0123456789ABCDEF");
+        let done = tempdir.path().join("sha256/0123");
+
+        assert!(!RaptorBuilder::migrate_layer(&legacy, &done)?);
+        assert!(!done.exists());
+
+        fs::create_dir(&legacy)?;
+        fs::write(legacy.join("file"), "data")?;
+
+        assert!(RaptorBuilder::migrate_layer(&legacy, &done)?);
+        assert!(!legacy.exists());
+        assert_eq!(fs::read_to_string(done.join("file"))?, "data");
+
+        Ok(())
+    }
+
+    #[test]
+    fn publish_layer() -> RaptorResult<()> {
+        let tempdir = Utf8TempDir::new()?;
+        let work = tempdir.path().join("build-0123");
+        let done = tempdir.path().join("sha256/0123");
+
+        fs::create_dir(&work)?;
+        fs::write(work.join("file"), "first")?;
+
+        RaptorBuilder::publish_layer(&work, &done)?;
+        assert!(!work.exists());
+        assert_eq!(fs::read_to_string(done.join("file"))?, "first");
+
+        /* a layer published concurrently is kept, and the work dir discarded */
+        fs::create_dir(&work)?;
+        fs::write(work.join("file"), "second")?;
+
+        RaptorBuilder::publish_layer(&work, &done)?;
+        assert!(!work.exists());
+        assert_eq!(fs::read_to_string(done.join("file"))?, "first");
+
+        /* a missing work dir is still an error */
+        RaptorBuilder::publish_layer(&work, &done).unwrap_err();
+
+        Ok(())
+    }
+}
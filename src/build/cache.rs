@@ -2,31 +2,69 @@ use std::collections::HashSet;
 use std::fs::File;
 use std::hash::{Hash, Hasher};
 use std::io::Read;
+use std::os::unix::fs::PermissionsExt;
 use std::sync::Arc;
 
 use camino::{Utf8Path, Utf8PathBuf};
+use dregistry::digest::Digest;
+use dregistry::platform::Platform;
+use dregistry::source::DockerSource;
 use itertools::Itertools;
+use serde::Serialize;
+use sha2::{Digest as _, Sha256};
 use siphasher::sip::SipHasher13;
 
 use crate::build::{BuildTarget, RaptorBuilder};
 use crate::dsl::{Item, Program};
+use crate::program::Loader;
 use crate::{RaptorError, RaptorResult};
-use raptor_parser::ast::{FromSource, Instruction, Statement};
+use raptor_parser::ast::{FromSource, Instruction, NetworkMode, Statement};
 
 pub struct Cacher;
 
 impl Cacher {
-    fn hash_file(path: &Utf8Path, state: &mut impl Hasher) -> RaptorResult<()> {
-        let mut file = File::open(path)?;
+    /// Hash a source path. Directories are hashed recursively (including the
+    /// names of all entries), and symlinks are hashed by their target. The
+    /// permission bits are included, since `COPY` preserves them.
+    fn hash_source(path: &Utf8Path, state: &mut KeyHasher) -> RaptorResult<()> {
+        let md = path.symlink_metadata()?;
+        state.u32(md.permissions().mode() & 0o7777);
+
+        if md.is_symlink() {
+            state.value(&path.read_link_utf8()?)?;
+        } else if md.is_dir() {
+            for entry in Self::dir_entries(path)? {
+                state.value(&entry.file_name())?;
+                Self::hash_source(&entry, state)?;
+            }
+        } else {
+            state.file(path)?;
+        }
 
-        let mut buf = vec![0; 128 * 1024];
-        loop {
-            match file.read(&mut buf)? {
-                0 => break,
-                n => &buf[..n].hash(state),
-            };
+        Ok(())
+    }
+
+    fn dir_entries(path: &Utf8Path) -> RaptorResult<Vec<Utf8PathBuf>> {
+        Ok(path
+            .read_dir_utf8()?
+            .map(|dent| Ok(dent?.into_path()))
+            .collect::<RaptorResult<Vec<_>>>()?
+            .into_iter()
+            .sorted()
+            .collect())
+    }
+
+    /// Expand a source path into itself, and (for directories) every path
+    /// below it
+    fn expand_source(path: Utf8PathBuf, out: &mut Vec<Utf8PathBuf>) -> RaptorResult<()> {
+        if path.symlink_metadata().is_ok_and(|md| md.is_dir()) {
+            for entry in Self::dir_entries(&path)? {
+                Self::expand_source(entry, out)?;
+            }
         }
 
+        out.push(path);
+
         Ok(())
     }
 
@@ -38,11 +76,15 @@ impl Cacher {
             | Instruction::Mkdir(_)
             | Instruction::Run(_)
             | Instruction::Env(_)
-            | Instruction::Workdir(_) => true,
+            | Instruction::Workdir(_)
+            | Instruction::User(_)
+            | Instruction::Network(_) => true,
 
+            /* build arguments are hashed with their values (see layer_key) */
             Instruction::From(_)
             | Instruction::Mount(_)
             | Instruction::Include(_)
+            | Instruction::Arg(_)
             | Instruction::Entrypoint(_)
             | Instruction::Cmd(_) => false,
         }
@@ -57,49 +99,240 @@ impl Cacher {
         }
     }
 
-    pub fn cache_key(program: &Arc<Program>, builder: &RaptorBuilder<'_>) -> RaptorResult<u64> {
-        let mut state = SipHasher13::new();
+    /// Calculate the cache key for a program, including the keys of all
+    /// programs it is built `FROM`
+    pub fn layer_key(
+        program: &Arc<Program>,
+        builder: &RaptorBuilder<'_>,
+        platform: &Platform,
+    ) -> RaptorResult<LayerKey> {
+        let mut state = KeyHasher::new();
+        let mut parent = None;
+
+        /* reproducible layers differ from regular ones (timestamps), so keep
+         * them apart in the cache */
+        if let Some(repro) = builder.reproducible() {
+            state.value(repro)?;
+        }
+
+        /* the network mode (from `NETWORK`, or the builder default) can
+         * change the result of `RUN` instructions. The default (host) is not
+         * hashed, so existing layers keep their ids */
+        let network = program
+            .network()?
+            .unwrap_or_else(|| builder.network().clone());
+        if network != NetworkMode::default() {
+            state.value(&network)?;
+        }
 
-        if let Some((from, origin)) = program.from() {
-            match from {
+        if let Some((inst, origin)) = program.from_inst() {
+            match &inst.from {
                 FromSource::Raptor(from) => {
-                    let prog = builder.loader().load_program(from, origin.clone())?;
-                    Self::cache_key(&prog, builder)?.hash(&mut state);
+                    let prog = builder.loader().load_program(
+                        from,
+                        &program.from_params()?,
+                        origin.clone(),
+                        &program.args,
+                    )?;
+                    let key = Self::layer_key(&prog, builder, platform)?;
+                    state.parent(&key);
+                    parent = Some(key);
+                }
+                FromSource::Docker(src) => {
+                    state.value(src)?;
+                    state.value(&RaptorBuilder::resolve_platform(
+                        inst.platform.as_deref(),
+                        platform,
+                    )?)?;
+
+                    /* images pinned by the lock file are rebuilt when the
+                     * pinned digest changes */
+                    let source = RaptorBuilder::parse_docker_source(src)?;
+                    if let Some(digest) = builder.locked_digest(&source)? {
+                        state.value(&digest)?;
+                    }
                 }
-                FromSource::Docker(src) => src.hash(&mut state),
             }
         }
 
         let mut code = vec![];
         Self::flatten_program(program, &mut code);
 
-        code.iter()
-            .as_ref()
-            .iter()
-            .filter(|stmt| Self::include_in_build_hash(stmt))
-            .for_each(|stmt| stmt.inst.hash(&mut state));
+        for stmt in code.iter().filter(|stmt| Self::include_in_build_hash(stmt)) {
+            state.value(&stmt.inst)?;
+        }
+
+        /* different build argument values produce different layers */
+        let arg_values = program.arg_values();
+        if !arg_values.is_empty() {
+            state.value(&arg_values)?;
+        }
+
+        /* as does each combination of FROM parameters */
+        if !program.params.is_empty() {
+            state.value(&program.params)?;
+        }
 
-        for source in &Self::sources(program)? {
+        let resolver = builder.loader().resolver();
+        for source in &Self::sources(program, builder.loader())? {
             trace!("Checking source [{source}]");
+            /* file names matter for copies into directories (and globs) */
+            state.value(&source.file_name())?;
+            Self::hash_source(&resolver.path(source), &mut state)?;
+        }
+
+        Ok(LayerKey {
+            digest: state.finish(),
+            legacy: Self::legacy_key(program, builder, platform, parent.as_ref())?,
+        })
+    }
+
+    /// Discriminant of `RUN` in the `Instruction` enum of the legacy layer
+    /// cache, which is hashed as part of the instruction
+    const LEGACY_RUN: isize = 7;
+
+    /// Calculate the hash of a program layer in the legacy layer cache.
+    ///
+    /// This is a frozen copy of the algorithm used before the
+    /// content-addressed store was introduced, and must never change. Layers
+    /// using any build input that did not exist back then cannot be in the
+    /// legacy layer cache, so they have no legacy hash.
+    fn legacy_key(
+        program: &Program,
+        builder: &RaptorBuilder<'_>,
+        platform: &Platform,
+        parent: Option<&LayerKey>,
+    ) -> RaptorResult<Option<u64>> {
+        if builder.reproducible().is_some()
+            || *builder.network() != NetworkMode::default()
+            || *platform != Platform::default()
+            || !program.arg_values().is_empty()
+            || !program.params.is_empty()
+        {
+            return Ok(None);
+        }
+
+        let mut state = SipHasher13::new();
+
+        if let Some((inst, _)) = program.from_inst() {
+            if inst.platform.is_some() || !inst.args.is_empty() {
+                return Ok(None);
+            }
+
+            match &inst.from {
+                FromSource::Raptor(_) => match parent.and_then(|key| key.legacy) {
+                    Some(hash) => hash.hash(&mut state),
+                    None => return Ok(None),
+                },
+                FromSource::Docker(src) => {
+                    let source = RaptorBuilder::parse_docker_source(src)?;
+                    if builder.locked_digest(&source)?.is_some() {
+                        return Ok(None);
+                    }
+                    src.hash(&mut state);
+                }
+            }
+        }
+
+        let mut code = vec![];
+        Self::flatten_program(program, &mut code);
+
+        for stmt in code {
+            match &stmt.inst {
+                Instruction::Copy(_)
+                | Instruction::Render(_)
+                | Instruction::Write(_)
+                | Instruction::Mkdir(_)
+                | Instruction::Env(_)
+                | Instruction::Workdir(_) => stmt.inst.hash(&mut state),
+
+                /* secrets were added to `RUN` later, so hash it field by
+                 * field, as the derived implementation did */
+                Instruction::Run(inst) => {
+                    if !inst.secrets.is_empty() {
+                        return Ok(None);
+                    }
+                    Self::LEGACY_RUN.hash(&mut state);
+                    inst.run.hash(&mut state);
+                }
+
+                Instruction::User(_) | Instruction::Network(_) | Instruction::Arg(_) => {
+                    return Ok(None);
+                }
+
+                Instruction::From(_)
+                | Instruction::Mount(_)
+                | Instruction::Include(_)
+                | Instruction::Entrypoint(_)
+                | Instruction::Cmd(_) => {}
+            }
+        }
+
+        let mut sources = HashSet::new();
+        program.traverse(&mut |stmt| {
+            match &stmt.inst {
+                Instruction::Copy(inst) => {
+                    for src in &inst.srcs {
+                        sources.insert(stmt.origin.path_for(src)?);
+                    }
+                }
+                Instruction::Render(inst) => {
+                    sources.insert(stmt.origin.path_for(&inst.src)?);
+                }
+                _ => {}
+            }
+
+            Ok(())
+        })?;
+
+        /* only plain files (no globs or directories) could be copied */
+        for source in sources.into_iter().sorted() {
             let path = builder.loader().resolver().path(source);
-            Self::hash_file(&path, &mut state)?;
+            if !path.is_file() {
+                return Ok(None);
+            }
+
+            let mut file = File::open(path)?;
+            let mut buf = vec![0; 128 * 1024];
+            loop {
+                match file.read(&mut buf)? {
+                    0 => break,
+                    n => buf[..n].hash(&mut state),
+                }
+            }
         }
 
-        Ok(state.finish())
+        Ok(Some(state.finish()))
+    }
+
+    /// Calculate the hash of a docker image layer in the legacy layer cache,
+    /// where all images were pulled for the default platform
+    #[must_use]
+    pub fn legacy_image_key(image: &DockerSource, platform: &Platform) -> Option<u64> {
+        (*platform == Platform::default()).then(|| {
+            let mut state = SipHasher13::new();
+            image.hash(&mut state);
+            state.finish()
+        })
+    }
+
+    pub fn cache_key(
+        program: &Arc<Program>,
+        builder: &RaptorBuilder<'_>,
+        platform: &Platform,
+    ) -> RaptorResult<Digest> {
+        Ok(Self::layer_key(program, builder, platform)?.digest)
     }
 
-    pub fn sources(prog: &Program) -> RaptorResult<Vec<Utf8PathBuf>> {
+    pub fn sources(prog: &Program, loader: &Loader) -> RaptorResult<Vec<Utf8PathBuf>> {
         let mut data = HashSet::<Utf8PathBuf>::new();
 
         prog.traverse(&mut |stmt| {
             match &stmt.inst {
                 Instruction::Copy(inst) => {
-                    data.extend(
-                        inst.srcs
-                            .iter()
-                            .map(|file| Ok(stmt.origin.path_for(file)?))
-                            .collect::<Result<Vec<_>, RaptorError>>()?,
-                    );
+                    for src in &inst.srcs {
+                        data.extend(loader.resolver().glob(&stmt.origin, src)?);
+                    }
                 }
 
                 Instruction::Render(inst) => {
@@ -114,6 +347,9 @@ impl Cacher {
                 | Instruction::Run(_)
                 | Instruction::Env(_)
                 | Instruction::Workdir(_)
+                | Instruction::User(_)
+                | Instruction::Network(_)
+                | Instruction::Arg(_)
                 | Instruction::Entrypoint(_)
                 | Instruction::Cmd(_) => {}
             }
@@ -124,12 +360,17 @@ impl Cacher {
         Ok(data.into_iter().sorted().collect())
     }
 
-    pub fn all_sources(prog: &Program, builder: &RaptorBuilder) -> RaptorResult<Vec<Utf8PathBuf>> {
+    pub fn all_sources(
+        prog: &Program,
+        builder: &RaptorBuilder,
+        platform: &Platform,
+    ) -> RaptorResult<Vec<Utf8PathBuf>> {
         let resolver = builder.loader().resolver();
-        let mut data: Vec<_> = Self::sources(prog)?
-            .iter()
-            .map(|x| resolver.path(x))
-            .collect();
+        let mut data = vec![];
+
+        for source in Self::sources(prog, builder.loader())? {
+            Self::expand_source(resolver.path(source), &mut data)?;
+        }
 
         data.push(resolver.path(&prog.path));
 
@@ -154,8 +395,12 @@ impl Cacher {
                         data.push(path);
                     }
                     FromSource::Docker(src) => {
-                        let source = RaptorBuilder::parse_docker_source(src)?;
-                        let info = builder.layer_info(&BuildTarget::DockerSource(source))?;
+                        let source =
+                            builder.pin_docker_source(RaptorBuilder::parse_docker_source(src)?)?;
+                        let platform =
+                            RaptorBuilder::resolve_platform(inst.platform.as_deref(), platform)?;
+                        let info =
+                            builder.layer_info(&BuildTarget::DockerSource(source, platform))?;
                         data.push(info.done_path());
                     }
                 },
@@ -170,18 +415,120 @@ impl Cacher {
     }
 }
 
+/// Cache key of a layer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayerKey {
+    /// Content address (sha256) of the build inputs
+    pub digest: Digest,
+
+    /// Hash used by the legacy layer cache (`layers/<name>-<hash>`), if the
+    /// layer could have been built before the content-addressed store was
+    /// introduced
+    pub legacy: Option<u64>,
+}
+
+/// Hasher for layer cache keys.
+///
+/// The digest is calculated over a canonical encoding of the inputs (values
+/// as length-prefixed JSON, integers as fixed-width little-endian, and the raw
+/// contents of files), so it does not depend on the host platform, or on the
+/// implementation details of [`Hash`].
+pub struct KeyHasher {
+    sha: Sha256,
+}
+
+impl KeyHasher {
+    #[must_use]
+    pub fn new() -> Self {
+        Self { sha: Sha256::new() }
+    }
+
+    fn length(&mut self, len: u64) {
+        self.sha.update(len.to_le_bytes());
+    }
+
+    /// Include a value, by its JSON encoding
+    pub fn value<T: Serialize + ?Sized>(&mut self, value: &T) -> RaptorResult<()> {
+        let data = serde_json::to_vec(value)?;
+        self.length(data.len() as u64);
+        self.sha.update(&data);
+
+        Ok(())
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.sha.update(value.to_le_bytes());
+    }
+
+    /// Include the contents of a file
+    pub fn file(&mut self, path: &Utf8Path) -> RaptorResult<()> {
+        let mut file = File::open(path)?;
+        self.length(file.metadata()?.len());
+
+        let mut buf = vec![0; 128 * 1024];
+        loop {
+            match file.read(&mut buf)? {
+                0 => break,
+                n => self.sha.update(&buf[..n]),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Include the cache key of a parent layer
+    pub fn parent(&mut self, key: &LayerKey) {
+        let Digest::Sha256(hash) = &key.digest;
+        self.sha.update(hash);
+    }
+
+    #[must_use]
+    pub fn finish(self) -> Digest {
+        Digest::Sha256(self.sha.finalize().into())
+    }
+}
+
+impl Default for KeyHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct LayerInfo {
     name: String,
-    hash: u64,
+    digest: Digest,
+    legacy: Option<String>,
 }
 
 impl LayerInfo {
+    /// Number of hex digits shown for abbreviated layer hashes
     pub const HASH_WIDTH: usize = 16;
 
+    /// Directory containing all layers (and layer work directories)
+    pub const ROOT: &str = "layers";
+
+    /// Directory (below [`LayerInfo::ROOT`]) of the content-addressed store
+    pub const STORE: &str = "sha256";
+
+    /// Name prefix of layer work directories
+    pub const WORK_PREFIX: &str = "build-";
+
+    #[must_use]
+    pub const fn new(name: String, digest: Digest) -> Self {
+        Self {
+            name,
+            digest,
+            legacy: None,
+        }
+    }
+
+    /// Set the name and hash of the same layer in the legacy layer cache (if
+    /// it can be there), so it can be migrated to the store
     #[must_use]
-    pub const fn new(name: String, hash: u64) -> Self {
-        Self { name, hash }
+    pub fn with_legacy(mut self, name: &str, hash: Option<u64>) -> Self {
+        self.legacy = hash.map(|hash| format!("{name}-{hash:0width$X}", width = Self::HASH_WIDTH));
+        self
     }
 
     #[must_use]
@@ -190,74 +537,156 @@ impl LayerInfo {
     }
 
     #[must_use]
-    pub const fn hash_value(&self) -> u64 {
-        self.hash
+    pub const fn digest(&self) -> &Digest {
+        &self.digest
     }
 
+    const fn bytes(&self) -> &[u8; 32] {
+        let Digest::Sha256(hash) = &self.digest;
+        hash
+    }
+
+    /// Short numeric id of the layer, for use within a single build
+    #[must_use]
+    pub fn hash_value(&self) -> u64 {
+        let (head, _) = self.bytes().split_first_chunk().unwrap_or((&[0; 8], &[]));
+        u64::from_be_bytes(*head)
+    }
+
+    /// Abbreviated layer hash, for display
     #[must_use]
     pub fn hash(&self) -> String {
-        format!("{:0width$X}", self.hash, width = Self::HASH_WIDTH)
+        self.id()[..Self::HASH_WIDTH].to_string()
     }
 
+    /// Full layer id (hex encoded sha256 digest)
     #[must_use]
     pub fn id(&self) -> String {
-        format!(
-            "{}-{:0width$X}",
-            self.name,
-            self.hash,
-            width = Self::HASH_WIDTH
-        )
+        hex::encode(self.bytes())
     }
 
     #[must_use]
     pub fn work_path(&self) -> Utf8PathBuf {
-        Utf8Path::new("layers").join(format!("build-{}", self.id()))
+        Utf8Path::new(Self::ROOT).join(format!("{}{}", Self::WORK_PREFIX, self.id()))
     }
 
     #[must_use]
     pub fn done_path(&self) -> Utf8PathBuf {
-        Utf8Path::new("layers").join(self.id())
+        Utf8Path::new(Self::ROOT).join(Self::STORE).join(self.id())
     }
-}
-
-impl TryFrom<&str> for LayerInfo {
-    type Error = RaptorError;
 
-    fn try_from(value: &str) -> Result<Self, Self::Error> {
-        let Some((head, tail)) = value.rsplit_once('-') else {
-            return Err(RaptorError::LayerCacheParseError);
-        };
+    /// Path of this layer in the legacy layer cache
+    #[must_use]
+    pub fn legacy_path(&self) -> Option<Utf8PathBuf> {
+        self.legacy
+            .as_ref()
+            .map(|legacy| Utf8Path::new(Self::ROOT).join(legacy))
+    }
 
-        if tail.len() != Self::HASH_WIDTH {
+    /// Parse a layer id, as found in the content-addressed store
+    pub fn parse_id(id: &str) -> RaptorResult<Digest> {
+        if id.len() != 64 {
             return Err(RaptorError::LayerCacheParseError);
         }
 
-        let name = head.to_string();
-        let hash = u64::from_str_radix(tail, 16)?;
+        let mut hash = [0u8; 32];
+        hex::decode_to_slice(id, &mut hash).map_err(|_| RaptorError::LayerCacheParseError)?;
 
-        Ok(Self::new(name, hash))
+        Ok(Digest::Sha256(hash))
+    }
+
+    /// Check if `value` is the name of a layer in the legacy layer cache
+    /// (`<name>-<hash>`)
+    #[must_use]
+    pub fn is_legacy_id(value: &str) -> bool {
+        value.rsplit_once('-').is_some_and(|(_, tail)| {
+            tail.len() == Self::HASH_WIDTH && u64::from_str_radix(tail, 16).is_ok()
+        })
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::build::LayerInfo;
+    use std::fs;
+
+    use camino_tempfile::Utf8TempDir;
+    use dregistry::digest::Digest;
+    use sha2::{Digest as _, Sha256};
+
+    use crate::RaptorResult;
+    use crate::build::{KeyHasher, LayerInfo};
+
+    const ID: &str = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
 
     #[test]
     fn layerinfo_format() {
-        let info = LayerInfo::new("name".to_string(), 0x0123_4567_89AB_CDEF);
+        let info = LayerInfo::new("name".to_string(), LayerInfo::parse_id(ID).unwrap())
+            .with_legacy("name", Some(0x0123_4567_89AB_CDEF));
         assert_eq!(info.name(), "name");
-        assert_eq!(info.hash(), "0123456789ABCDEF");
-        assert_eq!(info.id(), "name-0123456789ABCDEF");
+        assert_eq!(info.hash(), "0123456789abcdef");
+        assert_eq!(info.hash_value(), 0x0123_4567_89AB_CDEF);
+        assert_eq!(info.id(), ID);
+        assert_eq!(info.done_path(), format!("layers/sha256/{ID}"));
+        assert_eq!(info.work_path(), format!("layers/build-{ID}"));
+        assert_eq!(info.legacy_path().unwrap(), "layers/name-0123456789ABCDEF");
     }
 
     #[test]
     fn layerinfo_parse() {
-        let res = LayerInfo::try_from("name-0123456789ABCDEF").unwrap();
-        assert_eq!(res.name, "name");
-        assert_eq!(res.hash, 0x0123_4567_89AB_CDEF);
+        let Digest::Sha256(hash) = LayerInfo::parse_id(ID).unwrap();
+        assert_eq!(hash[..4], [0x01, 0x23, 0x45, 0x67]);
+
+        LayerInfo::parse_id(&ID[1..]).unwrap_err();
+        LayerInfo::parse_id(&ID.replace('0', "x")).unwrap_err();
+
+        assert!(LayerInfo::is_legacy_id("name-0123456789ABCDEF"));
+        assert!(!LayerInfo::is_legacy_id("name-123456789ABCDEF"));
+        assert!(!LayerInfo::is_legacy_id("name-0123456789ABCDEF0"));
+        assert!(!LayerInfo::is_legacy_id(ID));
+    }
 
-        LayerInfo::try_from("name-123456789ABCDEF").unwrap_err();
-        LayerInfo::try_from("name-0123456789ABCDEF0").unwrap_err();
+    #[test]
+    fn key_hasher_canonical() -> RaptorResult<()> {
+        let mut state = KeyHasher::new();
+        state.value("ab")?;
+        state.value("c")?;
+        state.u32(0o644);
+        let key = state.finish();
+
+        /* length-prefixed JSON, and fixed-width little-endian integers */
+        let mut sha = Sha256::new();
+        sha.update(4u64.to_le_bytes());
+        sha.update(b"\"ab\"");
+        sha.update(3u64.to_le_bytes());
+        sha.update(b"\"c\"");
+        sha.update(0o644u32.to_le_bytes());
+        assert_eq!(key, Digest::Sha256(sha.finalize().into()));
+
+        /* values are never merged */
+        let mut state = KeyHasher::new();
+        state.value("a")?;
+        state.value("bc")?;
+        state.u32(0o644);
+        assert_ne!(state.finish(), key);
+
+        Ok(())
+    }
+
+    #[test]
+    fn key_hasher_file() -> RaptorResult<()> {
+        let tmp = Utf8TempDir::new()?;
+        let path = tmp.path().join("file");
+        fs::write(&path, b"Raptor")?;
+
+        let mut state = KeyHasher::new();
+        state.file(&path)?;
+
+        /* the raw file contents, prefixed by the file size */
+        let mut sha = Sha256::new();
+        sha.update(6u64.to_le_bytes());
+        sha.update(b"Raptor");
+        assert_eq!(state.finish(), Digest::Sha256(sha.finalize().into()));
+
+        Ok(())
     }
 }
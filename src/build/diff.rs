@@ -0,0 +1,575 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+use bytesize::ByteSize;
+use camino::{Utf8Path, Utf8PathBuf};
+use colored::Colorize;
+use dregistry::archive::{is_opaque, is_whiteout};
+use itertools::{EitherOrBoth, Itertools};
+use serde::Serialize;
+
+use crate::build::{LayerInfo, RaptorBuilder};
+use crate::{RaptorError, RaptorResult};
+use raptor_parser::util::module_name::ModuleName;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum FileKind {
+    File,
+    Dir,
+    Symlink,
+
+    /// Overlayfs whiteout, hiding the file from the layers below
+    Whiteout,
+
+    /// Device node, fifo or socket
+    Special,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct FileInfo {
+    pub kind: FileKind,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u64,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<Utf8PathBuf>,
+
+    /// Overlayfs opaque directory, hiding the contents of the layers below
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub opaque: bool,
+
+    /// Location on disk, for comparing file contents
+    #[serde(skip)]
+    source: Utf8PathBuf,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Modified,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum FileField {
+    Kind,
+    Mode,
+    Owner,
+    Size,
+    Content,
+    Target,
+    Opaque,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct FileChange {
+    pub path: Utf8PathBuf,
+    pub change: ChangeKind,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FileField>,
+    pub old: Option<FileInfo>,
+    pub new: Option<FileInfo>,
+}
+
+/// File tree of one or more layers, as seen through overlayfs
+#[derive(Debug, Default)]
+pub struct LayerTree {
+    entries: BTreeMap<Utf8PathBuf, FileInfo>,
+}
+
+impl LayerTree {
+    /// Load the combined contents of `layers` (lowest first).
+    ///
+    /// For a single layer, whiteouts and opaque directories are kept, since
+    /// they are part of what the layer changes. For multiple layers, they are
+    /// applied instead, resulting in the file tree seen by a container.
+    pub fn load(layers: &[Utf8PathBuf]) -> RaptorResult<Self> {
+        let mut tree = Self::default();
+
+        for layer in layers {
+            tree.apply(layer, Utf8Path::new("/"))?;
+        }
+
+        if layers.len() > 1 {
+            tree.entries
+                .retain(|_, info| info.kind != FileKind::Whiteout);
+            tree.entries
+                .values_mut()
+                .for_each(|info| info.opaque = false);
+        }
+
+        Ok(tree)
+    }
+
+    /// Remove everything below `path` (but not `path` itself)
+    fn remove_below(&mut self, path: &Utf8Path) {
+        let below = self
+            .entries
+            .range(path.to_path_buf()..)
+            .map(|(key, _)| key)
+            .skip_while(|key| *key == path)
+            .take_while(|key| key.starts_with(path))
+            .cloned()
+            .collect_vec();
+
+        for key in below {
+            self.entries.remove(&key);
+        }
+    }
+
+    fn apply(&mut self, root: &Utf8Path, dir: &Utf8Path) -> RaptorResult<()> {
+        let full = root.join(dir.strip_prefix("/").unwrap_or(dir));
+
+        let names = full
+            .read_dir_utf8()?
+            .map(|dent| Ok(dent?.file_name().to_string()))
+            .collect::<RaptorResult<Vec<_>>>()?
+            .into_iter()
+            .sorted();
+
+        for name in names {
+            let path = dir.join(&name);
+            let source = full.join(&name);
+            let md = source.symlink_metadata()?;
+
+            let kind = if is_whiteout(&md) {
+                FileKind::Whiteout
+            } else if md.is_dir() {
+                FileKind::Dir
+            } else if md.is_symlink() {
+                FileKind::Symlink
+            } else if md.is_file() {
+                FileKind::File
+            } else {
+                FileKind::Special
+            };
+
+            let opaque = kind == FileKind::Dir && is_opaque(&source);
+
+            /* anything but a (non-opaque) directory replaces all of the
+             * lower layers below this path */
+            if kind != FileKind::Dir || opaque {
+                self.remove_below(&path);
+            }
+
+            let info = FileInfo {
+                kind,
+                mode: md.permissions().mode() & 0o7777,
+                uid: md.uid(),
+                gid: md.gid(),
+                size: if kind == FileKind::File { md.len() } else { 0 },
+                target: md
+                    .is_symlink()
+                    .then(|| source.read_link_utf8())
+                    .transpose()?,
+                opaque,
+                source,
+            };
+
+            self.entries.insert(path.clone(), info);
+
+            if kind == FileKind::Dir {
+                self.apply(root, &path)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn same_content(a: &Utf8Path, b: &Utf8Path) -> RaptorResult<bool> {
+        let mut a = BufReader::new(File::open(a)?);
+        let mut b = BufReader::new(File::open(b)?);
+
+        let mut buf_a = vec![0; 64 * 1024];
+        let mut buf_b = vec![0; 64 * 1024];
+
+        loop {
+            let n = a.read(&mut buf_a)?;
+            if n == 0 {
+                return Ok(b.read(&mut buf_b[..1])? == 0);
+            }
+
+            b.read_exact(&mut buf_b[..n])?;
+            if buf_a[..n] != buf_b[..n] {
+                return Ok(false);
+            }
+        }
+    }
+
+    /// List the fields that differ between `old` and `new`
+    fn compare(old: &FileInfo, new: &FileInfo) -> RaptorResult<Vec<FileField>> {
+        let mut res = vec![];
+
+        if old.kind != new.kind {
+            res.push(FileField::Kind);
+        }
+        if old.mode != new.mode {
+            res.push(FileField::Mode);
+        }
+        if (old.uid, old.gid) != (new.uid, new.gid) {
+            res.push(FileField::Owner);
+        }
+        if old.size != new.size {
+            res.push(FileField::Size);
+        } else if old.kind == FileKind::File
+            && new.kind == FileKind::File
+            && !Self::same_content(&old.source, &new.source)?
+        {
+            res.push(FileField::Content);
+        }
+        if old.target != new.target {
+            res.push(FileField::Target);
+        }
+        if old.opaque != new.opaque {
+            res.push(FileField::Opaque);
+        }
+
+        Ok(res)
+    }
+
+    /// Compare this tree (old) to `other` (new)
+    pub fn diff(&self, other: &Self) -> RaptorResult<Vec<FileChange>> {
+        let mut res = vec![];
+
+        for item in self
+            .entries
+            .iter()
+            .merge_join_by(&other.entries, |(a, _), (b, _)| a.cmp(b))
+        {
+            let change = match item {
+                EitherOrBoth::Left((path, old)) => FileChange {
+                    path: path.clone(),
+                    change: ChangeKind::Removed,
+                    fields: vec![],
+                    old: Some(old.clone()),
+                    new: None,
+                },
+                EitherOrBoth::Right((path, new)) => FileChange {
+                    path: path.clone(),
+                    change: ChangeKind::Added,
+                    fields: vec![],
+                    old: None,
+                    new: Some(new.clone()),
+                },
+                EitherOrBoth::Both((path, old), (_, new)) => {
+                    let fields = Self::compare(old, new)?;
+                    if fields.is_empty() {
+                        continue;
+                    }
+                    FileChange {
+                        path: path.clone(),
+                        change: ChangeKind::Modified,
+                        fields,
+                        old: Some(old.clone()),
+                        new: Some(new.clone()),
+                    }
+                }
+            };
+
+            res.push(change);
+        }
+
+        Ok(res)
+    }
+}
+
+/// Compares layers, given as targets, layer ids (or unique prefixes of
+/// them), or paths to layer directories.
+pub struct LayerDiff<'a> {
+    builder: &'a RaptorBuilder<'a>,
+    merged: bool,
+}
+
+impl<'a> LayerDiff<'a> {
+    #[must_use]
+    pub const fn new(builder: &'a RaptorBuilder<'a>) -> Self {
+        Self {
+            builder,
+            merged: false,
+        }
+    }
+
+    /// Compare the full file trees of targets (including all the layers they
+    /// are built `FROM`), instead of only their own layers
+    #[must_use]
+    pub const fn with_merged(mut self, merged: bool) -> Self {
+        self.merged = merged;
+        self
+    }
+
+    /// Find a layer in the store, by (a unique prefix of) its id
+    fn find_layer(id: &str) -> RaptorResult<Option<Utf8PathBuf>> {
+        let store = Utf8Path::new(LayerInfo::ROOT).join(LayerInfo::STORE);
+        if !store.exists() {
+            return Ok(None);
+        }
+
+        let mut found = vec![];
+        for dent in store.read_dir_utf8()? {
+            let dent = dent?;
+            if dent.file_name().starts_with(id) && LayerInfo::parse_id(dent.file_name()).is_ok() {
+                found.push(dent.into_path());
+            }
+        }
+
+        match found.len() {
+            0 => Ok(None),
+            1 => Ok(found.pop()),
+            _ => Err(RaptorError::LayerAmbiguous(id.to_string())),
+        }
+    }
+
+    /// Resolve a layer specification into a stack of layer paths (lowest
+    /// first)
+    pub fn resolve(&self, spec: &str) -> RaptorResult<Vec<Utf8PathBuf>> {
+        let path = Utf8Path::new(spec);
+        if spec.contains('/') && path.is_dir() {
+            return Ok(vec![path.to_path_buf()]);
+        }
+
+        let id = spec.strip_prefix("sha256:").unwrap_or(spec);
+        if !id.is_empty()
+            && id.chars().all(|c| c.is_ascii_hexdigit())
+            && let Some(path) = Self::find_layer(&id.to_ascii_lowercase())?
+        {
+            return Ok(vec![path]);
+        }
+
+        let program = self.builder.load(&ModuleName::from(spec))?;
+        let stack = self.builder.stack(program, self.builder.platform())?;
+
+        let mut layers = vec![];
+        for target in &stack {
+            let path = self.builder.layer_info(target)?.done_path();
+            if !path.exists() {
+                return Err(RaptorError::LayerNotFound(spec.to_string()));
+            }
+            layers.push(path);
+        }
+
+        if !self.merged {
+            layers.drain(..layers.len().saturating_sub(1));
+        }
+
+        Ok(layers)
+    }
+
+    pub fn diff(&self, old: &str, new: &str) -> RaptorResult<Vec<FileChange>> {
+        let old = LayerTree::load(&self.resolve(old)?)?;
+        let new = LayerTree::load(&self.resolve(new)?)?;
+
+        old.diff(&new)
+    }
+
+    fn describe(info: &FileInfo) -> String {
+        let kind = match info.kind {
+            FileKind::File => format!("file {}", ByteSize(info.size)),
+            FileKind::Dir if info.opaque => "dir (opaque)".to_string(),
+            FileKind::Dir => "dir".to_string(),
+            FileKind::Symlink => format!(
+                "symlink -> {}",
+                info.target.as_deref().map_or("", Utf8Path::as_str)
+            ),
+            FileKind::Whiteout => return "whiteout".to_string(),
+            FileKind::Special => "special".to_string(),
+        };
+
+        format!(
+            "{kind}, mode {:04o}, owner {}:{}",
+            info.mode, info.uid, info.gid
+        )
+    }
+
+    fn describe_change(field: FileField, old: &FileInfo, new: &FileInfo) -> String {
+        match field {
+            FileField::Kind => format!("{} -> {}", Self::describe(old), Self::describe(new)),
+            FileField::Mode => format!("mode {:04o} -> {:04o}", old.mode, new.mode),
+            FileField::Owner => format!("owner {}:{} -> {}:{}", old.uid, old.gid, new.uid, new.gid),
+            FileField::Size => format!("size {} -> {}", ByteSize(old.size), ByteSize(new.size)),
+            FileField::Content => "content".to_string(),
+            FileField::Target => format!(
+                "target {} -> {}",
+                old.target.as_deref().map_or("", Utf8Path::as_str),
+                new.target.as_deref().map_or("", Utf8Path::as_str)
+            ),
+            FileField::Opaque => format!("opaque {} -> {}", old.opaque, new.opaque),
+        }
+    }
+
+    pub fn present(changes: &[FileChange]) {
+        for change in changes {
+            match (change.change, &change.old, &change.new) {
+                (ChangeKind::Added, _, Some(new)) => {
+                    println!(
+                        "{} {} ({})",
+                        "A".green(),
+                        change.path.as_str().green(),
+                        Self::describe(new)
+                    );
+                }
+                (ChangeKind::Removed, Some(old), _) => {
+                    println!(
+                        "{} {} ({})",
+                        "D".red(),
+                        change.path.as_str().red(),
+                        Self::describe(old)
+                    );
+                }
+                (ChangeKind::Modified, Some(old), Some(new)) => {
+                    /* a change of file type says it all */
+                    let fields = if change.fields.contains(&FileField::Kind) {
+                        &[FileField::Kind][..]
+                    } else {
+                        &change.fields
+                    };
+                    println!(
+                        "{} {} ({})",
+                        "M".yellow(),
+                        change.path.as_str().yellow(),
+                        fields
+                            .iter()
+                            .map(|field| Self::describe_change(*field, old, new))
+                            .join(", ")
+                    );
+                }
+                _ => {}
+            }
+        }
+
+        let count = |kind| {
+            changes
+                .iter()
+                .filter(|change| change.change == kind)
+                .count()
+        };
+
+        info!(
+            "{} added, {} removed, {} modified",
+            count(ChangeKind::Added),
+            count(ChangeKind::Removed),
+            count(ChangeKind::Modified),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::{PermissionsExt, symlink};
+
+    use camino_tempfile::Utf8TempDir;
+    use nix::sys::stat::{Mode, SFlag, mknod};
+    use nix::unistd::Uid;
+
+    use crate::RaptorResult;
+    use crate::build::{ChangeKind, FileChange, FileField, FileKind, LayerTree};
+
+    fn summary(changes: &[FileChange]) -> Vec<(&str, ChangeKind, Vec<FileField>)> {
+        changes
+            .iter()
+            .map(|change| (change.path.as_str(), change.change, change.fields.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn diff_layers() -> RaptorResult<()> {
+        let tmp = Utf8TempDir::new()?;
+        let a = tmp.path().join("a");
+        let b = tmp.path().join("b");
+
+        for root in [&a, &b] {
+            fs::create_dir_all(root.join("etc"))?;
+            fs::write(root.join("etc/same"), "same")?;
+        }
+
+        fs::write(a.join("etc/removed"), "removed")?;
+        fs::write(b.join("etc/added"), "added")?;
+
+        fs::write(a.join("etc/content"), "old")?;
+        fs::write(b.join("etc/content"), "new")?;
+
+        fs::write(a.join("etc/size"), "old")?;
+        fs::write(b.join("etc/size"), "longer")?;
+
+        fs::write(a.join("etc/mode"), "mode")?;
+        fs::write(b.join("etc/mode"), "mode")?;
+        fs::set_permissions(b.join("etc/mode"), fs::Permissions::from_mode(0o600))?;
+
+        symlink("same", a.join("etc/link"))?;
+        symlink("content", b.join("etc/link"))?;
+
+        let old = LayerTree::load(&[a])?;
+        let new = LayerTree::load(&[b])?;
+
+        assert_eq!(
+            summary(&old.diff(&new)?),
+            [
+                ("/etc/added", ChangeKind::Added, vec![]),
+                (
+                    "/etc/content",
+                    ChangeKind::Modified,
+                    vec![FileField::Content]
+                ),
+                ("/etc/link", ChangeKind::Modified, vec![FileField::Target]),
+                ("/etc/mode", ChangeKind::Modified, vec![FileField::Mode]),
+                ("/etc/removed", ChangeKind::Removed, vec![]),
+                ("/etc/size", ChangeKind::Modified, vec![FileField::Size]),
+            ]
+        );
+
+        assert!(old.diff(&old)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn diff_whiteouts() -> RaptorResult<()> {
+        /* whiteouts can only be created by root */
+        if !Uid::effective().is_root() {
+            return Ok(());
+        }
+
+        let tmp = Utf8TempDir::new()?;
+        let lower = tmp.path().join("lower");
+        let upper = tmp.path().join("upper");
+
+        fs::create_dir_all(lower.join("etc/dir"))?;
+        fs::write(lower.join("etc/gone"), "gone")?;
+        fs::write(lower.join("etc/dir/file"), "file")?;
+
+        fs::create_dir_all(&upper)?;
+        fs::create_dir_all(upper.join("etc"))?;
+        mknod(
+            upper.join("etc/gone").as_std_path(),
+            SFlag::S_IFCHR,
+            Mode::empty(),
+            0,
+        )?;
+
+        /* a single layer shows the whiteout itself */
+        let layer = LayerTree::load(std::slice::from_ref(&upper))?;
+        let base = LayerTree::load(std::slice::from_ref(&lower))?;
+        let changes = base.diff(&layer)?;
+        let gone = changes
+            .iter()
+            .find(|change| change.path == "/etc/gone")
+            .unwrap();
+        assert_eq!(gone.new.as_ref().unwrap().kind, FileKind::Whiteout);
+
+        /* a stack applies it */
+        let merged = LayerTree::load(&[lower, upper])?;
+        assert_eq!(
+            summary(&base.diff(&merged)?),
+            [("/etc/gone", ChangeKind::Removed, vec![])]
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,193 @@
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::Arc;
+
+use camino::Utf8Path;
+use camino_tempfile::Builder;
+use dregistry::api::ImageRuntimeConfig;
+use dregistry::digest::Digest;
+use dregistry::oci::OciLayout;
+use dregistry::platform::Platform;
+use dregistry::push::ImagePusher;
+use dregistry::source::DockerSource;
+use raptor_parser::ast::Instruction;
+
+use crate::RaptorResult;
+use crate::build::{BuildTarget, RaptorBuilder};
+use crate::dsl::Program;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// OCI image layout (directory)
+    #[default]
+    Oci,
+
+    /// Tarball for `docker load`
+    DockerArchive,
+}
+
+impl Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Oci => write!(f, "oci"),
+            Self::DockerArchive => write!(f, "docker-archive"),
+        }
+    }
+}
+
+pub struct Exporter<'a> {
+    builder: &'a RaptorBuilder<'a>,
+}
+
+impl<'a> Exporter<'a> {
+    #[must_use]
+    pub const fn new(builder: &'a RaptorBuilder<'a>) -> Self {
+        Self { builder }
+    }
+
+    /// Collect the image configuration (`ENV`, `WORKDIR`, `USER`, `ENTRYPOINT`
+    /// and `CMD`) from a build stack, where later layers override earlier ones.
+    pub fn image_config(stack: &[BuildTarget]) -> RaptorResult<ImageRuntimeConfig> {
+        let mut env = BTreeMap::new();
+        let mut config = ImageRuntimeConfig::default();
+
+        for target in stack {
+            let BuildTarget::Program(program, _) = target else {
+                continue;
+            };
+
+            program.traverse(&mut |stmt| {
+                match &stmt.inst {
+                    Instruction::Env(inst) => {
+                        for assign in &inst.env {
+                            env.insert(assign.key.clone(), assign.value.clone());
+                        }
+                    }
+                    Instruction::Workdir(inst) => {
+                        config.working_dir = Some(inst.dir.to_string());
+                    }
+                    Instruction::User(inst) => {
+                        config.user = Some(inst.group.as_ref().map_or_else(
+                            || inst.user.clone(),
+                            |group| format!("{}:{group}", inst.user),
+                        ));
+                    }
+                    Instruction::Entrypoint(inst) => {
+                        config.entrypoint = Some(inst.entrypoint.clone());
+                    }
+                    Instruction::Cmd(inst) => {
+                        config.cmd = Some(inst.cmd.clone());
+                    }
+                    _ => {}
+                }
+
+                Ok(())
+            })?;
+        }
+
+        config.env = env.into_iter().map(|(k, v)| format!("{k}={v}")).collect();
+
+        Ok(config)
+    }
+
+    fn write_layout(
+        layout: &mut OciLayout,
+        layers: &[impl AsRef<Utf8Path>],
+        platform: &Platform,
+        config: ImageRuntimeConfig,
+        name: Option<&str>,
+    ) -> RaptorResult<Digest> {
+        let layers = layers
+            .iter()
+            .map(|layer| {
+                info!("Exporting layer {}", layer.as_ref());
+                layout.add_layer(layer.as_ref())
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let digest = layout.add_image(platform, config, layers, name)?;
+        info!("Exported image [{digest}]");
+
+        Ok(digest)
+    }
+
+    /// Build a program, and export it as an image with one layer per build
+    /// layer.
+    pub fn export(
+        &self,
+        program: Arc<Program>,
+        platform: &Platform,
+        format: ExportFormat,
+        output: &Utf8Path,
+        name: Option<&str>,
+    ) -> RaptorResult<()> {
+        let layers = self.builder.build_program(program.clone(), platform)?;
+        let config = Self::image_config(&self.builder.stack(program, platform)?)?;
+
+        if self.builder.dry_run() {
+            info!(
+                "Would export {} layers to {output} ({format})",
+                layers.len()
+            );
+            return Ok(());
+        }
+
+        match format {
+            ExportFormat::Oci => {
+                let mut layout = OciLayout::create(output)?;
+                Self::write_layout(&mut layout, &layers, platform, config, name)?;
+                layout.finish()?;
+            }
+
+            ExportFormat::DockerArchive => {
+                let tempdir = Builder::new().prefix("raptor-export-").tempdir()?;
+
+                let mut layout = OciLayout::create(tempdir.path())?;
+                Self::write_layout(&mut layout, &layers, platform, config, name)?;
+
+                let file = BufWriter::new(File::create(output)?);
+                layout.finish_archive(file)?.flush()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Build a program, and push it to a registry as an image with one layer
+    /// per build layer.
+    ///
+    /// Missing blobs are mounted from the repositories in `mount_from` (on
+    /// the same registry) when possible, instead of being uploaded.
+    pub fn push(
+        &self,
+        program: Arc<Program>,
+        platform: &Platform,
+        dest: &DockerSource,
+        mount_from: &[String],
+    ) -> RaptorResult<()> {
+        let layers = self.builder.build_program(program.clone(), platform)?;
+        let config = Self::image_config(&self.builder.stack(program, platform)?)?;
+
+        if self.builder.dry_run() {
+            info!("Would push {} layers to {dest}", layers.len());
+            return Ok(());
+        }
+
+        let tempdir = Builder::new().prefix("raptor-push-").tempdir()?;
+
+        let mut layout = OciLayout::create(tempdir.path())?;
+        let digest = Self::write_layout(&mut layout, &layers, platform, config, None)?;
+
+        let mut dc = self.builder.downloader()?.client(dest)?.with_push();
+
+        let digest = ImagePusher::new(&mut dc)
+            .with_mount_from(mount_from.iter().cloned())
+            .push(&layout, &digest, &dest.image_tag())?;
+
+        info!("Pushed image {dest} [{digest}]");
+
+        Ok(())
+    }
+}
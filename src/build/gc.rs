@@ -0,0 +1,347 @@
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::fmt::{self, Display};
+use std::fs;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use bytesize::ByteSize;
+use camino::{Utf8Path, Utf8PathBuf};
+use dregistry::digest::Digest;
+use dregistry::platform::Platform;
+
+use crate::RaptorResult;
+use crate::build::{BuildTarget, LayerIndex, LayerInfo, RaptorBuilder};
+use crate::dsl::Program;
+use crate::util::disk_usage;
+
+/// Retention policy for unreachable cache entries
+#[derive(Debug, Clone, Default)]
+pub struct GcPolicy {
+    /// Entries modified more recently than this are never removed
+    pub min_age: Duration,
+
+    /// If set, keep the most recent unreachable layers and blobs, up to this
+    /// total size (in bytes)
+    pub max_size: Option<u64>,
+}
+
+impl GcPolicy {
+    /// Select the unreachable entries to remove, as of `now`
+    #[must_use]
+    pub fn select(&self, items: Vec<GcItem>, now: SystemTime) -> Vec<GcItem> {
+        let mut items: Vec<GcItem> = items
+            .into_iter()
+            .filter(|item| now.duration_since(item.modified).unwrap_or_default() >= self.min_age)
+            .collect();
+
+        if let Some(max_size) = self.max_size {
+            /* most recently used first */
+            items.sort_by_key(|item| Reverse(item.modified));
+
+            let mut budget = max_size;
+            items.retain(|item| {
+                if item.kind == GcKind::Work {
+                    return true;
+                }
+
+                if item.size > budget {
+                    /* once the budget is exhausted, remove all older entries */
+                    budget = 0;
+                    return true;
+                }
+
+                budget -= item.size;
+                false
+            });
+        }
+
+        items.sort_by(|a, b| a.path.cmp(&b.path));
+
+        items
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GcKind {
+    /// Completed layer, not used by any target
+    Layer,
+
+    /// Work directory left behind by an aborted build
+    Work,
+
+    /// Downloaded blob, not used by any target
+    Blob,
+}
+
+impl Display for GcKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Layer => write!(f, "layer"),
+            Self::Work => write!(f, "work dir"),
+            Self::Blob => write!(f, "blob"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GcItem {
+    pub path: Utf8PathBuf,
+    pub kind: GcKind,
+    pub size: u64,
+    pub modified: SystemTime,
+}
+
+/// Garbage collector for the layer cache (`layers/`) and the download cache
+/// (`cache/layer/`).
+///
+/// Everything reachable from the added targets is kept. Other entries are
+/// removed, subject to a [`GcPolicy`].
+pub struct GarbageCollector<'a> {
+    builder: &'a RaptorBuilder<'a>,
+    layers: HashSet<Utf8PathBuf>,
+    blobs: HashSet<Digest>,
+    unresolved: bool,
+}
+
+impl<'a> GarbageCollector<'a> {
+    #[must_use]
+    pub fn new(builder: &'a RaptorBuilder<'a>) -> Self {
+        Self {
+            builder,
+            layers: HashSet::new(),
+            blobs: HashSet::new(),
+            unresolved: false,
+        }
+    }
+
+    /// Mark a single build target (and the blobs it was built from) as
+    /// reachable
+    pub fn add_target(&mut self, target: &BuildTarget) -> RaptorResult<()> {
+        let layer = self.builder.layer_info(target)?;
+        self.layers.insert(layer.done_path());
+
+        /* not migrated yet, but will be on the next build */
+        self.layers.extend(layer.legacy_path());
+
+        if let BuildTarget::DockerSource(source, platform) = target {
+            if let Some(digests) = self.builder.downloader()?.cached_blobs(source, platform)? {
+                self.blobs.extend(digests);
+            } else if !self.unresolved {
+                warn!("Manifest for {source} ({platform}) is not cached, keeping all blobs");
+                self.unresolved = true;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Mark all layers of a program as reachable
+    pub fn add_program(&mut self, program: Arc<Program>, platform: &Platform) -> RaptorResult<()> {
+        for target in &self.builder.stack(program, platform)? {
+            self.add_target(target)?;
+        }
+
+        Ok(())
+    }
+
+    fn item(path: Utf8PathBuf, kind: GcKind) -> RaptorResult<GcItem> {
+        Ok(GcItem {
+            size: disk_usage(&path)?,
+            modified: path.symlink_metadata()?.modified()?,
+            path,
+            kind,
+        })
+    }
+
+    fn unreachable_store_layers(&self, root: &Utf8Path) -> RaptorResult<Vec<GcItem>> {
+        let mut res = vec![];
+
+        for dent in root.read_dir_utf8()? {
+            let dent = dent?;
+
+            if LayerInfo::parse_id(dent.file_name()).is_err() {
+                debug!("Ignoring unknown layer store entry {}", dent.path());
+            } else if !self.layers.contains(dent.path()) {
+                res.push(Self::item(dent.into_path(), GcKind::Layer)?);
+            }
+        }
+
+        Ok(res)
+    }
+
+    fn unreachable_layers(&self) -> RaptorResult<Vec<GcItem>> {
+        let root = Utf8Path::new(LayerInfo::ROOT);
+        if !root.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut res = vec![];
+
+        for dent in root.read_dir_utf8()? {
+            let dent = dent?;
+            let name = dent.file_name();
+
+            if name == LayerInfo::STORE {
+                res.extend(self.unreachable_store_layers(dent.path())?);
+            } else if name.starts_with(LayerInfo::WORK_PREFIX) {
+                res.push(Self::item(dent.into_path(), GcKind::Work)?);
+            } else if !LayerInfo::is_legacy_id(name) {
+                if !LayerIndex::is_index_file(name) {
+                    debug!("Ignoring unknown layer cache entry {}", dent.path());
+                }
+            } else if !self.layers.contains(dent.path()) {
+                res.push(Self::item(dent.into_path(), GcKind::Layer)?);
+            }
+        }
+
+        Ok(res)
+    }
+
+    fn unreachable_blobs(&self) -> RaptorResult<Vec<GcItem>> {
+        let mut res = vec![];
+
+        for path in self.builder.downloader()?.blobs()? {
+            /* leftover temporary files and foreign files are always garbage */
+            let reachable = Digest::parse(path.file_name().unwrap_or_default())
+                .is_ok_and(|digest| self.unresolved || self.blobs.contains(&digest));
+
+            if !reachable {
+                res.push(Self::item(path, GcKind::Blob)?);
+            }
+        }
+
+        Ok(res)
+    }
+
+    /// Find all cache entries that should be removed under the given policy
+    pub fn collect(&self, policy: &GcPolicy) -> RaptorResult<Vec<GcItem>> {
+        let mut items = self.unreachable_layers()?;
+        items.extend(self.unreachable_blobs()?);
+
+        Ok(policy.select(items, SystemTime::now()))
+    }
+
+    /// Remove all cache entries selected by the policy (unless in dry-run
+    /// mode). Returns the removed entries.
+    pub fn run(&self, policy: &GcPolicy) -> RaptorResult<Vec<GcItem>> {
+        let items = self.collect(policy)?;
+
+        for item in &items {
+            if self.builder.dry_run() {
+                info!(
+                    "Would remove {} {} ({})",
+                    item.kind,
+                    item.path,
+                    ByteSize(item.size)
+                );
+                continue;
+            }
+
+            info!(
+                "Removing {} {} ({})",
+                item.kind,
+                item.path,
+                ByteSize(item.size)
+            );
+
+            if item.path.symlink_metadata()?.is_dir() {
+                fs::remove_dir_all(&item.path)?;
+            } else {
+                fs::remove_file(&item.path)?;
+            }
+        }
+
+        if !self.builder.dry_run() {
+            let root = Utf8Path::new(LayerInfo::ROOT);
+            let store = root.join(LayerInfo::STORE);
+            let removed: Vec<&str> = items
+                .iter()
+                .filter(|item| item.path.parent() == Some(&store))
+                .filter_map(|item| item.path.file_name())
+                .collect();
+
+            if !removed.is_empty() {
+                LayerIndex::update(root, |index| {
+                    for id in removed {
+                        index.remove(id);
+                    }
+                })?;
+            }
+        }
+
+        Ok(items)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use camino::Utf8PathBuf;
+
+    use crate::build::{GcItem, GcKind, GcPolicy};
+
+    const HOUR: Duration = Duration::from_hours(1);
+
+    fn item(name: &str, kind: GcKind, size: u64, hours: u32, now: SystemTime) -> GcItem {
+        GcItem {
+            path: Utf8PathBuf::from(name),
+            kind,
+            size,
+            modified: now - HOUR * hours,
+        }
+    }
+
+    fn select(policy: &GcPolicy) -> Vec<String> {
+        let now = SystemTime::now();
+
+        let items = vec![
+            item("work", GcKind::Work, 100, 2, now),
+            item("new", GcKind::Layer, 10, 0, now),
+            item("a", GcKind::Layer, 10, 2, now),
+            item("b", GcKind::Blob, 20, 3, now),
+            item("c", GcKind::Layer, 10, 4, now),
+        ];
+
+        policy
+            .select(items, now)
+            .into_iter()
+            .map(|item| item.path.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn gc_policy_age() {
+        let policy = GcPolicy::default();
+        assert_eq!(select(&policy), ["a", "b", "c", "new", "work"]);
+
+        let policy = GcPolicy {
+            min_age: HOUR,
+            max_size: None,
+        };
+        assert_eq!(select(&policy), ["a", "b", "c", "work"]);
+    }
+
+    #[test]
+    fn gc_policy_size() {
+        let policy = GcPolicy {
+            min_age: HOUR,
+            max_size: Some(15),
+        };
+        assert_eq!(select(&policy), ["b", "c", "work"]);
+
+        /* "c" fits the budget, but is older than the first entry that did not */
+        let policy = GcPolicy {
+            min_age: HOUR,
+            max_size: Some(25),
+        };
+        assert_eq!(select(&policy), ["b", "c", "work"]);
+
+        let policy = GcPolicy {
+            min_age: HOUR,
+            max_size: Some(40),
+        };
+        assert_eq!(select(&policy), ["work"]);
+    }
+}
@@ -0,0 +1,353 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::{self, Display};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use bytesize::ByteSize;
+use camino::{Utf8Path, Utf8PathBuf};
+use colored::Colorize;
+use dregistry::platform::Platform;
+use itertools::Itertools;
+use serde::Serialize;
+
+use crate::RaptorResult;
+use crate::build::{BuildTarget, LayerIndex, LayerInfo, RaptorBuilder};
+use crate::dsl::Program;
+use crate::util::disk_usage;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum LayerStatus {
+    /// Layer matches the current cache key of a target
+    Current,
+
+    /// Layer has the name of a target layer, but an outdated cache key
+    Stale,
+
+    /// Work directory of an unfinished (or aborted) build
+    Incomplete,
+
+    /// Layer of a target in the legacy layer cache, which will be moved to
+    /// the store on the next build
+    Legacy,
+
+    /// Layer does not belong to any known target
+    Unknown,
+}
+
+impl Display for LayerStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Current => write!(f, "current"),
+            Self::Stale => write!(f, "stale"),
+            Self::Incomplete => write!(f, "incomplete"),
+            Self::Legacy => write!(f, "legacy"),
+            Self::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct LayerEntry {
+    pub name: Option<String>,
+    pub hash: String,
+    pub path: Utf8PathBuf,
+    pub size: u64,
+    #[serde(serialize_with = "serialize_rfc3339")]
+    pub modified: SystemTime,
+    pub status: LayerStatus,
+    pub targets: BTreeSet<String>,
+}
+
+fn serialize_rfc3339<S: serde::Serializer>(time: &SystemTime, ser: S) -> Result<S::Ok, S::Error> {
+    ser.collect_str(&humantime::format_rfc3339_seconds(*time))
+}
+
+/// Inventory of the layer cache (`layers/`), with the status of each layer
+/// relative to a set of targets.
+pub struct LayerInventory<'a> {
+    builder: &'a RaptorBuilder<'a>,
+    refs: HashMap<Utf8PathBuf, BTreeSet<String>>,
+    names: HashSet<String>,
+}
+
+impl<'a> LayerInventory<'a> {
+    #[must_use]
+    pub fn new(builder: &'a RaptorBuilder<'a>) -> Self {
+        Self {
+            builder,
+            refs: HashMap::new(),
+            names: HashSet::new(),
+        }
+    }
+
+    /// Register all layers of a program as referenced by the target `label`
+    pub fn add_program(
+        &mut self,
+        label: &str,
+        program: Arc<Program>,
+        platform: &Platform,
+    ) -> RaptorResult<()> {
+        for target in &self.builder.stack(program, platform)? {
+            self.add_target(label, target)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn add_target(&mut self, label: &str, target: &BuildTarget) -> RaptorResult<()> {
+        let layer = self.builder.layer_info(target)?;
+
+        self.names.insert(layer.name().to_string());
+
+        for path in std::iter::once(layer.done_path()).chain(layer.legacy_path()) {
+            self.refs.entry(path).or_default().insert(label.to_string());
+        }
+
+        Ok(())
+    }
+
+    fn entry(
+        &self,
+        path: &Utf8Path,
+        name: Option<&str>,
+        hash: &str,
+        status: LayerStatus,
+    ) -> RaptorResult<LayerEntry> {
+        Ok(LayerEntry {
+            name: name.map(ToString::to_string),
+            hash: hash.to_string(),
+            path: path.to_path_buf(),
+            size: disk_usage(path)?,
+            modified: path.symlink_metadata()?.modified()?,
+            status,
+            targets: self.refs.get(path).cloned().unwrap_or_default(),
+        })
+    }
+
+    fn store_entries(&self, root: &Utf8Path, index: &LayerIndex) -> RaptorResult<Vec<LayerEntry>> {
+        let names = index.names();
+        let mut res = vec![];
+
+        for dent in root.read_dir_utf8()? {
+            let dent = dent?;
+            let id = dent.file_name();
+
+            if LayerInfo::parse_id(id).is_err() {
+                debug!("Ignoring unknown layer store entry {}", dent.path());
+                continue;
+            }
+
+            let name = names.get(id).copied();
+
+            let status = if self.refs.contains_key(dent.path()) {
+                LayerStatus::Current
+            } else if name.is_some_and(|name| self.names.contains(name)) {
+                LayerStatus::Stale
+            } else {
+                LayerStatus::Unknown
+            };
+
+            res.push(self.entry(dent.path(), name, id, status)?);
+        }
+
+        Ok(res)
+    }
+
+    fn cache_entry(&self, path: &Utf8Path, index: &LayerIndex) -> RaptorResult<Option<LayerEntry>> {
+        let name = path.file_name().unwrap_or_default();
+
+        if let Some(id) = name.strip_prefix(LayerInfo::WORK_PREFIX) {
+            let entry = if LayerInfo::parse_id(id).is_ok() {
+                let name = index.names().get(id).copied();
+                self.entry(path, name, id, LayerStatus::Incomplete)?
+            } else {
+                let (name, hash) = id.rsplit_once('-').unwrap_or((id, ""));
+                self.entry(path, Some(name), hash, LayerStatus::Incomplete)?
+            };
+
+            return Ok(Some(entry));
+        }
+
+        if LayerInfo::is_legacy_id(name) {
+            let (name, hash) = name.rsplit_once('-').unwrap_or_default();
+
+            let status = if self.refs.contains_key(path) {
+                LayerStatus::Legacy
+            } else {
+                LayerStatus::Unknown
+            };
+
+            return Ok(Some(self.entry(path, Some(name), hash, status)?));
+        }
+
+        if !LayerIndex::is_index_file(name) {
+            debug!("Ignoring unknown layer cache entry {path}");
+        }
+
+        Ok(None)
+    }
+
+    /// List all layers (and work directories) in the layer cache, sorted by
+    /// name and age
+    pub fn list(&self) -> RaptorResult<Vec<LayerEntry>> {
+        let root = Utf8Path::new(LayerInfo::ROOT);
+        if !root.exists() {
+            return Ok(vec![]);
+        }
+
+        let index = LayerIndex::load(root)?;
+        let mut res = vec![];
+
+        for dent in root.read_dir_utf8()? {
+            let dent = dent?;
+
+            if dent.file_name() == LayerInfo::STORE {
+                res.extend(self.store_entries(dent.path(), &index)?);
+            } else if let Some(entry) = self.cache_entry(dent.path(), &index)? {
+                res.push(entry);
+            }
+        }
+
+        res.sort_by(|a, b| (&a.name, a.modified).cmp(&(&b.name, b.modified)));
+
+        Ok(res)
+    }
+
+    pub fn present(entries: &[LayerEntry]) {
+        println!(
+            "{:<24} {:<16} {:>10} {:<20} {:<10} TARGETS",
+            "LAYER", "HASH", "SIZE", "MODIFIED", "STATUS"
+        );
+
+        for entry in entries {
+            let status = format!("{:<10}", entry.status.to_string());
+            let status = match entry.status {
+                LayerStatus::Current => status.green(),
+                LayerStatus::Stale => status.yellow(),
+                LayerStatus::Incomplete => status.red(),
+                LayerStatus::Legacy => status.cyan(),
+                LayerStatus::Unknown => status.dimmed(),
+            };
+
+            println!(
+                "{:<24} {} {:>10} {:<20} {status} {}",
+                entry.name.as_deref().unwrap_or("-"),
+                entry
+                    .hash
+                    .get(..LayerInfo::HASH_WIDTH)
+                    .unwrap_or(&entry.hash)
+                    .dimmed(),
+                ByteSize(entry.size).to_string(),
+                humantime::format_rfc3339_seconds(entry.modified).to_string(),
+                entry.targets.iter().join(", "),
+            );
+        }
+
+        let total = ByteSize(entries.iter().map(|entry| entry.size).sum());
+        info!("{} layers, {total} total", entries.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use camino::{Utf8Path, Utf8PathBuf};
+    use camino_tempfile::Utf8TempDir;
+
+    use crate::RaptorResult;
+    use crate::build::{LayerIndex, LayerInfo, LayerInventory, LayerStatus, RaptorBuilder};
+    use crate::program::Loader;
+
+    fn layer(name: &str, byte: &str) -> LayerInfo {
+        LayerInfo::new(name.into(), LayerInfo::parse_id(&byte.repeat(32)).unwrap())
+    }
+
+    fn mkdir(path: &Utf8Path) -> Utf8PathBuf {
+        std::fs::create_dir_all(path).unwrap();
+        path.to_path_buf()
+    }
+
+    #[test]
+    fn classify_store() -> RaptorResult<()> {
+        let tmp = Utf8TempDir::new()?;
+        let builder = RaptorBuilder::new(Loader::new()?, Utf8PathBuf::new(), true);
+
+        let current = layer("app", "01");
+        let stale = layer("app", "02");
+        let unknown = layer("other", "03");
+
+        let mut index = LayerIndex::default();
+        for info in [&current, &stale, &unknown] {
+            index.insert(info);
+            mkdir(&tmp.path().join(info.id()));
+        }
+        mkdir(&tmp.path().join("not-a-layer"));
+
+        let mut inventory = LayerInventory::new(&builder);
+        inventory.names.insert("app".into());
+        inventory
+            .refs
+            .entry(tmp.path().join(current.id()))
+            .or_default()
+            .insert("target".into());
+
+        let mut entries = inventory.store_entries(tmp.path(), &index)?;
+        entries.sort_by(|a, b| a.hash.cmp(&b.hash));
+
+        let status = entries.iter().map(|entry| entry.status).collect::<Vec<_>>();
+        assert_eq!(
+            status,
+            [
+                LayerStatus::Current,
+                LayerStatus::Stale,
+                LayerStatus::Unknown
+            ]
+        );
+        assert_eq!(entries[0].name.as_deref(), Some("app"));
+        assert_eq!(entries[0].targets.iter().collect::<Vec<_>>(), ["target"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn classify_cache() -> RaptorResult<()> {
+        let tmp = Utf8TempDir::new()?;
+        let builder = RaptorBuilder::new(Loader::new()?, Utf8PathBuf::new(), true);
+
+        let work = layer("app", "01");
+        let mut index = LayerIndex::default();
+        index.insert(&work);
+
+        let legacy = mkdir(&tmp.path().join("base-000000000000ABCD"));
+
+        let mut inventory = LayerInventory::new(&builder);
+        inventory
+            .refs
+            .entry(legacy.clone())
+            .or_default()
+            .insert("target".into());
+
+        let status = |path: &Utf8Path| {
+            inventory
+                .cache_entry(path, &index)
+                .unwrap()
+                .map(|entry| (entry.name, entry.status))
+        };
+
+        assert_eq!(
+            status(&mkdir(&tmp.path().join(format!("build-{}", work.id())))),
+            Some((Some("app".into()), LayerStatus::Incomplete))
+        );
+        assert_eq!(
+            status(&legacy),
+            Some((Some("base".into()), LayerStatus::Legacy))
+        );
+        assert_eq!(
+            status(&mkdir(&tmp.path().join("other-0000000000005678"))),
+            Some((Some("other".into()), LayerStatus::Unknown))
+        );
+        assert_eq!(status(&mkdir(&tmp.path().join("not-a-layer"))), None);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,125 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+
+use camino::Utf8Path;
+use dregistry::credentials::CredentialStore;
+use dregistry::digest::Digest;
+use dregistry::source::DockerSource;
+use serde::{Deserialize, Serialize};
+
+use crate::RaptorResult;
+
+/// Lock file (`Raptor.lock`), pinning the docker images used by `FROM
+/// docker://` to the digests they resolved to.
+///
+/// Pinned images are pulled by digest, so all builds (on all machines) use
+/// the same base images, until the lock file is updated.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+pub struct LockFile {
+    #[serde(default)]
+    pub docker: BTreeMap<String, Digest>,
+}
+
+impl LockFile {
+    pub const FILE: &str = "Raptor.lock";
+
+    const HEADER: &str =
+        "# This file is generated by raptor, and updated with `raptor update`.\n\n";
+
+    pub fn load(path: &Utf8Path) -> RaptorResult<Self> {
+        match fs::read_to_string(path) {
+            Ok(text) => Ok(toml::from_str(&text)?),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Utf8Path) -> RaptorResult<()> {
+        let tmp = path.with_extension("lock.tmp");
+        fs::write(&tmp, format!("{}{}", Self::HEADER, toml::to_string(self)?))?;
+        fs::rename(&tmp, path)?;
+
+        Ok(())
+    }
+
+    /// Name of a docker source in the lock file, with the default registry,
+    /// namespace and tag filled in (e.g. `index.docker.io/library/debian:trixie`)
+    #[must_use]
+    pub fn key(source: &DockerSource) -> String {
+        DockerSource {
+            host: Some(CredentialStore::normalize(&source.domain())),
+            port: None,
+            namespace: None,
+            repository: source.image_ref(),
+            tag: Some(source.image_tag().to_string()),
+            digest: None,
+        }
+        .to_string()
+    }
+
+    /// Digest pinned for `source`, unless the source already specifies one
+    #[must_use]
+    pub fn get(&self, source: &DockerSource) -> Option<&Digest> {
+        if source.digest.is_some() {
+            return None;
+        }
+
+        self.docker.get(&Self::key(source))
+    }
+
+    /// Pin `source` to `digest`, returning the previously pinned digest
+    pub fn insert(&mut self, source: &DockerSource, digest: Digest) -> Option<Digest> {
+        self.docker.insert(Self::key(source), digest)
+    }
+
+    /// Apply the pinned digest (if any) to `source`
+    #[must_use]
+    pub fn pin(&self, mut source: DockerSource) -> DockerSource {
+        if let Some(digest) = self.get(&source) {
+            source.digest = Some(digest.clone());
+        }
+        source
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use camino_tempfile::Utf8TempDir;
+    use dregistry::digest::Digest;
+
+    use crate::RaptorResult;
+    use crate::build::{LockFile, RaptorBuilder};
+
+    #[test]
+    fn lock_file() -> RaptorResult<()> {
+        let tmp = Utf8TempDir::new()?;
+        let path = tmp.path().join(LockFile::FILE);
+
+        let digest = Digest::sha256(b"manifest");
+        let source = RaptorBuilder::parse_docker_source("debian:trixie")?;
+
+        let mut lock = LockFile::load(&path)?;
+        assert_eq!(lock.pin(source.clone()), source);
+
+        assert_eq!(lock.insert(&source, digest.clone()), None);
+        assert_eq!(
+            LockFile::key(&source),
+            "index.docker.io/library/debian:trixie"
+        );
+
+        /* the same image, written differently */
+        let same = RaptorBuilder::parse_docker_source("docker.io/library/debian:trixie")?;
+        assert_eq!(lock.pin(same).digest, Some(digest));
+
+        /* explicit digests are never overridden */
+        let other = Digest::sha256(b"other");
+        let explicit = RaptorBuilder::parse_docker_source(&format!("debian@{other}"))?;
+        assert_eq!(lock.pin(explicit).digest, Some(other));
+
+        lock.save(&path)?;
+        assert_eq!(LockFile::load(&path)?, lock);
+
+        Ok(())
+    }
+}
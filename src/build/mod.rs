@@ -1,9 +1,27 @@
 mod builder;
 mod cache;
+mod diff;
+mod export;
+mod gc;
+mod inventory;
+mod lock;
 mod present;
+mod remote;
+mod reproducible;
+mod secrets;
 mod stats;
+mod store;
 
 pub use builder::*;
 pub use cache::*;
+pub use diff::*;
+pub use export::*;
+pub use gc::*;
+pub use inventory::*;
+pub use lock::*;
 pub use present::*;
+pub use remote::*;
+pub use reproducible::*;
+pub use secrets::*;
 pub use stats::*;
+pub use store::*;
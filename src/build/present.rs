@@ -18,7 +18,7 @@ impl<'a> Presenter<'a> {
         println!("{prefix}");
         println!("{prefix}{} [{}]", "# target".dimmed(), name.bright_white());
 
-        if let BuildTarget::Program(program) = &self.0.targets[name] {
+        if let BuildTarget::Program(program, _) = &self.0.targets[name] {
             for inst in &program.code {
                 match inst {
                     Item::Statement(stmt) => {
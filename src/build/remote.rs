@@ -0,0 +1,403 @@
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, IntoInnerError};
+use std::str::FromStr;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use camino_tempfile::NamedUtf8TempFile;
+use dregistry::api::{DockerLayer, DockerLayers, Manifest, MediaType, V2Manifest};
+use dregistry::archive::{LayerArchiver, LayerUnarchiver};
+use dregistry::client::DockerClient;
+use dregistry::digest::{Digest, DigestWriter};
+use dregistry::downloader::DockerDownloader;
+use dregistry::error::DockerError;
+use dregistry::push::ImagePusher;
+use dregistry::source::DockerSource;
+
+use crate::RaptorResult;
+use crate::build::{LayerInfo, RaptorBuilder};
+
+/// Location of a shared layer cache
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RemoteCacheUrl {
+    /// A directory (e.g. on a network filesystem), holding one
+    /// `sha256/<id>.tar.zst` archive per layer
+    Dir(Utf8PathBuf),
+
+    /// A repository in an OCI registry (`docker://host/repo`), holding one
+    /// single-layer image per layer, tagged with the layer id
+    Registry(DockerSource),
+}
+
+impl RemoteCacheUrl {
+    const DOCKER_PREFIX: &str = "docker://";
+    const FILE_PREFIX: &str = "file://";
+}
+
+impl FromStr for RemoteCacheUrl {
+    type Err = DockerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(image) = s.strip_prefix(Self::DOCKER_PREFIX) {
+            Ok(Self::Registry(dregistry::reference::parse(image)?))
+        } else {
+            Ok(Self::Dir(
+                s.strip_prefix(Self::FILE_PREFIX).unwrap_or(s).into(),
+            ))
+        }
+    }
+}
+
+impl Display for RemoteCacheUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Dir(dir) => write!(f, "{dir}"),
+            Self::Registry(source) => write!(f, "{}{source}", Self::DOCKER_PREFIX),
+        }
+    }
+}
+
+/// Shared layer cache, consulted before building a missing layer, and
+/// updated with freshly built layers.
+///
+/// Layers are stored by id (their cache key), so any layer found in the
+/// remote cache is identical to what a local build would have produced.
+#[derive(Debug, Clone)]
+pub struct RemoteCache {
+    url: RemoteCacheUrl,
+    readonly: bool,
+}
+
+impl RemoteCache {
+    const EXTENSION: &str = "tar.zst";
+
+    /// Compression level for uploaded layers (zstd default)
+    const LEVEL: i32 = 3;
+
+    /// Annotation recording the target name on uploaded layers
+    const TITLE: &str = "org.opencontainers.image.title";
+
+    #[must_use]
+    pub const fn new(url: RemoteCacheUrl) -> Self {
+        Self {
+            url,
+            readonly: false,
+        }
+    }
+
+    /// Only fetch layers from the remote cache, never upload
+    #[must_use]
+    pub const fn with_readonly(mut self, readonly: bool) -> Self {
+        self.readonly = readonly;
+        self
+    }
+
+    #[must_use]
+    pub const fn url(&self) -> &RemoteCacheUrl {
+        &self.url
+    }
+
+    #[must_use]
+    pub const fn readonly(&self) -> bool {
+        self.readonly
+    }
+
+    fn archive_path(dir: &Utf8Path, layer: &LayerInfo) -> Utf8PathBuf {
+        dir.join(LayerInfo::STORE)
+            .join(format!("{}.{}", layer.id(), Self::EXTENSION))
+    }
+
+    fn fetch_dir(dir: &Utf8Path, layer: &LayerInfo, dest: &Utf8Path) -> RaptorResult<bool> {
+        let path = Self::archive_path(dir, layer);
+        if !fs::exists(&path)? {
+            return Ok(false);
+        }
+
+        Self::unpack(&path, dest)?;
+
+        Ok(true)
+    }
+
+    fn store_dir(dir: &Utf8Path, layer: &LayerInfo, path: &Utf8Path) -> RaptorResult<()> {
+        let target = Self::archive_path(dir, layer);
+        if fs::exists(&target)? {
+            return Ok(());
+        }
+
+        let parent = target.parent().unwrap();
+        fs::create_dir_all(parent)?;
+
+        /* write to a temporary file first, so concurrent readers never see a
+         * partial archive */
+        let tmp = NamedUtf8TempFile::new_in(parent)?;
+        Self::pack(path, tmp.as_file())?;
+        tmp.persist(&target).map_err(|err| err.error)?;
+
+        Ok(())
+    }
+
+    fn fetch_registry(
+        downloader: &DockerDownloader,
+        source: &DockerSource,
+        layer: &LayerInfo,
+        dest: &Utf8Path,
+    ) -> RaptorResult<bool> {
+        let mut dc = downloader.client(source)?;
+
+        let Some(manifest) = dc.find_manifest(&layer.id())? else {
+            return Ok(false);
+        };
+
+        let Manifest::V2(V2Manifest::Manifest(DockerLayers { layers, .. })) = manifest else {
+            return Err(DockerError::ManifestNotFound.into());
+        };
+
+        let [blob] = &layers[..] else {
+            return Err(DockerError::ManifestNotFound.into());
+        };
+
+        downloader.download_blob(&mut dc, &blob.digest)?;
+
+        /* the unpacked layer is all we need, so do not keep the blob around
+         * in the download cache */
+        let path = downloader.layer_file_name(&blob.digest);
+        let res = Self::unpack(&path, dest);
+        fs::remove_file(&path)?;
+        res?;
+
+        Ok(true)
+    }
+
+    fn store_registry(
+        downloader: &DockerDownloader,
+        source: &DockerSource,
+        layer: &LayerInfo,
+        path: &Utf8Path,
+    ) -> RaptorResult<()> {
+        let mut dc = downloader.client(source)?.with_push();
+
+        if dc.find_manifest(&layer.id())?.is_some() {
+            return Ok(());
+        }
+
+        let tmp = NamedUtf8TempFile::new()?;
+        let (digest, size) = Self::pack(path, tmp.as_file())?;
+
+        if !dc.blob_exists(&digest)? {
+            dc.push_blob_chunked(
+                &digest,
+                File::open(tmp.path())?,
+                ImagePusher::DEFAULT_CHUNK_SIZE,
+            )?;
+        }
+
+        /* the layers are not runnable images by themselves, so use an empty
+         * configuration */
+        let config = b"{}";
+        let config_digest = Digest::sha256(config);
+        if !dc.blob_exists(&config_digest)? {
+            dc.push_blob(&config_digest, config)?;
+        }
+
+        let manifest = V2Manifest::Manifest(DockerLayers {
+            config: DockerLayer {
+                data: None,
+                annotations: BTreeMap::new(),
+                digest: config_digest,
+                media_type: MediaType::ImageConfig,
+                size: config.len() as u64,
+            },
+            layers: vec![DockerLayer {
+                data: None,
+                annotations: BTreeMap::from([(Self::TITLE.to_string(), layer.name().to_string())]),
+                digest,
+                media_type: MediaType::ImageLayerZstd,
+                size,
+            }],
+            schema_version: 2,
+        });
+
+        let manifest = serde_json::to_vec(&manifest)?;
+        dc.put_manifest(&layer.id(), DockerClient::MIME_TYPE_MANIFEST, &manifest)?;
+
+        Ok(())
+    }
+
+    /// Try to fetch `layer` from the remote cache, unpacking it into `dest`.
+    ///
+    /// Returns `false` if the remote cache does not have the layer.
+    pub fn fetch(
+        &self,
+        builder: &RaptorBuilder,
+        layer: &LayerInfo,
+        dest: &Utf8Path,
+    ) -> RaptorResult<bool> {
+        match &self.url {
+            RemoteCacheUrl::Dir(dir) => Self::fetch_dir(dir, layer, dest),
+            RemoteCacheUrl::Registry(source) => {
+                Self::fetch_registry(&builder.downloader()?, source, layer, dest)
+            }
+        }
+    }
+
+    /// Upload a freshly built layer (stored at `path`) to the remote cache,
+    /// unless it is already present there.
+    pub fn store(
+        &self,
+        builder: &RaptorBuilder,
+        layer: &LayerInfo,
+        path: &Utf8Path,
+    ) -> RaptorResult<()> {
+        match &self.url {
+            RemoteCacheUrl::Dir(dir) => Self::store_dir(dir, layer, path),
+            RemoteCacheUrl::Registry(source) => {
+                Self::store_registry(&builder.downloader()?, source, layer, path)
+            }
+        }
+    }
+
+    /// Write the layer at `path` as a zstd-compressed archive, returning the
+    /// digest and size of the archive
+    fn pack(path: &Utf8Path, file: &File) -> RaptorResult<(Digest, u64)> {
+        let writer = DigestWriter::new(BufWriter::new(file));
+        let encoder = zstd::Encoder::new(writer, Self::LEVEL)?;
+        let (writer, digest, size) = LayerArchiver::new(path).write(encoder)?.finish()?.finish();
+        writer
+            .into_inner()
+            .map_err(IntoInnerError::into_error)?
+            .sync_all()?;
+
+        Ok((digest, size))
+    }
+
+    fn unpack(archive: &Utf8Path, dest: &Utf8Path) -> RaptorResult<()> {
+        fs::create_dir_all(dest)?;
+
+        let reader = BufReader::new(File::open(archive)?);
+        LayerUnarchiver::new(dest).apply(reader)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use camino_tempfile::Utf8TempDir;
+    use dregistry::downloader::DockerDownloader;
+    use dregistry::registry::{RegistryConfig, RegistryStore, Scheme};
+    use dregistry::testutil::serve_registry;
+
+    use crate::RaptorResult;
+    use crate::build::{LayerInfo, RemoteCache, RemoteCacheUrl};
+
+    #[test]
+    fn remote_cache_url() {
+        let url: RemoteCacheUrl = "/srv/raptor-cache".parse().unwrap();
+        assert_eq!(url, RemoteCacheUrl::Dir("/srv/raptor-cache".into()));
+
+        let url: RemoteCacheUrl = "file:///srv/raptor-cache".parse().unwrap();
+        assert_eq!(url, RemoteCacheUrl::Dir("/srv/raptor-cache".into()));
+
+        let url: RemoteCacheUrl = "docker://registry.example.org/raptor/cache"
+            .parse()
+            .unwrap();
+        let RemoteCacheUrl::Registry(source) = url else {
+            panic!("expected registry url");
+        };
+        assert_eq!(source.domain(), "registry.example.org");
+        assert_eq!(source.image_ref(), "raptor/cache");
+    }
+
+    #[test]
+    fn remote_cache_dir() -> RaptorResult<()> {
+        let tmp = Utf8TempDir::new()?;
+        let root = tmp.path();
+
+        let src = root.join("src");
+        fs::create_dir_all(src.join("etc"))?;
+        fs::write(src.join("etc/hostname"), "raptor")?;
+
+        let id = "ab".repeat(32);
+        let layer = LayerInfo::new("base".into(), LayerInfo::parse_id(&id)?);
+        let remote = root.join("remote");
+
+        let dest = root.join("dest");
+        assert!(!RemoteCache::fetch_dir(&remote, &layer, &dest)?);
+
+        RemoteCache::store_dir(&remote, &layer, &src)?;
+        assert!(remote.join(format!("sha256/{id}.tar.zst")).exists());
+
+        /* storing again is a no-op */
+        RemoteCache::store_dir(&remote, &layer, &src)?;
+
+        assert!(RemoteCache::fetch_dir(&remote, &layer, &dest)?);
+        assert_eq!(fs::read_to_string(dest.join("etc/hostname"))?, "raptor");
+
+        Ok(())
+    }
+
+    #[test]
+    fn remote_cache_registry() -> RaptorResult<()> {
+        let (addr, store) = serve_registry();
+
+        let tmp = Utf8TempDir::new()?;
+        let root = tmp.path();
+
+        let src = root.join("src");
+        fs::create_dir_all(src.join("etc"))?;
+        fs::write(src.join("etc/hostname"), "raptor")?;
+
+        let id = "cd".repeat(32);
+        let layer = LayerInfo::new("base".into(), LayerInfo::parse_id(&id)?);
+
+        let mut registries = RegistryStore::new();
+        registries.insert(
+            &addr,
+            RegistryConfig {
+                scheme: Scheme::Http,
+                ..RegistryConfig::default()
+            },
+        );
+        let downloader = DockerDownloader::new(root.join("cache"))?.with_registries(registries);
+
+        let RemoteCacheUrl::Registry(source) = format!("docker://{addr}/raptor/cache").parse()?
+        else {
+            panic!("expected registry url");
+        };
+
+        let dest = root.join("dest");
+        assert!(!RemoteCache::fetch_registry(
+            &downloader,
+            &source,
+            &layer,
+            &dest
+        )?);
+
+        RemoteCache::store_registry(&downloader, &source, &layer, &src)?;
+        assert!(
+            store
+                .lock()
+                .unwrap()
+                .contains_key(&format!("raptor/cache/manifests/{id}"))
+        );
+
+        /* storing again is a no-op */
+        RemoteCache::store_registry(&downloader, &source, &layer, &src)?;
+
+        assert!(RemoteCache::fetch_registry(
+            &downloader,
+            &source,
+            &layer,
+            &dest
+        )?);
+        assert_eq!(fs::read_to_string(dest.join("etc/hostname"))?, "raptor");
+
+        /* the fetched blob is not kept in the download cache */
+        assert!(downloader.blobs()?.is_empty());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,159 @@
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+
+use camino::Utf8Path;
+use nix::fcntl::AT_FDCWD;
+use nix::sys::stat::{UtimensatFlags, utimensat};
+use nix::sys::time::TimeSpec;
+use serde::Serialize;
+
+use crate::RaptorResult;
+
+/// Files that differ between otherwise identical builds (caches recording
+/// inode numbers or timestamps, and logs), relative to the layer root.
+const NON_DETERMINISTIC: &[&str] = &[
+    "var/cache/ldconfig/aux-cache",
+    "var/cache/apt/pkgcache.bin",
+    "var/cache/apt/srcpkgcache.bin",
+    "var/log/alternatives.log",
+    "var/log/apt/eipp.log.xz",
+    "var/log/apt/history.log",
+    "var/log/apt/term.log",
+    "var/log/dpkg.log",
+];
+
+/// Reproducible build settings.
+///
+/// Builds get `SOURCE_DATE_EPOCH` set in their environment, and after each
+/// build, known non-deterministic files are removed from the layer, and all
+/// modification times are clamped to the epoch. Building the same target
+/// twice then yields identical layers (and exported images).
+///
+/// See <https://reproducible-builds.org/specs/source-date-epoch/>
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub struct Reproducible {
+    epoch: i64,
+}
+
+impl Reproducible {
+    pub const ENV: &str = "SOURCE_DATE_EPOCH";
+
+    #[must_use]
+    pub const fn new(epoch: i64) -> Self {
+        Self { epoch }
+    }
+
+    #[must_use]
+    pub const fn epoch(self) -> i64 {
+        self.epoch
+    }
+
+    /// Normalize a freshly built layer (overlayfs upper directory) at `root`
+    pub fn normalize(self, root: &Utf8Path) -> RaptorResult<()> {
+        for name in NON_DETERMINISTIC {
+            let path = root.join(name);
+            if path.symlink_metadata().is_ok_and(|md| md.is_file()) {
+                debug!("Removing non-deterministic file {path}");
+                fs::remove_file(path)?;
+            }
+        }
+
+        /* the layer root itself is not part of the layer contents */
+        for dent in root.read_dir_utf8()? {
+            self.clamp(dent?.path())?;
+        }
+
+        Ok(())
+    }
+
+    /// Clamp the modification time of `path` (and everything below it) to
+    /// the epoch. Older timestamps are kept, as recommended by the spec.
+    fn clamp(self, path: &Utf8Path) -> RaptorResult<()> {
+        let md = path.symlink_metadata()?;
+
+        /* clamp directory contents first, since that does not change the
+         * modification time of the directory itself */
+        if md.is_dir() {
+            for dent in path.read_dir_utf8()? {
+                self.clamp(dent?.path())?;
+            }
+        }
+
+        if (md.mtime(), md.mtime_nsec()) > (self.epoch, 0) {
+            trace!("Clamping mtime of {path}");
+            utimensat(
+                AT_FDCWD,
+                path.as_std_path(),
+                &TimeSpec::UTIME_OMIT,
+                &TimeSpec::new(self.epoch, 0),
+                UtimensatFlags::NoFollowSymlink,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::{MetadataExt, symlink};
+
+    use camino::{Utf8Path, Utf8PathBuf};
+    use camino_tempfile::Utf8TempDir;
+
+    use crate::RaptorResult;
+    use crate::build::Reproducible;
+
+    fn mtimes(root: &Utf8Path, out: &mut Vec<(Utf8PathBuf, i64, i64)>) -> RaptorResult<()> {
+        let mut names = root
+            .read_dir_utf8()?
+            .map(|dent| Ok(dent?.into_path()))
+            .collect::<RaptorResult<Vec<_>>>()?;
+        names.sort();
+
+        for path in names {
+            let md = path.symlink_metadata()?;
+            out.push((path.clone(), md.mtime(), md.mtime_nsec()));
+            if md.is_dir() {
+                mtimes(&path, out)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn reproducible_normalize() -> RaptorResult<()> {
+        let tmp = Utf8TempDir::new()?;
+        let root = tmp.path();
+
+        fs::create_dir_all(root.join("etc"))?;
+        fs::create_dir_all(root.join("var/cache/ldconfig"))?;
+        fs::write(root.join("etc/hostname"), "raptor")?;
+        fs::write(root.join("var/cache/ldconfig/aux-cache"), "cache")?;
+        symlink("hostname", root.join("etc/link"))?;
+
+        let repro = Reproducible::new(1_700_000_000);
+        repro.normalize(root)?;
+
+        assert!(!root.join("var/cache/ldconfig/aux-cache").exists());
+
+        let mut times = vec![];
+        mtimes(root, &mut times)?;
+        assert_eq!(times.len(), 6);
+        for (path, mtime, nsec) in times {
+            assert_eq!((mtime, nsec), (repro.epoch(), 0), "{path}");
+        }
+
+        /* timestamps older than the epoch are kept */
+        let old = Reproducible::new(i64::MAX);
+        old.normalize(root)?;
+
+        let mut times = vec![];
+        mtimes(root, &mut times)?;
+        assert!(times.iter().all(|(_, mtime, _)| *mtime == repro.epoch()));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+use std::fs;
+use std::str::FromStr;
+
+use camino::Utf8PathBuf;
+use falcon::client::SecretMount;
+use raptor_parser::ast::RunSecret;
+
+use crate::{RaptorError, RaptorResult};
+
+/// Source of a build secret
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SecretSource {
+    /// Contents of a file on the host
+    File(Utf8PathBuf),
+
+    /// Value of an environment variable
+    Env(String),
+}
+
+impl SecretSource {
+    pub fn read(&self) -> RaptorResult<Vec<u8>> {
+        match self {
+            Self::File(path) => Ok(fs::read(path)?),
+            Self::Env(var) => Ok(std::env::var(var)?.into_bytes()),
+        }
+    }
+}
+
+/// Secret specified on the command line, as `id=<id>,src=<path>` or
+/// `id=<id>,env=<var>`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecretArg {
+    pub id: String,
+    pub source: SecretSource,
+}
+
+impl FromStr for SecretArg {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut id = None;
+        let mut source = None;
+
+        for opt in s.split(',') {
+            let Some((key, value)) = opt.split_once('=') else {
+                return Err("expected key=value");
+            };
+
+            match key {
+                "id" => id = Some(value.to_string()),
+                "src" | "source" => source = Some(SecretSource::File(value.into())),
+                "env" => source = Some(SecretSource::Env(value.to_string())),
+                _ => return Err("unknown secret option (expected id, src or env)"),
+            }
+        }
+
+        let id = id.ok_or("missing secret id")?;
+        if !RunSecret::valid_id(&id) {
+            return Err("invalid secret id (must be a plain file name)");
+        }
+
+        /* like docker, default to the environment variable named after the id */
+        let source = source.unwrap_or_else(|| SecretSource::Env(id.clone()));
+
+        Ok(Self { id, source })
+    }
+}
+
+/// Secrets read for a single build.
+///
+/// The secrets are only kept in memory. Falcon mounts each secret only for the
+/// `RUN` instruction that requests it, so it is never visible to other
+/// instructions, and never written to the build root.
+#[derive(Debug, Default)]
+pub struct SecretStore {
+    secrets: HashMap<String, Vec<u8>>,
+}
+
+impl SecretStore {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.secrets.is_empty()
+    }
+
+    pub fn add(&mut self, id: &str, source: &SecretSource) -> RaptorResult<()> {
+        if !RunSecret::valid_id(id) {
+            return Err(RaptorError::InvalidSecretId(id.to_string()));
+        }
+
+        if !self.secrets.contains_key(id) {
+            self.secrets.insert(id.to_string(), source.read()?);
+        }
+
+        Ok(())
+    }
+
+    /// Mount for a `RUN --secret`, or `None` if the secret is not available
+    #[must_use]
+    pub fn mount(&self, secret: &RunSecret) -> Option<SecretMount> {
+        self.secrets.get(&secret.id).map(|data| SecretMount {
+            data: data.clone(),
+            target: secret.target(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::RaptorError;
+    use crate::build::{SecretArg, SecretSource, SecretStore};
+
+    #[test]
+    fn secret_arg_parse() {
+        let arg: SecretArg = "id=npmrc,src=/home/user/.npmrc".parse().unwrap();
+        assert_eq!(arg.id, "npmrc");
+        assert_eq!(arg.source, SecretSource::File("/home/user/.npmrc".into()));
+
+        let arg: SecretArg = "id=token,env=NPM_TOKEN".parse().unwrap();
+        assert_eq!(arg.source, SecretSource::Env("NPM_TOKEN".into()));
+
+        let arg: SecretArg = "id=TOKEN".parse().unwrap();
+        assert_eq!(arg.source, SecretSource::Env("TOKEN".into()));
+
+        "src=/foo".parse::<SecretArg>().unwrap_err();
+        "id=foo,bar".parse::<SecretArg>().unwrap_err();
+        "id=foo,bar=baz".parse::<SecretArg>().unwrap_err();
+        "id=".parse::<SecretArg>().unwrap_err();
+        "id=..,env=FOO".parse::<SecretArg>().unwrap_err();
+        "id=a/b,env=FOO".parse::<SecretArg>().unwrap_err();
+    }
+
+    #[test]
+    fn secret_store_invalid_id() {
+        let mut store = SecretStore::new();
+        let source = SecretSource::Env("PATH".into());
+
+        for id in ["", ".", "..", "a/b", "../etc/passwd"] {
+            assert!(matches!(
+                store.add(id, &source),
+                Err(RaptorError::InvalidSecretId(_))
+            ));
+        }
+
+        store.add("path", &source).unwrap();
+        assert!(!store.is_empty());
+    }
+}
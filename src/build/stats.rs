@@ -31,7 +31,7 @@ impl BuildTargetStats {
     pub fn merge(&mut self, stack: Vec<BuildTarget>) -> RaptorResult<()> {
         for layer in stack {
             match layer {
-                BuildTarget::Program(ref program) => {
+                BuildTarget::Program(ref program, _) => {
                     let name = program.path.file_stem().unwrap().to_string();
 
                     if let Some((from, _origin)) = program.from() {
@@ -56,7 +56,7 @@ impl BuildTargetStats {
                     }
                     self.targets.insert(name, layer);
                 }
-                BuildTarget::DockerSource(ref src) => {
+                BuildTarget::DockerSource(ref src, _) => {
                     let name = format!("docker://{src}");
                     self.targets.insert(name.clone(), layer);
                     self.roots.insert(name);
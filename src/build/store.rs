@@ -0,0 +1,165 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::{self, File};
+use std::io::ErrorKind;
+
+use camino::Utf8Path;
+use nix::fcntl::{Flock, FlockArg};
+use serde::{Deserialize, Serialize};
+
+use crate::RaptorResult;
+use crate::build::LayerInfo;
+
+/// Index of the content-addressed layer store (`layers/index.json`), mapping
+/// target names to the ids of the layers built for them.
+///
+/// The layers themselves are only identified by their id, so the index is
+/// what allows telling stale layers of a target apart from unknown ones.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct LayerIndex {
+    #[serde(default)]
+    pub targets: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl LayerIndex {
+    pub const FILE: &str = "index.json";
+
+    const LOCK: &str = "index.lock";
+
+    /// Check if `name` is a file used by the index (and not a layer)
+    #[must_use]
+    pub fn is_index_file(name: &str) -> bool {
+        name == Self::FILE || name == Self::LOCK || name == format!("{}.tmp", Self::FILE)
+    }
+
+    /// Load the index of the layer cache in `root` (usually
+    /// [`LayerInfo::ROOT`])
+    pub fn load(root: &Utf8Path) -> RaptorResult<Self> {
+        match fs::read(root.join(Self::FILE)) {
+            Ok(data) => Ok(serde_json::from_slice(&data)?),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Modify the index while holding an exclusive lock, and replace it
+    /// atomically afterwards
+    pub fn update(root: &Utf8Path, fun: impl FnOnce(&mut Self)) -> RaptorResult<()> {
+        fs::create_dir_all(root)?;
+
+        let lock = File::create(root.join(Self::LOCK))?;
+        let _lock = Flock::lock(lock, FlockArg::LockExclusive).map_err(|(_, err)| err)?;
+
+        let mut index = Self::load(root)?;
+        fun(&mut index);
+
+        let tmp = root.join(format!("{}.tmp", Self::FILE));
+        fs::write(&tmp, serde_json::to_vec_pretty(&index)?)?;
+        fs::rename(&tmp, root.join(Self::FILE))?;
+
+        Ok(())
+    }
+
+    pub fn insert(&mut self, layer: &LayerInfo) {
+        self.targets
+            .entry(layer.name().to_string())
+            .or_default()
+            .insert(layer.id());
+    }
+
+    pub fn remove(&mut self, id: &str) {
+        for ids in self.targets.values_mut() {
+            ids.remove(id);
+        }
+        self.targets.retain(|_, ids| !ids.is_empty());
+    }
+
+    /// Map from layer id to the name of the target it was built for
+    #[must_use]
+    pub fn names(&self) -> HashMap<&str, &str> {
+        self.targets
+            .iter()
+            .flat_map(|(name, ids)| ids.iter().map(move |id| (id.as_str(), name.as_str())))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use camino_tempfile::Utf8TempDir;
+
+    use crate::RaptorResult;
+    use crate::build::{LayerIndex, LayerInfo};
+
+    fn layer(name: &str, n: usize) -> LayerInfo {
+        LayerInfo::new(
+            name.into(),
+            LayerInfo::parse_id(&format!("{n:064x}")).unwrap(),
+        )
+    }
+
+    #[test]
+    fn layer_index() {
+        let id = "ab".repeat(32);
+        let layer = LayerInfo::new("sub/base".into(), LayerInfo::parse_id(&id).unwrap());
+
+        let mut index = LayerIndex::default();
+        index.insert(&layer);
+        assert_eq!(index.names()[id.as_str()], "sub/base");
+
+        index.remove(&id);
+        assert!(index.targets.is_empty());
+    }
+
+    #[test]
+    fn layer_index_update() -> RaptorResult<()> {
+        let tmp = Utf8TempDir::new()?;
+        let root = tmp.path().join("layers");
+
+        assert!(LayerIndex::load(&root)?.targets.is_empty());
+
+        LayerIndex::update(&root, |index| index.insert(&layer("app", 1)))?;
+        LayerIndex::update(&root, |index| index.insert(&layer("app", 2)))?;
+
+        let index = LayerIndex::load(&root)?;
+        assert_eq!(index.targets["app"].len(), 2);
+
+        /* only the index (and its lock) is left behind */
+        let mut names = root
+            .read_dir_utf8()?
+            .map(|dent| Ok(dent?.file_name().to_string()))
+            .collect::<RaptorResult<Vec<_>>>()?;
+        names.sort();
+        assert_eq!(names, [LayerIndex::FILE, LayerIndex::LOCK]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn layer_index_concurrent() -> RaptorResult<()> {
+        const THREADS: usize = 8;
+        const UPDATES: usize = 20;
+
+        let tmp = Utf8TempDir::new()?;
+        let root = tmp.path();
+
+        /* without the lock, concurrent updates would overwrite each other */
+        thread::scope(|scope| {
+            for thread in 0..THREADS {
+                scope.spawn(move || {
+                    for n in 0..UPDATES {
+                        let layer = layer(&format!("target{thread}"), thread * UPDATES + n);
+                        LayerIndex::update(root, |index| index.insert(&layer)).unwrap();
+                    }
+                });
+            }
+        });
+
+        let index = LayerIndex::load(root)?;
+        assert_eq!(index.targets.len(), THREADS);
+        assert!(index.targets.values().all(|ids| ids.len() == UPDATES));
+
+        Ok(())
+    }
+}
@@ -4,11 +4,13 @@ use std::sync::Arc;
 
 use camino::Utf8Path;
 use minijinja::Value;
+use serde::Serialize;
 
-use crate::dsl::Program;
+use crate::dsl::{BuildArgs, FromParams, Program};
 use raptor_parser::ast::{Instruction, Origin, Statement};
 
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Item {
     Statement(Statement),
     Program(Arc<Program>),
@@ -25,6 +27,8 @@ impl Item {
             code: code.into_iter().collect(),
             ctx,
             path: path.as_ref().into(),
+            args: BuildArgs::new(),
+            params: FromParams::new(),
         }))
     }
 
@@ -1,27 +1,62 @@
+use std::collections::BTreeMap;
 use std::fmt::{self, Display};
 
 use camino::Utf8PathBuf;
 use colored::Colorize;
 use minijinja::Value;
+use serde::Serialize;
 
 use raptor_parser::ast::{
-    FromSource, InstCmd, InstEntrypoint, InstMount, Instruction, Origin, Statement,
+    FromSource, InstCmd, InstEntrypoint, InstFrom, InstMount, InstUser, Instruction, MountType,
+    NetworkMode, Origin, Statement,
 };
 
 use crate::RaptorResult;
 use crate::dsl::Item;
+use crate::program::ResolveArgs;
 
-#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+/// Build argument values (from `--arg` or `arg` in `Raptor.toml`), by name
+pub type BuildArgs = BTreeMap<String, String>;
+
+/// Parameters given on a `FROM` instruction, by name
+pub type FromParams = BTreeMap<String, Value>;
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize)]
 pub struct Program {
     pub code: Vec<Item>,
     pub ctx: Value,
     pub path: Utf8PathBuf,
+
+    /// Build arguments the program was loaded with (which are passed on to
+    /// the program it is built `FROM`)
+    #[serde(skip_serializing_if = "BuildArgs::is_empty")]
+    pub args: BuildArgs,
+
+    /// Parameters from the `FROM` instruction the program was loaded by
+    #[serde(skip_serializing_if = "FromParams::is_empty")]
+    pub params: FromParams,
 }
 
 impl Program {
     #[must_use]
     pub const fn new(code: Vec<Item>, ctx: Value, path: Utf8PathBuf) -> Self {
-        Self { code, ctx, path }
+        Self {
+            code,
+            ctx,
+            path,
+            args: BuildArgs::new(),
+            params: FromParams::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn with_args(self, args: BuildArgs) -> Self {
+        Self { args, ..self }
+    }
+
+    #[must_use]
+    pub fn with_params(self, params: FromParams) -> Self {
+        Self { params, ..self }
     }
 
     pub fn traverse(
@@ -41,13 +76,18 @@ impl Program {
 
     #[must_use]
     pub fn from(&self) -> Option<(&FromSource, &Origin)> {
+        self.from_inst().map(|(inst, origin)| (&inst.from, origin))
+    }
+
+    #[must_use]
+    pub fn from_inst(&self) -> Option<(&InstFrom, &Origin)> {
         for item in &self.code {
             if let Item::Statement(Statement {
                 inst: Instruction::From(inst),
                 origin,
             }) = item
             {
-                return Some((&inst.from, origin));
+                return Some((inst, origin));
             }
         }
 
@@ -84,6 +124,69 @@ impl Program {
         None
     }
 
+    /// The last `USER` instruction of the program (including included
+    /// modules), if any
+    pub fn user(&self) -> RaptorResult<Option<InstUser>> {
+        let mut user = None;
+
+        self.traverse(&mut |stmt| {
+            if let Instruction::User(inst) = &stmt.inst {
+                user = Some(inst.clone());
+            }
+            Ok(())
+        })?;
+
+        Ok(user)
+    }
+
+    /// The network mode of the last `NETWORK` instruction of the program
+    /// (including included modules), if any
+    pub fn network(&self) -> RaptorResult<Option<NetworkMode>> {
+        let mut network = None;
+
+        self.traverse(&mut |stmt| {
+            if let Instruction::Network(inst) = &stmt.inst {
+                network = Some(inst.mode.clone());
+            }
+            Ok(())
+        })?;
+
+        Ok(network)
+    }
+
+    /// Parameters for the program this program is built `FROM`, resolved in
+    /// the context of this program
+    pub fn from_params(&self) -> RaptorResult<FromParams> {
+        let Some((inst, _)) = self.from_inst() else {
+            return Ok(FromParams::new());
+        };
+
+        Ok(self
+            .ctx
+            .resolve_args(&inst.args)?
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect())
+    }
+
+    /// Build arguments declared with `ARG`, and their values
+    #[must_use]
+    pub fn arg_values(&self) -> Vec<(&str, Value)> {
+        self.code
+            .iter()
+            .filter_map(|item| match item {
+                Item::Statement(Statement {
+                    inst: Instruction::Arg(inst),
+                    ..
+                }) => Some((
+                    inst.name.as_str(),
+                    self.ctx.get_attr(&inst.name).unwrap_or_default(),
+                )),
+                _ => None,
+            })
+            .collect()
+    }
+
     #[must_use]
     pub fn mounts(&self) -> Vec<&InstMount> {
         let mut mounts = vec![];
@@ -100,6 +203,15 @@ impl Program {
 
         mounts
     }
+
+    /// Mounts of type `--cache`, which are only used when building
+    #[must_use]
+    pub fn cache_mounts(&self) -> Vec<&InstMount> {
+        self.mounts()
+            .into_iter()
+            .filter(|mount| mount.opts.mtype == MountType::Cache)
+            .collect()
+    }
 }
 
 impl Display for Program {
@@ -50,6 +50,9 @@ pub enum RaptorError {
     #[error(transparent)]
     ParseTomlError(#[from] toml::de::Error),
 
+    #[error(transparent)]
+    SerializeTomlError(#[from] toml::ser::Error),
+
     #[error(transparent)]
     WhichError(#[from] which::Error),
 
@@ -103,6 +106,48 @@ pub enum RaptorError {
 
     #[error("Unknown job: {0}")]
     UnknownJob(String),
+
+    #[error("No password specified for registry {0}")]
+    MissingPassword(String),
+
+    #[error("Found {0} invalid file(s) in download cache")]
+    CacheVerifyError(usize),
+
+    #[error("Found {0} file(s) that are not formatted")]
+    FormatCheckError(usize),
+
+    #[error("Could not parse {0} file(s)")]
+    FormatParseError(usize),
+
+    #[error("No files match pattern: {0}")]
+    GlobNoMatch(String, Origin),
+
+    #[error("Invalid pattern: {0}")]
+    GlobPatternError(glob::PatternError, Origin),
+
+    #[error("Destination for multiple sources or a directory must be a directory: {0}")]
+    CopyDestNotDirectory(camino::Utf8PathBuf),
+
+    #[error("Invalid cache mount name {0:?} (must be a plain file name)")]
+    InvalidCacheMountName(String),
+
+    #[error("Secret not specified: {0}")]
+    SecretMissing(String, Origin),
+
+    #[error("Build argument not specified: {0}")]
+    ArgMissing(String, Origin),
+
+    #[error("Secret {0} must specify either file or env")]
+    SecretSourceMissing(String),
+
+    #[error("Invalid secret id {0:?} (must be a plain file name)")]
+    InvalidSecretId(String),
+
+    #[error("Layer not found (target has not been built yet?): {0}")]
+    LayerNotFound(String),
+
+    #[error("Layer id is ambiguous: {0}")]
+    LayerAmbiguous(String),
 }
 
 impl RaptorError {
@@ -130,6 +175,7 @@ impl RaptorError {
             Self::SingleMountOnly(_) => "Single mount error",
             Self::ParseIntError(_) => "Parse int error",
             Self::ParseTomlError(_) => "Parse toml error",
+            Self::SerializeTomlError(_) => "Serialize toml error",
             Self::WhichError(_) => "Which error",
             Self::FromPathBufError(_) => "PathBuf conversion error",
             Self::LayerCacheParseError => "Layer cache parse error",
@@ -138,6 +184,20 @@ impl RaptorError {
             Self::NoCommandSpecified => "No command specified error",
             Self::PackageNotFound(_, _) => "Package not found",
             Self::UnknownJob(_) => "Unknown job",
+            Self::MissingPassword(_) => "Missing password",
+            Self::CacheVerifyError(_) => "Cache verify error",
+            Self::FormatCheckError(_) => "Format check error",
+            Self::FormatParseError(_) => "Format parse error",
+            Self::CopyDestNotDirectory(_) => "Copy error",
+            Self::InvalidCacheMountName(_) => "Invalid cache mount name",
+            Self::GlobNoMatch(_, _) => "Glob no match",
+            Self::GlobPatternError(_, _) => "Glob pattern error",
+            Self::SecretMissing(_, _) => "Missing secret",
+            Self::ArgMissing(_, _) => "Missing build argument",
+            Self::SecretSourceMissing(_) => "Secret source error",
+            Self::InvalidSecretId(_) => "Invalid secret id",
+            Self::LayerNotFound(_) => "Layer not found",
+            Self::LayerAmbiguous(_) => "Ambiguous layer id",
         }
     }
 }
@@ -2,11 +2,13 @@ use std::process::ExitStatus;
 use std::time::SystemTime;
 
 use camino::{Utf8Path, Utf8PathBuf};
+use dregistry::credentials::Credentials;
+use dregistry::platform::Platform;
 use itertools::Itertools;
 use raptor_parser::util::module_name::ModuleName;
 
-use crate::build::{BuildTarget, Cacher, RaptorBuilder};
-use crate::dsl::Program;
+use crate::build::{BuildTarget, Cacher, RaptorBuilder, RemoteCache, RemoteCacheUrl, SecretSource};
+use crate::dsl::{BuildArgs, Program};
 use crate::make::parser::{Make, MakeTarget, RunTarget};
 use crate::make::planner::BuildLayer;
 use crate::program::Loader;
@@ -42,8 +44,109 @@ impl<'a> Maker<'a> {
         }
     }
 
-    fn program_mtime(program: &Program, builder: &RaptorBuilder) -> RaptorResult<SystemTime> {
-        let sources = Cacher::all_sources(program, builder)?;
+    /// Register the registry settings (scheme, CA file, certificate
+    /// verification) from `[raptor.registry]`.
+    pub fn add_registries(&self) {
+        let resolver = self.builder.loader().resolver();
+
+        for (registry, config) in &self.make.raptor.registry {
+            let mut config = config.clone();
+            config.ca_file = config.ca_file.map(|ca_file| resolver.path(ca_file));
+            self.builder.add_registry(registry, config);
+        }
+    }
+
+    pub fn add_credentials(&self) -> RaptorResult<()> {
+        let resolver = self.builder.loader().resolver();
+
+        for (registry, auth) in &self.make.raptor.auth {
+            let password = if let Some(password) = &auth.password {
+                password.clone()
+            } else if let Some(var) = &auth.password_env {
+                std::env::var(var)?
+            } else if let Some(file) = &auth.password_file {
+                std::fs::read_to_string(resolver.path(file))?
+                    .trim_end()
+                    .to_string()
+            } else {
+                return Err(RaptorError::MissingPassword(registry.clone()));
+            };
+
+            self.builder
+                .add_credentials(registry, Credentials::basic(&auth.username, password));
+        }
+
+        Ok(())
+    }
+
+    /// Register the secrets from `[raptor.secret]`. Secrets given on the
+    /// command line take precedence.
+    pub fn add_secrets(&self) -> RaptorResult<()> {
+        let resolver = self.builder.loader().resolver();
+
+        for (id, secret) in &self.make.raptor.secret {
+            if self.builder.has_secret(id) {
+                continue;
+            }
+
+            let source = if let Some(file) = &secret.file {
+                SecretSource::File(resolver.path(file))
+            } else if let Some(var) = &secret.env {
+                SecretSource::Env(var.clone())
+            } else {
+                return Err(RaptorError::SecretSourceMissing(id.clone()));
+            };
+
+            self.builder.add_secret(id, source);
+        }
+
+        Ok(())
+    }
+
+    /// Use the shared layer cache from `[raptor.remote-cache]`, unless one
+    /// was given on the command line. Relative directories are resolved
+    /// against the location of `Raptor.toml`.
+    pub fn add_remote_cache(&self) -> RaptorResult<()> {
+        let Some(config) = &self.make.raptor.remote_cache else {
+            return Ok(());
+        };
+
+        let url = match config.url.parse()? {
+            RemoteCacheUrl::Dir(dir) => {
+                RemoteCacheUrl::Dir(self.builder.loader().resolver().path(dir))
+            }
+            url @ RemoteCacheUrl::Registry(_) => url,
+        };
+
+        self.builder
+            .set_remote_cache(RemoteCache::new(url).with_readonly(config.readonly));
+
+        Ok(())
+    }
+
+    /// Platform for a run target, falling back to the builder default
+    pub fn platform(&self, job: &RunTarget) -> RaptorResult<Platform> {
+        RaptorBuilder::resolve_platform(job.platform.as_deref(), self.builder.platform())
+    }
+
+    /// Build arguments for a run target. Arguments given on the command line
+    /// take precedence over the ones in the make file.
+    #[must_use]
+    pub fn build_args(&self, job: &RunTarget) -> BuildArgs {
+        let mut args = job.arg.clone();
+        args.extend(self.builder.args().clone());
+        args
+    }
+
+    fn program_mtime(
+        program: &Program,
+        builder: &RaptorBuilder,
+        platform: &Platform,
+    ) -> RaptorResult<SystemTime> {
+        let sources = Cacher::all_sources(program, builder, platform).or_else(|err| {
+            builder.loader().explain_error(&err, &[])?;
+            Err(err)
+        })?;
 
         let res = sources
             .into_iter()
@@ -74,19 +177,23 @@ impl<'a> Maker<'a> {
     pub fn run_job(&self, job: &RunTarget) -> RaptorResult<ExitStatus> {
         let builder = self.builder;
 
-        let program = builder.load(&job.target)?;
+        let platform = self.platform(job)?;
 
-        let mut newest = Self::program_mtime(&program, builder)?;
+        let args = self.build_args(job);
+
+        let program = builder.load_with_args(&job.target, &args)?;
+
+        let mut newest = Self::program_mtime(&program, builder, &platform)?;
 
         for input in &job.input {
-            let prog = builder.load(&ModuleName::from(input))?;
-            let stack = builder.stack(prog)?;
+            let prog = builder.load_with_args(&ModuleName::from(input), &args)?;
+            let stack = builder.stack(prog, &platform)?;
             for st in stack {
                 match st {
-                    BuildTarget::Program(program) => {
-                        newest = newest.max(Self::program_mtime(&program, builder)?);
+                    BuildTarget::Program(program, _) => {
+                        newest = newest.max(Self::program_mtime(&program, builder, &platform)?);
                     }
-                    BuildTarget::DockerSource(_) => {}
+                    BuildTarget::DockerSource(..) => {}
                 }
             }
         }
@@ -134,11 +241,11 @@ impl<'a> Maker<'a> {
             return Ok(ExitStatus::default());
         }
 
-        builder.build_program(program.clone())?;
+        builder.build_program(program.clone(), &platform)?;
 
         let mut layers = vec![];
 
-        for target in builder.stack(program.clone())? {
+        for target in builder.stack(program.clone(), &platform)? {
             layers.push(builder.layer_info(&target)?.done_path());
         }
 
@@ -162,7 +269,14 @@ impl<'a> Maker<'a> {
             .map(|(k, v)| format!("{k}={v}"))
             .collect_vec();
 
-        runner.with_env(&env).with_args(&job.args);
+        runner
+            .with_env(&env)
+            .with_args(&job.args)
+            .with_platform(platform);
+
+        if let Some(network) = &job.network {
+            runner.with_network(network.clone());
+        }
 
         if !job.entrypoint.is_empty() {
             runner.with_entrypoint(&job.entrypoint);
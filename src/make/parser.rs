@@ -4,6 +4,8 @@ use std::hash::{Hash, Hasher};
 use std::marker::PhantomData;
 use std::str::FromStr;
 
+use dregistry::registry::RegistryConfig;
+use raptor_parser::ast::NetworkMode;
 use raptor_parser::util::module_name::ModuleName;
 use serde::de::{DeserializeOwned, MapAccess, Unexpected, Visitor};
 use serde::{Deserialize, Deserializer};
@@ -21,9 +23,25 @@ pub struct Make {
 }
 
 #[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "kebab-case")]
 pub struct Raptor {
-    #[serde(deserialize_with = "de_map_string_or_struct")]
+    #[serde(deserialize_with = "de_map_string_or_struct", default)]
     pub link: BTreeMap<String, Link>,
+    #[serde(default)]
+    pub auth: BTreeMap<String, RegistryAuth>,
+    #[serde(deserialize_with = "de_map_string_or_table", default)]
+    pub registry: BTreeMap<String, RegistryConfig>,
+    #[serde(deserialize_with = "de_map_string_or_table", default)]
+    pub secret: BTreeMap<String, Secret>,
+    #[serde(default)]
+    pub remote_cache: Option<RemoteCacheConfig>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct RemoteCacheConfig {
+    pub url: String,
+    #[serde(default)]
+    pub readonly: bool,
 }
 
 #[derive(Deserialize, Debug, Default)]
@@ -31,6 +49,26 @@ pub struct Link {
     pub source: String,
 }
 
+#[derive(Deserialize, Debug, Default)]
+pub struct Secret {
+    #[serde(default)]
+    pub file: Option<String>,
+    #[serde(default)]
+    pub env: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct RegistryAuth {
+    pub username: String,
+    #[serde(default)]
+    pub password: Option<String>,
+    #[serde(default)]
+    pub password_env: Option<String>,
+    #[serde(default)]
+    pub password_file: Option<String>,
+}
+
 #[derive(Deserialize, Debug, Default)]
 pub struct GroupTarget {
     #[serde(default)]
@@ -65,6 +103,15 @@ pub struct RunTarget {
 
     #[serde(default)]
     pub env: BTreeMap<String, String>,
+
+    #[serde(default)]
+    pub platform: Option<String>,
+
+    #[serde(default)]
+    pub network: Option<NetworkMode>,
+
+    #[serde(default)]
+    pub arg: BTreeMap<String, String>,
 }
 
 impl RunTarget {
@@ -86,6 +133,17 @@ impl FromStr for Link {
     }
 }
 
+impl FromStr for Secret {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self {
+            file: Some(s.to_string()),
+            env: None,
+        })
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum MakeTarget {
     Job(String),
@@ -219,3 +277,95 @@ where
     let v = BTreeMap::<String, Wrapper<T>>::deserialize(deserializer)?;
     Ok(v.into_iter().map(|(k, Wrapper(v))| (k, v)).collect())
 }
+
+/// Like [`de_map_string_or_struct`], but values can also be given as tables
+pub fn de_map_string_or_table<'de, D, T>(deserializer: D) -> Result<BTreeMap<String, T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned + FromStr,
+    T::Err: Display,
+{
+    use serde::de::Error;
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrTable<T> {
+        String(String),
+        Table(T),
+    }
+
+    BTreeMap::<String, StringOrTable<T>>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(key, value)| match value {
+            StringOrTable::String(value) => Ok((key, value.parse().map_err(Error::custom)?)),
+            StringOrTable::Table(value) => Ok((key, value)),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use dregistry::registry::Scheme;
+
+    use crate::make::parser::Make;
+
+    #[test]
+    fn parse_registry() {
+        let make: Make = toml::from_str(
+            r#"
+            [raptor.registry]
+            "localhost:5000" = "http"
+
+            [raptor.registry."registry.example.org"]
+            ca-file = "ca.pem"
+
+            [raptor.link]
+            lib = "../lib"
+            "#,
+        )
+        .unwrap();
+
+        let registry = &make.raptor.registry;
+        assert_eq!(registry["localhost:5000"].scheme, Scheme::Http);
+        assert_eq!(registry["registry.example.org"].scheme, Scheme::Https);
+        assert_eq!(
+            registry["registry.example.org"].ca_file,
+            Some("ca.pem".into())
+        );
+        assert_eq!(make.raptor.link["lib"].source, "../lib");
+    }
+
+    #[test]
+    fn parse_secret() {
+        let make: Make = toml::from_str(
+            r#"
+            [raptor.secret]
+            npmrc = "secrets/npmrc"
+            token = { env = "NPM_TOKEN" }
+            "#,
+        )
+        .unwrap();
+
+        let secret = &make.raptor.secret;
+        assert_eq!(secret["npmrc"].file.as_deref(), Some("secrets/npmrc"));
+        assert_eq!(secret["npmrc"].env, None);
+        assert_eq!(secret["token"].file, None);
+        assert_eq!(secret["token"].env.as_deref(), Some("NPM_TOKEN"));
+    }
+
+    #[test]
+    fn parse_remote_cache() {
+        let make: Make = toml::from_str(
+            r#"
+            [raptor.remote-cache]
+            url = "/srv/raptor-cache"
+            readonly = true
+            "#,
+        )
+        .unwrap();
+
+        let remote = make.raptor.remote_cache.unwrap();
+        assert_eq!(remote.url, "/srv/raptor-cache");
+        assert!(remote.readonly);
+    }
+}
@@ -3,10 +3,12 @@ use std::fmt::Display;
 
 use camino::Utf8PathBuf;
 use dep_graph::{DepGraph, Node};
+use dregistry::platform::Platform;
 use itertools::Itertools;
 use raptor_parser::util::module_name::ModuleName;
 
 use crate::build::{BuildTarget, LayerInfo, RaptorBuilder};
+use crate::dsl::BuildArgs;
 use crate::make::maker::Maker;
 use crate::make::parser::{MakeTarget, RunTarget};
 use crate::{RaptorError, RaptorResult};
@@ -54,6 +56,7 @@ pub struct Planner<'a> {
     jobs: HashMap<u64, Job>,
     builder: &'a RaptorBuilder<'a>,
     maker: &'a Maker<'a>,
+    lock: bool,
 }
 
 impl<'a> Planner<'a> {
@@ -64,9 +67,18 @@ impl<'a> Planner<'a> {
             jobs: HashMap::new(),
             builder,
             maker,
+            lock: false,
         }
     }
 
+    /// Record the digests of unpinned docker images in the lock file while
+    /// planning (as needed before building)
+    #[must_use]
+    pub const fn with_lock(mut self, lock: bool) -> Self {
+        self.lock = lock;
+        self
+    }
+
     #[must_use]
     pub const fn builder(&self) -> &RaptorBuilder {
         self.builder
@@ -82,9 +94,19 @@ impl<'a> Planner<'a> {
         &self.jobs
     }
 
-    pub fn add_build_job(&mut self, input: &ModuleName) -> RaptorResult<Option<u64>> {
-        let prog = self.builder.load(input)?;
-        let targets = self.builder.stack(prog)?;
+    pub fn add_build_job(
+        &mut self,
+        input: &ModuleName,
+        platform: &Platform,
+        args: &BuildArgs,
+    ) -> RaptorResult<Option<u64>> {
+        let prog = self.builder.load_with_args(input, args)?;
+
+        if self.lock {
+            self.builder.lock_program(prog.clone(), platform)?;
+        }
+
+        let targets = self.builder.stack(prog, platform)?;
 
         let mut last = None;
         let mut layers = vec![];
@@ -102,10 +124,10 @@ impl<'a> Planner<'a> {
             let work = self.nodes.get_mut(&hash).unwrap();
 
             match &st {
-                BuildTarget::Program(_program) => {
+                BuildTarget::Program(..) => {
                     last.inspect(|id| work.add_dep(*id));
                 }
-                BuildTarget::DockerSource(_) => {}
+                BuildTarget::DockerSource(..) => {}
             }
 
             layers.push(done_path);
@@ -124,7 +146,9 @@ impl<'a> Planner<'a> {
     }
 
     pub fn add_run_job(&mut self, name: &str, job: &RunTarget) -> RaptorResult<()> {
-        let job_hash = self.add_build_job(&job.target)?;
+        let platform = self.maker.platform(job)?;
+        let args = self.maker.build_args(job);
+        let job_hash = self.add_build_job(&job.target, &platform, &args)?;
 
         let run_hash = job.hash_value();
 
@@ -140,7 +164,7 @@ impl<'a> Planner<'a> {
         );
 
         for input in &job.input {
-            let input_hash = self.add_build_job(&ModuleName::from(input))?;
+            let input_hash = self.add_build_job(&ModuleName::from(input), &platform, &args)?;
 
             if let Some(input_hash) = input_hash {
                 self.nodes
@@ -160,7 +184,7 @@ impl<'a> Planner<'a> {
                     self.add_named_run_job(name)?;
                 }
                 for name in &group.build {
-                    self.add_build_job(name)?;
+                    self.add_build_job(name, self.builder.platform(), self.builder.args())?;
                 }
             }
             MakeTarget::Job(name) => {
@@ -0,0 +1,96 @@
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+use std::sync::Arc;
+
+use camino::Utf8PathBuf;
+use raptor_parser::util::module_name::ModuleName;
+use serde::Serialize;
+
+use crate::dsl::{Item, Program};
+use crate::program::Loader;
+
+/// Version of the schema produced by `raptor dump --format json`.
+///
+/// Increase this when the serialized form of programs, or of the parser AST,
+/// changes in a way that is not backwards compatible.
+pub const DUMP_SCHEMA_VERSION: u32 = 1;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DumpFormat {
+    /// Output from the templating pass
+    #[default]
+    Text,
+
+    /// Parsed and resolved programs, as json
+    Json,
+}
+
+impl Display for DumpFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Text => write!(f, "text"),
+            Self::Json => write!(f, "json"),
+        }
+    }
+}
+
+/// Machine-readable dump of loaded programs
+#[derive(Serialize, Debug)]
+pub struct Dump {
+    pub version: u32,
+    pub targets: Vec<DumpTarget>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DumpTarget {
+    pub name: ModuleName,
+
+    /// Templated source of each file in the program. The spans of statement
+    /// origins are byte offsets into these.
+    pub sources: BTreeMap<Utf8PathBuf, String>,
+
+    pub program: Arc<Program>,
+}
+
+impl Default for Dump {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Dump {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            version: DUMP_SCHEMA_VERSION,
+            targets: vec![],
+        }
+    }
+
+    pub fn add_target(&mut self, loader: &Loader, name: ModuleName, program: Arc<Program>) {
+        let mut sources = BTreeMap::new();
+        Self::collect_sources(loader, &program, &mut sources);
+
+        self.targets.push(DumpTarget {
+            name,
+            sources,
+            program,
+        });
+    }
+
+    fn collect_sources(
+        loader: &Loader,
+        program: &Program,
+        sources: &mut BTreeMap<Utf8PathBuf, String>,
+    ) {
+        if let Some(source) = loader.source(program.path.as_str()) {
+            sources.insert(program.path.clone(), source);
+        }
+
+        for item in &program.code {
+            if let Item::Program(include) = item {
+                Self::collect_sources(loader, include, sources);
+            }
+        }
+    }
+}
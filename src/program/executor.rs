@@ -1,26 +1,40 @@
 use std::fs::File;
+use std::os::unix::fs::PermissionsExt;
 
-use camino::Utf8PathBuf;
+use camino::Utf8Path;
 use indicatif::{ProgressBar, ProgressStyle};
 use minijinja::Value;
+use nix::sys::stat::SFlag;
 
+use crate::build::SecretStore;
 use crate::dsl::Program;
-use crate::program::{Loader, ResolveArgs};
-use crate::sandbox::{Sandbox, SandboxExt};
+use crate::program::{Loader, ResolveArgs, Resolver};
+use crate::sandbox::{FalconClient, Sandbox, SandboxExt};
 use crate::util::io_fast_copy;
-use crate::{RaptorResult, template};
-use raptor_parser::ast::{Instruction, Statement};
+use crate::{RaptorError, RaptorResult, template};
+use raptor_parser::ast::{InstCopy, Instruction, Origin, Statement};
 
 pub struct Executor {
     sandbox: Sandbox,
+    secrets: SecretStore,
 }
 
 impl Executor {
     const PROGRESS_STYLE: &str = "[{elapsed_precise}] {bar:40.cyan/blue} {bytes:>7}/{total_bytes:7} {binary_bytes_per_sec} {msg}";
 
     #[must_use]
-    pub const fn new(sandbox: Sandbox) -> Self {
-        Self { sandbox }
+    pub fn new(sandbox: Sandbox) -> Self {
+        Self {
+            sandbox,
+            secrets: SecretStore::new(),
+        }
+    }
+
+    /// Secrets available to `RUN --secret` instructions
+    #[must_use]
+    pub fn with_secrets(mut self, secrets: SecretStore) -> Self {
+        self.secrets = secrets;
+        self
     }
 
     fn progress_bar(len: u64) -> ProgressBar {
@@ -31,28 +45,152 @@ impl Executor {
         ProgressBar::new(len).with_style(style)
     }
 
-    fn handle(&mut self, stmt: &Statement, ctx: &Value) -> RaptorResult<()> {
+    const fn is_dir(mode: u32) -> bool {
+        mode & SFlag::S_IFMT.bits() == SFlag::S_IFDIR.bits()
+    }
+
+    /// Total size of all regular files in a copy source
+    fn copy_size(path: &Utf8Path) -> RaptorResult<u64> {
+        let md = path.symlink_metadata()?;
+
+        if md.is_dir() {
+            let mut size = 0;
+            for dent in path.read_dir_utf8()? {
+                size += Self::copy_size(dent?.path())?;
+            }
+            Ok(size)
+        } else if md.is_file() {
+            Ok(md.len())
+        } else {
+            Ok(0)
+        }
+    }
+
+    /// Copy a single file, symlink or directory tree from `src` (on the host)
+    /// to `dest` (in the sandbox). Unless `--chmod` is specified, the mode of
+    /// each source file is preserved.
+    fn copy_entry(
+        client: &mut FalconClient,
+        pb: &ProgressBar,
+        src: &Utf8Path,
+        dest: &Utf8Path,
+        inst: &InstCopy,
+    ) -> RaptorResult<()> {
+        let md = src.symlink_metadata()?;
+        let mode = md.permissions().mode() & 0o7777;
+
+        if md.is_symlink() {
+            client.symlink(dest, &src.read_link_utf8()?, inst.chown.clone())?;
+        } else if md.is_dir() {
+            client.mkdir(&dest, inst.chown.clone(), Some(mode), true)?;
+            Self::copy_dir_contents(client, pb, src, dest, inst)?;
+        } else if md.is_file() {
+            let fd = client.create_file(dest, inst.chown.clone(), inst.chmod.or(Some(mode)))?;
+            io_fast_copy(File::open(src)?, pb.wrap_write(fd))?;
+        } else {
+            warn!("Skipping special file {src}");
+        }
+
+        Ok(())
+    }
+
+    fn copy_dir_contents(
+        client: &mut FalconClient,
+        pb: &ProgressBar,
+        src: &Utf8Path,
+        dest: &Utf8Path,
+        inst: &InstCopy,
+    ) -> RaptorResult<()> {
+        let mut names = src
+            .read_dir_utf8()?
+            .map(|dent| Ok(dent?.file_name().to_string()))
+            .collect::<RaptorResult<Vec<_>>>()?;
+
+        names.sort();
+
+        for name in names {
+            Self::copy_entry(client, pb, &src.join(&name), &dest.join(&name), inst)?;
+        }
+
+        Ok(())
+    }
+
+    fn copy(
+        client: &mut FalconClient,
+        resolver: &Resolver,
+        origin: &Origin,
+        inst: &InstCopy,
+    ) -> RaptorResult<()> {
+        let mut srcs = vec![];
+        for src in &inst.srcs {
+            srcs.extend(resolver.glob(origin, src)?);
+        }
+
+        /* a glob pattern with a single match is treated like a plain source */
+        let multiple = srcs.len() > 1;
+
+        let dest = inst.dest.as_path();
+        let dest_mode = client.stat(dest)?;
+
+        /* multiple sources, or the contents of a directory, can only be
+         * copied into a directory */
+        let dir_src = srcs
+            .iter()
+            .any(|src| src.symlink_metadata().is_ok_and(|md| md.is_dir()));
+
+        if (multiple || dir_src) && dest_mode.is_some_and(|mode| !Self::is_dir(mode)) {
+            return Err(RaptorError::CopyDestNotDirectory(dest.to_path_buf()));
+        }
+
+        /* copy into destination directory, if it exists, or is implied by
+         * multiple sources or a trailing slash */
+        let into_dir =
+            dest_mode.map_or_else(|| multiple || dest.as_str().ends_with('/'), Self::is_dir);
+
+        if into_dir && dest_mode.is_none() {
+            client.mkdir(&dest, inst.chown.clone(), Some(0o755), true)?;
+        }
+
+        let size = srcs
+            .iter()
+            .map(|src| Self::copy_size(src))
+            .sum::<RaptorResult<u64>>()?;
+        let pb = Self::progress_bar(size);
+
+        for src in &srcs {
+            if src.symlink_metadata()?.is_dir() {
+                /* like docker, copy the contents of directories, not the
+                 * directories themselves */
+                if dest_mode.is_none() && !into_dir {
+                    let mode = src.metadata()?.permissions().mode() & 0o7777;
+                    client.mkdir(&dest, inst.chown.clone(), Some(mode), true)?;
+                }
+                Self::copy_dir_contents(client, &pb, src, dest, inst)?;
+            } else if into_dir {
+                let name = src.file_name().unwrap_or_default();
+                Self::copy_entry(client, &pb, src, &dest.join(name), inst)?;
+            } else {
+                Self::copy_entry(client, &pb, src, dest, inst)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle(&mut self, resolver: &Resolver, stmt: &Statement, ctx: &Value) -> RaptorResult<()> {
         let client = self.sandbox.client();
         match &stmt.inst {
-            // Code merging and mount instruction have nothing to execute
+            // Code merging, mount, network and argument instructions have nothing to execute
             Instruction::From(_)
             | Instruction::Include(_)
             | Instruction::Mount(_)
+            | Instruction::Network(_)
+            | Instruction::Arg(_)
             | Instruction::Entrypoint(_)
             | Instruction::Cmd(_) => {}
 
             Instruction::Copy(inst) => {
-                let srcname = stmt.origin.path_for(&inst.srcs[0])?;
-                let src = File::open(&srcname)?;
-                let fd = client.create_file(
-                    &Utf8PathBuf::from(&inst.dest),
-                    inst.chown.clone(),
-                    inst.chmod,
-                )?;
-
-                let pb = Self::progress_bar(src.metadata()?.len());
-                let dst = pb.wrap_write(fd);
-                io_fast_copy(src, dst)?;
+                Self::copy(client, resolver, &stmt.origin, inst)?;
             }
 
             Instruction::Render(inst) => {
@@ -87,7 +225,17 @@ impl Executor {
             }
 
             Instruction::Run(inst) => {
-                client.run(&inst.run)?;
+                let mounts = inst
+                    .secrets
+                    .iter()
+                    .map(|secret| {
+                        self.secrets.mount(secret).ok_or_else(|| {
+                            RaptorError::SecretMissing(secret.id.clone(), stmt.origin.clone())
+                        })
+                    })
+                    .collect::<RaptorResult<_>>()?;
+
+                client.run_with_secrets(&inst.run, mounts)?;
             }
 
             Instruction::Env(inst) => {
@@ -99,6 +247,10 @@ impl Executor {
             Instruction::Workdir(inst) => {
                 client.chdir(inst.dir.as_str())?;
             }
+
+            Instruction::User(inst) => {
+                client.setuser(Some(&inst.user), inst.group.as_deref())?;
+            }
         }
 
         Ok(())
@@ -107,10 +259,11 @@ impl Executor {
     pub fn run(&mut self, loader: &Loader, program: &Program) -> RaptorResult<()> {
         program.traverse(&mut |stmt| {
             info!("{}", stmt.inst);
-            self.handle(stmt, &program.ctx).or_else(|err| {
-                loader.explain_error(&err, std::slice::from_ref(&stmt.origin))?;
-                Err(err)
-            })
+            self.handle(loader.resolver(), stmt, &program.ctx)
+                .or_else(|err| {
+                    loader.explain_error(&err, std::slice::from_ref(&stmt.origin))?;
+                    Err(err)
+                })
         })
     }
 
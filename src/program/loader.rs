@@ -4,11 +4,11 @@ use camino::{Utf8Path, Utf8PathBuf};
 use colored::Colorize;
 use dashmap::DashMap;
 use minijinja::{Environment, ErrorKind, Value, context};
-use raptor_parser::ast::{Instruction, Origin, Statement};
+use raptor_parser::ast::{InstArg, Instruction, Origin, Statement};
 use raptor_parser::parser;
 use raptor_parser::util::module_name::ModuleName;
 
-use crate::dsl::{Item, Program};
+use crate::dsl::{BuildArgs, FromParams, Item, Program};
 use crate::program::{
     ResolveArgs, Resolver, show_error_context, show_jinja_error_context, show_origin_error_context,
     show_parse_error_context,
@@ -21,7 +21,7 @@ pub struct Loader<'source> {
     dump: bool,
     sources: DashMap<String, String>,
     resolver: Resolver,
-    programs: DashMap<Utf8PathBuf, Arc<Program>>,
+    programs: DashMap<(Utf8PathBuf, Value, BuildArgs), Arc<Program>>,
 }
 
 const MAX_NESTED_INCLUDE: usize = 20;
@@ -52,6 +52,30 @@ impl Loader<'_> {
         &mut self.resolver
     }
 
+    /// Use `source` as the contents of the file at `path`, instead of reading
+    /// it from disk (e.g. for unsaved files in an editor)
+    pub fn add_source(
+        &mut self,
+        path: impl AsRef<Utf8Path>,
+        mut source: String,
+    ) -> RaptorResult<()> {
+        if !source.ends_with('\n') {
+            source.push('\n');
+        }
+
+        let name = self.resolver.path(path).into_string();
+        self.env.add_template_owned(name, source)?;
+
+        Ok(())
+    }
+
+    /// Rendered source of a loaded file (i.e. the template output, which
+    /// origins of parsed statements refer to)
+    #[must_use]
+    pub fn source(&self, path: &str) -> Option<String> {
+        self.sources.get(path).map(|source| source.clone())
+    }
+
     pub fn clear_cache(&mut self) {
         self.env.clear_templates();
         self.sources.clear();
@@ -81,13 +105,34 @@ impl Loader<'_> {
                 context = context! { instance, ..context };
             }
 
+            /* include arguments declared with ARG can have a default (errors
+             * reading the source are reported when parsing it below) */
+            let defaults: BuildArgs = self
+                .declared_args(&src)
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|arg| Some((arg.name, arg.default?)))
+                .collect();
+            context = context! { ..context, ..Value::from(defaults) };
+
             origins.push(origin.clone());
             let include = self.parse_template(&src, origins, context)?;
             origins.pop();
 
             prog.code.push(Item::Statement(Statement { inst, origin }));
-            prog.code.push(Item::Program(include));
+            prog.code.push(Item::Program(Arc::new(include)));
         } else {
+            /* check that FROM parameters resolve, to report errors here */
+            if let Instruction::From(from) = &inst {
+                prog.ctx.resolve_args(&from.args)?;
+            }
+
+            if let Instruction::Arg(arg) = &inst
+                && prog.ctx.get_attr(&arg.name)?.is_undefined()
+            {
+                return Err(RaptorError::ArgMissing(arg.name.clone(), origin));
+            }
+
             prog.code.push(Item::Statement(Statement { inst, origin }));
         }
 
@@ -119,6 +164,33 @@ impl Loader<'_> {
                     &err.to_string(),
                 );
             }
+            RaptorError::GlobNoMatch(_, origin) | RaptorError::GlobPatternError(_, origin) => {
+                self.show_include_stack(origins);
+                show_origin_error_context(
+                    &self.sources.get(origin.path.as_str()).unwrap(),
+                    origin,
+                    "Copy Error",
+                    &err.to_string(),
+                );
+            }
+            RaptorError::SecretMissing(_, origin) => {
+                self.show_include_stack(origins);
+                show_origin_error_context(
+                    &self.sources.get(origin.path.as_str()).unwrap(),
+                    origin,
+                    "Secret Error",
+                    &err.to_string(),
+                );
+            }
+            RaptorError::ArgMissing(_, origin) => {
+                self.show_include_stack(origins);
+                show_origin_error_context(
+                    &self.sources.get(origin.path.as_str()).unwrap(),
+                    origin,
+                    "Argument Error",
+                    &err.to_string(),
+                );
+            }
             RaptorError::MinijinjaError(err) => {
                 if err.kind() == ErrorKind::BadInclude {
                     if let Some((last, origins)) = &origins.split_last() {
@@ -188,7 +260,7 @@ impl Loader<'_> {
         path: impl AsRef<Utf8Path>,
         origins: &mut Vec<Origin>,
         ctx: Value,
-    ) -> RaptorResult<Arc<Program>> {
+    ) -> RaptorResult<Program> {
         let tmpl = self.env.get_template(self.resolver.path(&path).as_str())?;
         let (source, state) = tmpl
             .render_and_return_state(ctx.clone())
@@ -222,38 +294,109 @@ impl Loader<'_> {
             self.handle(&mut program, origins, stmt)?;
         }
 
-        Ok(Arc::new(program))
+        Ok(program)
     }
 
-    pub fn load_template(
+    /// Build arguments declared with `ARG` in the source of a program.
+    ///
+    /// These are read before templating, so their values can be used by the
+    /// template. Lines that fail to parse here (e.g. because they use
+    /// template syntax) are reported when parsing the template output.
+    pub fn declared_args(&self, path: &Utf8Path) -> RaptorResult<Vec<InstArg>> {
+        let tmpl = self.env.get_template(self.resolver.path(path).as_str())?;
+        let mut res = vec![];
+
+        for line in tmpl.source().lines() {
+            if !line.trim_start().starts_with("ARG") {
+                continue;
+            }
+
+            let Ok(statements) = parser::parse(path.as_str(), &format!("{line}\n")) else {
+                continue;
+            };
+
+            for stmt in statements {
+                if let Instruction::Arg(arg) = stmt.inst {
+                    res.push(arg);
+                }
+            }
+        }
+
+        Ok(res)
+    }
+
+    fn load(
         &self,
-        path: impl AsRef<Utf8Path>,
+        path: &Utf8Path,
         ctx: Value,
+        params: &FromParams,
+        args: &BuildArgs,
         origins: &mut Vec<Origin>,
     ) -> RaptorResult<Arc<Program>> {
-        let path = path.as_ref();
+        let key = (path.to_path_buf(), ctx.clone(), args.clone());
 
-        if let Some(program) = self.programs.get(path) {
+        if let Some(program) = self.programs.get(&key) {
             return Ok(program.clone());
         }
 
-        let program = self.parse_template(path, origins, ctx)?;
+        /* declared arguments get the given value, or their default */
+        let values: BuildArgs = self
+            .declared_args(path)?
+            .into_iter()
+            .filter_map(|arg| {
+                let value = args.get(&arg.name).or(arg.default.as_ref())?.clone();
+                Some((arg.name, value))
+            })
+            .collect();
 
-        self.programs.insert(path.into(), program);
+        let ctx = context! {
+            ..ctx,
+            ..Value::from(values),
+        };
+
+        let program = self
+            .parse_template(path, origins, ctx)?
+            .with_args(args.clone())
+            .with_params(params.clone());
 
-        Ok(self.programs.get(path).unwrap().clone())
+        Ok(self
+            .programs
+            .entry(key)
+            .or_insert(Arc::new(program))
+            .clone())
     }
 
-    pub fn load_program(&self, name: &ModuleName, origin: Origin) -> RaptorResult<Arc<Program>> {
+    pub fn load_template(
+        &self,
+        path: impl AsRef<Utf8Path>,
+        ctx: Value,
+        origins: &mut Vec<Origin>,
+    ) -> RaptorResult<Arc<Program>> {
+        self.load(
+            path.as_ref(),
+            ctx,
+            &FromParams::new(),
+            &BuildArgs::new(),
+            origins,
+        )
+    }
+
+    pub fn load_program(
+        &self,
+        name: &ModuleName,
+        params: &FromParams,
+        origin: Origin,
+        args: &BuildArgs,
+    ) -> RaptorResult<Arc<Program>> {
         let path = self.resolver.to_program_path(name, &origin)?;
-        let context = name
-            .instance()
-            .as_ref()
-            .map_or_else(|| context! {}, |instance| context! { instance });
+        let context = name.instance().as_ref().map_or_else(
+            || Value::from(params.clone()),
+            |instance| context! { instance, ..Value::from(params.clone()) },
+        );
 
         let mut origins = vec![origin];
 
-        self.load_template(&path, context, &mut origins)
+        self.load(&path, context, params, args, &mut origins)
             .or_else(|err| {
                 self.explain_error(&err, &origins)?;
                 Err(err)
@@ -1,3 +1,4 @@
+mod dump;
 mod error;
 mod executor;
 mod loader;
@@ -5,6 +6,7 @@ mod printer;
 mod resolve;
 mod resolver;
 
+pub use dump::*;
 pub use error::*;
 pub use executor::*;
 pub use loader::*;
@@ -1,6 +1,7 @@
 use camino::{Utf8Path, Utf8PathBuf};
 use dashmap::DashMap;
 use dashmap::mapref::one::Ref;
+use glob::Pattern;
 use raptor_parser::ast::Origin;
 use raptor_parser::util::module_name::{ModuleName, ModuleRoot};
 
@@ -29,6 +30,46 @@ impl Resolver {
         self.base = base.as_ref().to_path_buf();
     }
 
+    #[must_use]
+    pub fn is_glob(path: &Utf8Path) -> bool {
+        path.as_str().contains(['*', '?', '['])
+    }
+
+    /// Resolve a source path (e.g. for `COPY`), relative to `origin`.
+    ///
+    /// If the path contains glob patterns (`*`, `?` or `[..]`), it is
+    /// expanded to all matching paths (sorted). The result is relative to the
+    /// base, like [`Origin::path_for`].
+    pub fn glob(&self, origin: &Origin, path: &Utf8Path) -> RaptorResult<Vec<Utf8PathBuf>> {
+        let relpath = origin.path_for(path)?;
+
+        if !Self::is_glob(path) {
+            return Ok(vec![relpath]);
+        }
+
+        /* escape the base and origin dirs, so only the pattern itself is expanded */
+        let basedir = self.path(origin.basedir()?);
+        let pattern = Utf8Path::new(&Pattern::escape(basedir.as_str())).join(path);
+
+        let mut res = vec![];
+
+        for entry in glob::glob(pattern.as_str())
+            .map_err(|err| RaptorError::GlobPatternError(err, origin.clone()))?
+        {
+            let entry = Utf8PathBuf::try_from(entry.map_err(std::io::Error::from)?)?;
+            let relative = entry.strip_prefix(&basedir).unwrap_or(&entry);
+            res.push(origin.path_for(relative)?);
+        }
+
+        if res.is_empty() {
+            return Err(RaptorError::GlobNoMatch(path.to_string(), origin.clone()));
+        }
+
+        res.sort();
+
+        Ok(res)
+    }
+
     pub fn add_package(&self, name: String, path: Utf8PathBuf) {
         self.packages.insert(name, path);
     }
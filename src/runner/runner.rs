@@ -3,18 +3,20 @@ use std::fs::{self, File};
 use std::hash::BuildHasher;
 use std::io::{ErrorKind, IsTerminal, stdout};
 use std::process::ExitStatus;
+use std::sync::Arc;
 
 use camino::{Utf8Path, Utf8PathBuf};
 use camino_tempfile::{Builder, Utf8TempDir};
+use dregistry::platform::Platform;
 use raptor_parser::util::module_name::ModuleName;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::build::RaptorBuilder;
+use crate::build::{BuildTarget, RaptorBuilder};
 use crate::dsl::Program;
 use crate::sandbox::{BindMount, ConsoleMode, Sandbox, SpawnBuilder};
 use crate::{RaptorError, RaptorResult};
-use raptor_parser::ast::{InstMount, MountType};
+use raptor_parser::ast::{InstMount, InstUser, MountType, NetworkMode};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct MountsInfo {
@@ -36,6 +38,7 @@ pub trait AddMounts: Sized {
         self,
         prog_mounts: &[&InstMount],
         builder: &RaptorBuilder,
+        platform: &Platform,
         mounts: &HashMap<String, Vec<String>, S>,
         tempdir: impl AsRef<Utf8Path>,
     ) -> RaptorResult<Self>;
@@ -46,10 +49,16 @@ impl AddMounts for SpawnBuilder {
         mut self,
         prog_mounts: &[&InstMount],
         builder: &RaptorBuilder,
+        platform: &Platform,
         mounts: &HashMap<String, Vec<String>, S>,
         tempdir: impl AsRef<Utf8Path>,
     ) -> RaptorResult<Self> {
         for mount in prog_mounts {
+            /* cache mounts are only used when building */
+            if mount.opts.mtype == MountType::Cache {
+                continue;
+            }
+
             let mount_list = mounts.get(&mount.name);
 
             if mount.opts.optional && mount_list.is_none() {
@@ -108,7 +117,7 @@ impl AddMounts for SpawnBuilder {
                     for src in srcs {
                         let name = ModuleName::from(&src);
                         let program = builder.load(&name)?;
-                        let layers = builder.build_program(program)?;
+                        let layers = builder.build_program(program, platform)?;
 
                         info.targets.push(src.clone());
 
@@ -133,9 +142,11 @@ impl AddMounts for SpawnBuilder {
                     }
 
                     let program = builder.load(&ModuleName::from(&srcs[0]))?;
-                    let layers = builder.build_program(program)?;
+                    let layers = builder.build_program(program, platform)?;
                     self = self.overlay_ro(&layers, &mount.dest);
                 }
+
+                MountType::Cache => {}
             }
         }
 
@@ -171,6 +182,8 @@ pub struct Runner<'a> {
     entrypoint: &'a [String],
     state_dir: Option<Utf8PathBuf>,
     mounts: HashMap<String, Vec<String>>,
+    platform: Option<Platform>,
+    network: Option<NetworkMode>,
 }
 
 impl<'a> Runner<'a> {
@@ -183,6 +196,8 @@ impl<'a> Runner<'a> {
             entrypoint: EMPTY,
             state_dir: None,
             mounts: HashMap::new(),
+            platform: None,
+            network: None,
         })
     }
 
@@ -211,6 +226,16 @@ impl<'a> Runner<'a> {
         self
     }
 
+    pub fn with_platform(&mut self, platform: Platform) -> &mut Self {
+        self.platform = Some(platform);
+        self
+    }
+
+    pub fn with_network(&mut self, network: NetworkMode) -> &mut Self {
+        self.network = Some(network);
+        self
+    }
+
     pub const fn with_entrypoint(&mut self, entrypoint: &'a [String]) -> &mut Self {
         self.entrypoint = entrypoint;
         self
@@ -221,9 +246,30 @@ impl<'a> Runner<'a> {
         self
     }
 
+    /// The `USER` to run a program as. Like for exported images, this can be
+    /// set in any layer of the build stack, where later layers override
+    /// earlier ones.
+    pub fn user(
+        program: &Arc<Program>,
+        builder: &RaptorBuilder,
+        platform: &Platform,
+    ) -> RaptorResult<Option<InstUser>> {
+        let mut user = None;
+
+        for target in builder.stack(program.clone(), platform)? {
+            if let BuildTarget::Program(prog, _) = target
+                && let Some(inst) = prog.user()?
+            {
+                user = Some(inst);
+            }
+        }
+
+        Ok(user)
+    }
+
     pub fn spawn(
         self,
-        program: &Program,
+        program: &Arc<Program>,
         builder: &RaptorBuilder,
         layers: &[Utf8PathBuf],
     ) -> RaptorResult<ExitStatus> {
@@ -263,7 +309,9 @@ impl<'a> Runner<'a> {
             ConsoleMode::Pipe
         };
 
-        let res = Sandbox::builder()
+        let platform = self.platform.as_ref().unwrap_or_else(|| builder.platform());
+
+        let mut spawn = Sandbox::builder()
             .uuid(Uuid::new_v4())
             .console(console_mode)
             .arg("--background=")
@@ -273,11 +321,22 @@ impl<'a> Runner<'a> {
             .directory(&root)
             .bind(BindMount::new("/dev/kvm", "/dev/kvm"))
             .args(&command)
-            .add_mounts(&program.mounts(), builder, &self.mounts, &self.tempdir)?
+            .add_mounts(
+                &program.mounts(),
+                builder,
+                platform,
+                &self.mounts,
+                &self.tempdir,
+            )?
             .add_environment(self.env)
-            .command()
-            .spawn()?
-            .wait()?;
+            .network(self.network.unwrap_or_else(|| builder.network().clone()));
+
+        /* systemd-nspawn always uses the primary group of the user */
+        if let Some(user) = Self::user(program, builder, platform)? {
+            spawn = spawn.user(&user.user);
+        }
+
+        let res = spawn.command().spawn()?.wait()?;
 
         Ok(res)
     }
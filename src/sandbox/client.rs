@@ -10,8 +10,9 @@ use nix::errno::Errno;
 use crate::sandbox::SandboxFile;
 use crate::{RaptorError, RaptorResult};
 use falcon::client::{
-    Account, FramedRead, FramedWrite, Request, RequestChangeDir, RequestCreateDir, RequestRun,
-    RequestSetEnv, Response,
+    Account, FramedRead, FramedWrite, Request, RequestChangeDir, RequestCreateDir,
+    RequestCreateSymlink, RequestRun, RequestSetEnv, RequestSetUser, RequestStat, Response,
+    SecretMount,
 };
 use raptor_parser::ast::Chown;
 
@@ -62,9 +63,20 @@ impl FalconClient {
     }
 
     pub fn run(&mut self, cmd: &[String]) -> RaptorResult<()> {
+        self.run_with_secrets(cmd, vec![])
+    }
+
+    /// Run a command, with `secrets` bind-mounted only for the duration of
+    /// the command
+    pub fn run_with_secrets(
+        &mut self,
+        cmd: &[String],
+        secrets: Vec<SecretMount>,
+    ) -> RaptorResult<()> {
         match self.rpc(&Request::Run(RequestRun {
             arg0: cmd[0].clone(),
             argv: cmd.to_vec(),
+            secrets,
         })) {
             Ok(0) => Ok(()),
             Ok(n) => Err(RaptorError::SandboxRunError(ExitStatus::from_raw(n))),
@@ -100,6 +112,35 @@ impl FalconClient {
         Ok(())
     }
 
+    pub fn symlink(
+        &mut self,
+        path: &Utf8Path,
+        target: &Utf8Path,
+        owner: Option<Chown>,
+    ) -> RaptorResult<()> {
+        let Chown { user, group } = owner.unwrap_or_default();
+
+        self.rpc(&Request::CreateSymlink(RequestCreateSymlink {
+            path: path.to_path_buf(),
+            target: target.to_path_buf(),
+            user: user.map(Account::Name),
+            group: group.map(Account::Name),
+        }))?;
+        Ok(())
+    }
+
+    /// Returns the file mode (`st_mode`) of a path in the sandbox, following
+    /// symlinks, or `None` if it does not exist.
+    pub fn stat(&mut self, path: &Utf8Path) -> RaptorResult<Option<u32>> {
+        match self.rpc(&Request::Stat(RequestStat {
+            path: path.to_path_buf(),
+        })) {
+            Ok(mode) => Ok(Some(mode.cast_unsigned())),
+            Err(RaptorError::SandboxRequestError(Errno::ENOENT)) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
     pub fn chdir(&mut self, dir: &str) -> RaptorResult<()> {
         self.rpc(&Request::ChangeDir(RequestChangeDir {
             cd: dir.to_string(),
@@ -115,6 +156,21 @@ impl FalconClient {
         Ok(())
     }
 
+    /// Run all following commands as `user` (and `group`, if specified), or
+    /// as root again, if `user` is `None`. Numeric values are used as ids.
+    pub fn setuser(&mut self, user: Option<&str>, group: Option<&str>) -> RaptorResult<()> {
+        fn account(name: &str) -> Account {
+            name.parse()
+                .map_or_else(|_| Account::Name(name.to_string()), Account::Id)
+        }
+
+        self.rpc(&Request::SetUser(RequestSetUser {
+            user: user.map(account),
+            group: group.map(account),
+        }))?;
+        Ok(())
+    }
+
     pub fn close(&mut self) -> RaptorResult<()> {
         self.conn.write_framed(Request::Shutdown)?;
         self.conn.shutdown(std::net::Shutdown::Write)?;
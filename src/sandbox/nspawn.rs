@@ -3,6 +3,7 @@ use std::{collections::BTreeMap, fmt::Display};
 
 use camino::{Utf8Path, Utf8PathBuf};
 use itertools::Itertools;
+use raptor_parser::ast::NetworkMode;
 use serde::{Deserialize, Serialize};
 use serde_variant::to_variant_name;
 use uuid::Uuid;
@@ -144,7 +145,9 @@ pub struct SpawnBuilder {
     link_journal: Option<LinkJournal>,
     resolv_conf: Option<ResolvConf>,
     timezone: Option<Timezone>,
+    network: Option<NetworkMode>,
     directory: Option<Utf8PathBuf>,
+    user: Option<String>,
     root_overlay: Vec<Utf8PathBuf>,
     overlay: Vec<Vec<Utf8PathBuf>>,
     overlay_ro: Vec<Vec<Utf8PathBuf>>,
@@ -220,6 +223,19 @@ impl SpawnBuilder {
         self
     }
 
+    /// Set the network mode, along with a matching resolv.conf mode: private
+    /// networks cannot reach a resolver on the host loopback device, so they
+    /// get the upstream servers of the host instead.
+    #[must_use]
+    pub fn network(mut self, network: NetworkMode) -> Self {
+        self.resolv_conf = Some(match network {
+            NetworkMode::Host | NetworkMode::None => ResolvConf::Off,
+            NetworkMode::Veth | NetworkMode::Zone(_) => ResolvConf::BindUplink,
+        });
+        self.network = Some(network);
+        self
+    }
+
     #[must_use]
     pub const fn timezone(mut self, timezone: Timezone) -> Self {
         self.timezone = Some(timezone);
@@ -232,6 +248,12 @@ impl SpawnBuilder {
         self
     }
 
+    #[must_use]
+    pub fn user(mut self, user: &str) -> Self {
+        self.user = Some(user.to_string());
+        self
+    }
+
     #[must_use]
     pub fn root_overlay(mut self, overlay: impl Into<Utf8PathBuf>) -> Self {
         self.root_overlay.push(overlay.into());
@@ -342,6 +364,13 @@ impl SpawnBuilder {
             res.push(to_variant_name(&timezone).unwrap().into());
         }
 
+        match &self.network {
+            None | Some(NetworkMode::Host) => {}
+            Some(NetworkMode::None) => res.push("--private-network".into()),
+            Some(NetworkMode::Veth) => res.push("--network-veth".into()),
+            Some(NetworkMode::Zone(zone)) => res.push(format!("--network-zone={zone}")),
+        }
+
         if !self.root_overlay.is_empty() {
             res.push("--overlay".into());
 
@@ -404,6 +433,11 @@ impl SpawnBuilder {
             res.push((*dir).to_string());
         }
 
+        if let Some(user) = &self.user {
+            res.push("--user".into());
+            res.push(user.clone());
+        }
+
         for (name, value) in &self.environment {
             res.push("--setenv".into());
             res.push(format!("{name}={value}"));
@@ -456,22 +490,46 @@ mod tests {
         let mounts2 = hashmap! { "foo2".into() => vec!["/dev/null".into()] };
 
         // required mount (exists)
-        let sb = SpawnBuilder::new().add_mounts(&[&mount], &builder, &mounts1, "/tmp")?;
+        let sb = SpawnBuilder::new().add_mounts(
+            &[&mount],
+            &builder,
+            builder.platform(),
+            &mounts1,
+            "/tmp",
+        )?;
         assert_eq!(sb.bind.len(), 1);
 
         // required mount (missing)
-        let sb = SpawnBuilder::new().add_mounts(&[&mount], &builder, &mounts2, "/tmp");
+        let sb = SpawnBuilder::new().add_mounts(
+            &[&mount],
+            &builder,
+            builder.platform(),
+            &mounts2,
+            "/tmp",
+        );
         assert!(matches!(sb.unwrap_err(), RaptorError::MountMissing(_)));
 
         // optional mounts below
         mount.opts.optional = true;
 
         // optional mount (exists)
-        let sb = SpawnBuilder::new().add_mounts(&[&mount], &builder, &mounts1, "/tmp")?;
+        let sb = SpawnBuilder::new().add_mounts(
+            &[&mount],
+            &builder,
+            builder.platform(),
+            &mounts1,
+            "/tmp",
+        )?;
         assert_eq!(sb.bind.len(), 1);
 
         // optional mount (missing)
-        let sb = SpawnBuilder::new().add_mounts(&[&mount], &builder, &mounts2, "/tmp")?;
+        let sb = SpawnBuilder::new().add_mounts(
+            &[&mount],
+            &builder,
+            builder.platform(),
+            &mounts2,
+            "/tmp",
+        )?;
         assert_eq!(sb.bind.len(), 0);
 
         Ok(())
@@ -496,22 +554,46 @@ mod tests {
         let mounts2 = hashmap! { "foo2".into() => vec!["/dev/null".into()] };
 
         // required mount (exists)
-        let sb = SpawnBuilder::new().add_mounts(&[&mount], &builder, &mounts1, "/tmp")?;
+        let sb = SpawnBuilder::new().add_mounts(
+            &[&mount],
+            &builder,
+            builder.platform(),
+            &mounts1,
+            "/tmp",
+        )?;
         assert_eq!(sb.bind_ro.len(), 1);
 
         // required mount (missing)
-        let sb = SpawnBuilder::new().add_mounts(&[&mount], &builder, &mounts2, "/tmp");
+        let sb = SpawnBuilder::new().add_mounts(
+            &[&mount],
+            &builder,
+            builder.platform(),
+            &mounts2,
+            "/tmp",
+        );
         assert!(matches!(sb.unwrap_err(), RaptorError::MountMissing(_)));
 
         // optional mounts below
         mount.opts.optional = true;
 
         // optional mount (exists)
-        let sb = SpawnBuilder::new().add_mounts(&[&mount], &builder, &mounts1, "/tmp")?;
+        let sb = SpawnBuilder::new().add_mounts(
+            &[&mount],
+            &builder,
+            builder.platform(),
+            &mounts1,
+            "/tmp",
+        )?;
         assert_eq!(sb.bind_ro.len(), 1);
 
         // optional mount (missing)
-        let sb = SpawnBuilder::new().add_mounts(&[&mount], &builder, &mounts2, "/tmp")?;
+        let sb = SpawnBuilder::new().add_mounts(
+            &[&mount],
+            &builder,
+            builder.platform(),
+            &mounts2,
+            "/tmp",
+        )?;
         assert_eq!(sb.bind_ro.len(), 0);
 
         Ok(())
@@ -31,6 +31,22 @@ pub fn link_or_copy_file(from: impl AsRef<Utf8Path>, to: impl AsRef<Utf8Path>) -
     std::fs::hard_link(from.as_ref(), to.as_ref()).or_else(|_| copy_file(from, to))
 }
 
+/// Total size of a file, or a directory tree (without following symlinks)
+pub fn disk_usage(path: &Utf8Path) -> RaptorResult<u64> {
+    let md = path.symlink_metadata()?;
+
+    if !md.is_dir() {
+        return Ok(md.len());
+    }
+
+    let mut size = md.len();
+    for dent in path.read_dir_utf8()? {
+        size += disk_usage(dent?.path())?;
+    }
+
+    Ok(size)
+}
+
 pub mod capture_proc_fd;
 pub mod clapcolor;
 pub mod flag;
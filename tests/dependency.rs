@@ -6,14 +6,15 @@ use std::time::SystemTime;
 
 use camino::{Utf8Path, Utf8PathBuf};
 use camino_tempfile::Utf8TempDir;
+use dregistry::digest::Digest;
 use tap::Tap;
 
 use raptor::RaptorResult;
-use raptor::build::{Cacher, RaptorBuilder};
+use raptor::build::{Cacher, LockFile, RaptorBuilder, Reproducible, SecretSource};
 use raptor::dsl::Program;
 use raptor::program::Loader;
 use raptor::sandbox::Sandbox;
-use raptor_parser::ast::Origin;
+use raptor_parser::ast::{NetworkMode, Origin};
 use raptor_parser::util::module_name::ModuleName;
 
 trait Writable {
@@ -44,7 +45,7 @@ struct Tester {
     program_name: ModuleName,
     builder: RaptorBuilder<'static>,
     tempdir: Utf8TempDir,
-    hash: u64,
+    hash: Digest,
 }
 
 impl Tester {
@@ -69,7 +70,7 @@ impl Tester {
             program_name,
             builder,
             tempdir,
-            hash: 0,
+            hash: Digest::Sha256([0; 32]),
         };
         res.program_write(program)?;
         init(&res)?;
@@ -84,7 +85,7 @@ impl Tester {
         &mut self,
         act: &str,
         fun: impl Fn(&mut Self) -> RaptorResult<()>,
-    ) -> RaptorResult<u64> {
+    ) -> RaptorResult<Digest> {
         // clear cache to prevent stale programs
         self.builder.clear_cache();
 
@@ -95,7 +96,7 @@ impl Tester {
             eprintln!("{}", self.load(&self.program_name)?);
             panic!("Program hash did not change after changing {act}");
         }
-        self.hash = hash;
+        self.hash = hash.clone();
 
         Ok(hash)
     }
@@ -106,7 +107,7 @@ impl Tester {
         &mut self,
         act: &str,
         fun: impl Fn(&mut Self) -> RaptorResult<()>,
-    ) -> RaptorResult<u64> {
+    ) -> RaptorResult<Digest> {
         // clear cache to prevent stale programs
         self.builder.clear_cache();
 
@@ -127,8 +128,8 @@ impl Tester {
         &mut self,
         act: &str,
         fun: impl Fn(&mut Self) -> RaptorResult<()>,
-        expected: u64,
-    ) -> RaptorResult<u64> {
+        expected: Digest,
+    ) -> RaptorResult<Digest> {
         // clear cache to prevent stale programs
         self.builder.clear_cache();
 
@@ -163,7 +164,7 @@ impl Tester {
         Ok(fs::write(self.program_path(), value.into_string())?)
     }
 
-    fn program_hash(&self) -> RaptorResult<u64> {
+    fn program_hash(&self) -> RaptorResult<Digest> {
         self.hash(&self.program_name)
     }
 
@@ -200,9 +201,9 @@ impl Tester {
         self.builder.load(name)
     }
 
-    fn hash(&self, name: &ModuleName) -> RaptorResult<u64> {
+    fn hash(&self, name: &ModuleName) -> RaptorResult<Digest> {
         let prog = self.load(name)?;
-        Cacher::cache_key(&prog, &self.builder)
+        Cacher::cache_key(&prog, &self.builder, self.builder.platform())
     }
 }
 
@@ -215,6 +216,181 @@ fn dep_copy() -> RaptorResult<()> {
     Ok(())
 }
 
+#[test]
+fn dep_copy_dir() -> RaptorResult<()> {
+    let mut test = Tester::setup(["COPY dir /dest"], |test| {
+        test.mkdir("dir/sub")?;
+        test.write("dir/a", "1234")?;
+        test.write("dir/sub/b", "5678")
+    })?;
+
+    test.expect_same("COPY dir", |test| test.touch("dir/sub/b"))?;
+    test.expect_new("COPY dir file", |test| test.append("dir/sub/b", "more"))?;
+    test.expect_new("COPY dir new file", |test| test.write("dir/c", ""))?;
+    test.expect_new("COPY dir rename", |test| {
+        Ok(fs::rename(test.path("dir/c"), test.path("dir/d"))?)
+    })?;
+    test.expect_new("COPY dir symlink", |test| {
+        Ok(std::os::unix::fs::symlink("a", test.path("dir/link"))?)
+    })?;
+    test.expect_new("COPY dir mode", |test| {
+        Ok(fs::set_permissions(
+            test.path("dir/a"),
+            std::os::unix::fs::PermissionsExt::from_mode(0o600),
+        )?)
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn dep_copy_glob() -> RaptorResult<()> {
+    let mut test = Tester::setup(["COPY conf/*.conf /dest/"], |test| {
+        test.mkdir("conf")?;
+        test.write("conf/a.conf", "1234")
+    })?;
+
+    test.expect_same("COPY glob", |test| test.touch("conf/a.conf"))?;
+    test.expect_same("COPY glob other file", |test| test.write("conf/b.txt", ""))?;
+    test.expect_new("COPY glob file", |test| test.append("conf/a.conf", "more"))?;
+    test.expect_new("COPY glob new file", |test| test.write("conf/b.conf", ""))?;
+    test.expect_new("COPY glob rename", |test| {
+        Ok(fs::rename(
+            test.path("conf/b.conf"),
+            test.path("conf/c.conf"),
+        )?)
+    })?;
+    test.expect_new("COPY glob remove", |test| {
+        Ok(fs::remove_file(test.path("conf/c.conf"))?)
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn dep_user() -> RaptorResult<()> {
+    let mut test = Tester::setup(["USER app", "RUN id"], |_| Ok(()))?;
+
+    test.expect_new("USER group", |test| {
+        test.program_write(["USER app:app", "RUN id"])
+    })?;
+    test.expect_new("USER name", |test| {
+        test.program_write(["USER www-data:app", "RUN id"])
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn dep_cache_mount() -> RaptorResult<()> {
+    let mut test = Tester::setup(["RUN apt-get update"], |_| Ok(()))?;
+
+    test.expect_same("MOUNT --cache", |test| {
+        test.program_write(["MOUNT --cache apt /var/cache/apt", "RUN apt-get update"])
+    })?;
+    test.expect_same("MOUNT --cache dest", |test| {
+        test.program_write(["MOUNT --cache apt /var/lib/apt", "RUN apt-get update"])
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn dep_run_secret() -> RaptorResult<()> {
+    let mut test = Tester::setup(["RUN --secret id=token cat /run/secrets/token"], |test| {
+        test.write("token", "1234")?;
+        test.builder
+            .add_secret("token", SecretSource::File(test.path("token")));
+        Ok(())
+    })?;
+
+    test.expect_same("secret contents", |test| test.write("token", "5678"))?;
+    test.expect_new("secret target", |test| {
+        test.program_write(["RUN --secret id=token,target=/token cat /token"])
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn dep_network() -> RaptorResult<()> {
+    let mut test = Tester::setup(["RUN true"], |_| Ok(()))?;
+
+    test.expect_new("NETWORK none", |test| {
+        test.program_write(["NETWORK none", "RUN true"])
+    })?;
+    test.expect_new("NETWORK zone", |test| {
+        test.program_write(["NETWORK zone:build", "RUN true"])
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn dep_network_default() -> RaptorResult<()> {
+    let mut test = Tester::setup(["RUN true"], |_| Ok(()))?;
+
+    /* the default network mode applies to targets without NETWORK.. */
+    test.expect_new("--network none", |test| {
+        test.builder.set_network(NetworkMode::None);
+        Ok(())
+    })?;
+
+    /* ..but not to targets with NETWORK */
+    test.expect_new("NETWORK none", |test| {
+        test.program_write(["NETWORK none", "RUN true"])
+    })?;
+    test.expect_same("--network host", |test| {
+        test.builder.set_network(NetworkMode::Host);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn dep_arg() -> RaptorResult<()> {
+    let mut test = Tester::setup(["ARG version=1", "RUN true"], |_| Ok(()))?;
+
+    test.expect_new("ARG value", |test| {
+        test.builder.set_arg("version", "2");
+        Ok(())
+    })?;
+    test.expect_same("unused ARG value", |test| {
+        test.builder.set_arg("other", "3");
+        Ok(())
+    })?;
+    test.expect_same("overridden ARG default", |test| {
+        test.program_write(["ARG version=4", "RUN true"])
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn dep_from_params() -> RaptorResult<()> {
+    let mut test = Tester::setup(["FROM a region=\"eu\""], |test| {
+        test.write("a.rapt", "RUN echo {{ region }}")
+    })?;
+
+    test.expect_new("FROM parameter", |test| {
+        test.program_write(["FROM a region=\"us\""])
+    })?;
+    test.expect_new("unused FROM parameter", |test| {
+        test.program_write(["FROM a region=\"us\" replicas=3"])
+    })?;
+    test.expect_same("FROM parameter order", |test| {
+        test.program_write(["FROM a replicas=3 region=\"us\""])
+    })?;
+
+    let prog = test.load(&test.program_name)?;
+    let stack = test.builder.stack(prog, test.builder.platform())?;
+    let info = test.builder.layer_info(&stack[0])?;
+    assert_eq!(info.name(), r#"a(region="us",replicas=3)"#);
+
+    Ok(())
+}
+
 #[test]
 fn dep_render() -> RaptorResult<()> {
     let mut test = Tester::setup(["RENDER a a"], |test| test.write("a", "1234"))?;
@@ -275,6 +451,79 @@ fn dep_from3() -> RaptorResult<()> {
     Ok(())
 }
 
+#[test]
+fn dep_from_platform() -> RaptorResult<()> {
+    let mut test = Tester::setup(["FROM docker://debian:stable"], |_| Ok(()))?;
+    let hash = test.hash.clone();
+
+    test.expect_new("FROM platform", |test| {
+        test.program_write(["FROM --platform=linux/arm64 docker://debian:stable"])
+    })?;
+
+    test.expect_hash(
+        "FROM default platform",
+        |test| test.program_write(["FROM --platform=linux/amd64 docker://debian:stable"]),
+        hash,
+    )?;
+
+    Ok(())
+}
+
+#[test]
+fn dep_from_lock() -> RaptorResult<()> {
+    let mut test = Tester::setup(["FROM docker://debian:stable"], |_| Ok(()))?;
+
+    let lock = |data: &'static [u8]| {
+        move |test: &mut Tester| {
+            let path = test.path(LockFile::FILE);
+            let source = RaptorBuilder::parse_docker_source("debian:stable")?;
+
+            let mut lock = LockFile::default();
+            lock.insert(&source, Digest::sha256(data));
+            lock.save(&path)?;
+
+            test.builder.set_lock_file(path);
+            Ok(())
+        }
+    };
+
+    test.expect_same("missing lock file", |test| {
+        let path = test.path(LockFile::FILE);
+        test.builder.set_lock_file(path);
+        Ok(())
+    })?;
+    test.expect_new("locked image", lock(b"1"))?;
+    test.expect_same("locked image", lock(b"1"))?;
+    test.expect_new("locked image digest", lock(b"2"))?;
+
+    Ok(())
+}
+
+#[test]
+fn dep_reproducible() -> RaptorResult<()> {
+    let mut test = Tester::setup(["WRITE \"data\" /a"], |_| Ok(()))?;
+
+    test.expect_new("reproducible mode", |test| {
+        test.builder.set_reproducible(Some(Reproducible::new(0)));
+        Ok(())
+    })?;
+    test.expect_same("reproducible mode", |test| {
+        test.builder.set_reproducible(Some(Reproducible::new(0)));
+        Ok(())
+    })?;
+    test.expect_new("source date epoch", |test| {
+        test.builder
+            .set_reproducible(Some(Reproducible::new(1_700_000_000)));
+        Ok(())
+    })?;
+    test.expect_new("regular mode", |test| {
+        test.builder.set_reproducible(None);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
 #[test]
 fn dep_self() -> RaptorResult<()> {
     let mut test = Tester::setup([""], |test| test.write("a.rapt", ""))?;
@@ -369,7 +618,7 @@ fn dep_instance2() -> RaptorResult<()> {
         test.write("b@.rinc", "WRITE {{instance}} /tmp/foo")
     })?;
 
-    let orig = test.hash;
+    let orig = test.hash.clone();
 
     test.expect_new("instance", |test| test.write("a.rinc", "INCLUDE b@two"))?;
     test.expect_hash(
@@ -389,7 +638,7 @@ fn dep_instance3() -> RaptorResult<()> {
         |_test| Ok(()),
     )?;
 
-    let orig = test.hash;
+    let orig = test.hash.clone();
 
     test.program_name = ModuleName::from("$.program@two");
     test.builder.clear_cache();
@@ -402,6 +651,80 @@ fn dep_instance3() -> RaptorResult<()> {
     Ok(())
 }
 
+/// Legacy layer cache paths of every layer in the stack of the program
+fn legacy_paths(test: &Tester) -> RaptorResult<Vec<Option<Utf8PathBuf>>> {
+    let prog = test.load(&test.program_name)?;
+    test.builder
+        .stack(prog, test.builder.platform())?
+        .iter()
+        .map(|target| Ok(test.builder.layer_info(target)?.legacy_path()))
+        .collect()
+}
+
+#[test]
+fn dep_legacy_key() -> RaptorResult<()> {
+    let mut test = Tester::setup(
+        [
+            "FROM base",
+            "COPY a /a",
+            "COPY a b /dir/",
+            "RENDER c /c",
+            "WRITE \"data\" /d",
+            "MKDIR -p /e",
+            "WORKDIR /e",
+            "ENV A=\"1\" B=\"x y\"",
+            "RUN echo hello",
+            "CMD [\"sh\"]",
+        ],
+        |test| {
+            test.write(
+                "base.rapt",
+                ["FROM docker://debian:stable", "RUN apt-get update"],
+            )?;
+            test.write("a", "1234")?;
+            test.write("b", "5678")?;
+            test.write("c", "{{ 1 + 2 }}")
+        },
+    )?;
+
+    /* calculated by raptor before the content-addressed store (paths are
+     * hashed by camino, so this depends on its version) */
+    assert_eq!(
+        legacy_paths(&test)?,
+        [
+            Some("layers/index.docker.io-library-debian-stable-FFD588C1A6D65743".into()),
+            Some("layers/base-F31F2B9A7DDA69E7".into()),
+            Some("layers/program-A7EA2973F8C6F79A".into()),
+        ]
+    );
+
+    /* layers using newer build inputs cannot be in the legacy cache */
+    test.expect_new("base image platform", |test| {
+        test.write(
+            "base.rapt",
+            [
+                "FROM --platform=linux/arm64 docker://debian:stable",
+                "RUN apt-get update",
+            ],
+        )
+    })?;
+    assert_eq!(legacy_paths(&test)?, [None, None, None]);
+
+    test.expect_new("base secret", |test| {
+        test.write(
+            "base.rapt",
+            [
+                "FROM docker://debian:stable",
+                "RUN --secret id=token apt-get update",
+            ],
+        )
+    })?;
+    assert!(legacy_paths(&test)?[0].is_some());
+    assert_eq!(legacy_paths(&test)?[1..], [None, None]);
+
+    Ok(())
+}
+
 #[test]
 fn basedir_sources() -> RaptorResult<()> {
     let test = Tester::setup(["INCLUDE inc.a"], |test| {
@@ -418,7 +741,7 @@ fn basedir_sources() -> RaptorResult<()> {
     })?;
 
     let program = test.builder.load(&test.program_name)?;
-    let sources = Cacher::sources(&program)?;
+    let sources = Cacher::sources(&program, test.builder.loader())?;
 
     // sources must be sorted, to have predictable cache key
     let mut sorted_sources = sources.clone();
@@ -454,7 +777,7 @@ fn basedir_all_sources() -> RaptorResult<()> {
     test.builder.loader_mut().resolver_mut().set_base(base);
 
     let program = test.builder.load(&test.program_name)?;
-    let sources = Cacher::all_sources(&program, &test.builder)?;
+    let sources = Cacher::all_sources(&program, &test.builder, test.builder.platform())?;
 
     // sources must be sorted, to have predictable cache key
     let mut sorted_sources = sources.clone();
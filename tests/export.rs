@@ -0,0 +1,57 @@
+use std::fs;
+
+use camino_tempfile::Utf8TempDir;
+use dregistry::api::ImageRuntimeConfig;
+use dregistry::platform::Platform;
+use tap::Tap;
+
+use raptor::RaptorResult;
+use raptor::build::{Exporter, RaptorBuilder};
+use raptor::program::Loader;
+use raptor::sandbox::Sandbox;
+use raptor_parser::util::module_name::ModuleName;
+
+/// Write `base.rapt` and `program.rapt` (which builds on `base`), and collect
+/// the image configuration for `program`.
+fn image_config(base: &str, program: &str) -> RaptorResult<ImageRuntimeConfig> {
+    let tempdir = Utf8TempDir::new()?;
+    fs::write(tempdir.path().join("base.rapt"), base)?;
+    fs::write(tempdir.path().join("program.rapt"), program)?;
+
+    let loader = Loader::new()?.tap_mut(|ldr| ldr.resolver_mut().set_base(&tempdir));
+    let builder = RaptorBuilder::new(loader, Sandbox::find_falcon_dev().unwrap(), true);
+
+    let program = builder.load(&ModuleName::from("$.program"))?;
+    let stack = builder.stack(program, &Platform::default())?;
+
+    Exporter::image_config(&stack)
+}
+
+#[test]
+fn image_config_mapping() -> RaptorResult<()> {
+    let config = image_config(
+        "WORKDIR /base\nUSER app:staff\nENV A=a B=b\nENTRYPOINT /bin/sh -c\nCMD true\n",
+        "FROM base\nENV B=x C=c\nCMD echo hello\n",
+    )?;
+
+    assert_eq!(config.env, ["A=a", "B=x", "C=c"]);
+    assert_eq!(config.working_dir.as_deref(), Some("/base"));
+    assert_eq!(config.user.as_deref(), Some("app:staff"));
+    assert_eq!(config.entrypoint, Some(vec!["/bin/sh".into(), "-c".into()]));
+    assert_eq!(config.cmd, Some(vec!["echo".into(), "hello".into()]));
+
+    Ok(())
+}
+
+#[test]
+fn image_config_empty() -> RaptorResult<()> {
+    let config = image_config("", "FROM base\n")?;
+
+    assert!(config.env.is_empty());
+    assert_eq!(config.working_dir, None);
+    assert_eq!(config.user, None);
+    assert_eq!(config.entrypoint, None);
+    assert_eq!(config.cmd, None);
+
+    Ok(())
+}
@@ -1,9 +1,13 @@
+use std::fs::Permissions;
+use std::os::unix::fs::PermissionsExt;
 use std::os::unix::net::UnixListener;
 use std::process::Command;
 
 use camino_tempfile::{NamedUtf8TempFile, Utf8TempDir};
 use nix::errno::Errno;
+use nix::unistd::{Uid, User};
 
+use falcon::client::SecretMount;
 use raptor::sandbox::{FalconClient, SandboxExt};
 use raptor::{RaptorError, RaptorResult};
 
@@ -58,3 +62,204 @@ fn client_write_file() -> RaptorResult<()> {
 
     sc.close()
 }
+
+#[test]
+fn client_symlink_stat() -> RaptorResult<()> {
+    let mut sc = spawn_client()?;
+
+    let tempdir = Utf8TempDir::new()?;
+    let link = tempdir.path().join("link");
+
+    assert_eq!(sc.stat(&link)?, None);
+
+    sc.symlink(&link, tempdir.path(), None)?;
+    assert_eq!(link.read_link_utf8()?, tempdir.path());
+
+    /* stat follows symlinks */
+    let mode = sc.stat(&link)?.unwrap();
+    assert_eq!(mode & 0o170_000, 0o040_000);
+
+    sc.close()
+}
+
+#[test]
+fn client_mkdir_parents_existing() -> RaptorResult<()> {
+    let mut sc = spawn_client()?;
+
+    let tempdir = Utf8TempDir::new()?;
+    let path = tempdir.path().join("dir");
+    std::fs::create_dir(&path)?;
+    std::fs::set_permissions(&path, Permissions::from_mode(0o755))?;
+
+    /* existing directories keep their mode */
+    sc.mkdir(&path, None, Some(0o700), true)?;
+    assert_eq!(path.metadata()?.permissions().mode() & 0o7777, 0o755);
+
+    /* new directories get the requested mode */
+    let new = path.join("new");
+    sc.mkdir(&new, None, Some(0o700), true)?;
+    assert_eq!(new.metadata()?.permissions().mode() & 0o7777, 0o700);
+
+    sc.close()
+}
+
+#[test]
+fn client_setuser() -> RaptorResult<()> {
+    let mut sc = spawn_client()?;
+
+    let uid = Uid::current();
+    let home = User::from_uid(uid)?.unwrap().dir;
+
+    sc.setuser(Some(&uid.to_string()), None)?;
+    sc.run(&[
+        "/bin/sh".into(),
+        "-c".into(),
+        format!("test \"$(id -u)\" = {uid} && test \"$HOME\" = {home:?}"),
+    ])?;
+
+    assert!(sc.setuser(Some("raptor-missing-user"), None).is_err());
+
+    sc.close()
+}
+
+#[test]
+fn client_run_secret() -> RaptorResult<()> {
+    /* mounting requires root */
+    if !Uid::effective().is_root() {
+        return Ok(());
+    }
+
+    let mut sc = spawn_client()?;
+
+    let tempdir = Utf8TempDir::new()?;
+    let target = tempdir.path().join("missing/dir/secret");
+
+    let secret = SecretMount {
+        data: TEST_DATA.to_vec(),
+        target: target.clone(),
+    };
+
+    sc.run_with_secrets(
+        &[
+            "/bin/sh".into(),
+            "-c".into(),
+            format!("test \"$(cat {target})\" = Raptortest && ! touch {target} 2>/dev/null"),
+        ],
+        vec![secret],
+    )?;
+
+    /* mount points are removed again */
+    assert!(!tempdir.path().join("missing").exists());
+
+    sc.close()
+}
+
+#[test]
+fn client_run_secret_existing_target() -> RaptorResult<()> {
+    /* mounting requires root */
+    if !Uid::effective().is_root() {
+        return Ok(());
+    }
+
+    let mut sc = spawn_client()?;
+
+    let tempdir = Utf8TempDir::new()?;
+    let target = tempdir.path().join("secret");
+    std::fs::write(&target, b"original\n")?;
+
+    let secret = SecretMount {
+        data: TEST_DATA.to_vec(),
+        target: target.clone(),
+    };
+
+    sc.run_with_secrets(
+        &[
+            "/bin/sh".into(),
+            "-c".into(),
+            format!("test \"$(cat {target})\" = Raptortest && test -z \"$(ls -A {tempdir} | grep -v secret)\"", tempdir = tempdir.path()),
+        ],
+        vec![secret],
+    )?;
+
+    /* the file mounted over is left as it was */
+    assert_eq!(std::fs::read(&target)?, b"original\n");
+
+    sc.close()
+}
+
+#[test]
+fn client_run_secret_write_next() -> RaptorResult<()> {
+    /* mounting requires root */
+    if !Uid::effective().is_root() {
+        return Ok(());
+    }
+
+    let mut sc = spawn_client()?;
+
+    let tempdir = Utf8TempDir::new()?;
+    let dir = tempdir.path().join("missing");
+    let existing = tempdir.path().join("existing");
+
+    std::fs::create_dir(&existing)?;
+    let mtime = tempdir.path().metadata()?.modified()?;
+
+    let secret = SecretMount {
+        data: TEST_DATA.to_vec(),
+        target: dir.join("secret"),
+    };
+
+    /* writing next to the secret does not fail the command */
+    sc.run_with_secrets(
+        &[
+            "/bin/sh".into(),
+            "-c".into(),
+            format!("echo data > {dir}/other && echo data > {existing}/file"),
+        ],
+        vec![secret],
+    )?;
+
+    /* mount points are never created outside the command, but changes to
+     * existing directories are kept */
+    assert!(!dir.exists());
+    assert_eq!(tempdir.path().metadata()?.modified()?, mtime);
+    assert_eq!(std::fs::read(existing.join("file"))?, b"data\n");
+
+    sc.close()
+}
+
+#[test]
+fn client_run_without_secret() -> RaptorResult<()> {
+    /* mounting requires root */
+    if !Uid::effective().is_root() {
+        return Ok(());
+    }
+
+    let mut sc = spawn_client()?;
+
+    let tempdir = Utf8TempDir::new()?;
+    let dir = tempdir.path();
+
+    let secret = SecretMount {
+        data: TEST_DATA.to_vec(),
+        target: dir.join("secret"),
+    };
+
+    /* while one command uses a secret, its contents are nowhere else to be found */
+    sc.run_with_secrets(
+        &[
+            "/bin/sh".into(),
+            "-c".into(),
+            format!("test \"$(grep -rl Raptortest {dir})\" = {dir}/secret"),
+        ],
+        vec![secret],
+    )?;
+
+    /* a command without the secret cannot read it */
+    sc.run(&[
+        "/bin/sh".into(),
+        "-c".into(),
+        format!("! grep -rq Raptortest {dir} && test -z \"$(ls -A {dir})\""),
+    ])?;
+
+    sc.close()
+}
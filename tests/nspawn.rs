@@ -269,3 +269,33 @@ fn nspawn_write_chmod() -> RaptorResult<()> {
     sbx.close()?;
     Ok(())
 }
+
+#[test]
+fn nspawn_symlink_stat() -> RaptorResult<()> {
+    let mut sbx = spawn_sandbox("symlink_stat")?;
+    let client = sbx.client();
+
+    client.mkdir(&"/tmp/dir", None, Some(0o0750), false)?;
+    client.shell(&["[ $(stat -c '%04a' /tmp/dir) = 0750 ]"])?;
+
+    client.write_file("/tmp/dir/a", None, None, b"Hello world\n")?;
+    client.symlink("/tmp/link".into(), "dir/a".into(), None)?;
+    client.shell(&["[ $(readlink /tmp/link) = dir/a ]"])?;
+
+    /* replacing an existing symlink */
+    client.symlink("/tmp/link".into(), "dir".into(), None)?;
+    client.shell(&["[ $(readlink /tmp/link) = dir ]"])?;
+
+    assert_eq!(
+        client.stat("/tmp/dir".into())?.map(|mode| mode & 0o170_000),
+        Some(0o040_000)
+    );
+    assert_eq!(
+        client.stat("/tmp/link/a".into())?.map(|mode| mode & 0o7777),
+        Some(0o0750)
+    );
+    assert_eq!(client.stat("/tmp/missing".into())?, None);
+
+    sbx.close()?;
+    Ok(())
+}
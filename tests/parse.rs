@@ -5,15 +5,17 @@ use std::sync::Arc;
 use camino::{Utf8Path, Utf8PathBuf};
 use minijinja::{Value, context};
 use pretty_assertions::assert_eq;
+use serde_json::json;
 use tap::Tap;
 
-use raptor::RaptorResult;
 use raptor::dsl::{Item, Program};
-use raptor::program::Loader;
+use raptor::program::{DUMP_SCHEMA_VERSION, Dump, Loader};
+use raptor::{RaptorError, RaptorResult};
 use raptor_parser::ast::{
-    Chown, FromSource, IncludeArg, InstEnvAssign, InstFrom, InstMkdir, InstMount, Instruction,
-    MountOptions, MountType, Origin,
+    Chown, FromSource, IncludeArg, InstEnvAssign, InstFrom, InstMkdir, InstMount, InstRun,
+    Instruction, MountOptions, MountType, NetworkMode, Origin, RunSecret,
 };
+use raptor_parser::util::module_name::ModuleName;
 
 fn base_path() -> Utf8PathBuf {
     Utf8Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/cases/inst")
@@ -56,12 +58,22 @@ fn parse_copy02() -> RaptorResult<()> {
     )
 }
 
+#[test]
+fn parse_copy03() -> RaptorResult<()> {
+    test_single_inst_parse(
+        "copy03.rapt",
+        Instruction::copy(&["conf/*.conf", "file?.txt", "[ab].txt"], "/etc/app/"),
+    )
+}
+
 #[test]
 fn parse_from01() -> RaptorResult<()> {
     test_single_inst_parse(
         "from01.rapt",
         Instruction::From(InstFrom {
             from: FromSource::Raptor("baselayer".into()),
+            platform: None,
+            args: vec![],
         }),
     )
 }
@@ -72,10 +84,69 @@ fn parse_from02() -> RaptorResult<()> {
         "from02.rapt",
         Instruction::From(InstFrom {
             from: FromSource::Docker("debian:stable".into()),
+            platform: None,
+            args: vec![],
+        }),
+    )
+}
+
+#[test]
+fn parse_from03() -> RaptorResult<()> {
+    test_single_inst_parse(
+        "from03.rapt",
+        Instruction::From(InstFrom {
+            from: FromSource::Docker("debian:stable".into()),
+            platform: Some("linux/arm64/v8".into()),
+            args: vec![],
         }),
     )
 }
 
+#[test]
+fn parse_from04() -> RaptorResult<()> {
+    test_single_inst_parse(
+        "from04.rapt",
+        Instruction::From(InstFrom {
+            from: FromSource::Raptor("base@prod".into()),
+            platform: None,
+            args: vec![
+                IncludeArg::value("region", "eu"),
+                IncludeArg::value("replicas", 3),
+            ],
+        }),
+    )?;
+
+    let program = load_file("from04.rapt")?;
+    let params = BTreeMap::from([
+        ("region".to_string(), Value::from("eu")),
+        ("replicas".to_string(), Value::from(3)),
+    ]);
+    assert_eq!(program.from_params()?, params);
+
+    Ok(())
+}
+
+#[test]
+fn parse_from05() -> RaptorResult<()> {
+    let loader = Loader::new()?.tap_mut(|ldr| ldr.resolver_mut().set_base(base_path()));
+    let params = BTreeMap::from([("region".to_string(), Value::from("eu"))]);
+
+    let program = loader.load_program(
+        &ModuleName::from("from05"),
+        &params,
+        Origin::inline(),
+        &BTreeMap::new(),
+    )?;
+
+    assert_eq!(program.params, params);
+    let Item::Statement(stmt) = &program.code[1] else {
+        panic!("expected statement");
+    };
+    assert_eq!(stmt.inst, Instruction::run(&["echo", "eu"]));
+
+    Ok(())
+}
+
 #[test]
 fn parse_run01() -> RaptorResult<()> {
     test_single_inst_parse("run01.rapt", Instruction::run(&["id"]))
@@ -94,6 +165,26 @@ fn parse_run03() -> RaptorResult<()> {
     )
 }
 
+#[test]
+fn parse_run04() -> RaptorResult<()> {
+    test_single_inst_parse(
+        "run04.rapt",
+        Instruction::Run(InstRun {
+            run: vec!["npm".into(), "install".into()],
+            secrets: vec![
+                RunSecret {
+                    id: "npmrc".into(),
+                    target: Some("/root/.npmrc".into()),
+                },
+                RunSecret {
+                    id: "token".into(),
+                    target: None,
+                },
+            ],
+        }),
+    )
+}
+
 #[test]
 fn parse_write01() -> RaptorResult<()> {
     test_single_inst_parse("write01.rinc", Instruction::write("bar", "/foo"))
@@ -168,6 +259,83 @@ fn parse_env02() -> RaptorResult<()> {
     )
 }
 
+#[test]
+fn parse_user01() -> RaptorResult<()> {
+    test_single_inst_parse("user01.rapt", Instruction::user("www-data", None))
+}
+
+#[test]
+fn parse_user02() -> RaptorResult<()> {
+    test_single_inst_parse("user02.rapt", Instruction::user("app", Some("1000")))
+}
+
+#[test]
+fn parse_network01() -> RaptorResult<()> {
+    test_single_inst_parse("network01.rapt", Instruction::network(NetworkMode::None))
+}
+
+#[test]
+fn parse_network02() -> RaptorResult<()> {
+    test_single_inst_parse(
+        "network02.rapt",
+        Instruction::network(NetworkMode::Zone("build-net".into())),
+    )
+}
+
+#[test]
+fn parse_network_mode() {
+    for mode in ["host", "none", "veth", "zone:build"] {
+        assert_eq!(mode.parse::<NetworkMode>().unwrap().to_string(), mode);
+    }
+
+    assert!("zone:".parse::<NetworkMode>().is_err());
+    assert!("bridge".parse::<NetworkMode>().is_err());
+}
+
+#[test]
+fn parse_arg01() -> RaptorResult<()> {
+    test_single_inst_parse("arg01.rapt", Instruction::arg("version", Some("1.0")))
+}
+
+#[test]
+fn parse_arg02() -> RaptorResult<()> {
+    let err = load_file("arg02.rapt").unwrap_err();
+    assert!(matches!(err, RaptorError::ArgMissing(name, _) if name == "version"));
+
+    let loader = Loader::new()?.tap_mut(|ldr| ldr.resolver_mut().set_base(base_path()));
+    let args = BTreeMap::from([("version".to_string(), "2.0".to_string())]);
+    let program = loader.load_program(
+        &ModuleName::from("arg02"),
+        &BTreeMap::new(),
+        Origin::inline(),
+        &args,
+    )?;
+
+    assert_eq!(program.arg_values(), [("version", Value::from("2.0"))]);
+    Ok(())
+}
+
+#[test]
+fn parse_arg03() -> RaptorResult<()> {
+    let loader = Loader::new()?.tap_mut(|ldr| ldr.resolver_mut().set_base(base_path()));
+    let name = ModuleName::from("arg03");
+
+    let run = |program: &Program| match &program.code[1] {
+        Item::Statement(stmt) => stmt.inst.clone(),
+        Item::Program(_) => panic!("expected statement"),
+    };
+
+    let program =
+        loader.load_program(&name, &BTreeMap::new(), Origin::inline(), &BTreeMap::new())?;
+    assert_eq!(run(&program), Instruction::run(&["echo", "1.0"]));
+
+    let args = BTreeMap::from([("version".to_string(), "2.0".to_string())]);
+    let program = loader.load_program(&name, &BTreeMap::new(), Origin::inline(), &args)?;
+    assert_eq!(run(&program), Instruction::run(&["echo", "2.0"]));
+
+    Ok(())
+}
+
 #[test]
 fn parse_workdir01() -> RaptorResult<()> {
     test_single_inst_parse("workdir01.rapt", Instruction::workdir("/foo"))
@@ -252,6 +420,29 @@ fn parse_cmd02() -> RaptorResult<()> {
     )
 }
 
+#[test]
+fn parse_include_arg_default() -> RaptorResult<()> {
+    let program = load_file("include05.rapt")?;
+
+    let mut runs = vec![];
+    program.traverse(&mut |stmt| {
+        if let Instruction::Run(_) = &stmt.inst {
+            runs.push(stmt.inst.clone());
+        }
+        Ok(())
+    })?;
+
+    assert_eq!(
+        runs,
+        [
+            Instruction::run(&["echo", "hello", "world"]),
+            Instruction::run(&["echo", "hi", "world"]),
+        ]
+    );
+
+    Ok(())
+}
+
 #[test]
 fn parse_include01() -> RaptorResult<()> {
     let program = load_file("include01.rapt")?;
@@ -446,3 +637,49 @@ fn parse_mount01() -> RaptorResult<()> {
         }),
     )
 }
+
+#[test]
+fn parse_dump_json() -> RaptorResult<()> {
+    let loader = Loader::new()?.tap_mut(|ldr| ldr.resolver_mut().set_base(base_path()));
+    let mut origins = vec![Origin::inline()];
+    let program = loader.load_template("include03.rapt", context! {}, &mut origins)?;
+
+    let mut dump = Dump::new();
+    dump.add_target(&loader, ModuleName::from("include03"), program);
+
+    assert_eq!(
+        serde_json::to_value(&dump)?,
+        json!({
+            "version": DUMP_SCHEMA_VERSION,
+            "targets": [{
+                "name": "include03",
+                "sources": {
+                    "include/run01.rinc": "RUN id\n\n",
+                    "include03.rapt": "INCLUDE include.run01\n\n",
+                },
+                "program": {
+                    "code": [
+                        {"statement": {
+                            "inst": {"type": "INCLUDE", "src": "include.run01", "args": []},
+                            "origin": {"path": "include03.rapt", "span": {"start": 0, "end": 21}},
+                        }},
+                        {"program": {
+                            "code": [
+                                {"statement": {
+                                    "inst": {"type": "RUN", "run": ["id"], "secrets": []},
+                                    "origin": {"path": "include/run01.rinc", "span": {"start": 0, "end": 6}},
+                                }},
+                            ],
+                            "ctx": {},
+                            "path": "include/run01.rinc",
+                        }},
+                    ],
+                    "ctx": {},
+                    "path": "include03.rapt",
+                },
+            }],
+        })
+    );
+
+    Ok(())
+}
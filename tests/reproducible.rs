@@ -0,0 +1,121 @@
+use std::env;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::sync::Arc;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use camino_tempfile::Utf8TempDir;
+use dregistry::digest::Digest;
+use nix::unistd::Uid;
+use tap::Tap;
+
+use raptor::RaptorResult;
+use raptor::build::{BuildTarget, LayerInfo, RaptorBuilder, Reproducible};
+use raptor::program::Loader;
+use raptor::sandbox::Sandbox;
+use raptor_parser::util::module_name::ModuleName;
+
+const EPOCH: i64 = 1_700_000_000;
+
+#[derive(Debug, PartialEq, Eq)]
+struct Entry {
+    path: Utf8PathBuf,
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    mtime: (i64, i64),
+    data: Vec<u8>,
+}
+
+/// Collect every entry below `root`, with all metadata that ends up in an
+/// exported layer
+fn tree(root: &Utf8Path, dir: &Utf8Path, out: &mut Vec<Entry>) -> RaptorResult<()> {
+    let mut names = root
+        .join(dir)
+        .read_dir_utf8()?
+        .map(|dent| Ok(dent?.file_name().to_string()))
+        .collect::<RaptorResult<Vec<_>>>()?;
+    names.sort();
+
+    for name in names {
+        let path = dir.join(name);
+        let full = root.join(&path);
+        let md = full.symlink_metadata()?;
+
+        let data = if md.is_file() {
+            fs::read(&full)?
+        } else if md.is_symlink() {
+            full.read_link_utf8()?.into_string().into_bytes()
+        } else {
+            vec![]
+        };
+
+        out.push(Entry {
+            path: path.clone(),
+            mode: md.mode(),
+            uid: md.uid(),
+            gid: md.gid(),
+            mtime: (md.mtime(), md.mtime_nsec()),
+            data,
+        });
+
+        if md.is_dir() {
+            tree(root, &path, out)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn reproducible_build() -> RaptorResult<()> {
+    /* building requires root */
+    if !Uid::effective().is_root() {
+        return Ok(());
+    }
+
+    let tempdir = Utf8TempDir::new()?;
+    fs::write(
+        tempdir.path().join("program.rapt"),
+        [
+            "MKDIR -p /srv/data",
+            "WRITE \"hello\" /srv/data/hello",
+            "COPY input.txt /etc/input.txt",
+        ]
+        .join("\n"),
+    )?;
+    fs::write(tempdir.path().join("input.txt"), "input")?;
+
+    let falcon = Sandbox::find_falcon_dev().unwrap().canonicalize_utf8()?;
+
+    /* layers are stored in `layers/` below the working directory, so build
+     * inside the tempdir (this is the only test in this binary) */
+    env::set_current_dir(&tempdir)?;
+
+    let loader = Loader::new()?.tap_mut(|ldr| ldr.resolver_mut().set_base(&tempdir));
+    let mut builder = RaptorBuilder::new(loader, falcon, false);
+    builder.set_reproducible(Some(Reproducible::new(EPOCH)));
+
+    let program = builder.load(&ModuleName::from("$.program"))?;
+    let target = BuildTarget::Program(Arc::clone(&program), builder.platform().clone());
+
+    /* build the same target twice, as two distinct layers */
+    let mut trees = vec![];
+    for n in 1..=2u8 {
+        let layer = LayerInfo::new("reproducible".into(), Digest::Sha256([n; 32]));
+        let path = builder.build_layer(&[], &target, &layer)?;
+
+        let mut entries = vec![];
+        let res = tree(&path, Utf8Path::new(""), &mut entries);
+        fs::remove_dir_all(&path)?;
+        res?;
+
+        trees.push(entries);
+    }
+
+    assert!(!trees[0].is_empty());
+    assert!(trees[0].iter().all(|entry| entry.mtime <= (EPOCH, 0)));
+    assert_eq!(trees[0], trees[1]);
+
+    Ok(())
+}
@@ -0,0 +1,48 @@
+use std::fs;
+
+use camino_tempfile::Utf8TempDir;
+use dregistry::platform::Platform;
+use tap::Tap;
+
+use raptor::RaptorResult;
+use raptor::build::RaptorBuilder;
+use raptor::program::Loader;
+use raptor::runner::Runner;
+use raptor::sandbox::Sandbox;
+use raptor_parser::ast::InstUser;
+use raptor_parser::util::module_name::ModuleName;
+
+/// Write `base.rapt` and `program.rapt` (which builds on `base`), and resolve
+/// the user to run `program` as.
+fn run_user(base: &str, program: &str) -> RaptorResult<Option<InstUser>> {
+    let tempdir = Utf8TempDir::new()?;
+    fs::write(tempdir.path().join("base.rapt"), base)?;
+    fs::write(tempdir.path().join("program.rapt"), program)?;
+
+    let loader = Loader::new()?.tap_mut(|ldr| ldr.resolver_mut().set_base(&tempdir));
+    let builder = RaptorBuilder::new(loader, Sandbox::find_falcon_dev().unwrap(), true);
+
+    let program = builder.load(&ModuleName::from("$.program"))?;
+
+    Runner::user(&program, &builder, &Platform::default())
+}
+
+#[test]
+fn user_from_parent() -> RaptorResult<()> {
+    let user = run_user("USER app:staff\n", "FROM base\nCMD id\n")?.unwrap();
+
+    assert_eq!(user.user, "app");
+    assert_eq!(user.group.as_deref(), Some("staff"));
+
+    Ok(())
+}
+
+#[test]
+fn user_override() -> RaptorResult<()> {
+    let user = run_user("USER app\n", "FROM base\nUSER other\n")?.unwrap();
+    assert_eq!(user.user, "other");
+
+    assert!(run_user("", "FROM base\n")?.is_none());
+
+    Ok(())
+}